@@ -0,0 +1,9 @@
+fn main() -> std::io::Result<()> {
+    // an explicitly set PROTOC wins, otherwise the vendored binary is used, so no system protoc is needed
+    if std::env::var_os("PROTOC").is_none() {
+        let protoc = protoc_bin_vendored::protoc_bin_path()
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::NotFound, err.to_string()))?;
+        std::env::set_var("PROTOC", protoc);
+    }
+    prost_build::compile_protos(&["proto/characteristic_payload.proto"], &["proto/"])
+}
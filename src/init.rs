@@ -10,30 +10,46 @@ use metrics_tracing_context::{MetricsLayer, TracingContextLayer};
 use metrics_util::layers::Stack;
 use metrics_util::MetricKindMask;
 use rocket::{routes, Build, Rocket};
-use rumqttc::v5::MqttOptions;
+use rumqttc::v5::mqttbytes::v5::Packet;
+use rumqttc::v5::mqttbytes::QoS;
+use rumqttc::v5::{AsyncClient, ClientError, Event, MqttOptions};
+use rumqttc::Outgoing;
 use tokio::task::JoinSet;
-use tracing::error;
+use tracing::{error, warn};
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
-use tracing_subscriber::EnvFilter;
+use tracing_subscriber::{reload, EnvFilter};
 
 use crate::inner::adapter_manager::AdapterManager;
 use crate::inner::api::{
-    describe_adapters, get_collector_data, get_connected_peripherals, get_metrics, list_adapters, list_configurations,
-    read_write_characteristic,
+    bulk_write_characteristic, convert, describe_adapters, dump_gatt, export_configurations, get_catalog,
+    get_circuit_breaker, get_collector_data, get_connected_peripherals, get_events, get_latest_value,
+    get_lifecycle_events, get_matching_peripherals, get_metrics, get_peripheral_properties, get_peripheral_services,
+    get_peripheral_signal, get_recent_logs, get_scan_filter, get_tasks, list_adapters, list_configurations,
+    listen_notifications, pause_discovery, probe_peripheral, read_characteristics, read_write_characteristic,
+    restart_scan, resume_discovery, set_log_level, set_scan_filter, stream_read_write_characteristic,
 };
+use crate::inner::compression::GzipCompression;
 use crate::inner::conf::manager::ConfigurationManager;
 use crate::inner::error::CollectorError;
+use crate::inner::log_level::LogLevelManager;
 use crate::inner::metrics::describe_metrics;
 use crate::inner::model::collector_event::CollectorEvent;
 use crate::inner::publish::api_publisher::ApiPublisher;
-use crate::inner::publish::dto::MqttDataPoint;
+use crate::inner::publish::dto::MqttHeartbeat;
+use crate::inner::publish::event_stream::EventStreamPublisher;
+use crate::inner::publish::lifecycle_publisher::LifecyclePublisher;
 use crate::inner::publish::metric_publisher::MetricPublisher;
+use crate::inner::publish::mqtt_command::{MqttCommand, MqttCommandRouter};
 use crate::inner::publish::mqtt_interpolator::MqttInterpolator;
+use crate::inner::publish::mqtt_target::MqttTarget;
 use crate::inner::publish::multi_publisher::MultiPublisher;
 use crate::inner::publish::PublishPayload;
+use crate::inner::recent_log::{RecentLogBuffer, RecentLogLayer};
+use crate::inner::request_timeout::with_request_timeout;
+use crate::inner::supervisor::supervise;
 
-pub(super) fn init_tracing() -> anyhow::Result<()> {
+pub(super) fn init_tracing(recent_log_buffer: Arc<RecentLogBuffer>) -> anyhow::Result<LogLevelManager> {
     let metrics_layer = MetricsLayer::new();
     let console_layer = ConsoleLayer::builder().with_default_env().spawn();
     let fmt_layer = tracing_subscriber::fmt::layer()
@@ -41,15 +57,18 @@ pub(super) fn init_tracing() -> anyhow::Result<()> {
         .with_ansi(atty::is(atty::Stream::Stdout))
         .with_target(false);
     let filter_layer = EnvFilter::try_from_default_env().or_else(|_| EnvFilter::try_new("info"))?;
+    let (filter_layer, reload_handle) = reload::Layer::new(filter_layer);
+    let recent_log_layer = RecentLogLayer::new(recent_log_buffer);
 
     tracing_subscriber::registry()
         .with(filter_layer)
         .with(fmt_layer)
         .with(metrics_layer)
         .with(console_layer)
+        .with(recent_log_layer)
         .init();
 
-    Ok(())
+    Ok(LogLevelManager::new(reload_handle))
 }
 
 pub(super) fn init_prometheus(idle_timeout: Duration) -> anyhow::Result<PrometheusHandle> {
@@ -85,7 +104,8 @@ pub(super) fn init_prometheus(idle_timeout: Duration) -> anyhow::Result<Promethe
 pub(super) fn init_multi_publisher(
     api_publisher: &Arc<ApiPublisher>,
     metric_publisher: &Arc<MetricPublisher>,
-    payload_receiver: kanal::Receiver<CollectorEvent>,
+    event_stream_publisher: &Arc<EventStreamPublisher>,
+    payload_receiver: AsyncReceiver<CollectorEvent>,
 ) -> Arc<MultiPublisher> {
     let api_publisher = Arc::clone(api_publisher);
     let payload_storage_processor: Arc<dyn PublishPayload + Sync + Send> = api_publisher;
@@ -93,9 +113,16 @@ pub(super) fn init_multi_publisher(
     let metric_publisher = Arc::clone(metric_publisher);
     let payload_metric_publisher: Arc<dyn PublishPayload + Sync + Send> = metric_publisher;
 
+    let event_stream_publisher = Arc::clone(event_stream_publisher);
+    let payload_event_stream_publisher: Arc<dyn PublishPayload + Sync + Send> = event_stream_publisher;
+
     Arc::new(MultiPublisher::new(
         payload_receiver,
-        vec![payload_storage_processor, payload_metric_publisher],
+        vec![
+            payload_storage_processor,
+            payload_metric_publisher,
+            payload_event_stream_publisher,
+        ],
     ))
 }
 
@@ -104,25 +131,65 @@ pub(super) fn init_rocket(
     adapter_manager: Arc<AdapterManager>,
     api_publisher: Arc<ApiPublisher>,
     prometheus_handle: PrometheusHandle,
+    log_level_manager: LogLevelManager,
+    recent_log_buffer: Arc<RecentLogBuffer>,
     listen_address: SocketAddr,
+    api_request_timeout: Duration,
 ) -> Rocket<Build> {
     rocket::build()
         .manage(configuration_manager)
         .manage(adapter_manager)
         .manage(api_publisher)
         .manage(prometheus_handle)
+        .manage(log_level_manager)
+        .manage(recent_log_buffer)
+        .mount(
+            "/ble",
+            with_request_timeout(
+                routes![
+                    describe_adapters,
+                    get_catalog,
+                    list_configurations,
+                    export_configurations,
+                    get_matching_peripherals,
+                    get_collector_data,
+                    get_latest_value,
+                    list_adapters,
+                    convert,
+                    get_connected_peripherals,
+                    get_tasks,
+                    get_scan_filter,
+                    set_scan_filter,
+                    restart_scan,
+                    pause_discovery,
+                    resume_discovery,
+                    set_log_level,
+                    get_recent_logs,
+                    get_lifecycle_events,
+                    get_events,
+                    probe_peripheral,
+                    get_peripheral_services,
+                    get_peripheral_properties,
+                    get_peripheral_signal,
+                    get_circuit_breaker,
+                    read_characteristics,
+                    dump_gatt,
+                    listen_notifications
+                ],
+                api_request_timeout,
+            ),
+        )
+        // commands of I/O and bulk write requests have their own timeouts, which may add up to more
         .mount(
             "/ble",
             routes![
-                describe_adapters,
-                list_configurations,
-                get_collector_data,
-                list_adapters,
+                bulk_write_characteristic,
                 read_write_characteristic,
-                get_connected_peripherals
+                stream_read_write_characteristic
             ],
         )
-        .mount("/", routes![get_metrics])
+        .mount("/", with_request_timeout(routes![get_metrics], api_request_timeout))
+        .attach(GzipCompression)
         .configure(
             rocket::config::Config::figment()
                 .merge(("address", Arc::new(listen_address.ip().to_string())))
@@ -130,56 +197,156 @@ pub(super) fn init_rocket(
         )
 }
 
+/// Delay before a failed MQTT publisher consumes the events again.
+const MQTT_PUBLISHER_RESTART_DELAY: Duration = Duration::from_secs(1);
+
 pub(super) async fn init_mqtt(
+    name: String,
     opts: MqttOptions,
     payload_receiver: AsyncReceiver<CollectorEvent>,
     cap: usize,
+    target: MqttTarget,
+    command_router: Option<Arc<MqttCommandRouter>>,
     join_set: &mut JoinSet<anyhow::Result<()>>,
-) -> anyhow::Result<()> {
-    let (mqtt_client, mut event_loop) = rumqttc::v5::AsyncClient::new(opts, cap);
+) -> anyhow::Result<AsyncClient> {
+    let (mqtt_client, mut event_loop) = AsyncClient::new(opts, cap);
 
+    let client = mqtt_client.clone();
+    let router = command_router.clone();
     join_set.spawn(async move {
-        let interpolator = MqttInterpolator::default();
-        let mut stream = payload_receiver.stream();
+        let (receiver, publisher, target, router) = (&payload_receiver, &mqtt_client, &target, router.as_ref());
+        // the client fails only when the event loop has ended, restarting won't help
+        let fatal = |err: &anyhow::Error| err.downcast_ref::<ClientError>().is_some();
+        supervise(&name, MQTT_PUBLISHER_RESTART_DELAY, fatal, || {
+            publish_mqtt_events(receiver, publisher, target, router)
+        })
+        .await?;
 
-        while let Some(collector_event) = stream.next().await {
-            match collector_event {
-                CollectorEvent::Payload(payload) => {
-                    let Some(mqtt_conf) = payload.conf.publish_mqtt() else {
+        // all senders are gone: disconnect once the queued messages are sent
+        mqtt_client.disconnect().await?;
+        warn!("MQTT publisher has ended");
+        Ok(())
+    });
+
+    join_set.spawn(async move {
+        loop {
+            match event_loop.poll().await {
+                Ok(Event::Incoming(Packet::Publish(publish))) => {
+                    let command = MqttCommand {
+                        topic: String::from_utf8_lossy(&publish.topic).into_owned(),
+                        payload: publish.payload.to_vec(),
+                    };
+                    let Some(command_router) = command_router.as_ref() else {
                         continue;
                     };
+                    if let Err(err) = command_router.enqueue(command).await {
+                        error!("Failed to enqueue MQTT command: {}", err);
+                    }
+                }
+                Ok(Event::Outgoing(Outgoing::Disconnect)) => return Ok(()),
+                Ok(_) => {}
+                Err(err) => error!("Failed to poll MQTT event loop: {}", err),
+            }
+        }
+    });
+
+    Ok(client)
+}
+
+/// Publishes the collected events to MQTT; returns once all senders are gone and the queue is drained.
+async fn publish_mqtt_events(
+    payload_receiver: &AsyncReceiver<CollectorEvent>,
+    mqtt_client: &AsyncClient,
+    target: &MqttTarget,
+    router: Option<&Arc<MqttCommandRouter>>,
+) -> anyhow::Result<()> {
+    let interpolator = MqttInterpolator::default();
+    let mut stream = payload_receiver.stream();
 
-                    let state_topic = interpolator.interpolate_state_topic(mqtt_conf.state_topic.as_str(), &payload)?;
-                    let data_point = serde_json::to_string(&MqttDataPoint::from(payload.as_ref()))?;
-                    mqtt_client
-                        .publish(state_topic, mqtt_conf.qos(), mqtt_conf.retain, data_point)
-                        .await?;
+    while let Some(collector_event) = stream.next().await {
+        match collector_event {
+            CollectorEvent::Payload(payload) => {
+                let Some(message) = target.state_message(&interpolator, &payload)? else {
+                    continue;
+                };
+                mqtt_client
+                    .publish(message.topic, message.qos, message.retain, message.data_point)
+                    .await?;
+            }
+            CollectorEvent::Connect(request) => {
+                if let Some(router) = router {
+                    match router.register(&interpolator, &request) {
+                        Ok(Some(command_topic)) => {
+                            mqtt_client.subscribe(command_topic, QoS::AtLeastOnce).await?;
+                        }
+                        Ok(None) => {}
+                        Err(err) => error!(fqcn = %request.fqcn, "Failed to register MQTT command topic: {err}"),
+                    }
                 }
-                CollectorEvent::Connect(request) => {
-                    let payload = match interpolator.interpolate_discovery(request) {
-                        Ok(payload) => payload,
-                        Err(CollectorError::NoMqttDiscoveryConfig) | Err(CollectorError::NoMqttConfig) => continue,
-                        err => err?,
-                    };
-                    let discovery_data = serde_json::to_string(&payload.discovery_config)?;
-                    mqtt_client
-                        .publish(payload.config_topic, payload.qos, payload.retain, discovery_data)
-                        .await?;
+
+                if target.skip_discovery {
+                    continue;
                 }
-                CollectorEvent::Disconnect(_fqcn, _char_conf) => {}
+                let payload = match interpolator.interpolate_discovery(request) {
+                    Ok(payload) => payload,
+                    Err(CollectorError::NoMqttDiscoveryConfig) | Err(CollectorError::NoMqttConfig) => continue,
+                    err => err?,
+                };
+                let discovery_data = serde_json::to_string(&payload.discovery_config)?;
+                mqtt_client
+                    .publish(payload.config_topic, payload.qos, payload.retain, discovery_data)
+                    .await?;
             }
+            CollectorEvent::Disconnect(_fqcn, _char_conf) => {}
         }
+    }
+
+    Ok(())
+}
+
+pub(super) fn init_lifecycle_publisher(
+    lifecycle_publisher: Arc<LifecyclePublisher>,
+    lifecycle_receiver: AsyncReceiver<CollectorEvent>,
+    join_set: &mut JoinSet<anyhow::Result<()>>,
+) {
+    join_set.spawn(async move {
+        lifecycle_publisher.block_on_receiving(lifecycle_receiver).await;
+        warn!("Lifecycle receiver has ended");
+        Ok(())
+    });
+}
 
+pub(super) fn init_mqtt_commands(
+    command_router: Arc<MqttCommandRouter>,
+    adapter_manager: Arc<AdapterManager>,
+    join_set: &mut JoinSet<anyhow::Result<()>>,
+) {
+    join_set.spawn(async move {
+        command_router.block_on_receiving(adapter_manager.as_ref()).await;
         Err::<(), anyhow::Error>(CollectorError::EndOfStream.into())
     });
+}
 
+pub(super) fn init_mqtt_heartbeat(
+    mqtt_client: AsyncClient,
+    adapter_manager: Arc<AdapterManager>,
+    topic: String,
+    heartbeat_interval: Duration,
+    join_set: &mut JoinSet<anyhow::Result<()>>,
+) {
     join_set.spawn(async move {
+        let mut interval = tokio::time::interval(heartbeat_interval);
         loop {
-            if let Err(err) = event_loop.poll().await {
-                error!("Failed to poll MQTT event loop: {}", err);
-            }
+            interval.tick().await;
+            let heartbeat = MqttHeartbeat {
+                ts: chrono::offset::Utc::now(),
+                adapter_count: adapter_manager.get_adapter_count().await,
+                connected_peripherals: adapter_manager.get_connected_peripheral_count().await,
+            };
+            let heartbeat = serde_json::to_string(&heartbeat)?;
+            mqtt_client
+                .publish(topic.as_str(), QoS::AtLeastOnce, false, heartbeat)
+                .await?;
         }
     });
-
-    Ok(())
 }
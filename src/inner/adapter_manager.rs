@@ -1,27 +1,40 @@
-use std::sync::Arc;
+use std::sync::{Arc, Mutex as StdMutex};
 
 use crate::inner::conf::cmd_args::AppConf;
 use crate::inner::conf::manager::ConfigurationManager;
+use crate::inner::conf::model::flat_peripheral_config::FlatPeripheralConfig;
+use crate::inner::conf::traits::Evaluate;
 use btleplug::api::{Central, Manager as _};
 use btleplug::platform::{Adapter, Manager, Peripheral};
 use futures_util::stream;
 use futures_util::StreamExt;
 use tokio::sync::Mutex;
-use tokio::task::JoinSet;
+use tokio::task::{AbortHandle, JoinSet};
 use tracing::{info, info_span, warn};
 
-use crate::inner::dto::{AdapterDto, PeripheralDto};
+use crate::inner::dto::{AdapterDto, CatalogPeripheralDto, MatchingPeripheralDto, PeripheralDto};
 use crate::inner::error::{CollectorError, CollectorResult};
 use crate::inner::model::adapter_info::AdapterInfo;
 use crate::inner::model::collector_event::CollectorEvent;
+use crate::inner::model::peripheral_key::PeripheralKey;
 use crate::inner::peripheral_manager::PeripheralManager;
 use crate::inner::publish::FanOutSender;
 
+/// Adapter id of the API that refers to `--default-adapter` or to the only adapter.
+const DEFAULT_ADAPTER_ID: &str = "default";
+
 pub(crate) struct AdapterManager {
     peripheral_managers: Mutex<Vec<Arc<PeripheralManager>>>,
     fanout_sender: Arc<FanOutSender<CollectorEvent>>,
     configuration_manager: Arc<ConfigurationManager>,
-    app_conf: Arc<AppConf>,
+    pub(crate) app_conf: Arc<AppConf>,
+    task_handles: StdMutex<Vec<AbortHandle>>,
+}
+
+impl Drop for AdapterManager {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
 }
 
 impl AdapterManager {
@@ -35,6 +48,20 @@ impl AdapterManager {
             fanout_sender: Arc::new(fanout_sender),
             configuration_manager,
             app_conf,
+            task_handles: Default::default(),
+        }
+    }
+
+    fn track_task(&self, handle: AbortHandle) {
+        let mut task_handles = self.task_handles.lock().unwrap();
+        task_handles.retain(|existing| !existing.is_finished());
+        task_handles.push(handle);
+    }
+
+    /// Aborts the tasks spawned by the manager, i.e. peripheral discovery.
+    pub(crate) fn shutdown(&self) {
+        for handle in self.task_handles.lock().unwrap().drain(..) {
+            handle.abort();
         }
     }
     pub(crate) async fn init(&self) -> CollectorResult<()> {
@@ -43,7 +70,7 @@ impl AdapterManager {
             let adapters = manager.adapters().await?;
             info!(adapters = ?adapters, "Discovered {} adapter(s)", adapters.len());
             for adapter in adapters {
-                let info = AdapterInfo::try_from(adapter.adapter_info().await?)?;
+                let info = self.resolve_adapter_info(&adapter).await?;
                 info!(adapter_info = %info, "Discovered adapter");
                 self.init_peripheral_manager(adapter).await?;
             }
@@ -53,8 +80,8 @@ impl AdapterManager {
     }
 
     async fn init_peripheral_manager(&self, adapter: Adapter) -> CollectorResult<()> {
-        let adapter_info = AdapterInfo::try_from(adapter.adapter_info().await?)?;
-        let span = info_span!("PeripheralManager", adapter = adapter_info.id);
+        let adapter_info = self.resolve_adapter_info(&adapter).await?;
+        let span = info_span!("PeripheralManager", adapter = adapter_info.label());
         self.peripheral_managers
             .lock()
             .await
@@ -69,27 +96,33 @@ impl AdapterManager {
         Ok(())
     }
 
+    async fn resolve_adapter_info(&self, adapter: &Adapter) -> CollectorResult<AdapterInfo> {
+        let adapter_info = AdapterInfo::from_adapter(adapter).await?;
+        let alias = self.app_conf.get_adapter_alias(&adapter_info.id);
+        Ok(adapter_info.with_alias(alias))
+    }
+
+    /// Returns the manager of the adapter matching `adapter_id`, which can be an id, an alias or `default`.
     pub(crate) async fn get_peripheral_manager(
         &self,
         adapter_id: &str,
     ) -> CollectorResult<Option<Arc<PeripheralManager>>> {
         let managers = self.peripheral_managers.lock().await;
 
+        let mut adapters = Vec::with_capacity(managers.len());
         for manager in managers.iter() {
-            let adapter_info = manager.adapter.adapter_info().await?;
-            let adapter_info = AdapterInfo::try_from(adapter_info)?;
-            if adapter_info.id == adapter_id {
-                return Ok(Some(Arc::clone(manager)));
-            }
+            adapters.push((self.resolve_adapter_info(&manager.adapter).await?, manager));
         }
 
-        Ok(None)
+        let manager = select_adapter(&adapters, adapter_id, self.app_conf.default_adapter.as_deref())?;
+        Ok(manager.map(|manager| Arc::clone(manager)))
     }
 
     pub(crate) async fn start_discovery(&self) -> CollectorResult<()> {
         let mut join_set = JoinSet::new();
         for peripheral_manager in self.peripheral_managers.lock().await.iter().cloned() {
-            join_set.spawn(async move { peripheral_manager.start_discovery().await });
+            let handle = join_set.spawn(async move { peripheral_manager.start_discovery().await });
+            self.track_task(handle);
         }
 
         if let Some(result) = join_set.join_next().await {
@@ -99,25 +132,98 @@ impl AdapterManager {
         Ok(())
     }
 
+    /// Reads the configured peripherals of every adapter once; returns the number of successful reads.
+    pub(crate) async fn read_once(&self) -> CollectorResult<usize> {
+        let mut join_set = JoinSet::new();
+        for peripheral_manager in self.peripheral_managers.lock().await.iter().cloned() {
+            join_set.spawn(async move { peripheral_manager.read_once().await });
+        }
+
+        let mut reads = 0;
+        while let Some(result) = join_set.join_next().await {
+            reads += result??;
+        }
+
+        Ok(reads)
+    }
+
     pub(crate) async fn list_adapters(&self) -> CollectorResult<Vec<AdapterInfo>> {
         let managers = self.peripheral_managers.lock().await;
 
-        let infos = stream::iter(managers.iter())
-            .map(Arc::clone)
-            .map(|adapter_service_manager| async move { adapter_service_manager.adapter.adapter_info().await })
-            .buffered(4)
-            .collect::<Vec<_>>()
-            .await;
+        let infos =
+            stream::iter(managers.iter())
+                .map(Arc::clone)
+                .map(|adapter_service_manager| async move {
+                    self.resolve_adapter_info(&adapter_service_manager.adapter).await
+                })
+                .buffered(self.app_conf.adapter_parallelism.get())
+                .collect::<Vec<_>>()
+                .await;
+
+        infos.into_iter().collect()
+    }
+
+    pub(crate) async fn get_adapter_count(&self) -> usize {
+        self.peripheral_managers.lock().await.len()
+    }
+
+    pub(crate) async fn get_connected_peripheral_count(&self) -> usize {
+        let managers = self.peripheral_managers.lock().await;
+        let mut count = 0;
+        for manager in managers.iter() {
+            count += manager.get_all_connected_peripherals().await.get_all().len();
+        }
+        count
+    }
+
+    /// Lists the cached peripherals of every adapter that match the filters of the config.
+    pub(crate) async fn get_matching_peripherals(
+        &self,
+        config: &FlatPeripheralConfig,
+    ) -> CollectorResult<Vec<MatchingPeripheralDto>> {
+        let managers = self.peripheral_managers.lock().await;
+        let mut matching_peripherals = vec![];
+
+        for manager in managers.iter() {
+            for peripheral_key in manager.get_cached_peripherals_as_keys().await? {
+                if !config.evaluate(&peripheral_key) {
+                    continue;
+                }
+                matching_peripherals.push(MatchingPeripheralDto {
+                    address: peripheral_key.peripheral_address,
+                    currently_connected: manager.is_connected(&peripheral_key.peripheral_address).await?,
+                    name: peripheral_key.name,
+                    adapter: peripheral_key.adapter_id,
+                });
+            }
+        }
 
-        let mut adapters = vec![];
+        Ok(matching_peripherals)
+    }
 
-        for info in infos {
-            let info = info?;
-            let adapter_info = AdapterInfo::try_from(info)?;
-            adapters.push(adapter_info);
+    /// Lists the connected peripherals of every adapter that match the filters of the config.
+    pub(crate) async fn get_connected_matching_peripherals(
+        &self,
+        config: &FlatPeripheralConfig,
+    ) -> CollectorResult<Vec<PeripheralKey>> {
+        let managers = self.peripheral_managers.lock().await;
+        let mut matching_peripherals = vec![];
+        for manager in managers.iter() {
+            let peripheral_keys = manager.get_connected_peripherals_as_keys().await?;
+            matching_peripherals.extend(config.select_matching(peripheral_keys));
         }
 
-        Ok(adapters)
+        Ok(matching_peripherals)
+    }
+
+    /// Lists the services and characteristics already discovered by every adapter; nothing is connected to.
+    pub(crate) async fn get_catalog(&self) -> CollectorResult<Vec<CatalogPeripheralDto>> {
+        let managers = self.peripheral_managers.lock().await;
+        let mut catalog = vec![];
+        for manager in managers.iter() {
+            catalog.extend(manager.get_catalog().await?);
+        }
+        Ok(catalog)
     }
 
     pub(crate) async fn describe_adapters(&self) -> CollectorResult<Vec<AdapterDto>> {
@@ -126,8 +232,7 @@ impl AdapterManager {
         let peripherals_per_adapter = stream::iter(device_managers.iter())
             .map(Arc::clone)
             .map(|peripheral_manager| async move {
-                let adapter_info = peripheral_manager.adapter.adapter_info().await?;
-                let adapter_dto = AdapterDto::try_from(adapter_info)?;
+                let adapter_dto = AdapterDto::from(self.resolve_adapter_info(&peripheral_manager.adapter).await?);
                 let peripherals = peripheral_manager.adapter.peripherals().await?;
 
                 Ok::<(Arc<Mutex<AdapterDto>>, Vec<Peripheral>), CollectorError>((
@@ -135,7 +240,7 @@ impl AdapterManager {
                     peripherals,
                 ))
             })
-            .buffer_unordered(4)
+            .buffer_unordered(self.app_conf.adapter_parallelism.get())
             .collect::<Vec<_>>()
             .await
             .into_iter()
@@ -158,7 +263,7 @@ impl AdapterManager {
                 }
                 Ok::<Arc<Mutex<AdapterDto>>, CollectorError>(adapter_dto)
             })
-            .buffer_unordered(32)
+            .buffer_unordered(self.app_conf.describe_peripheral_parallelism.get())
             .collect::<Vec<_>>()
             .await
             .into_iter()
@@ -175,10 +280,129 @@ impl AdapterManager {
                 continue;
             }
             let mut dto = dto.clone();
-            dto.peripherals.sort_unstable_by_key(|peripheral| peripheral.id.clone());
+            dto.sort();
             result.push(dto);
         }
 
+        result.sort_unstable_by(|left, right| left.adapter_info.id.cmp(&right.adapter_info.id));
+
         Ok(result)
     }
 }
+
+/// Picks the adapter matching `adapter_id`. `default` refers to `default_adapter` if set, or to the only adapter;
+/// it's an error if there are several.
+fn select_adapter<'a, T>(
+    adapters: &'a [(AdapterInfo, T)],
+    adapter_id: &str,
+    default_adapter: Option<&str>,
+) -> CollectorResult<Option<&'a T>> {
+    let adapter_id = match (adapter_id, default_adapter) {
+        (DEFAULT_ADAPTER_ID, Some(default_adapter)) => default_adapter,
+        (DEFAULT_ADAPTER_ID, None) => {
+            return match adapters {
+                [(_, adapter)] => Ok(Some(adapter)),
+                [] => Ok(None),
+                _ => Err(CollectorError::AmbiguousDefaultAdapter(adapters.len())),
+            };
+        }
+        (adapter_id, _) => adapter_id,
+    };
+
+    Ok(adapters
+        .iter()
+        .find(|(adapter_info, _)| adapter_info.matches(adapter_id))
+        .map(|(_, adapter)| adapter))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[tokio::test]
+    async fn test_drop_aborts_tasks() {
+        let app_conf = AppConf::parse_from(["ble-collector", "--config", "config.yaml"]);
+        let adapter_manager = AdapterManager::new(
+            Arc::new(ConfigurationManager::default()),
+            FanOutSender::new(vec![]),
+            Arc::new(app_conf),
+        );
+
+        let task = tokio::spawn(futures_util::future::pending::<()>());
+        adapter_manager.track_task(task.abort_handle());
+        drop(adapter_manager);
+
+        assert!(task.await.unwrap_err().is_cancelled());
+    }
+
+    #[tokio::test]
+    async fn test_parallelism_is_configurable() {
+        let default_conf = AppConf::parse_from(["ble-collector", "--config", "config.yaml"]);
+        assert_eq!(default_conf.adapter_parallelism.get(), 4);
+        assert_eq!(default_conf.describe_peripheral_parallelism.get(), 32);
+
+        let app_conf = AppConf::parse_from([
+            "ble-collector",
+            "--config",
+            "config.yaml",
+            "--adapter-parallelism",
+            "8",
+            "--describe-peripheral-parallelism",
+            "64",
+        ]);
+        let adapter_manager = AdapterManager::new(
+            Arc::new(ConfigurationManager::default()),
+            FanOutSender::new(vec![]),
+            Arc::new(app_conf),
+        );
+        assert_eq!(adapter_manager.app_conf.adapter_parallelism.get(), 8);
+        assert_eq!(adapter_manager.app_conf.describe_peripheral_parallelism.get(), 64);
+
+        for flag in ["--adapter-parallelism", "--describe-peripheral-parallelism"] {
+            assert!(AppConf::try_parse_from(["ble-collector", "--config", "config.yaml", flag, "0"]).is_err());
+        }
+
+        assert!(adapter_manager.list_adapters().await.unwrap().is_empty());
+        assert!(adapter_manager.describe_adapters().await.unwrap().is_empty());
+    }
+
+    fn adapters() -> Vec<(AdapterInfo, &'static str)> {
+        let adapter_info = |id: &str, alias: Option<&str>| AdapterInfo {
+            id: id.to_string(),
+            modalias: "usb:v1D6Bp0246d0537".to_string(),
+            address: None,
+            alias: alias.map(str::to_string),
+        };
+        vec![
+            (adapter_info("hci0", None), "first"),
+            (adapter_info("hci1", Some("garage")), "second"),
+        ]
+    }
+
+    #[test]
+    fn test_select_single_default_adapter() {
+        let adapters = &adapters()[..1];
+        assert_eq!(select_adapter(adapters, "default", None).unwrap(), Some(&"first"));
+        assert_eq!(select_adapter(adapters, "hci0", None).unwrap(), Some(&"first"));
+        assert_eq!(select_adapter(adapters, "hci1", None).unwrap(), None);
+        assert_eq!(select_adapter::<&str>(&[], "default", None).unwrap(), None);
+    }
+
+    #[test]
+    fn test_select_ambiguous_default_adapter() {
+        let adapters = adapters();
+        assert!(matches!(
+            select_adapter(&adapters, "default", None),
+            Err(CollectorError::AmbiguousDefaultAdapter(2))
+        ));
+        assert_eq!(select_adapter(&adapters, "garage", None).unwrap(), Some(&"second"));
+
+        // --default-adapter resolves the ambiguity, aliases included
+        assert_eq!(
+            select_adapter(&adapters, "default", Some("garage")).unwrap(),
+            Some(&"second")
+        );
+        assert_eq!(select_adapter(&adapters, "default", Some("hci2")).unwrap(), None);
+    }
+}
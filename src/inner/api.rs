@@ -1,19 +1,99 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
 use std::sync::Arc;
 
+use btleplug::api::BDAddr;
+use futures_util::{Stream, StreamExt};
 use metrics_exporter_prometheus::PrometheusHandle;
-use rocket::http::Status;
-use rocket::{get, post};
+use rocket::http::{ContentType, Status};
+use rocket::request::FromParam;
+use rocket::response::stream::{Event, EventStream, TextStream};
+use rocket::{get, post, put};
+use uuid::Uuid;
 
 use crate::inner::adapter_manager::AdapterManager;
-use crate::inner::batch_executor::execute_batches;
+use crate::inner::batch_executor::{execute_batches, execute_bulk_write, stream_batches};
+use crate::inner::conf::dto::short_uuid::parse_uuid;
 use crate::inner::conf::manager::ConfigurationManager;
 use crate::inner::conf::model::flat_peripheral_config::FlatPeripheralConfig;
-use crate::inner::dto::{AdapterDto, Envelope, PeripheralIoRequestDto, PeripheralIoResponseDto, ResultDto};
+use crate::inner::conv::converter::CharacteristicValue;
+use crate::inner::dto::{
+    AdapterDto, AdapterStateDto, BulkWriteRequestDto, BulkWriteResponseDto, CatalogPeripheralDto,
+    CharacteristicReadDto, CircuitBreakerDto, ConvertRequestDto, Envelope, GattAttributeDto, MatchingPeripheralDto,
+    NotificationDto, PeripheralDto, PeripheralIoRequestDto, PeripheralIoResponseDto, PeripheralPropertiesDto,
+    ResultDto, RssiReadingDto, ScanFilterDto, ServiceDto, TaskDto,
+};
 use crate::inner::error::{CollectorError, CollectorResult};
 use crate::inner::http_error::{ApiResult, HttpError};
+use crate::inner::log_level::LogLevelManager;
 use crate::inner::model::adapter_info::AdapterInfo;
 use crate::inner::model::connected_peripherals::ConnectedPeripherals;
+use crate::inner::peripheral_manager::PeripheralManager;
 use crate::inner::publish::api_publisher::ApiPublisher;
+use crate::inner::publish::dto::LatestApiDataPoint;
+use crate::inner::publish::event_stream::{EventStreamPublisher, StreamFilter};
+use crate::inner::publish::lifecycle_publisher::{LifecycleEventDto, LifecyclePublisher};
+use crate::inner::publish::metric_publisher::MetricPublisher;
+use crate::inner::recent_log::{RecentLogBuffer, RecentLogEntry};
+
+/// API-wide settings from the command line.
+pub(crate) struct ApiSettings {
+    pub(crate) read_only: bool,
+}
+
+/// A peripheral address in a path or a query, i.e. `AA:BB:CC:DD:EE:FF`; a malformed one is a 400 rather than a forward.
+pub(crate) struct PeripheralAddress(pub(crate) BDAddr);
+
+impl FromStr for PeripheralAddress {
+    type Err = HttpError<CollectorError>;
+
+    fn from_str(addr: &str) -> Result<Self, Self::Err> {
+        let address = BDAddr::from_str_delim(addr).map_err(|err| {
+            HttpError::new(CollectorError::ApiError(format!(
+                "Invalid peripheral address `{addr}`: {err}"
+            )))
+            .with_status(Status::BadRequest)
+        })?;
+        Ok(Self(address))
+    }
+}
+
+impl<'a> FromParam<'a> for PeripheralAddress {
+    type Error = HttpError<CollectorError>;
+
+    fn from_param(param: &'a str) -> Result<Self, Self::Error> {
+        param.parse()
+    }
+}
+
+async fn get_peripheral_manager(
+    adapter_manager: &AdapterManager,
+    adapter_id: &str,
+) -> Result<Arc<PeripheralManager>, HttpError<CollectorError>> {
+    let peripheral_manager = adapter_manager
+        .get_peripheral_manager(adapter_id)
+        .await
+        .map_err(|err| match err {
+            CollectorError::AmbiguousDefaultAdapter(_) => HttpError::new(err).with_status(Status::BadRequest),
+            err => HttpError::new(err),
+        })?;
+    let Some(peripheral_manager) = peripheral_manager else {
+        return Err(
+            HttpError::new(CollectorError::AdapterNotFound(adapter_id.to_string())).with_status(Status::NotFound)
+        );
+    };
+    Ok(peripheral_manager)
+}
+
+/// Responds with `404 Not Found` if the adapter doesn't know the peripheral or its characteristic.
+fn peripheral_error(err: CollectorError) -> HttpError<CollectorError> {
+    match err {
+        CollectorError::PeripheralNotFound(_) | CollectorError::CharacteristicNotFound(..) => {
+            HttpError::new(err).with_status(Status::NotFound)
+        }
+        err => HttpError::new(err),
+    }
+}
 
 #[get("/adapters/describe")]
 pub(crate) async fn describe_adapters(
@@ -29,6 +109,13 @@ pub(crate) async fn list_adapters(adapter_manager: &rocket::State<Arc<AdapterMan
     Ok(wrapped.into())
 }
 
+#[get("/catalog")]
+pub(crate) async fn get_catalog(
+    adapter_manager: &rocket::State<Arc<AdapterManager>>,
+) -> ApiResult<Vec<CatalogPeripheralDto>> {
+    Ok(Envelope::from(adapter_manager.get_catalog().await?).into())
+}
+
 #[get("/configurations")]
 pub(crate) async fn list_configurations(
     configuration_manager: &rocket::State<Arc<ConfigurationManager>>,
@@ -37,6 +124,56 @@ pub(crate) async fn list_configurations(
     Ok(wrapped.into())
 }
 
+/// Dumps the loaded peripheral configs and adapter filters as a YAML configuration file.
+#[get("/configurations/export")]
+pub(crate) async fn export_configurations(
+    configuration_manager: &rocket::State<Arc<ConfigurationManager>>,
+) -> Result<(ContentType, String), HttpError<CollectorError>> {
+    let exported = serde_yaml::to_string(&configuration_manager.export().await).map_err(CollectorError::from)?;
+    Ok((ContentType::new("application", "yaml"), exported))
+}
+
+#[get("/configurations/<name>/matching-peripherals")]
+pub(crate) async fn get_matching_peripherals(
+    name: &str,
+    configuration_manager: &rocket::State<Arc<ConfigurationManager>>,
+    adapter_manager: &rocket::State<Arc<AdapterManager>>,
+) -> ApiResult<Vec<MatchingPeripheralDto>> {
+    let Some(config) = configuration_manager.get_peripheral_config(name).await else {
+        return Err(
+            HttpError::new(CollectorError::ConfigurationNotFound(name.to_string())).with_status(Status::NotFound)
+        );
+    };
+    let matching_peripherals = adapter_manager.get_matching_peripherals(&config).await?;
+
+    Ok(Envelope::from(matching_peripherals).into())
+}
+
+/// Writes the same value to every connected peripheral matching the configuration; the response has the result of
+/// every write.
+#[post("/configurations/<name>/write", format = "json", data = "<request>")]
+pub(crate) async fn bulk_write_characteristic(
+    name: &str,
+    request: rocket::serde::json::Json<BulkWriteRequestDto>,
+    configuration_manager: &rocket::State<Arc<ConfigurationManager>>,
+    adapter_manager: &rocket::State<Arc<AdapterManager>>,
+    api_settings: &rocket::State<ApiSettings>,
+) -> ApiResult<Vec<BulkWriteResponseDto>> {
+    if api_settings.read_only {
+        return Err(HttpError::new(CollectorError::ReadOnlyApi).with_status(Status::Forbidden));
+    }
+
+    let Some(config) = configuration_manager.get_peripheral_config(name).await else {
+        return Err(
+            HttpError::new(CollectorError::ConfigurationNotFound(name.to_string())).with_status(Status::NotFound)
+        );
+    };
+    let targets = adapter_manager.get_connected_matching_peripherals(&config).await?;
+    let responses = execute_bulk_write(Arc::clone(adapter_manager), targets, request.into_inner()).await;
+
+    Ok(Envelope::from(responses).into())
+}
+
 #[get("/data")]
 pub(crate) async fn get_collector_data(storage: &rocket::State<Arc<ApiPublisher>>) -> ApiResult<Arc<ApiPublisher>> {
     Ok(Envelope::from(Arc::clone(storage)).into())
@@ -50,13 +187,19 @@ pub(crate) async fn read_write_characteristic(
     adapter_id: &str,
     request: rocket::serde::json::Json<PeripheralIoRequestDto>,
     adapter_manager: &rocket::State<Arc<AdapterManager>>,
+    api_settings: &rocket::State<ApiSettings>,
 ) -> ApiResult<PeripheralIoResponseDto> {
-    let Some(peripheral_manager) = adapter_manager.get_peripheral_manager(adapter_id).await? else {
-        return Err(
-            HttpError::new(CollectorError::AdapterNotFound(adapter_id.to_string())).with_status(Status::NotFound)
-        );
-    };
-    let response = execute_batches(peripheral_manager, request.into_inner()).await;
+    if api_settings.read_only && request.has_writes() {
+        return Err(HttpError::new(CollectorError::ReadOnlyApi).with_status(Status::Forbidden));
+    }
+
+    let peripheral_manager = get_peripheral_manager(adapter_manager, adapter_id).await?;
+    let response = execute_batches(peripheral_manager, request.into_inner())
+        .await
+        .map_err(|err| match err {
+            CollectorError::DuplicateFqcnInBatch(_) => HttpError::new(err).with_status(Status::BadRequest),
+            err => HttpError::new(err),
+        })?;
     let has_errors = response
         .batch_responses
         .iter()
@@ -72,22 +215,576 @@ pub(crate) async fn read_write_characteristic(
     Ok(Envelope::from(response).into())
 }
 
+/// Same as `/io`, but sends the result of every command as a line of JSON as soon as it completes.
+#[post("/adapters/<adapter_id>/io/stream", format = "json", data = "<request>")]
+pub(crate) async fn stream_read_write_characteristic(
+    adapter_id: &str,
+    request: rocket::serde::json::Json<PeripheralIoRequestDto>,
+    adapter_manager: &rocket::State<Arc<AdapterManager>>,
+    api_settings: &rocket::State<ApiSettings>,
+) -> Result<(ContentType, TextStream<impl Stream<Item = String>>), HttpError<CollectorError>> {
+    if api_settings.read_only && request.has_writes() {
+        return Err(HttpError::new(CollectorError::ReadOnlyApi).with_status(Status::Forbidden));
+    }
+
+    let peripheral_manager = get_peripheral_manager(adapter_manager, adapter_id).await?;
+    let responses = stream_batches(peripheral_manager, request.into_inner()).map_err(|err| match err {
+        CollectorError::DuplicateFqcnInBatch(_) => HttpError::new(err).with_status(Status::BadRequest),
+        err => HttpError::new(err),
+    })?;
+
+    let lines = responses.map(|response| match serde_json::to_string(&response) {
+        Ok(line) => line + "\n",
+        Err(err) => format!("{{\"error\": {:?}}}\n", err.to_string()),
+    });
+    Ok((ContentType::new("application", "x-ndjson"), TextStream::from(lines)))
+}
+
 #[get("/adapters/<adapter_id>/peripherals")]
 pub(crate) async fn get_connected_peripherals(
     adapter_id: &str,
     adapter_manager: &rocket::State<Arc<AdapterManager>>,
 ) -> ApiResult<ConnectedPeripherals> {
-    let Some(peripheral_manager) = adapter_manager.get_peripheral_manager(adapter_id).await? else {
+    let peripheral_manager = get_peripheral_manager(adapter_manager, adapter_id).await?;
+    let connected_peripherals = peripheral_manager.get_all_connected_peripherals().await;
+
+    Ok(Envelope::from(connected_peripherals).into())
+}
+
+/// Poll tasks and subscriptions of the adapter; subscriptions include how long they have been active.
+#[get("/adapters/<adapter_id>/tasks")]
+pub(crate) async fn get_tasks(
+    adapter_id: &str,
+    adapter_manager: &rocket::State<Arc<AdapterManager>>,
+) -> ApiResult<Vec<TaskDto>> {
+    let peripheral_manager = get_peripheral_manager(adapter_manager, adapter_id).await?;
+    let tasks = peripheral_manager.get_tasks().await;
+
+    Ok(Envelope::from(tasks).into())
+}
+
+#[post("/adapters/<adapter_id>/peripherals/<addr>/probe")]
+pub(crate) async fn probe_peripheral(
+    adapter_id: &str,
+    addr: Result<PeripheralAddress, HttpError<CollectorError>>,
+    adapter_manager: &rocket::State<Arc<AdapterManager>>,
+) -> ApiResult<PeripheralDto> {
+    let PeripheralAddress(address) = addr?;
+    let peripheral_manager = get_peripheral_manager(adapter_manager, adapter_id).await?;
+    let peripheral_dto = peripheral_manager.probe(address).await.map_err(peripheral_error)?;
+
+    Ok(Envelope::from(peripheral_dto).into())
+}
+
+#[get("/adapters/<adapter_id>/peripherals/<addr>/services")]
+pub(crate) async fn get_peripheral_services(
+    adapter_id: &str,
+    addr: Result<PeripheralAddress, HttpError<CollectorError>>,
+    adapter_manager: &rocket::State<Arc<AdapterManager>>,
+) -> ApiResult<Vec<ServiceDto>> {
+    let PeripheralAddress(address) = addr?;
+    let peripheral_manager = get_peripheral_manager(adapter_manager, adapter_id).await?;
+    let services = peripheral_manager
+        .get_services(address)
+        .await
+        .map_err(peripheral_error)?;
+
+    Ok(Envelope::from(services).into())
+}
+
+#[get("/adapters/<adapter_id>/peripherals/<addr>/read?<characteristics>")]
+pub(crate) async fn read_characteristics(
+    adapter_id: &str,
+    addr: Result<PeripheralAddress, HttpError<CollectorError>>,
+    characteristics: &str,
+    adapter_manager: &rocket::State<Arc<AdapterManager>>,
+) -> ApiResult<BTreeMap<Uuid, CharacteristicReadDto>> {
+    let PeripheralAddress(address) = addr?;
+    let peripheral_manager = get_peripheral_manager(adapter_manager, adapter_id).await?;
+    let characteristic_uuids = characteristics
+        .split(',')
+        .map(str::trim)
+        .filter(|uuid| !uuid.is_empty())
+        .map(|uuid| {
+            Uuid::parse_str(uuid).map_err(|err| {
+                HttpError::new(CollectorError::ApiError(format!(
+                    "Invalid characteristic uuid `{uuid}`: {err}"
+                )))
+                .with_status(Status::BadRequest)
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let results = peripheral_manager
+        .read_characteristics(address, characteristic_uuids)
+        .await
+        .map_err(peripheral_error)?;
+
+    Ok(Envelope::from(results).into())
+}
+
+/// Connects to the peripheral and reads all its characteristics, disconnecting afterwards.
+#[get("/adapters/<adapter_id>/peripherals/<addr>/gatt-dump")]
+pub(crate) async fn dump_gatt(
+    adapter_id: &str,
+    addr: Result<PeripheralAddress, HttpError<CollectorError>>,
+    adapter_manager: &rocket::State<Arc<AdapterManager>>,
+) -> ApiResult<Vec<GattAttributeDto>> {
+    let PeripheralAddress(address) = addr?;
+    let peripheral_manager = get_peripheral_manager(adapter_manager, adapter_id).await?;
+    let attributes = peripheral_manager.dump_gatt(address).await.map_err(peripheral_error)?;
+
+    Ok(Envelope::from(attributes).into())
+}
+
+/// Streams the next `count` notifications of the characteristic as Server-Sent Events, then unsubscribes.
+#[get("/adapters/<adapter_id>/peripherals/<addr>/characteristics/<uuid>/subscribe?<count>")]
+pub(crate) async fn listen_notifications(
+    adapter_id: &str,
+    addr: Result<PeripheralAddress, HttpError<CollectorError>>,
+    uuid: &str,
+    count: Option<usize>,
+    adapter_manager: &rocket::State<Arc<AdapterManager>>,
+) -> Result<EventStream<impl Stream<Item = Event>>, HttpError<CollectorError>> {
+    let PeripheralAddress(address) = addr?;
+    let peripheral_manager = get_peripheral_manager(adapter_manager, adapter_id).await?;
+    let characteristic_uuid = Uuid::parse_str(uuid).map_err(|err| {
+        HttpError::new(CollectorError::ApiError(format!(
+            "Invalid characteristic uuid `{uuid}`: {err}"
+        )))
+        .with_status(Status::BadRequest)
+    })?;
+
+    let notifications = peripheral_manager
+        .listen_notifications(address, characteristic_uuid, count.unwrap_or(1))
+        .await
+        .map_err(peripheral_error)?;
+
+    Ok(EventStream::from(
+        notifications.map(|notification| Event::json(&NotificationDto::from(notification))),
+    ))
+}
+
+/// Returns the most recent collected value of the characteristic, without the rest of its history.
+#[get("/adapters/<adapter_id>/peripherals/<addr>/characteristic/<uuid>/last?<service>")]
+pub(crate) async fn get_latest_value(
+    adapter_id: &str,
+    addr: Result<PeripheralAddress, HttpError<CollectorError>>,
+    uuid: &str,
+    service: Option<&str>,
+    adapter_manager: &rocket::State<Arc<AdapterManager>>,
+    storage: &rocket::State<Arc<ApiPublisher>>,
+) -> ApiResult<LatestApiDataPoint> {
+    let PeripheralAddress(address) = addr?;
+    get_peripheral_manager(adapter_manager, adapter_id).await?;
+    let parse = |uuid: &str| {
+        parse_uuid(uuid).map_err(|err| {
+            HttpError::new(CollectorError::ApiError(format!("Invalid uuid `{uuid}`: {err}")))
+                .with_status(Status::BadRequest)
+        })
+    };
+    let characteristic_uuid = parse(uuid)?;
+    let service_uuid = service.map(parse).transpose()?;
+
+    let Some(latest) = storage.get_latest(adapter_id, address, service_uuid, characteristic_uuid) else {
         return Err(
-            HttpError::new(CollectorError::AdapterNotFound(adapter_id.to_string())).with_status(Status::NotFound)
+            HttpError::new(CollectorError::CharacteristicNotFound(address, characteristic_uuid))
+                .with_status(Status::NotFound),
         );
     };
-    let connected_peripherals = peripheral_manager.get_all_connected_peripherals().await;
 
-    Ok(Envelope::from(connected_peripherals).into())
+    Ok(Envelope::from(latest).into())
+}
+
+#[get("/adapters/<adapter_id>/peripherals/<addr>/properties")]
+pub(crate) async fn get_peripheral_properties(
+    adapter_id: &str,
+    addr: Result<PeripheralAddress, HttpError<CollectorError>>,
+    adapter_manager: &rocket::State<Arc<AdapterManager>>,
+) -> ApiResult<PeripheralPropertiesDto> {
+    let PeripheralAddress(address) = addr?;
+    let peripheral_manager = get_peripheral_manager(adapter_manager, adapter_id).await?;
+    let properties = peripheral_manager
+        .get_peripheral_properties(address)
+        .await
+        .map_err(peripheral_error)?;
+
+    Ok(Envelope::from(PeripheralPropertiesDto::from(properties)).into())
+}
+
+#[get("/adapters/<adapter_id>/peripherals/<addr>/signal")]
+pub(crate) async fn get_peripheral_signal(
+    adapter_id: &str,
+    addr: Result<PeripheralAddress, HttpError<CollectorError>>,
+    adapter_manager: &rocket::State<Arc<AdapterManager>>,
+) -> ApiResult<Vec<RssiReadingDto>> {
+    let PeripheralAddress(address) = addr?;
+    let peripheral_manager = get_peripheral_manager(adapter_manager, adapter_id).await?;
+    let readings = peripheral_manager
+        .get_rssi_history(&address)
+        .await
+        .into_iter()
+        .map(RssiReadingDto::from)
+        .collect();
+
+    Ok(Envelope::from(readings).into())
+}
+
+/// Shows whether connecting to the peripheral is paused after repeated connect failures.
+#[get("/adapters/<adapter_id>/peripherals/<addr>/circuit-breaker")]
+pub(crate) async fn get_circuit_breaker(
+    adapter_id: &str,
+    addr: Result<PeripheralAddress, HttpError<CollectorError>>,
+    adapter_manager: &rocket::State<Arc<AdapterManager>>,
+) -> ApiResult<CircuitBreakerDto> {
+    let PeripheralAddress(address) = addr?;
+    let peripheral_manager = get_peripheral_manager(adapter_manager, adapter_id).await?;
+
+    Ok(Envelope::from(peripheral_manager.get_circuit_breaker_state(&address)).into())
+}
+
+#[get("/adapters/<adapter_id>/scan/filter")]
+pub(crate) async fn get_scan_filter(
+    adapter_id: &str,
+    adapter_manager: &rocket::State<Arc<AdapterManager>>,
+) -> ApiResult<ScanFilterDto> {
+    let peripheral_manager = get_peripheral_manager(adapter_manager, adapter_id).await?;
+    let scan_filter = peripheral_manager.get_scan_filter().await;
+
+    Ok(Envelope::from(ScanFilterDto::from(scan_filter)).into())
+}
+
+#[put("/adapters/<adapter_id>/scan/filter", format = "json", data = "<request>")]
+pub(crate) async fn set_scan_filter(
+    adapter_id: &str,
+    request: rocket::serde::json::Json<ScanFilterDto>,
+    adapter_manager: &rocket::State<Arc<AdapterManager>>,
+) -> ApiResult<ScanFilterDto> {
+    let peripheral_manager = get_peripheral_manager(adapter_manager, adapter_id).await?;
+    peripheral_manager.set_scan_filter(request.into_inner().into()).await?;
+    let scan_filter = peripheral_manager.get_scan_filter().await;
+
+    Ok(Envelope::from(ScanFilterDto::from(scan_filter)).into())
+}
+
+#[post("/adapters/<adapter_id>/scan/restart")]
+pub(crate) async fn restart_scan(
+    adapter_id: &str,
+    adapter_manager: &rocket::State<Arc<AdapterManager>>,
+) -> ApiResult<AdapterStateDto> {
+    let peripheral_manager = get_peripheral_manager(adapter_manager, adapter_id).await?;
+    peripheral_manager.restart_scan().await?;
+    let adapter_state = peripheral_manager.get_adapter_state().await?;
+
+    Ok(Envelope::from(adapter_state).into())
+}
+
+#[post("/adapters/<adapter_id>/scan/pause")]
+pub(crate) async fn pause_discovery(
+    adapter_id: &str,
+    adapter_manager: &rocket::State<Arc<AdapterManager>>,
+) -> ApiResult<AdapterStateDto> {
+    let peripheral_manager = get_peripheral_manager(adapter_manager, adapter_id).await?;
+    peripheral_manager.pause_discovery().await?;
+    let adapter_state = peripheral_manager.get_adapter_state().await?;
+
+    Ok(Envelope::from(adapter_state).into())
+}
+
+#[post("/adapters/<adapter_id>/scan/resume")]
+pub(crate) async fn resume_discovery(
+    adapter_id: &str,
+    adapter_manager: &rocket::State<Arc<AdapterManager>>,
+) -> ApiResult<AdapterStateDto> {
+    let peripheral_manager = get_peripheral_manager(adapter_manager, adapter_id).await?;
+    peripheral_manager.resume_discovery().await?;
+    let adapter_state = peripheral_manager.get_adapter_state().await?;
+
+    Ok(Envelope::from(adapter_state).into())
+}
+
+#[post("/convert", format = "json", data = "<request>")]
+pub(crate) async fn convert(request: rocket::serde::json::Json<ConvertRequestDto>) -> ApiResult<CharacteristicValue> {
+    let ConvertRequestDto { converter, value } = request.into_inner();
+    let value = converter
+        .convert(value)
+        .map_err(|err| HttpError::new(CollectorError::from(err)).with_status(Status::BadRequest))?;
+
+    Ok(Envelope::from(value).into())
+}
+
+#[post("/loglevel", data = "<directive>")]
+pub(crate) async fn set_log_level(
+    directive: String,
+    log_level_manager: &rocket::State<LogLevelManager>,
+) -> ApiResult<String> {
+    let applied = log_level_manager.set(directive.trim()).map_err(|err| match err {
+        CollectorError::TracingFilterParseError(_) => HttpError::new(err).with_status(Status::BadRequest),
+        err => HttpError::new(err),
+    })?;
+
+    Ok(Envelope::from(applied).into())
+}
+
+#[get("/lifecycle?<peripheral>")]
+pub(crate) async fn get_lifecycle_events(
+    peripheral: Option<&str>,
+    lifecycle_publisher: &rocket::State<Arc<LifecyclePublisher>>,
+) -> ApiResult<Vec<LifecycleEventDto>> {
+    let peripheral = peripheral
+        .map(str::parse::<PeripheralAddress>)
+        .transpose()?
+        .map(|PeripheralAddress(address)| address);
+    Ok(Envelope::from(lifecycle_publisher.get_history(peripheral)).into())
+}
+
+/// Streams the payloads of the matching characteristics as Server-Sent Events, as they are collected.
+#[get("/events?<peripheral>&<service>&<characteristic>")]
+pub(crate) async fn get_events(
+    peripheral: Option<&str>,
+    service: Option<&str>,
+    characteristic: Option<&str>,
+    event_stream_publisher: &rocket::State<Arc<EventStreamPublisher>>,
+) -> Result<EventStream<impl Stream<Item = Event>>, HttpError<CollectorError>> {
+    let parse = |uuid: Option<&str>| {
+        uuid.map(|uuid| {
+            parse_uuid(uuid).map_err(|err| {
+                HttpError::new(CollectorError::ApiError(format!("Invalid uuid `{uuid}`: {err}")))
+                    .with_status(Status::BadRequest)
+            })
+        })
+        .transpose()
+    };
+    let peripheral = peripheral
+        .map(str::parse::<PeripheralAddress>)
+        .transpose()?
+        .map(|PeripheralAddress(address)| address);
+    let filter = StreamFilter {
+        peripheral,
+        service: parse(service)?,
+        characteristic: parse(characteristic)?,
+    };
+
+    Ok(EventStream::from(
+        event_stream_publisher
+            .subscribe(filter)
+            .map(|event| Event::json(&event)),
+    ))
+}
+
+#[get("/logs/recent?<limit>")]
+pub(crate) async fn get_recent_logs(
+    limit: Option<usize>,
+    recent_log_buffer: &rocket::State<Arc<RecentLogBuffer>>,
+) -> ApiResult<Vec<RecentLogEntry>> {
+    Ok(Envelope::from(recent_log_buffer.get_recent(limit)).into())
 }
 
 #[get("/metrics")]
-pub(crate) async fn get_metrics(handle: &rocket::State<PrometheusHandle>) -> String {
+pub(crate) async fn get_metrics(
+    handle: &rocket::State<PrometheusHandle>,
+    metric_publisher: &rocket::State<Arc<MetricPublisher>>,
+) -> String {
+    metric_publisher.record_ages(chrono::Utc::now());
     handle.render()
 }
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use rocket::http::ContentType;
+    use rocket::local::asynchronous::Client;
+    use rocket::routes;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::inner::conf::cmd_args::AppConf;
+    use crate::inner::publish::FanOutSender;
+
+    /// An adapter manager without any adapters.
+    fn adapter_manager() -> Arc<AdapterManager> {
+        let app_conf = AppConf::parse_from(["ble-collector", "--config", "config.yaml"]);
+        Arc::new(AdapterManager::new(
+            Arc::new(ConfigurationManager::default()),
+            FanOutSender::new(vec![]),
+            Arc::new(app_conf),
+        ))
+    }
+
+    async fn post_convert(client: &Client, body: Value) -> (Status, String) {
+        let response = client
+            .post("/ble/convert")
+            .header(ContentType::JSON)
+            .body(body.to_string())
+            .dispatch()
+            .await;
+        (response.status(), response.into_string().await.unwrap())
+    }
+
+    #[rocket::async_test]
+    async fn test_convert() {
+        let client = Client::tracked(rocket::build().mount("/ble", routes![convert]))
+            .await
+            .unwrap();
+
+        let cases = [
+            (json!("Raw"), vec![1, 2], json!([1, 2])),
+            (json!("Utf8"), b"21.5\0".to_vec(), json!("21.5")),
+            (json!("F32"), 1.5f32.to_le_bytes().to_vec(), json!(1.5)),
+            (
+                json!({"Unsigned": {"l": 2, "m": 1, "d": -1, "b": 0}}),
+                vec![0xd7, 0x00],
+                json!(21.5),
+            ),
+            (
+                json!({"Negate": {"inner": {"Signed": {"l": 1, "m": 1, "d": 0, "b": 0}}}}),
+                vec![0x05],
+                json!(-5),
+            ),
+            (
+                json!({"Chain": [{"Unsigned": {"l": 1, "m": 1, "d": 0, "b": 0}}, {"Clamp": {"min": null, "max": 10}}]}),
+                vec![42],
+                json!(10),
+            ),
+        ];
+        for (converter, value, expected) in cases {
+            let (status, body) = post_convert(&client, json!({"converter": converter, "value": value})).await;
+            assert_eq!(status, Status::Ok, "{converter}: {body}");
+            let body: Value = serde_json::from_str(&body).unwrap();
+            assert_eq!(body["data"], expected, "{converter}");
+        }
+
+        let (status, body) = post_convert(&client, json!({"converter": "F32", "value": [1, 2]})).await;
+        assert_eq!(status, Status::BadRequest);
+        assert!(body.contains("LenMismatch"), "{body}");
+    }
+
+    #[rocket::async_test]
+    async fn test_malformed_peripheral_address() {
+        let rocket = rocket::build()
+            .manage(adapter_manager())
+            .manage(Arc::new(LifecyclePublisher::new(10)))
+            .mount("/ble", routes![get_circuit_breaker, get_lifecycle_events]);
+        let client = Client::tracked(rocket).await.unwrap();
+        let client = &client;
+        let get = move |uri: &'static str| async move { client.get(uri).dispatch().await.status() };
+
+        // the address is checked before the adapter
+        assert_eq!(
+            get("/ble/adapters/hci0/peripherals/garbage/circuit-breaker").await,
+            Status::BadRequest
+        );
+        assert_eq!(
+            get("/ble/adapters/hci0/peripherals/AA:BB:CC:DD:EE:FF/circuit-breaker").await,
+            Status::NotFound
+        );
+
+        assert_eq!(get("/ble/lifecycle?peripheral=garbage").await, Status::BadRequest);
+        assert_eq!(get("/ble/lifecycle?peripheral=AA:BB:CC:DD:EE:FF").await, Status::Ok);
+    }
+
+    #[rocket::async_test]
+    async fn test_probe_peripheral() {
+        let rocket = rocket::build()
+            .manage(adapter_manager())
+            .mount("/ble", routes![probe_peripheral]);
+        let client = Client::tracked(rocket).await.unwrap();
+        let client = &client;
+        let post = move |uri: &'static str| async move {
+            let response = client.post(uri).dispatch().await;
+            (response.status(), response.into_string().await.unwrap_or_default())
+        };
+
+        let (status, body) = post("/ble/adapters/hci0/peripherals/garbage/probe").await;
+        assert_eq!(status, Status::BadRequest, "{body}");
+        let (status, body) = post("/ble/adapters/hci0/peripherals/AA:BB:CC:DD:EE:FF/probe").await;
+        assert_eq!(status, Status::NotFound);
+        assert!(body.contains("Adapter `hci0` not found"), "{body}");
+
+        // probing connects, so it isn't served on GET
+        let response = client
+            .get("/ble/adapters/hci0/peripherals/AA:BB:CC:DD:EE:FF/probe")
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[rocket::async_test]
+    async fn test_read_write_keep_connected() {
+        let rocket = rocket::build()
+            .manage(adapter_manager())
+            .manage(ApiSettings { read_only: true })
+            .mount("/ble", routes![read_write_characteristic]);
+        let client = Client::tracked(rocket).await.unwrap();
+        let client = &client;
+        let post = move |keep_connected: Value, command: Value| async move {
+            let request = json!({
+                "batches": [{
+                    "commands": [command],
+                    "parallelism": null,
+                    "retry_count": null,
+                    "retry_delay_ms": null,
+                    "default_timeout_ms": null,
+                }],
+                "parallelism": null,
+                "keep_connected": keep_connected,
+            });
+            let response = client
+                .post("/ble/adapters/hci0/io")
+                .header(ContentType::JSON)
+                .body(request.to_string())
+                .dispatch()
+                .await;
+            (response.status(), response.into_string().await.unwrap_or_default())
+        };
+        let fqcn = json!({
+            "peripheral": "11:22:33:44:55:66",
+            "service": "0000180f-0000-1000-8000-00805f9b34fb",
+            "characteristic": "00002a19-0000-1000-8000-00805f9b34fb",
+        });
+        let read = json!({"Read": {"fqcn": fqcn, "wait_notification": false, "timeout_ms": null}});
+        let write = json!({"Write": {"fqcn": fqcn, "value": [1], "wait_response": true, "timeout_ms": null}});
+
+        // the request is accepted and only fails on the unknown adapter
+        let (status, body) = post(json!(true), read.clone()).await;
+        assert_eq!(status, Status::NotFound, "{body}");
+        assert!(body.contains("Adapter `hci0` not found"), "{body}");
+
+        let (status, body) = post(json!("yes"), read).await;
+        assert_eq!(status, Status::UnprocessableEntity, "{body}");
+
+        // keeping the peripherals connected doesn't get a write past the read-only API
+        let (status, body) = post(json!(true), write).await;
+        assert_eq!(status, Status::Forbidden, "{body}");
+    }
+
+    /// Fails the way the peripheral manager does for a peripheral its adapter doesn't know.
+    #[get("/unknown/<addr>")]
+    fn unknown_peripheral(addr: PeripheralAddress) -> Result<(), HttpError<CollectorError>> {
+        let PeripheralAddress(address) = addr;
+        Err(peripheral_error(CollectorError::PeripheralNotFound(address)))
+    }
+
+    #[rocket::async_test]
+    async fn test_peripheral_services() {
+        let rocket = rocket::build()
+            .manage(adapter_manager())
+            .mount("/ble", routes![get_peripheral_services, unknown_peripheral]);
+        let client = Client::tracked(rocket).await.unwrap();
+        let client = &client;
+        let get = move |uri: &'static str| async move {
+            let response = client.get(uri).dispatch().await;
+            (response.status(), response.into_string().await.unwrap_or_default())
+        };
+
+        let (status, body) = get("/ble/adapters/hci0/peripherals/garbage/services").await;
+        assert_eq!(status, Status::BadRequest, "{body}");
+        let (status, body) = get("/ble/adapters/hci0/peripherals/AA:BB:CC:DD:EE:FF/services").await;
+        assert_eq!(status, Status::NotFound);
+        assert!(body.contains("Adapter `hci0` not found"), "{body}");
+
+        let (status, body) = get("/ble/unknown/AA:BB:CC:DD:EE:FF").await;
+        assert_eq!(status, Status::NotFound);
+        assert!(body.contains("Peripheral `AA:BB:CC:DD:EE:FF` not found"), "{body}");
+    }
+}
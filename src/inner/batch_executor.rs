@@ -1,18 +1,29 @@
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::hash::Hash;
 use std::sync::Arc;
+use std::time::Duration;
 
 use bounded_integer::BoundedUsize;
-use btleplug::api::Peripheral as _;
-use futures_util::{stream, StreamExt};
-use tracing::{info, Instrument, Span};
+use btleplug::api::{BDAddr, Peripheral as _};
+use futures_util::{future, stream, Stream, StreamExt};
+use tracing::{info, warn, Instrument, Span};
 
+use crate::inner::adapter_manager::AdapterManager;
+use crate::inner::conf::cmd_args::AppConf;
 use crate::inner::countdown_latch::CountDownLatch;
 use crate::inner::dto::{
-    IoCommand, PeripheralIoBatchRequestDto, PeripheralIoBatchResponseDto, PeripheralIoRequestDto,
-    PeripheralIoResponseDto, ResultDto,
+    BulkWriteRequestDto, BulkWriteResponseDto, IoCommand, PeripheralIoBatchRequestDto, PeripheralIoBatchResponseDto,
+    PeripheralIoCommandResponseDto, PeripheralIoRequestDto, PeripheralIoResponseDto, ResultDto,
 };
 use crate::inner::error::{CollectorError, CollectorResult};
+use crate::inner::key_lock::KeyLock;
+
+use crate::inner::model::peripheral_key::PeripheralKey;
 use crate::inner::peripheral_manager::PeripheralManager;
 
+const DEFAULT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
 impl PeripheralIoBatchRequestDto {
     fn get_async_reads_count(&self) -> usize {
         self.commands
@@ -26,21 +37,157 @@ impl PeripheralIoBatchRequestDto {
     }
 }
 
+/// Settings shared by the commands of a batch.
+#[derive(Clone)]
+struct BatchContext {
+    latch: Arc<CountDownLatch>,
+    retry_count: u8,
+    retry_delay: Duration,
+    default_timeout: Option<Duration>,
+}
+
+impl From<&PeripheralIoBatchRequestDto> for BatchContext {
+    fn from(batch: &PeripheralIoBatchRequestDto) -> Self {
+        Self {
+            latch: Arc::new(CountDownLatch::new(batch.get_async_reads_count())),
+            retry_count: batch.retry_count.unwrap_or(0),
+            retry_delay: batch.retry_delay_ms.unwrap_or(DEFAULT_RETRY_DELAY),
+            default_timeout: batch.default_timeout_ms,
+        }
+    }
+}
+
 #[tracing::instrument(level = "info", skip_all)]
 pub(crate) async fn execute_batches(
     peripheral_manager: Arc<PeripheralManager>,
     request: PeripheralIoRequestDto,
+) -> CollectorResult<PeripheralIoResponseDto> {
+    request.validate()?;
+    // before the commands, so they don't disconnect in between
+    let kept_peripherals = keep_peripherals_connected(&peripheral_manager, &request);
+
+    let response = if request.flatten {
+        execute_flattened(Arc::clone(&peripheral_manager), request)
+            .in_current_span()
+            .await
+    } else {
+        execute_batches_in_order(Arc::clone(&peripheral_manager), request)
+            .in_current_span()
+            .await
+    };
+
+    // the idle timeout starts after the last command
+    for address in &kept_peripherals {
+        peripheral_manager.keep_connected(*address);
+    }
+
+    Ok(response)
+}
+
+/// Like `execute_batches`, but yields the result of every command as soon as it completes, so a large response isn't
+/// buffered; the results of a batch may arrive out of order.
+pub(crate) fn stream_batches(
+    peripheral_manager: Arc<PeripheralManager>,
+    request: PeripheralIoRequestDto,
+) -> CollectorResult<impl Stream<Item = PeripheralIoCommandResponseDto>> {
+    request.validate()?;
+    let kept_peripherals = keep_peripherals_connected(&peripheral_manager, &request);
+
+    let span = Span::current();
+    let manager = Arc::clone(&peripheral_manager);
+    let responses = stream_commands(request, &peripheral_manager.app_conf, move |ctx, cmd| {
+        execute_command(Arc::clone(&manager), ctx, cmd, span.clone())
+    })
+    .map(|(batch_index, command_index, result)| PeripheralIoCommandResponseDto {
+        batch_index,
+        command_index,
+        result,
+    });
+
+    // the idle timeout starts after the last command
+    let refresh_kept_peripherals = stream::once(async move {
+        for address in &kept_peripherals {
+            peripheral_manager.keep_connected(*address);
+        }
+    })
+    .filter_map(|()| future::ready(None));
+
+    Ok(responses.chain(refresh_kept_peripherals))
+}
+
+/// Marks the peripherals of the request as kept connected if it asks for it; returns them to be refreshed after the
+/// last command.
+fn keep_peripherals_connected(
+    peripheral_manager: &PeripheralManager,
+    request: &PeripheralIoRequestDto,
+) -> BTreeSet<BDAddr> {
+    let kept_peripherals = if request.keep_connected {
+        request.peripherals()
+    } else {
+        Default::default()
+    };
+    for address in &kept_peripherals {
+        peripheral_manager.keep_connected(*address);
+    }
+    kept_peripherals
+}
+
+fn multi_batch_parallelism(request: &PeripheralIoRequestDto, app_conf: &AppConf) -> usize {
+    request
+        .parallelism
+        .map(BoundedUsize::get)
+        .unwrap_or(app_conf.default_multi_batch_parallelism)
+}
+
+/// Runs `execute` for every command of the request, flattened or batch by batch as `execute_batches` does; yields
+/// `(batch index, command index, result)` as soon as a command completes.
+fn stream_commands<R, F, Fut>(
+    request: PeripheralIoRequestDto,
+    app_conf: &AppConf,
+    execute: F,
+) -> impl Stream<Item = (usize, usize, R)>
+where
+    F: Fn(BatchContext, IoCommand) -> Fut + Clone,
+    Fut: Future<Output = R>,
+{
+    let parallelism = multi_batch_parallelism(&request, app_conf);
+
+    if request.flatten {
+        let contexts = request.batches.iter().map(BatchContext::from).collect::<Vec<_>>();
+        let batches = request.batches.into_iter().map(|batch| batch.commands).collect();
+        let contexts = Arc::new(contexts);
+        stream_flattened(batches, parallelism, serialization_key, move |batch_index, cmd| {
+            execute(contexts[batch_index].clone(), cmd)
+        })
+        .left_stream()
+    } else {
+        let batches = request
+            .batches
+            .into_iter()
+            .map(|batch| {
+                let batch_parallelism = batch
+                    .parallelism
+                    .map(BoundedUsize::get)
+                    .unwrap_or(app_conf.default_batch_parallelism);
+                let ctx = BatchContext::from(&batch);
+                let commands = batch.commands.into_iter().map(|cmd| (ctx.clone(), cmd)).collect();
+                (commands, batch_parallelism)
+            })
+            .collect();
+        stream_unordered(batches, parallelism, move |(ctx, cmd)| execute(ctx, cmd)).right_stream()
+    }
+}
+
+async fn execute_batches_in_order(
+    peripheral_manager: Arc<PeripheralManager>,
+    request: PeripheralIoRequestDto,
 ) -> PeripheralIoResponseDto {
+    let parallelism = multi_batch_parallelism(&request, &peripheral_manager.app_conf);
     let manager_stream = std::iter::repeat_with(|| Arc::clone(&peripheral_manager));
     let span = Span::current();
     let batch_responses = stream::iter(request.batches.into_iter().zip(manager_stream))
         .map(|(batch, peripheral_manager)| async { execute_batch(peripheral_manager, batch, span.clone()).await })
-        .buffered(
-            request
-                .parallelism
-                .map(BoundedUsize::get)
-                .unwrap_or(peripheral_manager.app_conf.default_multi_batch_parallelism),
-        )
+        .buffered(parallelism)
         .collect::<Vec<_>>()
         .in_current_span()
         .await;
@@ -48,46 +195,278 @@ pub(crate) async fn execute_batches(
     PeripheralIoResponseDto { batch_responses }
 }
 
+/// Writes the value of the request to every target, at most `parallelism` peripherals at a time; a failed write
+/// doesn't stop the others. The responses are in the order of the targets.
+#[tracing::instrument(level = "info", skip_all)]
+pub(crate) async fn execute_bulk_write(
+    adapter_manager: Arc<AdapterManager>,
+    targets: Vec<PeripheralKey>,
+    request: BulkWriteRequestDto,
+) -> Vec<BulkWriteResponseDto> {
+    let parallelism = request
+        .parallelism
+        .map(BoundedUsize::get)
+        .unwrap_or(adapter_manager.app_conf.default_batch_parallelism);
+    let span = Span::current();
+
+    write_to_each(targets, &request, parallelism, |target, cmd| {
+        let adapter_manager = Arc::clone(&adapter_manager);
+        let adapter_id = target.adapter_id.clone();
+        let span = span.clone();
+        async move {
+            let manager = adapter_manager
+                .get_peripheral_manager(&adapter_id)
+                .await?
+                .ok_or(CollectorError::AdapterNotFound(adapter_id))?;
+            // nothing waits for a notification, so the write doesn't wait either
+            let latch = Arc::new(CountDownLatch::new(0));
+            write_value_with_timeout(manager, latch, cmd, span).await
+        }
+    })
+    .await
+}
+
+async fn write_to_each<F, Fut>(
+    targets: Vec<PeripheralKey>,
+    request: &BulkWriteRequestDto,
+    parallelism: usize,
+    write: F,
+) -> Vec<BulkWriteResponseDto>
+where
+    F: Fn(&PeripheralKey, IoCommand) -> Fut,
+    Fut: Future<Output = CollectorResult<()>>,
+{
+    stream::iter(targets)
+        .map(|target| {
+            let result = write(&target, request.command_for(target.peripheral_address));
+            async move {
+                BulkWriteResponseDto {
+                    result: result.await.into(),
+                    adapter: target.adapter_id,
+                    address: target.peripheral_address,
+                    name: target.name,
+                }
+            }
+        })
+        .buffered(parallelism)
+        .collect()
+        .await
+}
+
 #[tracing::instrument(level = "info", skip_all, parent = &_parent_span)]
 async fn execute_batch(
     peripheral_manager: Arc<PeripheralManager>,
     batch: PeripheralIoBatchRequestDto,
     _parent_span: Span,
 ) -> PeripheralIoBatchResponseDto {
-    let latch = Arc::new(CountDownLatch::new(batch.get_async_reads_count()));
-    let manager_stream = std::iter::repeat_with(|| Arc::clone(&peripheral_manager));
-    let latch_stream = std::iter::repeat_with(|| Arc::clone(&latch));
+    let ctx = BatchContext::from(&batch);
+    let span = Span::current();
 
+    let command_responses: Vec<Option<ResultDto<Vec<u8>>>> = stream::iter(batch.commands)
+        .map(|cmd| execute_command(Arc::clone(&peripheral_manager), ctx.clone(), cmd, span.clone()))
+        .buffered(
+            batch
+                .parallelism
+                .map(BoundedUsize::get)
+                .unwrap_or(peripheral_manager.app_conf.default_batch_parallelism),
+        )
+        .collect::<Vec<_>>()
+        .await;
+
+    PeripheralIoBatchResponseDto { command_responses }
+}
+
+/// Runs the commands of all batches in a single pool bounded by the request `parallelism`, so a long batch doesn't
+/// hold back the others. Batch `parallelism` is ignored, commands to the same peripheral are run one at a time.
+#[tracing::instrument(level = "info", skip_all)]
+async fn execute_flattened(
+    peripheral_manager: Arc<PeripheralManager>,
+    request: PeripheralIoRequestDto,
+) -> PeripheralIoResponseDto {
+    let parallelism = multi_batch_parallelism(&request, &peripheral_manager.app_conf);
+    let contexts = request.batches.iter().map(BatchContext::from).collect::<Vec<_>>();
+    let batches = request.batches.into_iter().map(|batch| batch.commands).collect();
     let span = Span::current();
 
-    let command_responses: Vec<Option<ResultDto<Vec<u8>>>> =
-        stream::iter(batch.commands.into_iter().zip(manager_stream).zip(latch_stream))
-            .map(|((cmd, manager), latch)| async {
-                let span = span.clone();
-                match cmd {
-                    IoCommand::Read { .. } => {
-                        let read_result = read_value_with_timeout(manager, latch, cmd, span).await;
-                        Some(read_result.into())
-                    }
-                    IoCommand::Write { .. } => {
-                        if let Err(err) = write_value_with_timeout(manager, latch, cmd, span).await {
-                            Some(Err(err).into())
-                        } else {
-                            None
-                        }
-                    }
-                }
+    let batch_responses = run_flattened(batches, parallelism, serialization_key, |batch_index, cmd| {
+        execute_command(
+            Arc::clone(&peripheral_manager),
+            contexts[batch_index].clone(),
+            cmd,
+            span.clone(),
+        )
+    })
+    .await;
+
+    PeripheralIoResponseDto {
+        batch_responses: batch_responses
+            .into_iter()
+            .map(|command_responses| PeripheralIoBatchResponseDto { command_responses })
+            .collect(),
+    }
+}
+
+/// Commands waiting for a notification only listen, so they don't block the write that triggers it.
+fn serialization_key(cmd: &IoCommand) -> Option<BDAddr> {
+    match cmd {
+        IoCommand::Read {
+            wait_notification: true,
+            ..
+        } => None,
+        cmd => Some(cmd.get_fqcn().peripheral),
+    }
+}
+
+/// Runs `execute` for the items of all batches with at most `parallelism` items in flight; items sharing a key are
+/// run one at a time. The results are grouped by batch in the original order.
+async fn run_flattened<C, K, R, F, Fut>(
+    batches: Vec<Vec<C>>,
+    parallelism: usize,
+    key: fn(&C) -> Option<K>,
+    execute: F,
+) -> Vec<Vec<R>>
+where
+    K: Hash + Eq + Clone + Send + Sync + 'static,
+    F: Fn(usize, C) -> Fut + Clone,
+    Fut: Future<Output = R>,
+{
+    let batch_sizes = batches.iter().map(Vec::len).collect::<Vec<_>>();
+    let mut results = stream_flattened(batches, parallelism, key, execute)
+        .collect::<Vec<_>>()
+        .await;
+    results.sort_unstable_by_key(|(batch_index, item_index, _)| (*batch_index, *item_index));
+
+    let mut results = results.into_iter().map(|(_, _, result)| result);
+    batch_sizes
+        .into_iter()
+        .map(|size| results.by_ref().take(size).collect())
+        .collect()
+}
+
+/// Streaming counterpart of `run_flattened`: yields `(batch index, item index, result)` as soon as an item completes.
+fn stream_flattened<C, K, R, F, Fut>(
+    batches: Vec<Vec<C>>,
+    parallelism: usize,
+    key: fn(&C) -> Option<K>,
+    execute: F,
+) -> impl Stream<Item = (usize, usize, R)>
+where
+    K: Hash + Eq + Clone + Send + Sync + 'static,
+    F: Fn(usize, C) -> Fut + Clone,
+    Fut: Future<Output = R>,
+{
+    let key_lock = Arc::new(KeyLock::default());
+    let items = batches.into_iter().enumerate().flat_map(|(batch_index, items)| {
+        items
+            .into_iter()
+            .enumerate()
+            .map(move |(item_index, item)| (batch_index, item_index, item))
+    });
+
+    stream::iter(items)
+        .map(move |(batch_index, item_index, item)| {
+            let key_lock = Arc::clone(&key_lock);
+            let execute = execute.clone();
+            async move {
+                let _guard = match key(&item) {
+                    Some(key) => key_lock.lock_for(key).await.ok(),
+                    None => None,
+                };
+                (batch_index, item_index, execute(batch_index, item).await)
+            }
+        })
+        .buffer_unordered(parallelism)
+}
+
+/// Runs `execute` for the items of at most `parallelism` batches at a time, with at most the batch parallelism items
+/// of each batch in flight; yields `(batch index, item index, result)` as soon as an item completes.
+fn stream_unordered<C, R, F, Fut>(
+    batches: Vec<(Vec<C>, usize)>,
+    parallelism: usize,
+    execute: F,
+) -> impl Stream<Item = (usize, usize, R)>
+where
+    F: Fn(C) -> Fut + Clone,
+    Fut: Future<Output = R>,
+{
+    stream::iter(batches.into_iter().enumerate())
+        .map(move |(batch_index, (items, batch_parallelism))| {
+            let execute = execute.clone();
+            stream::iter(items.into_iter().enumerate())
+                .map(move |(item_index, item)| {
+                    let result = execute(item);
+                    async move { (batch_index, item_index, result.await) }
+                })
+                .buffer_unordered(batch_parallelism)
+        })
+        .flatten_unordered(parallelism)
+}
+
+/// Returns `None` for a successful write.
+async fn execute_command(
+    manager: Arc<PeripheralManager>,
+    ctx: BatchContext,
+    cmd: IoCommand,
+    span: Span,
+) -> Option<ResultDto<Vec<u8>>> {
+    let BatchContext {
+        latch,
+        retry_count,
+        retry_delay,
+        default_timeout,
+    } = ctx;
+    let cmd = cmd.with_default_timeout(default_timeout);
+
+    match cmd {
+        IoCommand::Read { .. } => {
+            let read_result = with_retries(retry_count, retry_delay, || {
+                read_value_with_timeout(Arc::clone(&manager), Arc::clone(&latch), cmd.clone(), span.clone())
             })
-            .buffered(
-                batch
-                    .parallelism
-                    .map(BoundedUsize::get)
-                    .unwrap_or(peripheral_manager.app_conf.default_batch_parallelism),
-            )
-            .collect::<Vec<_>>()
             .await;
+            Some(read_result.into())
+        }
+        IoCommand::Write { .. } => {
+            let write_result = with_retries(retry_count, retry_delay, || {
+                write_value_with_timeout(Arc::clone(&manager), Arc::clone(&latch), cmd.clone(), span.clone())
+            })
+            .await;
+            if let Err(err) = write_result {
+                Some(Err(err).into())
+            } else {
+                None
+            }
+        }
+    }
+}
 
-    PeripheralIoBatchResponseDto { command_responses }
+/// Executes a command, retrying it up to `retry_count` times if it fails with a Bluetooth error; the delay before
+/// each retry is doubled, starting at `retry_delay`.
+async fn with_retries<T, F, Fut>(retry_count: u8, retry_delay: Duration, mut execute: F) -> CollectorResult<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = CollectorResult<T>>,
+{
+    let mut attempt = 0;
+    loop {
+        match execute().await {
+            Err(CollectorError::BluetoothError(err)) if attempt < retry_count => {
+                let delay = retry_delay.saturating_mul(2u32.saturating_pow(attempt.into()));
+                warn!("Command failed with {err:?}, retrying in {delay:?}");
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// The timeout of the command itself or of its batch (see `IoCommand::with_default_timeout`), falling back to the
+/// default read or write timeout.
+fn command_timeout(cmd: &IoCommand, app_conf: &AppConf) -> Duration {
+    cmd.get_timeout().unwrap_or(match cmd {
+        IoCommand::Read { .. } => app_conf.default_read_timeout,
+        IoCommand::Write { .. } => app_conf.default_write_timeout,
+    })
 }
 
 #[tracing::instrument(level = "info", skip_all, parent = &_parent_span, err, fields(
@@ -102,7 +481,7 @@ async fn read_value_with_timeout(
     cmd: IoCommand,
     _parent_span: Span,
 ) -> CollectorResult<Vec<u8>> {
-    let timeout_duration = cmd.get_timeout().unwrap_or(manager.app_conf.default_read_timeout);
+    let timeout_duration = command_timeout(&cmd, &manager.app_conf);
     let result = tokio::time::timeout(timeout_duration, read_value(manager, latch, cmd)).await??;
     Ok(result)
 }
@@ -162,7 +541,7 @@ async fn write_value_with_timeout(
     cmd: IoCommand,
     _parent_span: Span,
 ) -> CollectorResult<()> {
-    let timeout_duration = cmd.get_timeout().unwrap_or(manager.app_conf.default_write_timeout);
+    let timeout_duration = command_timeout(&cmd, &manager.app_conf);
     tokio::time::timeout(timeout_duration, write_value(manager, latch, cmd)).await??;
     Ok(())
 }
@@ -196,3 +575,283 @@ async fn write_value(
     result?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    use clap::Parser;
+
+    use super::*;
+    use crate::inner::conf::cmd_args::AppConf;
+    use crate::inner::conf::dto::peripheral::PeripheralConfigDto;
+    use crate::inner::conf::model::flat_peripheral_config::FlatPeripheralConfig;
+    use crate::inner::test_fixtures::peripheral_fqcn;
+
+    fn failing_until(
+        attempts: &AtomicUsize,
+        successful_attempt: usize,
+        error: fn() -> CollectorError,
+    ) -> impl Future<Output = CollectorResult<usize>> {
+        let attempt = attempts.fetch_add(1, Ordering::SeqCst);
+        async move {
+            if attempt < successful_attempt {
+                Err(error())
+            } else {
+                Ok(attempt)
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bluetooth_errors_are_retried() {
+        let attempts = AtomicUsize::new(0);
+        let result = with_retries(3, Duration::from_millis(1), || {
+            failing_until(&attempts, 2, || {
+                CollectorError::BluetoothError(btleplug::Error::NotConnected)
+            })
+        })
+        .await;
+        assert_eq!(result.unwrap(), 2);
+
+        let attempts = AtomicUsize::new(0);
+        let result = with_retries(1, Duration::from_millis(1), || {
+            failing_until(&attempts, 5, || {
+                CollectorError::BluetoothError(btleplug::Error::NotConnected)
+            })
+        })
+        .await;
+        assert!(matches!(result, Err(CollectorError::BluetoothError(_))));
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_other_errors_are_not_retried() {
+        let attempts = AtomicUsize::new(0);
+        let result = with_retries(3, Duration::from_millis(1), || {
+            failing_until(&attempts, 5, || CollectorError::EndOfStream)
+        })
+        .await;
+        assert!(matches!(result, Err(CollectorError::EndOfStream)));
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_flattened_batches_keep_order() {
+        // (key, delay in ms)
+        let batches = vec![
+            vec![("a", 30), ("b", 1)],
+            vec![("c", 1), ("c", 1), ("c", 1), ("d", 1), ("d", 1)],
+            vec![],
+            vec![("e", 5)],
+        ];
+        let running = Mutex::new(HashMap::<&str, usize>::new());
+        let overlapping = AtomicUsize::new(0);
+
+        let results = run_flattened(
+            batches.clone(),
+            4,
+            |(key, _)| Some(*key),
+            |batch_index, (key, delay)| {
+                let running = &running;
+                let overlapping = &overlapping;
+                async move {
+                    let count = {
+                        let mut running = running.lock().unwrap();
+                        let count = running.entry(key).or_default();
+                        *count += 1;
+                        *count
+                    };
+                    if count > 1 {
+                        overlapping.fetch_add(1, Ordering::SeqCst);
+                    }
+                    tokio::time::sleep(Duration::from_millis(delay)).await;
+                    *running.lock().unwrap().get_mut(key).unwrap() -= 1;
+                    (batch_index, key)
+                }
+            },
+        )
+        .await;
+
+        let expected = batches
+            .iter()
+            .enumerate()
+            .map(|(batch_index, items)| items.iter().map(|(key, _)| (batch_index, *key)).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(results, expected);
+        // commands with the same key are run one at a time
+        assert_eq!(overlapping.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_bulk_write_to_selected_peripherals() {
+        let config: PeripheralConfigDto = serde_yaml::from_str(
+            r#"
+            name: thermostats
+            device_name: !StartsWith 'Thermostat'
+            services: []
+            "#,
+        )
+        .unwrap();
+        let config = FlatPeripheralConfig::try_from(config).unwrap();
+        let request: BulkWriteRequestDto = serde_json::from_str(
+            r#"{"service": "0000181a-0000-1000-8000-00805f9b34fb", "characteristic": "00002a6e-0000-1000-8000-00805f9b34fb",
+                "value": [21], "wait_response": true, "timeout_ms": null, "parallelism": null}"#,
+        )
+        .unwrap();
+        let targets = [
+            ("11:22:33:44:55:01", "Thermostat Kitchen"),
+            ("11:22:33:44:55:02", "Sensor Hub"),
+            ("11:22:33:44:55:03", "Thermostat Bedroom"),
+        ]
+        .map(|(address, name)| PeripheralKey {
+            adapter_id: "hci0".to_string(),
+            peripheral_address: address.parse().unwrap(),
+            name: Some(name.to_string()),
+        });
+        let targets = config.select_matching(targets).collect::<Vec<_>>();
+        let written = Mutex::new(vec![]);
+        // every write waits for the other one, so the test only ends if both are in flight at once
+        let latch = CountDownLatch::new(targets.len());
+
+        let responses = write_to_each(targets, &request, 2, |target, cmd| {
+            assert_eq!(cmd.get_fqcn().peripheral, target.peripheral_address);
+            if let IoCommand::Write { fqcn, value, .. } = cmd {
+                written.lock().unwrap().push((fqcn.peripheral.to_string(), value));
+            }
+            let latch = &latch;
+            async move {
+                latch.countdown();
+                latch.wait().await;
+                Ok(())
+            }
+        });
+        let responses = tokio::time::timeout(Duration::from_secs(1), responses).await.unwrap();
+
+        assert_eq!(
+            written.into_inner().unwrap(),
+            vec![
+                ("11:22:33:44:55:01".to_string(), vec![21]),
+                ("11:22:33:44:55:03".to_string(), vec![21]),
+            ]
+        );
+        let names = responses
+            .iter()
+            .filter(|response| matches!(response.result, ResultDto::Ok(())))
+            .map(|response| response.name.as_deref().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["Thermostat Kitchen", "Thermostat Bedroom"]);
+    }
+
+    #[test]
+    fn test_timeout_precedence() {
+        let app_conf = AppConf::parse_from([
+            "ble-collector",
+            "--config",
+            "config.yaml",
+            "--default-read-timeout",
+            "7s",
+            "--default-write-timeout",
+            "9s",
+        ]);
+        let fqcn = r#"{"peripheral": "11:22:33:44:55:66", "service": "0000180f-0000-1000-8000-00805f9b34fb", "characteristic": "00002a19-0000-1000-8000-00805f9b34fb"}"#;
+        let batch = |default_timeout_ms: &str| -> PeripheralIoBatchRequestDto {
+            serde_json::from_str(&format!(
+                r#"{{"commands": [
+                    {{"Read": {{"fqcn": {fqcn}, "wait_notification": false, "timeout_ms": 100}}}},
+                    {{"Read": {{"fqcn": {fqcn}, "wait_notification": false, "timeout_ms": null}}}},
+                    {{"Write": {{"fqcn": {fqcn}, "value": [1], "wait_response": true, "timeout_ms": null}}}}
+                ], "parallelism": null, "default_timeout_ms": {default_timeout_ms}}}"#
+            ))
+            .unwrap()
+        };
+        let timeouts = |batch: PeripheralIoBatchRequestDto| {
+            let ctx = BatchContext::from(&batch);
+            batch
+                .commands
+                .into_iter()
+                .map(|cmd| command_timeout(&cmd.with_default_timeout(ctx.default_timeout), &app_conf))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            timeouts(batch("2000")),
+            vec![
+                Duration::from_millis(100),
+                Duration::from_millis(2000),
+                Duration::from_millis(2000)
+            ]
+        );
+        assert_eq!(
+            timeouts(batch("null")),
+            vec![
+                Duration::from_millis(100),
+                Duration::from_secs(7),
+                Duration::from_secs(9)
+            ]
+        );
+    }
+
+    /// Two commands to the first peripheral taking 60ms and 1ms, two to the second one taking 1ms each.
+    fn timed_request(flatten: bool) -> PeripheralIoRequestDto {
+        let batch = |peripheral: &str, delays: [u64; 2], parallelism: usize| PeripheralIoBatchRequestDto {
+            commands: delays
+                .into_iter()
+                .zip(0x2a19..)
+                .map(|(delay, characteristic)| IoCommand::Read {
+                    fqcn: (*peripheral_fqcn(peripheral, characteristic)).clone(),
+                    wait_notification: false,
+                    timeout_ms: Some(Duration::from_millis(delay)),
+                })
+                .collect(),
+            parallelism: BoundedUsize::new(parallelism),
+            retry_count: None,
+            retry_delay_ms: None,
+            default_timeout_ms: None,
+        };
+        PeripheralIoRequestDto {
+            batches: vec![
+                batch("11:22:33:44:55:01", [60, 1], 2),
+                batch("11:22:33:44:55:02", [1, 1], 1),
+            ],
+            parallelism: BoundedUsize::new(3),
+            flatten,
+            keep_connected: false,
+        }
+    }
+
+    /// Streams the request, every command sleeping for its timeout; returns `(batch index, command index, ms since
+    /// the start)` ordered by arrival time, then by index.
+    async fn stream_timed(request: PeripheralIoRequestDto) -> Vec<(usize, usize, u128)> {
+        let app_conf = AppConf::parse_from(["ble-collector", "--config", "config.yaml"]);
+        let started_at = tokio::time::Instant::now();
+        let mut results = stream_commands(request, &app_conf, |_, cmd| async move {
+            tokio::time::sleep(cmd.get_timeout().unwrap()).await;
+        })
+        .map(|(batch_index, command_index, ())| (batch_index, command_index, started_at.elapsed().as_millis()))
+        .collect::<Vec<_>>()
+        .await;
+        results.sort_unstable_by_key(|&(batch_index, command_index, received_after)| {
+            (received_after, batch_index, command_index)
+        });
+        results
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_results_are_streamed_as_they_complete() {
+        let results = stream_timed(timed_request(false)).await;
+
+        // the slow command doesn't hold back the others
+        assert_eq!(results, vec![(0, 1, 1), (1, 0, 1), (1, 1, 2), (0, 0, 60)]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_flattened_results_are_streamed() {
+        let results = stream_timed(timed_request(true)).await;
+
+        // commands to the same peripheral are run one at a time, whatever the batch parallelism
+        assert_eq!(results, vec![(1, 0, 1), (1, 1, 2), (0, 0, 60), (0, 1, 61)]);
+    }
+}
@@ -0,0 +1,203 @@
+use std::io::{Cursor, Write};
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Header;
+use rocket::{Request, Response};
+use tracing::error;
+
+/// Responses smaller than this are sent as is, compressing them is not worth the CPU.
+const MIN_COMPRESSED_SIZE: usize = 1024;
+
+/// Gzips response bodies for clients that send `Accept-Encoding: gzip`.
+pub(crate) struct GzipCompression;
+
+fn accepts_gzip(request: &Request<'_>) -> bool {
+    request
+        .headers()
+        .get("Accept-Encoding")
+        .flat_map(|value| value.split(','))
+        .any(|encoding| {
+            let mut parts = encoding.split(';').map(str::trim);
+            let is_gzip = parts
+                .next()
+                .map(|name| name.eq_ignore_ascii_case("gzip"))
+                .unwrap_or(false);
+            // `gzip;q=0` explicitly forbids the encoding
+            let is_rejected = parts.any(|param| {
+                param
+                    .strip_prefix("q=")
+                    .and_then(|quality| quality.trim().parse::<f32>().ok())
+                    == Some(0.0)
+            });
+            is_gzip && !is_rejected
+        })
+}
+
+fn gzip(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+#[rocket::async_trait]
+impl Fairing for GzipCompression {
+    fn info(&self) -> Info {
+        Info {
+            name: "Gzip compression",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        if !accepts_gzip(request) || response.headers().contains("Content-Encoding") {
+            return;
+        }
+        // buffering a streamed body, i.e. Server-Sent Events or ndjson, would hold it back until the stream ends
+        if response.body().preset_size().is_none() {
+            return;
+        }
+
+        let body = match response.body_mut().to_bytes().await {
+            Ok(body) => body,
+            Err(err) => {
+                error!("Failed to read response body for compression: {err}");
+                return;
+            }
+        };
+
+        response.set_header(Header::new("Vary", "Accept-Encoding"));
+
+        if body.len() < MIN_COMPRESSED_SIZE {
+            response.set_sized_body(body.len(), Cursor::new(body));
+            return;
+        }
+
+        match gzip(&body) {
+            Ok(compressed) => {
+                response.set_header(Header::new("Content-Encoding", "gzip"));
+                response.set_sized_body(compressed.len(), Cursor::new(compressed));
+            }
+            Err(err) => {
+                error!("Failed to compress response body: {err}");
+                response.set_sized_body(body.len(), Cursor::new(body));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use flate2::read::GzDecoder;
+    use rocket::http::ContentType;
+    use rocket::local::asynchronous::Client;
+    use rocket::response::stream::{Event, EventStream, TextStream};
+    use rocket::{get, routes};
+
+    use super::*;
+
+    #[get("/large")]
+    fn large() -> String {
+        "0123456789".repeat(500)
+    }
+
+    #[get("/small")]
+    fn small() -> &'static str {
+        "ok"
+    }
+
+    #[get("/events")]
+    fn events() -> EventStream![] {
+        EventStream! {
+            yield Event::data(large());
+        }
+    }
+
+    #[get("/lines")]
+    fn lines() -> (ContentType, TextStream![String]) {
+        let lines = TextStream! {
+            yield large() + "\n";
+        };
+        (ContentType::new("application", "x-ndjson"), lines)
+    }
+
+    async fn client() -> Client {
+        let rocket = rocket::build()
+            .mount("/", routes![large, small, events, lines])
+            .attach(GzipCompression);
+        Client::tracked(rocket).await.unwrap()
+    }
+
+    #[rocket::async_test]
+    async fn test_gzip_when_accepted() {
+        let client = client().await;
+        let response = client
+            .get("/large")
+            .header(Header::new("Accept-Encoding", "deflate, gzip;q=0.8"))
+            .dispatch()
+            .await;
+
+        assert_eq!(response.headers().get_one("Content-Encoding"), Some("gzip"));
+        let compressed = response.into_bytes().await.unwrap();
+        let mut decompressed = String::new();
+        GzDecoder::new(compressed.as_slice())
+            .read_to_string(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, large());
+    }
+
+    #[rocket::async_test]
+    async fn test_plain_when_not_accepted() {
+        let client = client().await;
+
+        let response = client.get("/large").dispatch().await;
+        assert_eq!(response.headers().get_one("Content-Encoding"), None);
+        assert_eq!(response.into_string().await.unwrap(), large());
+
+        let response = client
+            .get("/large")
+            .header(Header::new("Accept-Encoding", "gzip;q=0"))
+            .dispatch()
+            .await;
+        assert_eq!(response.headers().get_one("Content-Encoding"), None);
+
+        let response = client
+            .get("/small")
+            .header(Header::new("Accept-Encoding", "gzip"))
+            .dispatch()
+            .await;
+        assert_eq!(response.headers().get_one("Content-Encoding"), None);
+        assert_eq!(response.into_string().await.unwrap(), "ok");
+    }
+
+    #[rocket::async_test]
+    async fn test_event_stream_is_not_buffered() {
+        let client = client().await;
+        let response = client
+            .get("/events")
+            .header(Header::new("Accept-Encoding", "gzip"))
+            .dispatch()
+            .await;
+
+        assert_eq!(response.headers().get_one("Content-Encoding"), None);
+        // the body may also contain heartbeat comments
+        let body = response.into_string().await.unwrap();
+        assert!(body.contains(&format!("data:{}\n\n", large())));
+    }
+
+    #[rocket::async_test]
+    async fn test_ndjson_stream_is_not_buffered() {
+        let client = client().await;
+        let response = client
+            .get("/lines")
+            .header(Header::new("Accept-Encoding", "gzip"))
+            .dispatch()
+            .await;
+
+        assert_eq!(response.headers().get_one("Content-Encoding"), None);
+        assert_eq!(response.into_string().await.unwrap(), large() + "\n");
+    }
+}
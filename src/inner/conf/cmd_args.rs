@@ -1,14 +1,19 @@
 use std::net::SocketAddr;
+use std::num::NonZeroUsize;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::Context;
 use clap::Parser;
+use rumqttc::v5::mqttbytes::v5::ConnectProperties;
 use rumqttc::v5::MqttOptions;
 
 use crate::inner::conf::dto::collector_configuration::CollectorConfigurationDto;
+use crate::inner::debounce_limiter::EventThrottlingMode;
 use crate::inner::error::CollectorError;
+use crate::inner::model::adapter_info::{AdapterAlias, AdapterInfo, ScanRestartInterval};
+use crate::inner::publish::dto::{MqttSerialization, TimestampFormat};
 
 #[derive(Parser, Debug)]
 #[command(
@@ -23,14 +28,50 @@ pub(crate) struct AppConf {
     #[arg(long)]
     pub(crate) config: PathBuf,
 
+    /// Friendly adapter name in the `hci0=garage` form; can be repeated.
+    #[arg(long)]
+    pub(crate) adapter_alias: Vec<AdapterAlias>,
+
+    /// Periodically stop and start scanning, for controllers that silently stop reporting advertisements after a while;
+    /// connected peripherals are kept. In the `30m` (every adapter) or `hci0=30m` form; can be repeated.
+    #[arg(long)]
+    pub(crate) scan_restart_interval: Vec<ScanRestartInterval>,
+
+    /// Adapter id or alias the `default` adapter id of the API refers to. If not set, `default` refers to the only
+    /// adapter and is rejected if there are several.
+    #[arg(long)]
+    pub(crate) default_adapter: Option<String>,
+
     /// Server listen address.
     #[arg(long, default_value = "127.0.0.1:8000")]
     pub(crate) listen_address: SocketAddr,
 
+    /// Respond with `504 Gateway Timeout` to API requests that take longer, i.e. describing adapters with many
+    /// peripherals. I/O and bulk write requests are only limited by the timeouts of their commands.
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "30s")]
+    pub(crate) api_request_timeout: Duration,
+
+    /// Reject API requests that write characteristics; reads are still served.
+    #[arg(long)]
+    pub(crate) read_only_api: bool,
+
     /// Throttle events for the same peripheral for at least this time in milliseconds.
     #[arg(long, value_parser = humantime::parse_duration, default_value = "30s")]
     pub(crate) event_throttling: Duration,
 
+    /// Number of independently locked shards the throttled peripherals are spread over.
+    #[arg(long, default_value = "16")]
+    pub(crate) event_throttling_shards: usize,
+
+    /// Whether events are throttled per peripheral, connecting its first matching config, or per peripheral and
+    /// matching config, connecting every matching config that isn't throttled.
+    #[arg(long, value_enum, default_value_t = EventThrottlingMode::Address)]
+    pub(crate) event_throttling_mode: EventThrottlingMode,
+
+    /// Log each peripheral without a matching config at most once per this interval.
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "5m")]
+    pub(crate) unmatched_peripheral_log_interval: Duration,
+
     /// Throttle purge samples
     #[arg(long, default_value = "100")]
     pub(crate) event_throttling_purge_samples: usize,
@@ -43,6 +84,10 @@ pub(crate) struct AppConf {
     #[arg(long, value_parser = humantime::parse_duration, default_value = "60s")]
     pub(crate) peripheral_cache_ttl: Duration,
 
+    /// Maximum number of cached peripherals per adapter; the least recently used ones are evicted first.
+    #[arg(long)]
+    pub(crate) peripheral_cache_max_entries: Option<usize>,
+
     /// Default characteristic read timeout.
     #[arg(long, value_parser = humantime::parse_duration, default_value = "5s")]
     pub(crate) default_read_timeout: Duration,
@@ -63,10 +108,63 @@ pub(crate) struct AppConf {
     #[arg(long, default_value = "4")]
     pub(crate) service_discovery_parallelism: usize,
 
+    /// How many adapters are queried concurrently when listing / describing adapters.
+    #[arg(long, default_value = "4")]
+    pub(crate) adapter_parallelism: NonZeroUsize,
+
+    /// How many peripherals are described concurrently when describing adapters.
+    #[arg(long, default_value = "32")]
+    pub(crate) describe_peripheral_parallelism: NonZeroUsize,
+
+    /// How long stopped polling / notification tasks may take to publish their last value before being aborted.
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "1s")]
+    pub(crate) task_drain_timeout: Duration,
+
+    /// Re-acquire a notification stream that has ended while the peripheral is still connected this many times in a
+    /// row before tearing the subscription down.
+    #[arg(long, default_value = "0")]
+    pub(crate) notification_stream_reacquire_attempts: u32,
+
+    /// How long to wait for a characteristic subscription to be acknowledged by the peripheral.
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "10s")]
+    pub(crate) subscribe_timeout: Duration,
+
     /// Default peripheral connect timeout.
     #[arg(long, value_parser = humantime::parse_duration, default_value = "30s")]
     pub(crate) peripheral_connect_timeout: Duration,
 
+    /// Read every `Poll` characteristic of the configured peripherals once, publish the values and exit.
+    #[arg(long)]
+    pub(crate) once: bool,
+
+    /// How long to scan for peripherals before reading them in the `--once` mode.
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "10s")]
+    pub(crate) once_scan_duration: Duration,
+
+    /// How long to wait for the publishers to flush the values in the `--once` mode; exits with an error if exceeded.
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "10s")]
+    pub(crate) once_flush_timeout: Duration,
+
+    /// Initial reconnect delay for `persistent` peripherals; doubled after every failed attempt.
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "1s")]
+    pub(crate) persistent_reconnect_min_backoff: Duration,
+
+    /// Maximum reconnect delay for `persistent` peripherals.
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "60s")]
+    pub(crate) persistent_reconnect_max_backoff: Duration,
+
+    /// Stop connecting to a peripheral after this many consecutive connect failures. Disabled if 0.
+    #[arg(long, default_value = "5")]
+    pub(crate) circuit_breaker_threshold: u32,
+
+    /// How long to stop connecting for the first time the breaker opens; doubled every time it opens again.
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "30s")]
+    pub(crate) circuit_breaker_min_cooldown: Duration,
+
+    /// Maximum time to stop connecting to a failing peripheral for.
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "30m")]
+    pub(crate) circuit_breaker_max_cooldown: Duration,
+
     /// Metrics idle timeout. Metric is removed if no data received for this time.
     #[arg(long, value_parser = humantime::parse_duration, default_value = "5m")]
     pub(crate) metrics_idle_timeout: Duration,
@@ -75,6 +173,55 @@ pub(crate) struct AppConf {
     #[arg(long, value_parser = humantime::parse_duration, default_value = "5m")]
     pub(crate) notification_stream_read_timeout: Duration,
 
+    /// How many RSSI readings to keep per peripheral.
+    #[arg(long, default_value = "100")]
+    pub(crate) rssi_history_size: usize,
+
+    /// Disconnect peripherals left without poll / subscribe tasks after this delay. Disabled if not set.
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub(crate) unsubscribe_on_idle_delay: Option<Duration>,
+
+    /// Keep the tasks of a disconnected peripheral for this long and only tear them down if it hasn't reconnected in
+    /// the meantime. Torn down immediately if not set.
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub(crate) disconnect_grace_period: Option<Duration>,
+
+    /// How long a peripheral stays connected after an r/w request with `keep_connected`.
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "30s")]
+    pub(crate) keep_connected_timeout: Duration,
+
+    /// Record peripheral connect / disconnect events, keeping this many per peripheral. Disabled if not set.
+    #[arg(long)]
+    pub(crate) lifecycle_history_size: Option<usize>,
+
+    /// Add the change since the previous value of numeric characteristics to the API and MQTT data points.
+    #[arg(long)]
+    pub(crate) publish_value_delta: bool,
+
+    /// Disable a characteristic after this many consecutive converter length mismatches.
+    #[arg(long)]
+    pub(crate) max_conversion_length_mismatches: Option<usize>,
+
+    /// Comma-separated list of label keys allowed on user-defined metrics. All labels are kept if not set.
+    #[arg(long, value_delimiter = ',')]
+    pub(crate) metrics_labels_allowlist: Option<Vec<String>>,
+
+    /// How many recent WARN/ERROR log events to keep in memory for the API.
+    #[arg(long, default_value = "200")]
+    pub(crate) recent_log_capacity: usize,
+
+    /// How many payloads an `/events` client may fall behind before it skips the missed ones.
+    #[arg(long, default_value = "1024")]
+    pub(crate) event_stream_capacity: NonZeroUsize,
+
+    /// How often to evict API data points that fell out of their `history_window`.
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "60s")]
+    pub(crate) api_eviction_interval: Duration,
+
+    /// Data point timestamp representation in the API and in the JSON MQTT state messages.
+    #[arg(long, value_enum, default_value_t = TimestampFormat::Rfc3339)]
+    pub(crate) timestamp_format: TimestampFormat,
+
     /// MQTT broker address, i.e. localhost:1883
     #[clap(long)]
     pub(crate) mqtt_address: Option<SocketAddr>,
@@ -98,6 +245,45 @@ pub(crate) struct AppConf {
     /// MQTT cap is the capacity of the bounded async channel.
     #[arg(long, requires = "mqtt_address", default_value = "1000")]
     pub(crate) mqtt_cap: usize,
+
+    /// MQTT topic for periodic collector heartbeat messages.
+    #[arg(long, requires = "mqtt_address")]
+    pub(crate) mqtt_heartbeat_topic: Option<String>,
+
+    /// MQTT heartbeat interval.
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "60s")]
+    pub(crate) mqtt_heartbeat_interval: Duration,
+
+    /// MQTT state message encoding. Subscribers must decode `proto` messages with
+    /// `proto/characteristic_payload.proto`; discovery and heartbeat messages are always JSON.
+    #[arg(long, requires = "mqtt_address", value_enum, default_value_t = MqttSerialization::Json)]
+    pub(crate) mqtt_serialization: MqttSerialization,
+
+    /// MQTT v5 session expiry interval in seconds. When set, the broker keeps the session (and queued QoS 1/2
+    /// messages) for this long after the collector disconnects.
+    #[arg(long, requires = "mqtt_address")]
+    pub(crate) mqtt_session_expiry_interval: Option<u32>,
+}
+
+impl AppConf {
+    pub(crate) fn get_adapter_alias(&self, adapter_id: &str) -> Option<String> {
+        self.adapter_alias
+            .iter()
+            .find(|adapter_alias| adapter_alias.adapter_id == adapter_id)
+            .map(|adapter_alias| adapter_alias.alias.clone())
+    }
+
+    /// The scan restart interval of the adapter, falling back to the one set for every adapter.
+    pub(crate) fn get_scan_restart_interval(&self, adapter_info: &AdapterInfo) -> Option<Duration> {
+        self.scan_restart_interval
+            .iter()
+            .filter(|restart| match restart.adapter_id.as_deref() {
+                Some(adapter_id) => adapter_info.matches(adapter_id),
+                None => true,
+            })
+            .max_by_key(|restart| restart.adapter_id.is_some())
+            .map(|restart| restart.interval)
+    }
 }
 
 impl TryFrom<&AppConf> for CollectorConfigurationDto {
@@ -122,6 +308,29 @@ impl TryFrom<&AppConf> for MqttOptions {
         if let (Some(username), Some(password)) = (value.mqtt_username.as_ref(), value.mqtt_password.as_ref()) {
             mqtt_options.set_credentials(username.as_str(), password.as_str());
         }
+
+        if let Some(session_expiry_interval) = value.mqtt_session_expiry_interval {
+            let mut connect_properties = mqtt_options.connect_properties().unwrap_or_else(ConnectProperties::new);
+            connect_properties.session_expiry_interval = Some(session_expiry_interval);
+            mqtt_options
+                .set_connect_properties(connect_properties)
+                .set_clean_start(false);
+        }
         Ok(mqtt_options)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<AppConf, clap::Error> {
+        AppConf::try_parse_from(["ble-collector", "--config", "config.yaml"].iter().chain(args))
+    }
+
+    #[test]
+    fn test_zero_capacity_is_rejected() {
+        assert_eq!(parse(&[]).unwrap().event_stream_capacity.get(), 1024);
+        assert!(parse(&["--event-stream-capacity", "0"]).is_err());
+    }
+}
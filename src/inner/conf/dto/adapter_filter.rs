@@ -0,0 +1,118 @@
+use serde::{Deserialize, Serialize};
+
+use crate::inner::conf::model::filter::Filter;
+use crate::inner::conf::traits::Evaluate;
+use crate::inner::model::peripheral_key::PeripheralKey;
+
+/// Matches a peripheral by address and / or name; an empty filter matches every peripheral.
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub(crate) struct PeripheralFilterDto {
+    #[serde(default)]
+    pub(crate) device_id: Option<Filter>,
+    #[serde(default)]
+    pub(crate) device_name: Option<Filter>,
+}
+
+/// Peripherals seen by the matching adapters are ignored before any peripheral config is matched:
+/// a denied peripheral is always ignored, and if `allow` is not empty, only the listed peripherals are processed.
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub(crate) struct AdapterFilterDto {
+    pub(crate) adapter: Filter,
+    #[serde(default)]
+    pub(crate) allow: Vec<PeripheralFilterDto>,
+    #[serde(default)]
+    pub(crate) deny: Vec<PeripheralFilterDto>,
+}
+
+impl Evaluate<&PeripheralKey, bool> for PeripheralFilterDto {
+    fn evaluate(&self, source: &PeripheralKey) -> bool {
+        let device_id_matches = self
+            .device_id
+            .as_ref()
+            .map(|filter| filter.evaluate(&source.peripheral_address.to_string()))
+            .unwrap_or(true);
+
+        let name_matches = match (self.device_name.as_ref(), &source.name) {
+            (Some(filter), Some(name)) => filter.evaluate(name),
+            (Some(_), None) => false,
+            (None, _) => true,
+        };
+
+        device_id_matches && name_matches
+    }
+}
+
+impl Evaluate<&PeripheralKey, bool> for AdapterFilterDto {
+    /// Returns `true` if the peripheral is allowed on this adapter (or the filter targets another adapter).
+    fn evaluate(&self, source: &PeripheralKey) -> bool {
+        if !self.adapter.evaluate(&source.adapter_id) {
+            return true;
+        }
+        if self.deny.iter().any(|filter| filter.evaluate(source)) {
+            return false;
+        }
+
+        self.allow.is_empty() || self.allow.iter().any(|filter| filter.evaluate(source))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peripheral_key(adapter_id: &str, address: &str, name: Option<&str>) -> PeripheralKey {
+        PeripheralKey {
+            adapter_id: adapter_id.to_string(),
+            peripheral_address: address.parse().unwrap(),
+            name: name.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_allow_only() {
+        let filter: AdapterFilterDto = serde_yaml::from_str(
+            r#"
+            adapter: !Equals hci1
+            allow:
+              - device_id: !Equals '11:22:33:44:55:66'
+              - device_name: !StartsWith 'Sensor'
+            "#,
+        )
+        .unwrap();
+
+        assert!(filter.evaluate(&peripheral_key("hci1", "11:22:33:44:55:66", None)));
+        assert!(filter.evaluate(&peripheral_key("hci1", "AA:BB:CC:DD:EE:FF", Some("Sensor Hub"))));
+        assert!(!filter.evaluate(&peripheral_key("hci1", "AA:BB:CC:DD:EE:FF", Some("Phone"))));
+        assert!(!filter.evaluate(&peripheral_key("hci1", "AA:BB:CC:DD:EE:FF", None)));
+
+        // other adapters are not affected
+        assert!(filter.evaluate(&peripheral_key("hci0", "AA:BB:CC:DD:EE:FF", Some("Phone"))));
+    }
+
+    #[test]
+    fn test_deny() {
+        let filter: AdapterFilterDto = serde_yaml::from_str(
+            r#"
+            adapter: !StartsWith hci
+            allow:
+              - device_name: !StartsWith 'Sensor'
+            deny:
+              - device_name: !Contains 'Kitchen'
+              - device_id: !Equals '11:22:33:44:55:66'
+            "#,
+        )
+        .unwrap();
+
+        assert!(filter.evaluate(&peripheral_key("hci0", "AA:BB:CC:DD:EE:FF", Some("Sensor Hub"))));
+        assert!(!filter.evaluate(&peripheral_key("hci0", "AA:BB:CC:DD:EE:FF", Some("Sensor Kitchen"))));
+        assert!(!filter.evaluate(&peripheral_key("hci1", "11:22:33:44:55:66", Some("Sensor Hub"))));
+
+        let deny_only = AdapterFilterDto {
+            allow: vec![],
+            ..filter
+        };
+        assert!(deny_only.evaluate(&peripheral_key("hci0", "AA:BB:CC:DD:EE:FF", Some("Phone"))));
+        assert!(deny_only.evaluate(&peripheral_key("hci0", "AA:BB:CC:DD:EE:FF", None)));
+        assert!(!deny_only.evaluate(&peripheral_key("hci0", "11:22:33:44:55:66", None)));
+    }
+}
@@ -11,32 +11,108 @@ use crate::inner::conv::converter::Converter;
 pub(crate) enum CharacteristicConfigDto {
     Subscribe {
         name: Option<Arc<String>>,
+        #[serde(with = "characteristic_uuid")]
         uuid: Uuid,
         history_size: Option<usize>,
         #[serde(default)]
+        #[serde(with = "humantime_serde")]
+        history_window: Option<Duration>,
+        #[serde(default)]
         converter: Converter,
+        #[serde(default)]
+        record_raw_bytes: bool,
         publish_metrics: Option<PublishMetricConfigDto>,
         publish_mqtt: Option<PublishMqttConfigDto>,
     },
     Poll {
         name: Option<Arc<String>>,
+        #[serde(with = "characteristic_uuid")]
         uuid: Uuid,
         #[serde(default)]
         #[serde(with = "humantime_serde")]
         delay: Option<Duration>,
         history_size: Option<usize>,
         #[serde(default)]
+        #[serde(with = "humantime_serde")]
+        history_window: Option<Duration>,
+        #[serde(default)]
         converter: Converter,
+        #[serde(default)]
+        record_raw_bytes: bool,
+        publish_metrics: Option<PublishMetricConfigDto>,
+        publish_mqtt: Option<PublishMqttConfigDto>,
+    },
+    /// Decoded from advertisement data without connecting; `uuid` only identifies the value.
+    Broadcast {
+        name: Option<Arc<String>>,
+        #[serde(with = "characteristic_uuid")]
+        uuid: Uuid,
+        #[serde(default)]
+        source: AdvertisementSource,
+        /// The value is `length` bytes (or the rest of the data) starting at `offset`.
+        #[serde(default)]
+        offset: usize,
+        #[serde(default)]
+        length: Option<usize>,
+        history_size: Option<usize>,
+        #[serde(default)]
+        #[serde(with = "humantime_serde")]
+        history_window: Option<Duration>,
+        #[serde(default)]
+        converter: Converter,
+        #[serde(default)]
+        record_raw_bytes: bool,
         publish_metrics: Option<PublishMetricConfigDto>,
         publish_mqtt: Option<PublishMqttConfigDto>,
     },
 }
 
+/// Where a `Broadcast` characteristic takes its bytes from.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, Eq, PartialEq, Hash)]
+pub(crate) enum AdvertisementSource {
+    /// Service data advertised under the uuid of the enclosing service.
+    #[default]
+    ServiceData,
+    /// Manufacturer data advertised under the given company id.
+    ManufacturerData(u16),
+}
+
 impl CharacteristicConfigDto {
     pub(crate) fn uuid(&self) -> &Uuid {
         match self {
             CharacteristicConfigDto::Subscribe { uuid, .. } => uuid,
             CharacteristicConfigDto::Poll { uuid, .. } => uuid,
+            CharacteristicConfigDto::Broadcast { uuid, .. } => uuid,
+        }
+    }
+}
+
+/// Accepts `*` as a wildcard characteristic uuid in addition to regular and 16-bit short uuids.
+mod characteristic_uuid {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use uuid::Uuid;
+
+    use crate::inner::conf::dto::short_uuid::parse_uuid;
+    use crate::inner::conf::model::service_characteristic_key::WILDCARD_CHARACTERISTIC_UUID;
+
+    pub(super) fn serialize<S>(uuid: &Uuid, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if *uuid == WILDCARD_CHARACTERISTIC_UUID {
+            return serializer.serialize_str("*");
+        }
+        serializer.collect_str(uuid)
+    }
+
+    pub(super) fn deserialize<'de, D>(deserializer: D) -> Result<Uuid, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = String::deserialize(deserializer)?;
+        if value.trim() == "*" {
+            return Ok(WILDCARD_CHARACTERISTIC_UUID);
         }
+        parse_uuid(&value).map_err(serde::de::Error::custom)
     }
 }
@@ -1,20 +1,32 @@
 use serde::{Deserialize, Serialize};
 
+use crate::inner::conf::dto::adapter_filter::AdapterFilterDto;
+use crate::inner::conf::dto::mqtt_target::MqttTargetConfigDto;
 use crate::inner::conf::dto::peripheral::PeripheralConfigDto;
 
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
 pub(crate) struct CollectorConfigurationDto {
     pub(crate) peripherals: Vec<PeripheralConfigDto>,
+    /// MQTT brokers used in addition to the one passed with `--mqtt-address`.
+    #[serde(default)]
+    pub(crate) mqtt_targets: Vec<MqttTargetConfigDto>,
+    /// Adapter-scoped allow / deny lists, checked before matching peripheral configs.
+    #[serde(default)]
+    pub(crate) adapter_filters: Vec<AdapterFilterDto>,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::inner::conf::dto::adapter_filter::PeripheralFilterDto;
     use crate::inner::conf::dto::characteristic::CharacteristicConfigDto;
-    use crate::inner::conf::dto::publish::{PublishMetricConfigDto, PublishMqttConfigDto};
+    use crate::inner::conf::dto::peripheral::OnConnectWriteDto;
+    use crate::inner::conf::dto::publish::{PublishMetricConfigDto, PublishMqttConfigDto, Qos};
     use crate::inner::conf::dto::service::ServiceConfigDto;
+    use crate::inner::conf::model::discovery_filter::DiscoveryFilter;
     use crate::inner::conf::model::filter::Filter;
     use crate::inner::metrics::MetricType;
+    use crate::inner::publish::dto::MqttSerialization;
     use serde_json::json;
     use std::sync::Arc;
     use std::time::Duration;
@@ -29,6 +41,15 @@ mod tests {
                 adapter: Some(Filter::Contains("hci0".to_string())),
                 device_id: Some(Filter::StartsWith("FA:6F".to_string())),
                 device_name: Some(Filter::EndsWith("test".to_string())),
+                on_connect: vec![OnConnectWriteDto {
+                    service: Uuid::nil(),
+                    characteristic: Uuid::nil(),
+                    value: vec![1, 2, 3],
+                    wait_response: true,
+                }],
+                persistent: true,
+                max_reconnect_attempts: None,
+                discovery_filter: Some(DiscoveryFilter::MinRssi(-80)),
                 services: vec![ServiceConfigDto {
                     name: Some("test".to_string().into()),
                     uuid: Uuid::nil(),
@@ -40,18 +61,25 @@ mod tests {
                             name: Some("test".to_string().into()),
                             uuid: Uuid::nil(),
                             converter: Default::default(),
+                            history_window: None,
+                            record_raw_bytes: false,
                             publish_metrics: Some(PublishMetricConfigDto {
                                 metric_type: MetricType::Counter,
                                 name: Arc::new("test".to_string()),
                                 description: Some(Arc::new("test".to_string())),
                                 unit: Arc::new("test".to_string()),
                                 labels: Some(Arc::new(vec![("test".to_string(), "test".to_string())])),
+                                device_class: None,
+                                auto_fqcn_labels: true,
                             }),
                             publish_mqtt: Some(PublishMqttConfigDto {
                                 state_topic: Arc::new("test".to_string()),
                                 unit: Some(Arc::new("test".to_string())),
                                 retain: true,
                                 qos: Default::default(),
+                                command_topic: None,
+                                include_raw_service_data: false,
+                                include_metadata: false,
                                 discovery: None,
                             }),
                         },
@@ -61,12 +89,40 @@ mod tests {
                             uuid: Uuid::nil(),
                             delay: Some(Duration::from_secs(1)),
                             converter: Default::default(),
+                            history_window: None,
+                            record_raw_bytes: false,
                             publish_metrics: None,
                             publish_mqtt: None,
                         },
                     ],
                 }],
             }],
+            mqtt_targets: vec![MqttTargetConfigDto {
+                name: "cloud".to_string(),
+                host: "mqtt.example.com".to_string(),
+                port: 8883,
+                username: Some("test".to_string()),
+                password: Some("test".to_string()),
+                client_id: None,
+                keepalive: Duration::from_secs(30),
+                cap: 100,
+                session_expiry_interval: None,
+                topic_prefix: Some("home/".to_string()),
+                qos: Some(Qos::AtMostOnce),
+                discovery: false,
+                serialization: MqttSerialization::Proto,
+            }],
+            adapter_filters: vec![AdapterFilterDto {
+                adapter: Filter::Equals("hci1".to_string()),
+                allow: vec![PeripheralFilterDto {
+                    device_id: None,
+                    device_name: Some(Filter::StartsWith("Sensor".to_string())),
+                }],
+                deny: vec![PeripheralFilterDto {
+                    device_id: Some(Filter::Equals("11:22:33:44:55:66".to_string())),
+                    device_name: None,
+                }],
+            }],
         };
 
         let serialized = serde_yaml::to_string(&config).unwrap();
@@ -86,6 +142,7 @@ mod tests {
                     let (char_uuid, name) = match characteristic {
                         CharacteristicConfigDto::Subscribe { uuid, name, .. } => (uuid, name),
                         CharacteristicConfigDto::Poll { uuid, name, .. } => (uuid, name),
+                        CharacteristicConfigDto::Broadcast { uuid, name, .. } => (uuid, name),
                     };
 
                     let name = name.unwrap().split_whitespace().collect::<Vec<_>>().join("");
@@ -1,5 +1,8 @@
+pub(crate) mod adapter_filter;
 pub(crate) mod characteristic;
 pub(crate) mod collector_configuration;
+pub(crate) mod mqtt_target;
 pub(crate) mod peripheral;
 pub(crate) mod publish;
 pub(crate) mod service;
+pub(crate) mod short_uuid;
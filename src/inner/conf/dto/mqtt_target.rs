@@ -0,0 +1,82 @@
+use std::time::Duration;
+
+use rumqttc::v5::mqttbytes::v5::ConnectProperties;
+use rumqttc::v5::MqttOptions;
+use serde::{Deserialize, Serialize};
+
+use crate::inner::conf::dto::publish::Qos;
+use crate::inner::publish::dto::MqttSerialization;
+
+fn default_port() -> u16 {
+    1883
+}
+
+fn default_keepalive() -> Duration {
+    Duration::from_secs(10)
+}
+
+fn default_cap() -> usize {
+    1000
+}
+
+fn default_discovery() -> bool {
+    true
+}
+
+/// An additional MQTT broker with its own client; every target receives all state and discovery messages.
+/// Command topics are only subscribed on the broker passed with `--mqtt-address`.
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub(crate) struct MqttTargetConfigDto {
+    pub(crate) name: String,
+    pub(crate) host: String,
+    #[serde(default = "default_port")]
+    pub(crate) port: u16,
+    pub(crate) username: Option<String>,
+    pub(crate) password: Option<String>,
+    /// Defaults to `ble-collector-<name>`.
+    pub(crate) client_id: Option<String>,
+    #[serde(default = "default_keepalive", with = "humantime_serde")]
+    pub(crate) keepalive: Duration,
+    #[serde(default = "default_cap")]
+    pub(crate) cap: usize,
+    pub(crate) session_expiry_interval: Option<u32>,
+
+    /// Prepended to every state topic published to this broker.
+    #[serde(default)]
+    pub(crate) topic_prefix: Option<String>,
+    /// Overrides the characteristic QoS of state messages published to this broker.
+    #[serde(default)]
+    pub(crate) qos: Option<Qos>,
+    /// Publish discovery configs to this broker.
+    #[serde(default = "default_discovery")]
+    pub(crate) discovery: bool,
+    /// State message encoding, same as `--mqtt-serialization`.
+    #[serde(default)]
+    pub(crate) serialization: MqttSerialization,
+}
+
+impl From<&MqttTargetConfigDto> for MqttOptions {
+    fn from(value: &MqttTargetConfigDto) -> Self {
+        let client_id = value
+            .client_id
+            .clone()
+            .unwrap_or_else(|| format!("ble-collector-{}", value.name));
+        let mut mqtt_options = MqttOptions::new(client_id, value.host.as_str(), value.port);
+
+        mqtt_options.set_keep_alive(value.keepalive);
+
+        if let (Some(username), Some(password)) = (value.username.as_ref(), value.password.as_ref()) {
+            mqtt_options.set_credentials(username.as_str(), password.as_str());
+        }
+
+        if let Some(session_expiry_interval) = value.session_expiry_interval {
+            let mut connect_properties = mqtt_options.connect_properties().unwrap_or_else(ConnectProperties::new);
+            connect_properties.session_expiry_interval = Some(session_expiry_interval);
+            mqtt_options
+                .set_connect_properties(connect_properties)
+                .set_clean_start(false);
+        }
+
+        mqtt_options
+    }
+}
@@ -1,7 +1,10 @@
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
+use uuid::Uuid;
 
 use crate::inner::conf::dto::service::ServiceConfigDto;
+use crate::inner::conf::dto::short_uuid;
+use crate::inner::conf::model::discovery_filter::DiscoveryFilter;
 use crate::inner::conf::model::filter::Filter;
 
 #[serde_as]
@@ -11,5 +14,29 @@ pub(crate) struct PeripheralConfigDto {
     pub(crate) adapter: Option<Filter>,
     pub(crate) device_id: Option<Filter>,
     pub(crate) device_name: Option<Filter>,
+    /// Writes performed in order on every connection, before any subscription is set up.
+    #[serde(default)]
+    pub(crate) on_connect: Vec<OnConnectWriteDto>,
+    /// Keep the peripheral connected, reconnecting on disconnect instead of waiting for the next advertisement.
+    #[serde(default)]
+    pub(crate) persistent: bool,
+    /// Stop reconnecting a `persistent` peripheral after this many consecutive failed attempts, until it advertises
+    /// again. Retried forever if not set.
+    #[serde(default)]
+    pub(crate) max_reconnect_attempts: Option<u32>,
+    /// Checked against the advertised properties before connecting; the peripheral is skipped if it doesn't pass.
+    #[serde(default)]
+    pub(crate) discovery_filter: Option<DiscoveryFilter>,
     pub(crate) services: Vec<ServiceConfigDto>,
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub(crate) struct OnConnectWriteDto {
+    #[serde(with = "short_uuid")]
+    pub(crate) service: Uuid,
+    #[serde(with = "short_uuid")]
+    pub(crate) characteristic: Uuid,
+    pub(crate) value: Vec<u8>,
+    #[serde(default)]
+    pub(crate) wait_response: bool,
+}
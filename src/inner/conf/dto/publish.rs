@@ -1,5 +1,6 @@
 use crate::inner::metrics::MetricType;
-use metrics::Label;
+use crate::inner::model::fqcn::Fqcn;
+use metrics::{Label, Unit};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
@@ -10,6 +11,19 @@ pub(crate) struct PublishMetricConfigDto {
     pub(crate) description: Option<Arc<String>>,
     pub(crate) unit: Arc<String>,
     pub(crate) labels: Option<Arc<Vec<(String, String)>>>,
+
+    /// Added as the `device_class` label, i.e. the same value as in the MQTT discovery config.
+    #[serde(default)]
+    pub(crate) device_class: Option<Arc<String>>,
+
+    /// Add the `peripheral`, `service` and `characteristic` labels; disable to merge the values of many peripherals
+    /// into a single series.
+    #[serde(default = "default_auto_fqcn_labels")]
+    pub(crate) auto_fqcn_labels: bool,
+}
+
+fn default_auto_fqcn_labels() -> bool {
+    true
 }
 
 impl PublishMetricConfigDto {
@@ -18,9 +32,39 @@ impl PublishMetricConfigDto {
             .iter()
             .flat_map(|labels| labels.iter())
             .map(|(k, v)| Label::new(k.to_string(), v.to_string()))
+            .chain(
+                self.device_class
+                    .iter()
+                    .map(|device_class| Label::new("device_class", device_class.to_string())),
+            )
             .collect()
     }
+
+    /// The configured labels, followed by the FQCN labels unless `auto_fqcn_labels` is disabled.
+    pub(crate) fn with_fqcn_labels(&self, fqcn: &Fqcn) -> Vec<Label> {
+        let mut labels = self.labels();
+        if self.auto_fqcn_labels {
+            labels.extend([
+                fqcn.peripheral_label(),
+                fqcn.service_label(),
+                fqcn.characteristic_label(),
+            ]);
+        }
+        labels
+    }
+
+    /// Maps the configured unit to one known by the metrics recorder; units like `Volt` have no counterpart.
+    pub(crate) fn metric_unit(&self) -> Option<Unit> {
+        let unit = self.unit.trim().to_lowercase();
+        match unit.as_str() {
+            "%" => Some(Unit::Percent),
+            "s" => Some(Unit::Seconds),
+            "ms" => Some(Unit::Milliseconds),
+            _ => Unit::from_string(&unit).or_else(|| Unit::from_string(&format!("{unit}s"))),
+        }
+    }
 }
+
 #[allow(clippy::enum_variant_names)]
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Default, Copy)]
 pub(crate) enum Qos {
@@ -52,6 +96,18 @@ pub(crate) struct PublishMqttConfigDto {
     #[serde(default)]
     pub(crate) qos: Qos,
 
+    /// Topic to listen for write commands on; the payload is encoded with the characteristic converter.
+    #[serde(default)]
+    pub(crate) command_topic: Option<Arc<String>>,
+
+    /// Add the unconverted value as `raw_hex` to the published JSON state messages, i.e. for debugging converters.
+    #[serde(default)]
+    pub(crate) include_raw_service_data: bool,
+
+    /// Add the capture time (`ts`) and the `adapter_id` to the published JSON state messages.
+    #[serde(default)]
+    pub(crate) include_metadata: bool,
+
     pub(crate) discovery: Option<Arc<DiscoverySettings>>,
 }
 
@@ -70,3 +126,37 @@ impl From<Qos> for rumqttc::v5::mqttbytes::QoS {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_with_fqcn_labels() {
+        let fqcn = Fqcn {
+            peripheral: "11:22:33:44:55:66".parse().unwrap(),
+            service: "0000180f-0000-1000-8000-00805f9b34fb".parse().unwrap(),
+            characteristic: "00002a19-0000-1000-8000-00805f9b34fb".parse().unwrap(),
+        };
+        let mut metric_conf: PublishMetricConfigDto = serde_yaml::from_str(
+            r#"
+            metric_type: Gauge
+            name: 'battery_level_percent'
+            unit: 'percent'
+            labels:
+              - [ 'scope', 'device' ]
+            "#,
+        )
+        .unwrap();
+        assert!(metric_conf.auto_fqcn_labels);
+
+        let keys = |labels: Vec<Label>| labels.iter().map(|label| label.key().to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            keys(metric_conf.with_fqcn_labels(&fqcn)),
+            vec!["scope", "peripheral", "service", "characteristic"]
+        );
+
+        metric_conf.auto_fqcn_labels = false;
+        assert_eq!(keys(metric_conf.with_fqcn_labels(&fqcn)), vec!["scope"]);
+    }
+}
@@ -5,10 +5,12 @@ use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::inner::conf::dto::characteristic::CharacteristicConfigDto;
+use crate::inner::conf::dto::short_uuid;
 
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
 pub(crate) struct ServiceConfigDto {
     pub(crate) name: Option<Arc<String>>,
+    #[serde(with = "short_uuid")]
     pub(crate) uuid: Uuid,
     #[serde(with = "humantime_serde")]
     pub(crate) default_delay: Duration,
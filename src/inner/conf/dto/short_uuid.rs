@@ -0,0 +1,105 @@
+//! Uuids in the configuration can be written either in full or as 16-bit BLE shorthand, i.e. `0x180F`.
+//! The 32-bit shorthand (8 hex digits) is accepted as well.
+
+use btleplug::api::bleuuid::{uuid_from_u16, uuid_from_u32};
+use serde::{Deserialize, Deserializer, Serializer};
+use uuid::Uuid;
+
+pub(crate) fn parse_uuid(value: &str) -> Result<Uuid, String> {
+    let value = value.trim();
+    if let Some(short) = value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+        if short.len() == 8 {
+            return u32::from_str_radix(short, 16)
+                .map(uuid_from_u32)
+                .map_err(|err| format!("Invalid 32-bit uuid `{value}`: {err}"));
+        }
+        return u16::from_str_radix(short, 16)
+            .map(uuid_from_u16)
+            .map_err(|err| format!("Invalid 16-bit uuid `{value}`: {err}"));
+    }
+    Uuid::parse_str(value).map_err(|err| format!("Invalid uuid `{value}`: {err}"))
+}
+
+pub(crate) fn serialize<S>(uuid: &Uuid, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.collect_str(uuid)
+}
+
+pub(crate) fn deserialize<'de, D>(deserializer: D) -> Result<Uuid, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    parse_uuid(&value).map_err(serde::de::Error::custom)
+}
+
+/// The same for a list of uuids.
+pub(crate) mod list {
+    use serde::ser::SerializeSeq;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use uuid::Uuid;
+
+    pub(crate) fn serialize<S>(uuids: &[Uuid], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut seq = serializer.serialize_seq(Some(uuids.len()))?;
+        for uuid in uuids {
+            seq.serialize_element(&uuid.to_string())?;
+        }
+        seq.end()
+    }
+
+    pub(crate) fn deserialize<'de, D>(deserializer: D) -> Result<Vec<Uuid>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|value| super::parse_uuid(value).map_err(serde::de::Error::custom))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inner::conf::dto::service::ServiceConfigDto;
+
+    #[test]
+    fn test_parse_uuid() {
+        let battery_service: Uuid = "0000180f-0000-1000-8000-00805f9b34fb".parse().unwrap();
+        assert_eq!(parse_uuid("0x180F").unwrap(), battery_service);
+        assert_eq!(parse_uuid("0x180f").unwrap(), battery_service);
+        assert_eq!(
+            parse_uuid(" 0000180f-0000-1000-8000-00805f9b34fb ").unwrap(),
+            battery_service
+        );
+
+        assert_eq!(parse_uuid("0x11223344").unwrap(), uuid_from_u32(0x11223344));
+
+        assert!(parse_uuid("0x1234567").is_err());
+        assert!(parse_uuid("0xzz").is_err());
+        assert!(parse_uuid("180f").is_err());
+    }
+
+    #[test]
+    fn test_short_uuids_in_config() {
+        let service: ServiceConfigDto = serde_yaml::from_str(
+            r#"
+            uuid: '0x180F'
+            default_delay: 10s
+            default_history_size: 10
+            characteristics:
+              - !Poll
+                uuid: '0x2A19'
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(service.uuid, uuid_from_u16(0x180f));
+        assert_eq!(*service.characteristics[0].uuid(), uuid_from_u16(0x2a19));
+    }
+}
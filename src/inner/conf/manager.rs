@@ -1,17 +1,21 @@
 use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
+use crate::inner::conf::dto::adapter_filter::AdapterFilterDto;
+use crate::inner::conf::dto::collector_configuration::CollectorConfigurationDto;
 use crate::inner::conf::dto::peripheral::PeripheralConfigDto;
 use crate::inner::conf::model::flat_peripheral_config::FlatPeripheralConfig;
 use crate::inner::conf::traits::Evaluate;
 use tokio::sync::Mutex;
 
 use crate::inner::error::{CollectorError, CollectorResult};
+use crate::inner::metrics::CONFIGURATION_COUNT;
 use crate::inner::model::peripheral_key::PeripheralKey;
 
 #[derive(Default)]
 pub(crate) struct ConfigurationManager {
     peripheral_map: Arc<Mutex<HashMap<Arc<String>, Arc<FlatPeripheralConfig>>>>,
+    adapter_filters: Arc<Mutex<Vec<AdapterFilterDto>>>,
 }
 
 impl ConfigurationManager {
@@ -31,29 +35,62 @@ impl ConfigurationManager {
             }
         }
 
-        for peripheral_config in peripheral_configs {
-            let flat_conf = FlatPeripheralConfig::try_from(peripheral_config)?;
+        // all or nothing, so the count doesn't drift
+        let flat_confs = peripheral_configs
+            .into_iter()
+            .map(FlatPeripheralConfig::try_from)
+            .collect::<CollectorResult<Vec<_>>>()?;
+        for flat_conf in flat_confs {
             existing_services.insert(flat_conf.name.clone(), Arc::new(flat_conf));
         }
+        CONFIGURATION_COUNT.gauge(existing_services.len() as f64);
 
         Ok(())
     }
+    pub(crate) async fn add_adapter_filters(&self, adapter_filters: Vec<AdapterFilterDto>) {
+        self.adapter_filters.lock().await.extend(adapter_filters);
+    }
+    /// Checks the adapter allow / deny lists; peripherals that are not allowed must not be matched against configs.
+    pub(crate) async fn is_peripheral_allowed(&self, peripheral_key: &PeripheralKey) -> bool {
+        let adapter_filters = self.adapter_filters.lock().await;
+        adapter_filters.iter().all(|filter| filter.evaluate(peripheral_key))
+    }
     pub(crate) async fn add_peripheral_config(&self, peripheral_config: PeripheralConfigDto) -> CollectorResult<()> {
-        let existing_services = self.peripheral_map.lock().await;
+        let mut existing_services = self.peripheral_map.lock().await;
         if existing_services.contains_key(&peripheral_config.name) {
             return Err(CollectorError::DuplicateConfiguration(peripheral_config.name));
         }
         let flat_conf = FlatPeripheralConfig::try_from(peripheral_config)?;
-        self.peripheral_map
-            .lock()
-            .await
-            .insert(flat_conf.name.clone(), Arc::new(flat_conf));
+        existing_services.insert(flat_conf.name.clone(), Arc::new(flat_conf));
+        CONFIGURATION_COUNT.gauge(existing_services.len() as f64);
         Ok(())
     }
     pub(crate) async fn list_peripheral_configs(&self) -> Vec<Arc<FlatPeripheralConfig>> {
         let services = self.peripheral_map.lock().await;
         services.values().cloned().collect()
     }
+    pub(crate) async fn get_peripheral_config(&self, name: &str) -> Option<Arc<FlatPeripheralConfig>> {
+        let services = self.peripheral_map.lock().await;
+        services.get(&name.to_string()).cloned()
+    }
+    /// Rebuilds the configuration file from the loaded configs, i.e. for backups. MQTT targets are not managed here and
+    /// are left out.
+    pub(crate) async fn export(&self) -> CollectorConfigurationDto {
+        let mut peripherals: Vec<_> = self
+            .peripheral_map
+            .lock()
+            .await
+            .values()
+            .map(|conf| PeripheralConfigDto::from(conf.as_ref()))
+            .collect();
+        peripherals.sort_unstable_by(|left, right| left.name.cmp(&right.name));
+
+        CollectorConfigurationDto {
+            peripherals,
+            mqtt_targets: vec![],
+            adapter_filters: self.adapter_filters.lock().await.clone(),
+        }
+    }
     pub(crate) async fn get_matching_config(
         &self,
         peripheral_key: &PeripheralKey,
@@ -64,4 +101,178 @@ impl ConfigurationManager {
             .find(|conf| conf.evaluate(peripheral_key))
             .cloned()
     }
+    pub(crate) async fn get_all_matching_configs(
+        &self,
+        peripheral_key: &PeripheralKey,
+    ) -> Vec<Arc<FlatPeripheralConfig>> {
+        let peripheral_map = self.peripheral_map.lock().await;
+        let mut configs: Vec<_> = peripheral_map
+            .values()
+            .filter(|conf| conf.evaluate(peripheral_key))
+            .cloned()
+            .collect();
+        configs.sort_unstable_by(|left, right| left.name.cmp(&right.name));
+        configs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inner::conf::dto::adapter_filter::PeripheralFilterDto;
+    use crate::inner::conf::model::filter::Filter;
+
+    fn peripheral_config(name: &str, device_name: Filter) -> PeripheralConfigDto {
+        PeripheralConfigDto {
+            name: name.to_string(),
+            adapter: None,
+            device_id: None,
+            device_name: Some(device_name),
+            on_connect: vec![],
+            persistent: false,
+            max_reconnect_attempts: None,
+            discovery_filter: None,
+            services: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_all_matching_configs() {
+        let manager = ConfigurationManager::default();
+        manager
+            .add_peripherals(vec![
+                peripheral_config("prefix", Filter::StartsWith("Sensor".to_string())),
+                peripheral_config("suffix", Filter::EndsWith("Hub".to_string())),
+                peripheral_config("other", Filter::Equals("Thermostat".to_string())),
+            ])
+            .await
+            .unwrap();
+
+        let peripheral_key = PeripheralKey {
+            adapter_id: "hci0".to_string(),
+            peripheral_address: "11:22:33:44:55:66".parse().unwrap(),
+            name: Some("Sensor Hub".to_string()),
+        };
+
+        let names: Vec<_> = manager
+            .get_all_matching_configs(&peripheral_key)
+            .await
+            .into_iter()
+            .map(|conf| conf.name.to_string())
+            .collect();
+        assert_eq!(names, vec!["prefix", "suffix"]);
+        assert!(manager.get_matching_config(&peripheral_key).await.is_some());
+
+        let peripheral_key = PeripheralKey {
+            name: Some("Unknown".to_string()),
+            ..peripheral_key
+        };
+        assert!(manager.get_all_matching_configs(&peripheral_key).await.is_empty());
+        assert!(manager.get_matching_config(&peripheral_key).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_get_peripheral_config() {
+        let manager = ConfigurationManager::default();
+        manager
+            .add_peripherals(vec![peripheral_config(
+                "sensor",
+                Filter::StartsWith("Sensor".to_string()),
+            )])
+            .await
+            .unwrap();
+
+        let config = manager.get_peripheral_config("sensor").await.unwrap();
+        assert_eq!(config.name.as_str(), "sensor");
+        assert!(manager.get_peripheral_config("missing").await.is_none());
+    }
+
+    async fn load(config: CollectorConfigurationDto) -> ConfigurationManager {
+        let manager = ConfigurationManager::default();
+        manager.add_peripherals(config.peripherals).await.unwrap();
+        manager.add_adapter_filters(config.adapter_filters).await;
+        manager
+    }
+
+    #[tokio::test]
+    async fn test_export_round_trip() {
+        let example = include_str!("../../../example.yaml");
+        let manager = load(serde_yaml::from_str(example).unwrap()).await;
+
+        let exported = serde_yaml::to_string(&manager.export().await).unwrap();
+        let reloaded = load(serde_yaml::from_str(&exported).unwrap()).await;
+
+        let sorted = |mut configs: Vec<Arc<FlatPeripheralConfig>>| {
+            configs.sort_unstable_by(|left, right| left.name.cmp(&right.name));
+            configs
+        };
+        let original = sorted(manager.list_peripheral_configs().await);
+        assert!(!original.is_empty());
+        assert_eq!(original, sorted(reloaded.list_peripheral_configs().await));
+        assert_eq!(
+            *manager.adapter_filters.lock().await,
+            *reloaded.adapter_filters.lock().await
+        );
+
+        // exporting is stable
+        assert_eq!(exported, serde_yaml::to_string(&reloaded.export().await).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_is_peripheral_allowed() {
+        let manager = ConfigurationManager::default();
+        let peripheral_key = PeripheralKey {
+            adapter_id: "hci1".to_string(),
+            peripheral_address: "11:22:33:44:55:66".parse().unwrap(),
+            name: Some("Phone".to_string()),
+        };
+        assert!(manager.is_peripheral_allowed(&peripheral_key).await);
+
+        manager
+            .add_adapter_filters(vec![AdapterFilterDto {
+                adapter: Filter::Equals("hci1".to_string()),
+                allow: vec![PeripheralFilterDto {
+                    device_id: None,
+                    device_name: Some(Filter::StartsWith("Sensor".to_string())),
+                }],
+                deny: vec![],
+            }])
+            .await;
+        assert!(!manager.is_peripheral_allowed(&peripheral_key).await);
+
+        let peripheral_key = PeripheralKey {
+            name: Some("Sensor Hub".to_string()),
+            ..peripheral_key
+        };
+        assert!(manager.is_peripheral_allowed(&peripheral_key).await);
+    }
+
+    #[test]
+    fn test_configuration_count() {
+        use crate::inner::metrics::testing::MetricsCapture;
+        use metrics_util::debugging::DebugValue;
+
+        let metrics = MetricsCapture::new();
+        let manager = ConfigurationManager::default();
+        let count = || metrics.values().remove(CONFIGURATION_COUNT.metric_name);
+
+        metrics
+            .run(manager.add_peripherals(vec![
+                peripheral_config("first", Filter::Equals("A".to_string())),
+                peripheral_config("second", Filter::Equals("B".to_string())),
+            ]))
+            .unwrap();
+        assert_eq!(count(), Some(DebugValue::Gauge(2.0.into())));
+
+        metrics
+            .run(manager.add_peripheral_config(peripheral_config("third", Filter::Equals("C".to_string()))))
+            .unwrap();
+        assert_eq!(count(), Some(DebugValue::Gauge(3.0.into())));
+
+        // a rejected config leaves the count as is
+        let duplicate =
+            metrics.run(manager.add_peripheral_config(peripheral_config("third", Filter::Equals("C".to_string()))));
+        assert!(duplicate.is_err());
+        assert_eq!(count(), Some(DebugValue::Gauge(3.0.into())));
+    }
 }
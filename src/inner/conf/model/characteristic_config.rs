@@ -1,10 +1,11 @@
 use std::sync::Arc;
 use std::time::Duration;
 
-use crate::inner::conf::dto::characteristic::CharacteristicConfigDto;
+use crate::inner::conf::dto::characteristic::{AdvertisementSource, CharacteristicConfigDto};
 use crate::inner::conf::dto::publish::{PublishMetricConfigDto, PublishMqttConfigDto};
 use crate::inner::conf::dto::service::ServiceConfigDto;
 use crate::inner::conv::converter::Converter;
+use btleplug::api::CharPropFlags;
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
 use serde_with::DurationSeconds;
@@ -21,8 +22,12 @@ pub(crate) enum CharacteristicConfig {
         service_uuid: Uuid,
         uuid: Uuid,
         history_size: usize,
+        #[serde_as(as = "Option<DurationSeconds>")]
+        history_window_sec: Option<Duration>,
         #[serde(default)]
         converter: Converter,
+        #[serde(default)]
+        record_raw_bytes: bool,
         publish_metrics: Option<PublishMetricConfigDto>,
         publish_mqtt: Option<PublishMqttConfigDto>,
     },
@@ -34,8 +39,30 @@ pub(crate) enum CharacteristicConfig {
         #[serde_as(as = "DurationSeconds")]
         delay_sec: Duration,
         history_size: usize,
+        #[serde_as(as = "Option<DurationSeconds>")]
+        history_window_sec: Option<Duration>,
         #[serde(default)]
         converter: Converter,
+        #[serde(default)]
+        record_raw_bytes: bool,
+        publish_metrics: Option<PublishMetricConfigDto>,
+        publish_mqtt: Option<PublishMqttConfigDto>,
+    },
+    Broadcast {
+        name: Option<Arc<String>>,
+        service_name: Option<Arc<String>>,
+        service_uuid: Uuid,
+        uuid: Uuid,
+        source: AdvertisementSource,
+        offset: usize,
+        length: Option<usize>,
+        history_size: usize,
+        #[serde_as(as = "Option<DurationSeconds>")]
+        history_window_sec: Option<Duration>,
+        #[serde(default)]
+        converter: Converter,
+        #[serde(default)]
+        record_raw_bytes: bool,
         publish_metrics: Option<PublishMetricConfigDto>,
         publish_mqtt: Option<PublishMqttConfigDto>,
     },
@@ -53,7 +80,9 @@ impl TryFrom<(&CharacteristicConfigDto, &ServiceConfigDto)> for CharacteristicCo
                 name,
                 uuid,
                 history_size,
+                history_window,
                 converter,
+                record_raw_bytes,
                 publish_metrics,
                 publish_mqtt,
             } => Ok(CharacteristicConfig::Subscribe {
@@ -62,7 +91,9 @@ impl TryFrom<(&CharacteristicConfigDto, &ServiceConfigDto)> for CharacteristicCo
                 service_uuid,
                 uuid: *uuid,
                 history_size: history_size.unwrap_or(service_conf.default_history_size),
+                history_window_sec: *history_window,
                 converter: converter.clone(),
+                record_raw_bytes: *record_raw_bytes,
                 publish_metrics: publish_metrics.clone(),
                 publish_mqtt: publish_mqtt.clone(),
             }),
@@ -71,7 +102,9 @@ impl TryFrom<(&CharacteristicConfigDto, &ServiceConfigDto)> for CharacteristicCo
                 uuid,
                 delay: delay_sec,
                 history_size,
+                history_window,
                 converter,
+                record_raw_bytes,
                 publish_metrics,
                 publish_mqtt,
             } => Ok(CharacteristicConfig::Poll {
@@ -81,10 +114,115 @@ impl TryFrom<(&CharacteristicConfigDto, &ServiceConfigDto)> for CharacteristicCo
                 service_uuid,
                 delay_sec: delay_sec.unwrap_or(service_conf.default_delay),
                 history_size: history_size.unwrap_or(service_conf.default_history_size),
+                history_window_sec: *history_window,
                 converter: converter.clone(),
+                record_raw_bytes: *record_raw_bytes,
                 publish_metrics: publish_metrics.clone(),
                 publish_mqtt: publish_mqtt.clone(),
             }),
+            CharacteristicConfigDto::Broadcast {
+                name,
+                uuid,
+                source,
+                offset,
+                length,
+                history_size,
+                history_window,
+                converter,
+                record_raw_bytes,
+                publish_metrics,
+                publish_mqtt,
+            } => Ok(CharacteristicConfig::Broadcast {
+                name: name.clone(),
+                service_name,
+                service_uuid,
+                uuid: *uuid,
+                source: *source,
+                offset: *offset,
+                length: *length,
+                history_size: history_size.unwrap_or(service_conf.default_history_size),
+                history_window_sec: *history_window,
+                converter: converter.clone(),
+                record_raw_bytes: *record_raw_bytes,
+                publish_metrics: publish_metrics.clone(),
+                publish_mqtt: publish_mqtt.clone(),
+            }),
+        }
+    }
+}
+
+/// The reverse of the conversion above, with the service defaults written out for every characteristic.
+impl From<&CharacteristicConfig> for CharacteristicConfigDto {
+    fn from(value: &CharacteristicConfig) -> Self {
+        match value.clone() {
+            CharacteristicConfig::Subscribe {
+                name,
+                uuid,
+                history_size,
+                history_window_sec,
+                converter,
+                record_raw_bytes,
+                publish_metrics,
+                publish_mqtt,
+                ..
+            } => CharacteristicConfigDto::Subscribe {
+                name,
+                uuid,
+                history_size: Some(history_size),
+                history_window: history_window_sec,
+                converter,
+                record_raw_bytes,
+                publish_metrics,
+                publish_mqtt,
+            },
+            CharacteristicConfig::Poll {
+                characteristic_name,
+                uuid,
+                delay_sec,
+                history_size,
+                history_window_sec,
+                converter,
+                record_raw_bytes,
+                publish_metrics,
+                publish_mqtt,
+                ..
+            } => CharacteristicConfigDto::Poll {
+                name: characteristic_name,
+                uuid,
+                delay: Some(delay_sec),
+                history_size: Some(history_size),
+                history_window: history_window_sec,
+                converter,
+                record_raw_bytes,
+                publish_metrics,
+                publish_mqtt,
+            },
+            CharacteristicConfig::Broadcast {
+                name,
+                uuid,
+                source,
+                offset,
+                length,
+                history_size,
+                history_window_sec,
+                converter,
+                record_raw_bytes,
+                publish_metrics,
+                publish_mqtt,
+                ..
+            } => CharacteristicConfigDto::Broadcast {
+                name,
+                uuid,
+                source,
+                offset,
+                length,
+                history_size: Some(history_size),
+                history_window: history_window_sec,
+                converter,
+                record_raw_bytes,
+                publish_metrics,
+                publish_mqtt,
+            },
         }
     }
 }
@@ -97,6 +235,7 @@ impl CharacteristicConfig {
                 characteristic_name: name,
                 ..
             } => name.clone(),
+            CharacteristicConfig::Broadcast { name, .. } => name.clone(),
         }
     }
 
@@ -104,19 +243,81 @@ impl CharacteristicConfig {
         match self {
             CharacteristicConfig::Subscribe { history_size, .. } => *history_size,
             CharacteristicConfig::Poll { history_size, .. } => *history_size,
+            CharacteristicConfig::Broadcast { history_size, .. } => *history_size,
         }
     }
+    pub(crate) fn is_broadcast(&self) -> bool {
+        matches!(self, CharacteristicConfig::Broadcast { .. })
+    }
+
+    /// Checks that the characteristic can be subscribed to (NOTIFY / INDICATE) or polled (READ).
+    pub(crate) fn is_supported_by(&self, properties: CharPropFlags) -> bool {
+        match self {
+            CharacteristicConfig::Subscribe { .. } => {
+                properties.intersects(CharPropFlags::NOTIFY | CharPropFlags::INDICATE)
+            }
+            CharacteristicConfig::Poll { .. } => properties.contains(CharPropFlags::READ),
+            CharacteristicConfig::Broadcast { .. } => false,
+        }
+    }
+
+    /// Returns a copy bound to a concrete characteristic, used to resolve wildcard configs.
+    pub(crate) fn with_uuid(&self, characteristic_uuid: Uuid) -> Self {
+        let mut conf = self.clone();
+        match &mut conf {
+            CharacteristicConfig::Subscribe { uuid, .. } => *uuid = characteristic_uuid,
+            CharacteristicConfig::Poll { uuid, .. } => *uuid = characteristic_uuid,
+            CharacteristicConfig::Broadcast { uuid, .. } => *uuid = characteristic_uuid,
+        }
+        conf
+    }
+
+    pub(crate) fn history_window(&self) -> Option<Duration> {
+        match self {
+            CharacteristicConfig::Subscribe { history_window_sec, .. } => *history_window_sec,
+            CharacteristicConfig::Poll { history_window_sec, .. } => *history_window_sec,
+            CharacteristicConfig::Broadcast { history_window_sec, .. } => *history_window_sec,
+        }
+    }
+
     pub(crate) fn service_name(&self) -> Option<Arc<String>> {
         match self {
             CharacteristicConfig::Subscribe { service_name, .. } => service_name.clone(),
             CharacteristicConfig::Poll { service_name, .. } => service_name.clone(),
+            CharacteristicConfig::Broadcast { service_name, .. } => service_name.clone(),
+        }
+    }
+
+    pub(crate) fn converter(&self) -> &Converter {
+        match self {
+            CharacteristicConfig::Subscribe { converter, .. } => converter,
+            CharacteristicConfig::Poll { converter, .. } => converter,
+            CharacteristicConfig::Broadcast { converter, .. } => converter,
         }
     }
 
+    pub(crate) fn record_raw_bytes(&self) -> bool {
+        match self {
+            CharacteristicConfig::Subscribe { record_raw_bytes, .. } => *record_raw_bytes,
+            CharacteristicConfig::Poll { record_raw_bytes, .. } => *record_raw_bytes,
+            CharacteristicConfig::Broadcast { record_raw_bytes, .. } => *record_raw_bytes,
+        }
+    }
+
+    /// Raw bytes are kept in the payload if either the API or the MQTT state messages need them.
+    pub(crate) fn captures_raw_bytes(&self) -> bool {
+        self.record_raw_bytes()
+            || self
+                .publish_mqtt()
+                .map(|mqtt_conf| mqtt_conf.include_raw_service_data)
+                .unwrap_or(false)
+    }
+
     pub(crate) fn publish_metrics(&self) -> Option<&PublishMetricConfigDto> {
         match self {
             CharacteristicConfig::Subscribe { publish_metrics, .. } => publish_metrics.as_ref(),
             CharacteristicConfig::Poll { publish_metrics, .. } => publish_metrics.as_ref(),
+            CharacteristicConfig::Broadcast { publish_metrics, .. } => publish_metrics.as_ref(),
         }
     }
 
@@ -124,6 +325,7 @@ impl CharacteristicConfig {
         match self {
             CharacteristicConfig::Subscribe { publish_mqtt, .. } => publish_mqtt.as_ref(),
             CharacteristicConfig::Poll { publish_mqtt, .. } => publish_mqtt.as_ref(),
+            CharacteristicConfig::Broadcast { publish_mqtt, .. } => publish_mqtt.as_ref(),
         }
     }
 }
@@ -0,0 +1,85 @@
+use btleplug::api::PeripheralProperties;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::inner::conf::dto::short_uuid;
+use crate::inner::conf::traits::Evaluate;
+
+/// Checked against the advertised properties of a matching peripheral before connecting to it,
+/// i.e. `discovery_filter: !MinRssi -80`.
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub(crate) enum DiscoveryFilter {
+    /// The signal must be at least this strong; peripherals without a known RSSI don't pass.
+    MinRssi(i16),
+    /// Every listed service must be advertised.
+    RequiredServices(#[serde(with = "short_uuid::list")] Vec<Uuid>),
+    /// Manufacturer data must be advertised under this company id.
+    RequiredManufacturerId(u16),
+}
+
+impl Evaluate<&PeripheralProperties, bool> for DiscoveryFilter {
+    fn evaluate(&self, source: &PeripheralProperties) -> bool {
+        match self {
+            DiscoveryFilter::MinRssi(min_rssi) => source.rssi.is_some_and(|rssi| rssi >= *min_rssi),
+            DiscoveryFilter::RequiredServices(services) => {
+                services.iter().all(|service| source.services.contains(service))
+            }
+            DiscoveryFilter::RequiredManufacturerId(manufacturer_id) => {
+                source.manufacturer_data.contains_key(manufacturer_id)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use btleplug::api::bleuuid::uuid_from_u16;
+
+    use super::*;
+
+    #[test]
+    fn test_evaluate() {
+        let properties = PeripheralProperties {
+            rssi: Some(-70),
+            services: vec![uuid_from_u16(0x180f), uuid_from_u16(0x181a)],
+            manufacturer_data: [(0x0499, vec![0x01])].into(),
+            ..Default::default()
+        };
+
+        assert!(DiscoveryFilter::MinRssi(-80).evaluate(&properties));
+        assert!(DiscoveryFilter::MinRssi(-70).evaluate(&properties));
+        assert!(!DiscoveryFilter::MinRssi(-60).evaluate(&properties));
+        assert!(!DiscoveryFilter::MinRssi(-80).evaluate(&PeripheralProperties::default()));
+
+        assert!(DiscoveryFilter::RequiredServices(vec![uuid_from_u16(0x181a)]).evaluate(&properties));
+        assert!(DiscoveryFilter::RequiredServices(vec![]).evaluate(&properties));
+        assert!(
+            !DiscoveryFilter::RequiredServices(vec![uuid_from_u16(0x181a), uuid_from_u16(0x180a)])
+                .evaluate(&properties)
+        );
+
+        assert!(DiscoveryFilter::RequiredManufacturerId(0x0499).evaluate(&properties));
+        assert!(!DiscoveryFilter::RequiredManufacturerId(0x004c).evaluate(&properties));
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let filters: Vec<DiscoveryFilter> = serde_yaml::from_str(
+            r#"
+            - !MinRssi -80
+            - !RequiredServices ['0x180F', '0000181a-0000-1000-8000-00805f9b34fb']
+            - !RequiredManufacturerId 0x0499
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            filters,
+            vec![
+                DiscoveryFilter::MinRssi(-80),
+                DiscoveryFilter::RequiredServices(vec![uuid_from_u16(0x180f), uuid_from_u16(0x181a)]),
+                DiscoveryFilter::RequiredManufacturerId(0x0499),
+            ]
+        );
+    }
+}
@@ -1,18 +1,139 @@
+use std::fmt::{Display, Formatter};
+
 use crate::inner::conf::traits::Evaluate;
 use regex::Regex;
-use serde::{Deserialize, Serialize};
+use serde::de::{EnumAccess, Error, MapAccess, VariantAccess, Visitor};
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Serialized as `{"type": "contains", "value": "hci0"}`. Deserialization also accepts the config file
+/// format, i.e. `!Contains hci0` or `{"Contains": "hci0"}`.
+#[derive(Debug, Clone)]
 pub(crate) enum Filter {
     Contains(String),
     StartsWith(String),
     EndsWith(String),
     Equals(String),
     NotEquals(String),
-    #[serde(with = "serde_regex")]
     Regex(Regex),
 }
 
+impl Filter {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Filter::Contains(_) => "contains",
+            Filter::StartsWith(_) => "starts_with",
+            Filter::EndsWith(_) => "ends_with",
+            Filter::Equals(_) => "equals",
+            Filter::NotEquals(_) => "not_equals",
+            Filter::Regex(_) => "regex",
+        }
+    }
+
+    fn value(&self) -> &str {
+        match self {
+            Filter::Contains(value)
+            | Filter::StartsWith(value)
+            | Filter::EndsWith(value)
+            | Filter::Equals(value)
+            | Filter::NotEquals(value) => value,
+            Filter::Regex(value) => value.as_str(),
+        }
+    }
+
+    fn from_parts<E: Error>(type_name: &str, value: String) -> Result<Self, E> {
+        let filter = match type_name {
+            "Contains" | "contains" => Filter::Contains(value),
+            "StartsWith" | "starts_with" => Filter::StartsWith(value),
+            "EndsWith" | "ends_with" => Filter::EndsWith(value),
+            "Equals" | "equals" => Filter::Equals(value),
+            "NotEquals" | "not_equals" => Filter::NotEquals(value),
+            "Regex" | "regex" => Filter::Regex(Regex::new(&value).map_err(E::custom)?),
+            unknown => return Err(E::unknown_variant(unknown, FILTER_TYPES)),
+        };
+        Ok(filter)
+    }
+}
+
+/// A short form for log messages, i.e. `starts_with("Sensor Hub")`.
+impl Display for Filter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}({:?})", self.type_name(), self.value())
+    }
+}
+
+const FILTER_TYPES: &[&str] = &["contains", "starts_with", "ends_with", "equals", "not_equals", "regex"];
+
+impl Serialize for Filter {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("Filter", 2)?;
+        state.serialize_field("type", self.type_name())?;
+        state.serialize_field("value", self.value())?;
+        state.end()
+    }
+}
+
+struct FilterVisitor;
+
+impl<'de> Visitor<'de> for FilterVisitor {
+    type Value = Filter;
+
+    fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
+        formatter.write_str("a filter like `!Contains value` or `{type: contains, value: value}`")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut entries = vec![];
+        while let Some(entry) = map.next_entry::<String, String>()? {
+            entries.push(entry);
+        }
+
+        if let [(type_name, value)] = entries.as_slice() {
+            if type_name != "type" && type_name != "value" {
+                return Filter::from_parts(type_name, value.clone());
+            }
+        }
+
+        let mut type_name = None;
+        let mut value = None;
+        for (key, entry_value) in entries {
+            match key.as_str() {
+                "type" => type_name = Some(entry_value),
+                "value" => value = Some(entry_value),
+                unknown => return Err(A::Error::unknown_field(unknown, &["type", "value"])),
+            }
+        }
+
+        let type_name = type_name.ok_or_else(|| A::Error::missing_field("type"))?;
+        let value = value.ok_or_else(|| A::Error::missing_field("value"))?;
+        Filter::from_parts(&type_name, value)
+    }
+
+    fn visit_enum<A>(self, data: A) -> Result<Self::Value, A::Error>
+    where
+        A: EnumAccess<'de>,
+    {
+        let (type_name, variant) = data.variant::<String>()?;
+        let value = variant.newtype_variant::<String>()?;
+        Filter::from_parts(&type_name, value)
+    }
+}
+
+impl<'de> Deserialize<'de> for Filter {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(FilterVisitor)
+    }
+}
+
 impl PartialEq<Self> for Filter {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
@@ -41,3 +162,63 @@ impl Evaluate<&str, bool> for Filter {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_serialize() {
+        assert_eq!(
+            serde_json::to_value(Filter::Contains("hci0".to_string())).unwrap(),
+            json!({"type": "contains", "value": "hci0"})
+        );
+        assert_eq!(
+            serde_json::to_value(Filter::Regex(Regex::new("^Sensor.*$").unwrap())).unwrap(),
+            json!({"type": "regex", "value": "^Sensor.*$"})
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            Filter::StartsWith("Sensor Hub".to_string()).to_string(),
+            r#"starts_with("Sensor Hub")"#
+        );
+        assert_eq!(
+            Filter::Regex(Regex::new("^hci[0-9]$").unwrap()).to_string(),
+            r#"regex("^hci[0-9]$")"#
+        );
+    }
+
+    #[test]
+    fn test_deserialize_legacy_format() {
+        let filter: Filter = serde_yaml::from_str("!StartsWith 'Sensor Hub'").unwrap();
+        assert_eq!(filter, Filter::StartsWith("Sensor Hub".to_string()));
+
+        let filter: Filter = serde_yaml::from_str("NotEquals: hci1").unwrap();
+        assert_eq!(filter, Filter::NotEquals("hci1".to_string()));
+
+        let filter: Filter = serde_json::from_value(json!({"Regex": "^hci[0-9]$"})).unwrap();
+        assert_eq!(filter, Filter::Regex(Regex::new("^hci[0-9]$").unwrap()));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let filters = [
+            Filter::EndsWith("Hub".to_string()),
+            Filter::Regex(Regex::new("^hci[0-9]$").unwrap()),
+        ];
+        for filter in filters {
+            let json = serde_json::to_string(&filter).unwrap();
+            assert_eq!(serde_json::from_str::<Filter>(&json).unwrap(), filter);
+
+            let yaml = serde_yaml::to_string(&filter).unwrap();
+            assert_eq!(serde_yaml::from_str::<Filter>(&yaml).unwrap(), filter);
+        }
+
+        assert!(serde_json::from_value::<Filter>(json!({"type": "unknown", "value": "x"})).is_err());
+        assert!(serde_json::from_value::<Filter>(json!({"type": "regex", "value": "("})).is_err());
+    }
+}
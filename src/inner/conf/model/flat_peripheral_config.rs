@@ -1,24 +1,32 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::{Display, Formatter};
 use std::sync::Arc;
 
 use btleplug::api::Characteristic;
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
-use crate::inner::conf::dto::peripheral::PeripheralConfigDto;
+use crate::inner::conf::dto::characteristic::CharacteristicConfigDto;
+use crate::inner::conf::dto::peripheral::{OnConnectWriteDto, PeripheralConfigDto};
 use crate::inner::conf::dto::service::ServiceConfigDto;
 use crate::inner::conf::model::characteristic_config::CharacteristicConfig;
+use crate::inner::conf::model::discovery_filter::DiscoveryFilter;
 use crate::inner::conf::model::filter::Filter;
 use crate::inner::conf::model::service_characteristic_key::ServiceCharacteristicKey;
 use crate::inner::conf::traits::Evaluate;
 use crate::inner::error::{CollectorError, CollectorResult};
 use crate::inner::model::peripheral_key::PeripheralKey;
 
-#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct FlatPeripheralConfig {
     pub(crate) name: Arc<String>,
     pub(crate) adapter: Option<Filter>,
     pub(crate) device_id: Option<Filter>,
     pub(crate) device_name: Option<Filter>,
+    pub(crate) on_connect: Vec<OnConnectWriteDto>,
+    pub(crate) persistent: bool,
+    pub(crate) max_reconnect_attempts: Option<u32>,
+    pub(crate) discovery_filter: Option<DiscoveryFilter>,
 
     pub(crate) service_map: HashMap<ServiceCharacteristicKey, Arc<CharacteristicConfig>>,
 }
@@ -43,6 +51,7 @@ impl FlatPeripheralConfig {
 
         for char_conf_dto in &service.characteristics {
             let flat_char_conf = CharacteristicConfig::try_from((char_conf_dto, &service))?;
+            flat_char_conf.converter().validate()?;
             let key = ServiceCharacteristicKey {
                 service_uuid,
                 characteristic_uuid: *char_conf_dto.uuid(),
@@ -52,12 +61,84 @@ impl FlatPeripheralConfig {
 
         Ok(())
     }
+    /// Explicit characteristic configs take precedence over the service wildcard (`uuid: '*'`).
+    /// Broadcast configs are never bound to GATT characteristics.
     pub(crate) fn get_conf(&self, characteristic: &Characteristic) -> Option<Arc<CharacteristicConfig>> {
         let char_key = ServiceCharacteristicKey::from(characteristic);
-        self.service_map.get(&char_key).cloned()
+        if let Some(conf) = self.service_map.get(&char_key) {
+            return (!conf.is_broadcast()).then(|| Arc::clone(conf));
+        }
+
+        let wildcard_key = ServiceCharacteristicKey::wildcard(characteristic.service_uuid);
+        self.service_map
+            .get(&wildcard_key)
+            .filter(|conf| !conf.is_broadcast())
+            .map(|conf| Arc::new(conf.with_uuid(characteristic.uuid)))
+    }
+
+    pub(crate) fn broadcast_confs(&self) -> impl Iterator<Item = &Arc<CharacteristicConfig>> {
+        self.service_map.values().filter(|conf| conf.is_broadcast())
+    }
+
+    /// The peripherals that the filters of the config select, i.e. the targets of a bulk write.
+    pub(crate) fn select_matching<'a>(
+        &'a self,
+        peripheral_keys: impl IntoIterator<Item = PeripheralKey> + 'a,
+    ) -> impl Iterator<Item = PeripheralKey> + 'a {
+        peripheral_keys
+            .into_iter()
+            .filter(|peripheral_key| self.evaluate(peripheral_key))
+    }
+
+    /// A peripheral with only broadcast characteristics is never connected to.
+    pub(crate) fn is_broadcast_only(&self) -> bool {
+        !self.service_map.is_empty() && self.service_map.values().all(|conf| conf.is_broadcast())
+    }
+}
+
+/// Shows the config name with its filters, i.e. `Sensor Hub[adapter=equals("hci0"), device_name=starts_with("Sensor")]`.
+impl Display for FlatPeripheralConfig {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let filters = [
+            ("adapter", &self.adapter),
+            ("device_id", &self.device_id),
+            ("device_name", &self.device_name),
+        ]
+        .into_iter()
+        .filter_map(|(name, filter)| filter.as_ref().map(|filter| format!("{name}={filter}")))
+        .collect::<Vec<_>>();
+        write!(f, "{}[{}]", self.name, filters.join(", "))
+    }
+}
+
+/// Compares configs by content, so that configs loaded separately (i.e. on reload) are equal if nothing changed.
+impl PartialEq for FlatPeripheralConfig {
+    fn eq(&self, other: &Self) -> bool {
+        if self.name.as_str() != other.name.as_str()
+            || self.adapter != other.adapter
+            || self.device_id != other.device_id
+            || self.device_name != other.device_name
+            || self.on_connect != other.on_connect
+            || self.persistent != other.persistent
+            || self.max_reconnect_attempts != other.max_reconnect_attempts
+            || self.discovery_filter != other.discovery_filter
+            || self.service_map.len() != other.service_map.len()
+        {
+            return false;
+        }
+
+        self.service_map.iter().all(|(key, char_conf)| {
+            other
+                .service_map
+                .get(key)
+                .map(|other_char_conf| char_conf.as_ref() == other_char_conf.as_ref())
+                .unwrap_or(false)
+        })
     }
 }
 
+impl Eq for FlatPeripheralConfig {}
+
 impl TryFrom<PeripheralConfigDto> for FlatPeripheralConfig {
     type Error = CollectorError;
 
@@ -67,6 +148,10 @@ impl TryFrom<PeripheralConfigDto> for FlatPeripheralConfig {
             adapter: value.adapter,
             device_id: value.device_id,
             device_name: value.device_name,
+            on_connect: value.on_connect,
+            persistent: value.persistent,
+            max_reconnect_attempts: value.max_reconnect_attempts,
+            discovery_filter: value.discovery_filter,
             service_map: Default::default(),
         };
 
@@ -78,6 +163,56 @@ impl TryFrom<PeripheralConfigDto> for FlatPeripheralConfig {
     }
 }
 
+/// Regroups the characteristics into services, i.e. to export the live configuration. The service defaults are
+/// already applied to every characteristic, so the exported characteristics set them explicitly.
+impl From<&FlatPeripheralConfig> for PeripheralConfigDto {
+    fn from(value: &FlatPeripheralConfig) -> Self {
+        let mut service_map: BTreeMap<Uuid, BTreeMap<Uuid, &Arc<CharacteristicConfig>>> = BTreeMap::new();
+        for (key, char_conf) in &value.service_map {
+            service_map
+                .entry(key.service_uuid)
+                .or_default()
+                .insert(key.characteristic_uuid, char_conf);
+        }
+
+        let services = service_map
+            .into_iter()
+            .map(|(service_uuid, char_confs)| {
+                let first = char_confs.values().next().copied();
+                let default_delay = char_confs
+                    .values()
+                    .find_map(|char_conf| match char_conf.as_ref() {
+                        CharacteristicConfig::Poll { delay_sec, .. } => Some(*delay_sec),
+                        _ => None,
+                    })
+                    .unwrap_or_default();
+                ServiceConfigDto {
+                    name: first.and_then(|char_conf| char_conf.service_name()),
+                    uuid: service_uuid,
+                    default_delay,
+                    default_history_size: first.map(|char_conf| char_conf.history_size()).unwrap_or_default(),
+                    characteristics: char_confs
+                        .into_values()
+                        .map(|char_conf| CharacteristicConfigDto::from(char_conf.as_ref()))
+                        .collect(),
+                }
+            })
+            .collect();
+
+        Self {
+            name: value.name.to_string(),
+            adapter: value.adapter.clone(),
+            device_id: value.device_id.clone(),
+            device_name: value.device_name.clone(),
+            on_connect: value.on_connect.clone(),
+            persistent: value.persistent,
+            max_reconnect_attempts: value.max_reconnect_attempts,
+            discovery_filter: value.discovery_filter.clone(),
+            services,
+        }
+    }
+}
+
 impl Evaluate<&PeripheralKey, bool> for FlatPeripheralConfig {
     fn evaluate(&self, source: &PeripheralKey) -> bool {
         let adapter_matches = self
@@ -101,3 +236,211 @@ impl Evaluate<&PeripheralKey, bool> for FlatPeripheralConfig {
         adapter_matches && device_id_matches && name_matches
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inner::conf::dto::collector_configuration::CollectorConfigurationDto;
+    use crate::inner::conv::converter::ConversionError;
+    use btleplug::api::CharPropFlags;
+    use uuid::Uuid;
+
+    fn load_example() -> Vec<FlatPeripheralConfig> {
+        let example = include_str!("../../../../example.yaml");
+        let config: CollectorConfigurationDto = serde_yaml::from_str(example).unwrap();
+        config
+            .peripherals
+            .into_iter()
+            .map(|peripheral| FlatPeripheralConfig::try_from(peripheral).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_separately_loaded_configs_are_equal() {
+        let left = load_example();
+        let right = load_example();
+        assert!(!left.is_empty());
+
+        for (left, right) in left.iter().zip(right.iter()) {
+            assert!(!Arc::ptr_eq(&left.name, &right.name));
+            assert_eq!(left, right);
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        let config: PeripheralConfigDto = serde_yaml::from_str(
+            r#"
+            name: 'Sensor Hub'
+            adapter: !Equals 'hci0'
+            device_name: !StartsWith 'Sensor'
+            services: []
+            "#,
+        )
+        .unwrap();
+        let config = FlatPeripheralConfig::try_from(config).unwrap();
+        assert_eq!(
+            config.to_string(),
+            r#"Sensor Hub[adapter=equals("hci0"), device_name=starts_with("Sensor")]"#
+        );
+
+        let config = FlatPeripheralConfig {
+            adapter: None,
+            device_name: None,
+            ..config
+        };
+        assert_eq!(config.to_string(), "Sensor Hub[]");
+    }
+
+    fn characteristic(service_uuid: Uuid, uuid: u128) -> Characteristic {
+        Characteristic {
+            uuid: Uuid::from_u128(uuid),
+            service_uuid,
+            properties: Default::default(),
+            descriptors: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_wildcard_characteristic() {
+        let config: PeripheralConfigDto = serde_yaml::from_str(
+            r#"
+            name: 'Sensor'
+            services:
+              - uuid: '0000180a-0000-1000-8000-00805f9b34fb'
+                default_delay: 60s
+                default_history_size: 10
+                characteristics:
+                  - !Subscribe
+                    name: 'Any'
+                    uuid: '*'
+                  - !Poll
+                    name: 'Explicit'
+                    uuid: '00000000-0000-0000-0000-000000000002'
+            "#,
+        )
+        .unwrap();
+        let service_uuid = config.services[0].uuid;
+        let config = FlatPeripheralConfig::try_from(config).unwrap();
+
+        for uuid in [1, 3] {
+            let conf = config.get_conf(&characteristic(service_uuid, uuid)).unwrap();
+            assert!(matches!(conf.as_ref(), CharacteristicConfig::Subscribe { .. }));
+            assert_eq!(conf.name().unwrap().as_str(), "Any");
+            assert!(
+                matches!(conf.as_ref(), CharacteristicConfig::Subscribe { uuid: conf_uuid, .. } if conf_uuid.as_u128() == uuid)
+            );
+        }
+
+        let explicit = config.get_conf(&characteristic(service_uuid, 2)).unwrap();
+        assert_eq!(explicit.name().unwrap().as_str(), "Explicit");
+
+        assert!(config.get_conf(&characteristic(Uuid::from_u128(42), 1)).is_none());
+    }
+
+    #[test]
+    fn test_unsupported_characteristic_is_skipped() {
+        let config: PeripheralConfigDto = serde_yaml::from_str(
+            r#"
+            name: 'Sensor'
+            services:
+              - uuid: '0000180a-0000-1000-8000-00805f9b34fb'
+                default_delay: 60s
+                default_history_size: 10
+                characteristics:
+                  - !Subscribe
+                    name: 'Notifying'
+                    uuid: '00000000-0000-0000-0000-000000000001'
+                  - !Poll
+                    name: 'Readable'
+                    uuid: '00000000-0000-0000-0000-000000000002'
+            "#,
+        )
+        .unwrap();
+        let service_uuid = config.services[0].uuid;
+        let config = FlatPeripheralConfig::try_from(config).unwrap();
+
+        let mut read_only = characteristic(service_uuid, 1);
+        read_only.properties = CharPropFlags::READ;
+        let subscribe_conf = config.get_conf(&read_only).unwrap();
+        assert!(!subscribe_conf.is_supported_by(read_only.properties));
+        assert!(subscribe_conf.is_supported_by(CharPropFlags::READ | CharPropFlags::INDICATE));
+
+        let mut write_only = characteristic(service_uuid, 2);
+        write_only.properties = CharPropFlags::WRITE;
+        let poll_conf = config.get_conf(&write_only).unwrap();
+        assert!(!poll_conf.is_supported_by(write_only.properties));
+        assert!(poll_conf.is_supported_by(CharPropFlags::READ));
+    }
+
+    fn load_with_converter(converter: &str) -> CollectorResult<FlatPeripheralConfig> {
+        let config: PeripheralConfigDto = serde_yaml::from_str(&format!(
+            r#"
+            name: 'Sensor'
+            services:
+              - uuid: '0000180a-0000-1000-8000-00805f9b34fb'
+                default_delay: 60s
+                default_history_size: 10
+                characteristics:
+                  - !Subscribe
+                    uuid: '00000000-0000-0000-0000-000000000001'
+                    converter: {converter}
+            "#
+        ))
+        .unwrap();
+        FlatPeripheralConfig::try_from(config)
+    }
+
+    #[test]
+    fn test_invalid_converter_is_rejected() {
+        assert!(load_with_converter("!Average { window: 3, inner: Raw }").is_ok());
+        for converter in [
+            "!Average { window: 0, inner: Raw }",
+            "!Chain [ Raw, !Negate { inner: !Average { window: 0, inner: Raw } } ]",
+        ] {
+            assert!(
+                matches!(
+                    load_with_converter(converter),
+                    Err(CollectorError::ConversionError(ConversionError::EmptyWindow(_)))
+                ),
+                "{converter}"
+            );
+        }
+
+        assert!(load_with_converter("!LinearMap { in_min: 0, in_max: 4095, out_min: 0, out_max: 3.3 }").is_ok());
+        assert!(matches!(
+            load_with_converter("!LinearMap { in_min: 1, in_max: 1, out_min: 0, out_max: 3.3 }"),
+            Err(CollectorError::ConversionError(ConversionError::EmptyRange(_)))
+        ));
+        for converter in [
+            "!LinearMap { in_min: 0, in_max: 4095, out_min: .nan, out_max: 3.3 }",
+            "!Chain [ Raw, !LinearMap { in_min: 0, in_max: .inf, out_min: 0, out_max: 1 } ]",
+        ] {
+            assert!(
+                matches!(
+                    load_with_converter(converter),
+                    Err(CollectorError::ConversionError(ConversionError::NonFiniteParameter(_)))
+                ),
+                "{converter}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_changed_configs_are_not_equal() {
+        let original = load_example().remove(0);
+
+        let mut renamed = original.clone();
+        renamed.name = Arc::new(format!("{}-renamed", original.name));
+        assert_ne!(original, renamed);
+
+        let mut filtered = original.clone();
+        filtered.device_name = Some(Filter::Regex("^changed$".parse().unwrap()));
+        assert_ne!(original, filtered);
+
+        let mut trimmed = original.clone();
+        let key = trimmed.service_map.keys().next().unwrap().clone();
+        trimmed.service_map.remove(&key);
+        assert_ne!(original, trimmed);
+    }
+}
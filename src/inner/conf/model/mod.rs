@@ -1,4 +1,5 @@
 pub(crate) mod characteristic_config;
+pub(crate) mod discovery_filter;
 pub(crate) mod filter;
 pub(crate) mod flat_peripheral_config;
 pub(crate) mod service_characteristic_key;
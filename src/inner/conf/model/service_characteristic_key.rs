@@ -4,12 +4,24 @@ use std::fmt::{Display, Formatter};
 
 use uuid::Uuid;
 
+/// Characteristic uuid written as `*` in the config: applies to every characteristic of the service.
+pub(crate) const WILDCARD_CHARACTERISTIC_UUID: Uuid = Uuid::from_u128(u128::MAX);
+
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Hash)]
 pub(crate) struct ServiceCharacteristicKey {
     pub(crate) service_uuid: Uuid,
     pub(crate) characteristic_uuid: Uuid,
 }
 
+impl ServiceCharacteristicKey {
+    pub(crate) fn wildcard(service_uuid: Uuid) -> Self {
+        Self {
+            service_uuid,
+            characteristic_uuid: WILDCARD_CHARACTERISTIC_UUID,
+        }
+    }
+}
+
 impl From<&Characteristic> for ServiceCharacteristicKey {
     fn from(value: &Characteristic) -> Self {
         Self {
@@ -21,6 +33,9 @@ impl From<&Characteristic> for ServiceCharacteristicKey {
 
 impl Display for ServiceCharacteristicKey {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.characteristic_uuid == WILDCARD_CHARACTERISTIC_UUID {
+            return write!(f, "{}:*", self.service_uuid);
+        }
         write!(f, "{}:{}", self.service_uuid, self.characteristic_uuid)
     }
 }
@@ -1,9 +1,15 @@
+use std::collections::{HashMap, VecDeque};
 use std::fmt::{Display, Formatter};
 
+use base64::engine::general_purpose::{STANDARD, URL_SAFE};
+use base64::Engine;
 use bounded_integer::{BoundedI8, BoundedU8};
+use chrono::{DateTime, SecondsFormat, Utc};
 use num_bigint::{BigInt, BigUint};
 use num_traits::{FromBytes, ToPrimitive};
-use serde::{Deserialize, Serialize, Serializer};
+use serde::de::value::{EnumAccessDeserializer, MapAccessDeserializer};
+use serde::de::{EnumAccess, IntoDeserializer, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 #[derive(Debug, thiserror::Error)]
 pub(crate) enum ConversionError {
@@ -18,14 +24,134 @@ pub(crate) enum ConversionError {
 
     #[error("Utf8 conversion error: {0:?}")]
     Utf8Error(#[from] std::string::FromUtf8Error),
+
+    #[error("Converter {converter} can't accept value {value}")]
+    IncompatibleInput { converter: String, value: String },
+
+    #[error("Invalid command payload `{0}`")]
+    InvalidCommand(String),
+
+    #[error("Value {value} does not fit into {len} byte(s)")]
+    OutOfRange { value: i128, len: usize },
+
+    #[error("Converter {0} can't encode values")]
+    NotReversible(String),
+
+    #[error("Can't negate a non-numeric value {0}")]
+    NonNumericNegation(String),
+
+    #[error("Converter {0} has an empty input range")]
+    EmptyRange(String),
+
+    #[error("Converter {0} has an empty window")]
+    EmptyWindow(String),
+
+    #[error("Converter {0} has a parameter that is not a finite number")]
+    NonFiniteParameter(String),
+
+    #[error("Base64 decoding error: {0}")]
+    Base64Error(#[from] base64::DecodeError),
+
+    #[error("Timestamp {value} is out of range for {format:?}")]
+    TimestampOverflow { value: u64, format: EpochFormat },
+}
+
+/// A float converter parameter, compared bitwise so the configs stay `Eq`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(transparent)]
+pub(crate) struct ConfigF64(pub(crate) f64);
+
+impl PartialEq for ConfigF64 {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.to_bits() == other.0.to_bits()
+    }
+}
+
+impl Eq for ConfigF64 {}
+
+impl Display for ConfigF64 {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Eq, PartialEq, Default)]
+pub(crate) enum Base64Alphabet {
+    #[default]
+    Standard,
+    UrlSafe,
+}
+
+impl Base64Alphabet {
+    fn engine(self) -> &'static base64::engine::GeneralPurpose {
+        match self {
+            Self::Standard => &STANDARD,
+            Self::UrlSafe => &URL_SAFE,
+        }
+    }
+}
+
+/// Seconds between the Unix epoch and the BLE epoch, 2000-01-01T00:00:00Z.
+const BLE_EPOCH_OFFSET: i64 = 946_684_800;
+
+/// Unit and epoch of an unsigned little-endian timestamp.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Eq, PartialEq)]
+pub(crate) enum EpochFormat {
+    /// 4 bytes, seconds since 1970-01-01.
+    UnixSeconds,
+    /// 8 bytes, milliseconds since 1970-01-01.
+    UnixMilliseconds,
+    /// 4 bytes, seconds since 2000-01-01.
+    BleEpoch,
+}
+
+impl EpochFormat {
+    fn len(self) -> usize {
+        match self {
+            Self::UnixSeconds | Self::BleEpoch => 4,
+            Self::UnixMilliseconds => 8,
+        }
+    }
+
+    fn to_date_time(self, value: u64) -> Result<DateTime<Utc>, ConversionError> {
+        let date_time = match self {
+            Self::UnixSeconds => i64::try_from(value)
+                .ok()
+                .and_then(|secs| DateTime::from_timestamp(secs, 0)),
+            Self::UnixMilliseconds => i64::try_from(value).ok().and_then(DateTime::from_timestamp_millis),
+            Self::BleEpoch => i64::try_from(value)
+                .ok()
+                .and_then(|secs| secs.checked_add(BLE_EPOCH_OFFSET))
+                .and_then(|secs| DateTime::from_timestamp(secs, 0)),
+        };
+        date_time.ok_or(ConversionError::TimestampOverflow { value, format: self })
+    }
 }
 
+/// (De)serialized by hand on top of the derived implementation, see the `Serialize` and `Deserialize` impls below.
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Default)]
+#[serde(remote = "Self")]
 pub(crate) enum Converter {
     #[default]
     Raw,
-    Utf8,
+    /// A string, strict by default (a bare `!Utf8`). For devices padding their strings with arbitrary bytes: with
+    /// `lossy`, invalid sequences are replaced with `U+FFFD` instead of failing the conversion; with `trim`, leading
+    /// and trailing whitespace and control characters are removed.
+    #[serde(deserialize_with = "deserialize_utf8_options")]
+    Utf8 {
+        lossy: bool,
+        trim: bool,
+    },
     F32,
+    /// Encodes opaque binary data (i.e. a device certificate) as a base64 string; never published as a metric.
+    Base64 {
+        #[serde(default)]
+        alphabet: Base64Alphabet,
+    },
+    /// An unsigned little-endian timestamp, i.e. a device clock, as an RFC 3339 string.
+    Timestamp {
+        format: EpochFormat,
+    },
     Signed {
         l: BoundedU8<0, 8>,
         m: BoundedI8<-10, 10>,
@@ -38,16 +164,172 @@ pub(crate) enum Converter {
         d: i32,
         b: i32,
     },
+    /// Bitwise AND of the input with `mask` before passing it to `inner`; bytes beyond the mask are zeroed.
+    Masked {
+        mask: Vec<u8>,
+        inner: Box<Converter>,
+    },
+    /// Limits a numeric value to the `[min, max]` range; accepts only numeric input. The bounds are integers, so
+    /// integer input stays an integer; for fractional bounds use `LinearMap` with `clamp` over equal ranges.
+    Clamp {
+        min: Option<i64>,
+        max: Option<i64>,
+    },
+    /// Rounds a float to `digits` decimal places; integers are passed through. Accepts only numeric input.
+    Round {
+        digits: u8,
+    },
+    /// Linearly remaps a numeric value from `[in_min, in_max]` to `[out_min, out_max]`, i.e. an ADC count to volts;
+    /// always produces a float. With `clamp`, the result is limited to the output range. Accepts only numeric input.
+    LinearMap {
+        in_min: ConfigF64,
+        in_max: ConfigF64,
+        out_min: ConfigF64,
+        out_max: ConfigF64,
+        #[serde(default)]
+        clamp: bool,
+    },
+    /// Applies converters left to right. The first step gets the raw bytes, the following steps get the
+    /// previous step's output: byte converters (`Utf8`, `F32`, `Base64`, `Timestamp`, `Signed`, `Unsigned`, `Masked`)
+    /// accept only raw bytes, numeric converters (`Clamp`, `Round`, `LinearMap`) accept only numbers, and `Raw` passes
+    /// anything through.
+    Chain(Vec<Converter>),
+    /// Moving average of the last `window` readings converted by `inner`; always produces a float.
+    /// The window is kept per characteristic in a `ConverterState`, without it only the current reading is used.
+    /// Every `Average` of a chain keeps its own window.
+    Average {
+        window: usize,
+        inner: Box<Converter>,
+    },
+    /// Negates the numeric value produced by `inner`.
+    Negate {
+        inner: Box<Converter>,
+    },
+    /// Applies `then` if `condition` produces a non-zero number, `else` otherwise, i.e. for values with a validity
+    /// flag bit. All three converters get the same input.
+    Conditional {
+        condition: Box<Converter>,
+        then: Box<Converter>,
+        #[serde(rename = "else")]
+        else_: Box<Converter>,
+    },
+}
+
+/// The options of `Utf8`; a bare `!Utf8` has none of them.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Utf8Options {
+    lossy: bool,
+    trim: bool,
+}
+
+fn deserialize_utf8_options<'de, D>(deserializer: D) -> Result<(bool, bool), D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let options = Option::<Utf8Options>::deserialize(deserializer)?.unwrap_or_default();
+    Ok((options.lossy, options.trim))
+}
+
+/// A strict `Utf8` is serialized as the unit variant it used to be, so `"Utf8"` in JSON.
+impl Serialize for Converter {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            Self::Utf8 {
+                lossy: false,
+                trim: false,
+            } => serializer.serialize_unit_variant("Converter", 1, "Utf8"),
+            _ => Converter::serialize(self, serializer),
+        }
+    }
+}
+
+/// Accepts `Utf8` both as a unit variant (`"Utf8"` in JSON, where it can't be read as a struct variant) and as a
+/// struct variant with options; everything else goes to the derived implementation.
+impl<'de> Deserialize<'de> for Converter {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(ConverterVisitor)
+    }
+}
+
+struct ConverterVisitor;
+
+impl<'de> Visitor<'de> for ConverterVisitor {
+    type Value = Converter;
+
+    fn expecting(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+        formatter.write_str("a converter")
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        if value == "Utf8" {
+            return Ok(Converter::Utf8 {
+                lossy: false,
+                trim: false,
+            });
+        }
+        Converter::deserialize(value.into_deserializer())
+    }
+
+    fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        Converter::deserialize(MapAccessDeserializer::new(map))
+    }
+
+    /// YAML tags, i.e. `!Utf8` or `!Signed { .. }`.
+    fn visit_enum<A>(self, data: A) -> Result<Self::Value, A::Error>
+    where
+        A: EnumAccess<'de>,
+    {
+        Converter::deserialize(EnumAccessDeserializer::new(data))
+    }
 }
 
 impl Display for Converter {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Raw => write!(f, "Raw"),
-            Self::Utf8 => write!(f, "Utf8"),
+            Self::Utf8 {
+                lossy: false,
+                trim: false,
+            } => write!(f, "Utf8"),
+            Self::Utf8 { lossy, trim } => write!(f, "Utf8[lossy: {lossy}, trim: {trim}]"),
+            Self::Base64 { alphabet } => write!(f, "Base64[{alphabet:?}]"),
+            Self::Timestamp { format } => write!(f, "Timestamp[{format:?}]"),
             Self::Signed { l, m, d, b } => write!(f, "Signed[{l}]({m} {d} {b})",),
             Self::Unsigned { l, m, d, b } => write!(f, "Unsigned[{l}]({m} {d} {b})",),
             Self::F32 => write!(f, "F32"),
+            Self::Masked { mask, inner } => write!(f, "Masked[{mask:02x?}]({inner})"),
+            Self::Clamp { min, max } => write!(f, "Clamp[{min:?}..{max:?}]"),
+            Self::Round { digits } => write!(f, "Round[{digits}]"),
+            Self::LinearMap {
+                in_min,
+                in_max,
+                out_min,
+                out_max,
+                clamp,
+            } => {
+                let clamp = if *clamp { ", clamped" } else { "" };
+                write!(f, "LinearMap[{in_min}..{in_max} -> {out_min}..{out_max}{clamp}]")
+            }
+            Self::Chain(converters) => {
+                let converters = converters.iter().map(ToString::to_string).collect::<Vec<_>>();
+                write!(f, "Chain({})", converters.join(" -> "))
+            }
+            Self::Average { window, inner } => write!(f, "Average[{window}]({inner})"),
+            Self::Negate { inner } => write!(f, "Negate({inner})"),
+            Self::Conditional { condition, then, else_ } => write!(f, "If({condition} ? {then} : {else_})"),
         }
     }
 }
@@ -65,10 +347,11 @@ impl CharacteristicValue {
         matches!(self, Self::I64(_) | Self::F64(_))
     }
 
+    /// `None` for negative or non-finite values, which have no `u64` representation.
     pub(crate) fn as_u64(&self) -> Option<u64> {
         match self {
-            Self::I64(value) => Some(*value as u64),
-            Self::F64(value) => Some(*value as u64),
+            Self::I64(value) => u64::try_from(*value).ok(),
+            Self::F64(value) => (0.0..=u64::MAX as f64).contains(value).then_some(*value as u64),
             _ => None,
         }
     }
@@ -107,6 +390,24 @@ impl Display for CharacteristicValue {
     }
 }
 
+/// State of stateful converters, kept per characteristic: the readings in the moving window of every `Average`,
+/// keyed by the position of the `Average` in the converter tree.
+#[derive(Debug, Default)]
+pub(crate) struct ConverterState {
+    readings: HashMap<usize, VecDeque<f64>>,
+}
+
+impl ConverterState {
+    fn push_average(&mut self, node: usize, reading: f64, window: usize) -> f64 {
+        let readings = self.readings.entry(node).or_default();
+        readings.push_back(reading);
+        while readings.len() > window.max(1) {
+            readings.pop_front();
+        }
+        readings.iter().sum::<f64>() / readings.len() as f64
+    }
+}
+
 fn compute_r(value: i64, multiplier: i8, decimal_exponent: i32, binary_exponent: i32) -> CharacteristicValue {
     if decimal_exponent >= 0 && binary_exponent >= 0 {
         let result =
@@ -118,6 +419,64 @@ fn compute_r(value: i64, multiplier: i8, decimal_exponent: i32, binary_exponent:
     CharacteristicValue::F64(result)
 }
 
+fn parse_number(payload: &[u8]) -> Result<f64, ConversionError> {
+    let payload = String::from_utf8(payload.to_vec())?;
+    payload
+        .trim()
+        .parse::<f64>()
+        .map_err(|_| ConversionError::InvalidCommand(payload))
+}
+
+/// Reverse of `compute_r`: the raw integer that represents `value` after scaling.
+fn compute_raw(value: f64, multiplier: i8, decimal_exponent: i32, binary_exponent: i32) -> f64 {
+    (value / ((multiplier as f64) * 10f64.powi(decimal_exponent) * 2f64.powi(binary_exponent))).round()
+}
+
+fn negate(value: CharacteristicValue) -> Result<CharacteristicValue, ConversionError> {
+    match value {
+        CharacteristicValue::I64(value) => {
+            value
+                .checked_neg()
+                .map(CharacteristicValue::I64)
+                .ok_or(ConversionError::OutOfRange {
+                    value: -(value as i128),
+                    len: 8,
+                })
+        }
+        CharacteristicValue::F64(value) => Ok(CharacteristicValue::F64(-value)),
+        value => Err(ConversionError::NonNumericNegation(value.to_string())),
+    }
+}
+
+fn apply_mask(mask: &[u8], mut value: Vec<u8>) -> Result<Vec<u8>, ConversionError> {
+    if value.len() < mask.len() {
+        return Err(ConversionError::LenMismatch {
+            expected: mask.len(),
+            actual: value.len(),
+        });
+    }
+    for (index, byte) in value.iter_mut().enumerate() {
+        *byte &= mask.get(index).copied().unwrap_or(0);
+    }
+    Ok(value)
+}
+
+fn encode_integer(raw: f64, len: usize, signed: bool) -> Result<Vec<u8>, ConversionError> {
+    let bits = 8 * len as u32;
+    let (min, max) = match (signed, bits) {
+        (_, 0) => (0, 0),
+        (true, bits) => (-(1i128 << (bits - 1)), (1i128 << (bits - 1)) - 1),
+        (false, bits) => (0, (1i128 << bits) - 1),
+    };
+
+    let value = raw as i128;
+    if !raw.is_finite() || value < min || value > max {
+        return Err(ConversionError::OutOfRange { value, len });
+    }
+
+    Ok(value.to_le_bytes()[..len].to_vec())
+}
+
 impl Converter {
     fn check_length(&self, value: &[u8]) -> Result<(), ConversionError> {
         match self {
@@ -139,6 +498,15 @@ impl Converter {
                 }
                 Ok(())
             }
+            Self::Timestamp { format } => {
+                if value.len() != format.len() {
+                    return Err(ConversionError::LenMismatch {
+                        expected: format.len(),
+                        actual: value.len(),
+                    });
+                }
+                Ok(())
+            }
             _ => Ok(()),
         }
     }
@@ -151,11 +519,32 @@ impl Converter {
                 Ok(CharacteristicValue::F64(value as f64))
             }
             Self::Raw => Ok(CharacteristicValue::Raw(value)),
-            Self::Utf8 => {
+            &Self::Utf8 { lossy, trim } => {
                 value.retain(|&byte| byte != 0);
-                let result = String::from_utf8(value)?;
+                let result = if lossy {
+                    String::from_utf8_lossy(&value).into_owned()
+                } else {
+                    String::from_utf8(value)?
+                };
+                let result = if trim {
+                    result
+                        .trim_matches(|c: char| c.is_whitespace() || c.is_control())
+                        .to_string()
+                } else {
+                    result
+                };
                 Ok(CharacteristicValue::Utf8(result))
             }
+            Self::Base64 { alphabet } => Ok(CharacteristicValue::Utf8(alphabet.engine().encode(value))),
+            &Self::Timestamp { format } => {
+                self.check_length(&value)?;
+                let mut bytes = [0u8; 8];
+                bytes[..value.len()].copy_from_slice(&value);
+                let date_time = format.to_date_time(u64::from_le_bytes(bytes))?;
+                Ok(CharacteristicValue::Utf8(
+                    date_time.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+                ))
+            }
             &Self::Signed { m, d, b, .. } => {
                 self.check_length(&value)?;
                 let value = BigInt::from_le_bytes(&value);
@@ -178,6 +567,240 @@ impl Converter {
 
                 Ok(compute_r(value, i8::from(m), d, b))
             }
+            Self::Masked { mask, inner } => inner.convert(apply_mask(mask, value)?),
+            Self::Clamp { .. }
+            | Self::Round { .. }
+            | Self::LinearMap { .. }
+            | Self::Chain(_)
+            | Self::Average { .. }
+            | Self::Negate { .. }
+            | Self::Conditional { .. } => self.convert_value(CharacteristicValue::Raw(value)),
+        }
+    }
+
+    pub(crate) fn is_stateful(&self) -> bool {
+        match self {
+            Self::Average { .. } => true,
+            Self::Chain(converters) => converters.iter().any(Converter::is_stateful),
+            Self::Masked { inner, .. } | Self::Negate { inner } => inner.is_stateful(),
+            Self::Conditional { condition, then, else_ } => {
+                condition.is_stateful() || then.is_stateful() || else_.is_stateful()
+            }
+            _ => false,
+        }
+    }
+
+    /// Rejects parameters that would fail or poison every conversion, i.e. an `Average` over an empty window or a
+    /// `LinearMap` with an empty or non-finite range.
+    pub(crate) fn validate(&self) -> Result<(), ConversionError> {
+        match self {
+            Self::Average { window: 0, .. } => Err(ConversionError::EmptyWindow(self.to_string())),
+            Self::LinearMap {
+                in_min,
+                in_max,
+                out_min,
+                out_max,
+                ..
+            } => {
+                if [in_min, in_max, out_min, out_max]
+                    .iter()
+                    .any(|param| !param.0.is_finite())
+                {
+                    return Err(ConversionError::NonFiniteParameter(self.to_string()));
+                }
+                if in_min.0 == in_max.0 {
+                    return Err(ConversionError::EmptyRange(self.to_string()));
+                }
+                Ok(())
+            }
+            Self::Average { inner, .. } | Self::Masked { inner, .. } | Self::Negate { inner } => inner.validate(),
+            Self::Chain(converters) => converters.iter().try_for_each(Converter::validate),
+            Self::Conditional { condition, then, else_ } => {
+                condition.validate()?;
+                then.validate()?;
+                else_.validate()
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Number of converters in the tree rooted at this one, itself included.
+    fn node_count(&self) -> usize {
+        1 + match self {
+            Self::Average { inner, .. } | Self::Masked { inner, .. } | Self::Negate { inner } => inner.node_count(),
+            Self::Chain(converters) => converters.iter().map(Converter::node_count).sum(),
+            Self::Conditional { condition, then, else_ } => {
+                condition.node_count() + then.node_count() + else_.node_count()
+            }
+            _ => 0,
+        }
+    }
+
+    /// Same as `convert`, but every `Average` (also nested in other converters) keeps its moving window in `state`.
+    pub(crate) fn convert_with_state(
+        &self,
+        value: Vec<u8>,
+        state: &mut ConverterState,
+    ) -> Result<CharacteristicValue, ConversionError> {
+        self.convert_value_with_state(CharacteristicValue::Raw(value), state, 0)
+    }
+
+    /// `node` is the pre-order position of this converter in the tree, which keys the window of an `Average`.
+    fn convert_value_with_state(
+        &self,
+        value: CharacteristicValue,
+        state: &mut ConverterState,
+        node: usize,
+    ) -> Result<CharacteristicValue, ConversionError> {
+        match self {
+            Self::Average { window, inner } => {
+                let reading = self.numeric_input(inner.convert_value_with_state(value, state, node + 1)?)?;
+                Ok(CharacteristicValue::F64(state.push_average(node, reading, *window)))
+            }
+            Self::Chain(converters) => {
+                let mut child = node + 1;
+                converters.iter().try_fold(value, |value, converter| {
+                    let result = converter.convert_value_with_state(value, state, child);
+                    child += converter.node_count();
+                    result
+                })
+            }
+            Self::Masked { mask, inner } => match value {
+                CharacteristicValue::Raw(value) => {
+                    let value = CharacteristicValue::Raw(apply_mask(mask, value)?);
+                    inner.convert_value_with_state(value, state, node + 1)
+                }
+                value => Err(self.incompatible_input(&value)),
+            },
+            Self::Negate { inner } => negate(inner.convert_value_with_state(value, state, node + 1)?),
+            Self::Conditional { condition, then, else_ } => {
+                let then_node = node + 1 + condition.node_count();
+                let condition = condition.convert_value_with_state(value.clone(), state, node + 1)?;
+                if self.condition_holds(condition)? {
+                    then.convert_value_with_state(value, state, then_node)
+                } else {
+                    else_.convert_value_with_state(value, state, then_node + then.node_count())
+                }
+            }
+            _ => self.convert_value(value),
+        }
+    }
+
+    fn numeric_input(&self, value: CharacteristicValue) -> Result<f64, ConversionError> {
+        value.as_f64().ok_or_else(|| self.incompatible_input(&value))
+    }
+
+    fn condition_holds(&self, value: CharacteristicValue) -> Result<bool, ConversionError> {
+        match value {
+            CharacteristicValue::I64(value) => Ok(value != 0),
+            CharacteristicValue::F64(value) => Ok(value != 0.0),
+            value => Err(self.incompatible_input(&value)),
+        }
+    }
+
+    /// Converts an intermediate value produced by a previous converter in a chain.
+    pub(crate) fn convert_value(&self, value: CharacteristicValue) -> Result<CharacteristicValue, ConversionError> {
+        match (self, value) {
+            (Self::Raw, value) => Ok(value),
+            (Self::Clamp { min, max }, CharacteristicValue::I64(value)) => {
+                let value = min.map(|min| value.max(min)).unwrap_or(value);
+                let value = max.map(|max| value.min(max)).unwrap_or(value);
+                Ok(CharacteristicValue::I64(value))
+            }
+            (Self::Clamp { min, max }, CharacteristicValue::F64(value)) => {
+                let value = min.map(|min| value.max(min as f64)).unwrap_or(value);
+                let value = max.map(|max| value.min(max as f64)).unwrap_or(value);
+                Ok(CharacteristicValue::F64(value))
+            }
+            (Self::Round { .. }, CharacteristicValue::I64(value)) => Ok(CharacteristicValue::I64(value)),
+            (Self::Round { digits }, CharacteristicValue::F64(value)) => {
+                let factor = 10f64.powi(*digits as i32);
+                Ok(CharacteristicValue::F64((value * factor).round() / factor))
+            }
+            (
+                Self::LinearMap {
+                    in_min,
+                    in_max,
+                    out_min,
+                    out_max,
+                    clamp,
+                },
+                value @ (CharacteristicValue::I64(_) | CharacteristicValue::F64(_)),
+            ) => {
+                let reading = self.numeric_input(value)?;
+                if in_max.0 == in_min.0 {
+                    return Err(ConversionError::EmptyRange(self.to_string()));
+                }
+                let result = out_min.0 + (reading - in_min.0) * (out_max.0 - out_min.0) / (in_max.0 - in_min.0);
+                let result = if *clamp {
+                    result.clamp(out_min.0.min(out_max.0), out_min.0.max(out_max.0))
+                } else {
+                    result
+                };
+                Ok(CharacteristicValue::F64(result))
+            }
+            (Self::Chain(converters), value) => converters
+                .iter()
+                .try_fold(value, |value, converter| converter.convert_value(value)),
+            (Self::Average { inner, .. }, value) => {
+                let reading = self.numeric_input(inner.convert_value(value)?)?;
+                Ok(CharacteristicValue::F64(reading))
+            }
+            (Self::Negate { inner }, value) => negate(inner.convert_value(value)?),
+            (Self::Conditional { condition, then, else_ }, value) => {
+                let branch = if self.condition_holds(condition.convert_value(value.clone())?)? {
+                    then
+                } else {
+                    else_
+                };
+                branch.convert_value(value)
+            }
+            (Self::Clamp { .. } | Self::Round { .. } | Self::LinearMap { .. }, value) => {
+                Err(self.incompatible_input(&value))
+            }
+            (_, CharacteristicValue::Raw(value)) => self.convert(value),
+            (_, value) => Err(self.incompatible_input(&value)),
+        }
+    }
+
+    /// Encodes a command payload (i.e. the text received over MQTT) into characteristic bytes, the reverse of
+    /// `convert`. Numeric converters expect a decimal number, `Utf8` and `Raw` take the payload as is.
+    pub(crate) fn encode(&self, payload: &[u8]) -> Result<Vec<u8>, ConversionError> {
+        match self {
+            Self::Raw => Ok(payload.to_vec()),
+            Self::Utf8 { .. } => Ok(String::from_utf8(payload.to_vec())?.into_bytes()),
+            Self::Base64 { alphabet } => Ok(alphabet.engine().decode(payload)?),
+            Self::F32 => Ok((parse_number(payload)? as f32).to_le_bytes().to_vec()),
+            &Self::Signed { l, m, d, b } | &Self::Unsigned { l, m, d, b } => {
+                if i8::from(m) == 0 {
+                    return Err(ConversionError::NotReversible(self.to_string()));
+                }
+                let raw = compute_raw(parse_number(payload)?, i8::from(m), d, b);
+                encode_integer(raw, usize::from(l), matches!(self, Self::Signed { .. }))
+            }
+            Self::Masked { mask, inner } => {
+                let mut value = inner.encode(payload)?;
+                for (index, byte) in value.iter_mut().enumerate() {
+                    *byte &= mask.get(index).copied().unwrap_or(0);
+                }
+                Ok(value)
+            }
+            Self::Average { inner, .. } => inner.encode(payload),
+            Self::Negate { inner } => inner.encode((-parse_number(payload)?).to_string().as_bytes()),
+            Self::Timestamp { .. }
+            | Self::Clamp { .. }
+            | Self::Round { .. }
+            | Self::LinearMap { .. }
+            | Self::Chain(_) => Err(ConversionError::NotReversible(self.to_string())),
+            // the branch to encode with can't be told from the payload
+            Self::Conditional { .. } => Err(ConversionError::NotReversible(self.to_string())),
+        }
+    }
+
+    fn incompatible_input(&self, value: &CharacteristicValue) -> ConversionError {
+        ConversionError::IncompatibleInput {
+            converter: self.to_string(),
+            value: value.to_string(),
         }
     }
 }
@@ -212,4 +835,556 @@ mod tests {
 
         approx_eq!(f64, result, -12.4f64, ulps = 2);
     }
+
+    #[test]
+    fn test_masked() {
+        let converter = Converter::Masked {
+            mask: vec![0xff, 0x0f],
+            inner: Box::new(Converter::Unsigned {
+                l: BoundedU8::new(2).unwrap(),
+                m: BoundedI8::new(1).unwrap(),
+                d: 0,
+                b: 0,
+            }),
+        };
+
+        let CharacteristicValue::I64(result) = converter.convert(vec![0x34, 0xf2]).unwrap() else {
+            panic!("Unexpected result");
+        };
+        assert_eq!(result, 0x0234);
+
+        assert!(matches!(
+            converter.convert(vec![0x34]),
+            Err(ConversionError::LenMismatch { expected: 2, actual: 1 })
+        ));
+
+        let CharacteristicValue::Raw(result) = (Converter::Masked {
+            mask: vec![0x0f],
+            inner: Box::new(Converter::Raw),
+        })
+        .convert(vec![0xab, 0xcd])
+        .unwrap() else {
+            panic!("Unexpected result");
+        };
+        assert_eq!(result, vec![0x0b, 0x00]);
+    }
+
+    #[test]
+    fn test_chain() {
+        let converter = Converter::Chain(vec![
+            Converter::Signed {
+                l: BoundedU8::new(2).unwrap(),
+                m: BoundedI8::new(1).unwrap(),
+                d: -2,
+                b: 0,
+            },
+            Converter::Clamp {
+                min: Some(-40),
+                max: Some(85),
+            },
+            Converter::Round { digits: 1 },
+        ]);
+
+        let CharacteristicValue::F64(result) = converter.convert(2347i16.to_le_bytes().to_vec()).unwrap() else {
+            panic!("Unexpected result");
+        };
+        approx_eq!(f64, result, 23.5f64, ulps = 2);
+
+        let CharacteristicValue::F64(result) = converter.convert(12000i16.to_le_bytes().to_vec()).unwrap() else {
+            panic!("Unexpected result");
+        };
+        approx_eq!(f64, result, 85f64, ulps = 2);
+
+        assert!(matches!(
+            converter.convert(vec![0x01]),
+            Err(ConversionError::LenMismatch { expected: 2, actual: 1 })
+        ));
+        assert_eq!(
+            converter.to_string(),
+            "Chain(Signed[2](1 -2 0) -> Clamp[Some(-40)..Some(85)] -> Round[1])"
+        );
+    }
+
+    #[test]
+    fn test_chain_incompatible_steps() {
+        let numeric_after_bytes = Converter::Chain(vec![
+            Converter::Utf8 {
+                lossy: false,
+                trim: false,
+            },
+            Converter::Round { digits: 0 },
+        ]);
+        assert!(matches!(
+            numeric_after_bytes.convert(b"12".to_vec()),
+            Err(ConversionError::IncompatibleInput { .. })
+        ));
+
+        let bytes_after_numeric = Converter::Chain(vec![
+            Converter::F32,
+            Converter::Utf8 {
+                lossy: false,
+                trim: false,
+            },
+        ]);
+        assert!(matches!(
+            bytes_after_numeric.convert(1f32.to_le_bytes().to_vec()),
+            Err(ConversionError::IncompatibleInput { .. })
+        ));
+
+        assert!(matches!(
+            Converter::Clamp { min: None, max: None }.convert(vec![0x01]),
+            Err(ConversionError::IncompatibleInput { .. })
+        ));
+    }
+
+    #[test]
+    fn test_average() {
+        let converter = Converter::Average {
+            window: 3,
+            inner: Box::new(Converter::Unsigned {
+                l: BoundedU8::new(1).unwrap(),
+                m: BoundedI8::new(1).unwrap(),
+                d: 0,
+                b: 0,
+            }),
+        };
+        assert!(converter.is_stateful());
+
+        let mut state = ConverterState::default();
+        let averages = [10u8, 20, 30, 40]
+            .into_iter()
+            .map(
+                |reading| match converter.convert_with_state(vec![reading], &mut state).unwrap() {
+                    CharacteristicValue::F64(average) => average,
+                    value => panic!("Unexpected result: {value:?}"),
+                },
+            )
+            .collect::<Vec<_>>();
+        assert_eq!(averages, vec![10.0, 15.0, 20.0, 30.0]);
+
+        // without a state only the current reading is used
+        assert!(matches!(
+            converter.convert(vec![50]).unwrap(),
+            CharacteristicValue::F64(value) if value == 50.0
+        ));
+
+        assert!(matches!(
+            converter.convert_with_state(vec![1, 2], &mut state),
+            Err(ConversionError::LenMismatch { expected: 1, actual: 2 })
+        ));
+        assert!(matches!(
+            (Converter::Average {
+                window: 3,
+                inner: Box::new(Converter::Utf8 {
+                    lossy: false,
+                    trim: false,
+                }),
+            })
+            .convert_with_state(b"abc".to_vec(), &mut state),
+            Err(ConversionError::IncompatibleInput { .. })
+        ));
+    }
+
+    #[test]
+    fn test_average_in_chain() {
+        let converter = Converter::Chain(vec![
+            Converter::Unsigned {
+                l: BoundedU8::new(1).unwrap(),
+                m: BoundedI8::new(1).unwrap(),
+                d: 0,
+                b: 0,
+            },
+            Converter::Average {
+                window: 2,
+                inner: Box::new(Converter::Raw),
+            },
+            Converter::Round { digits: 0 },
+        ]);
+        assert!(converter.is_stateful());
+        assert!(!Converter::Round { digits: 0 }.is_stateful());
+
+        let mut state = ConverterState::default();
+        converter.convert_with_state(vec![1], &mut state).unwrap();
+        let CharacteristicValue::F64(result) = converter.convert_with_state(vec![4], &mut state).unwrap() else {
+            panic!("Unexpected result");
+        };
+        assert_eq!(result, 3.0);
+    }
+
+    #[test]
+    fn test_averages_keep_separate_windows() {
+        let unsigned = Converter::Unsigned {
+            l: BoundedU8::new(1).unwrap(),
+            m: BoundedI8::new(1).unwrap(),
+            d: 0,
+            b: 0,
+        };
+        // the outer average smooths the averages of the inner one
+        let converter = Converter::Chain(vec![
+            Converter::Average {
+                window: 2,
+                inner: Box::new(unsigned.clone()),
+            },
+            Converter::Average {
+                window: 3,
+                inner: Box::new(Converter::Raw),
+            },
+        ]);
+        let mut state = ConverterState::default();
+        let results = [2u8, 4, 8]
+            .into_iter()
+            .map(|reading| {
+                converter
+                    .convert_with_state(vec![reading], &mut state)
+                    .unwrap()
+                    .to_string()
+            })
+            .collect::<Vec<_>>();
+        // inner: 2, 3, 6; outer: 2, 2.5, 11 / 3
+        assert_eq!(results, vec!["2", "2.5", (11.0f64 / 3.0).to_string().as_str()]);
+
+        let masked = Converter::Masked {
+            mask: vec![0x7f],
+            inner: Box::new(Converter::Average {
+                window: 2,
+                inner: Box::new(unsigned),
+            }),
+        };
+        assert!(masked.is_stateful());
+        let mut state = ConverterState::default();
+        masked.convert_with_state(vec![0x82], &mut state).unwrap();
+        let CharacteristicValue::F64(result) = masked.convert_with_state(vec![0x06], &mut state).unwrap() else {
+            panic!("Unexpected result");
+        };
+        assert_eq!(result, 4.0);
+    }
+
+    #[test]
+    fn test_negate() {
+        let signed = Converter::Negate {
+            inner: Box::new(Converter::Signed {
+                l: BoundedU8::new(1).unwrap(),
+                m: BoundedI8::new(1).unwrap(),
+                d: 0,
+                b: 0,
+            }),
+        };
+        assert!(matches!(signed.convert(vec![5]).unwrap(), CharacteristicValue::I64(-5)));
+        assert!(matches!(
+            signed.convert(vec![0xfb]).unwrap(),
+            CharacteristicValue::I64(5)
+        ));
+        assert_eq!(signed.encode(b"5").unwrap(), vec![0xfb]);
+
+        let float = Converter::Negate {
+            inner: Box::new(Converter::F32),
+        };
+        assert!(matches!(
+            float.convert(1.5f32.to_le_bytes().to_vec()).unwrap(),
+            CharacteristicValue::F64(value) if value == -1.5
+        ));
+
+        for inner in [
+            Converter::Raw,
+            Converter::Utf8 {
+                lossy: false,
+                trim: false,
+            },
+        ] {
+            assert!(matches!(
+                (Converter::Negate { inner: Box::new(inner) }).convert(b"12".to_vec()),
+                Err(ConversionError::NonNumericNegation(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_conditional() {
+        // the high bit flags a valid reading, the remaining 15 bits are the value
+        let converter: Converter = serde_yaml::from_str(
+            r#"
+            !Conditional
+            condition: !Masked { mask: [0x00, 0x80], inner: !Unsigned { l: 2, m: 1, d: 0, b: 0 } }
+            then: !Masked { mask: [0xff, 0x7f], inner: !Unsigned { l: 2, m: 1, d: -1, b: 0 } }
+            else: !Chain [ !Unsigned { l: 2, m: 1, d: 0, b: 0 }, !Clamp { min: -1, max: -1 } ]
+            "#,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            converter.convert(vec![0xd7, 0x80]).unwrap(),
+            CharacteristicValue::F64(value) if approx_eq!(f64, value, 21.5, ulps = 2)
+        ));
+        assert!(matches!(
+            converter.convert(vec![0xd7, 0x00]).unwrap(),
+            CharacteristicValue::I64(-1)
+        ));
+        assert!(matches!(
+            converter.encode(b"21.5"),
+            Err(ConversionError::NotReversible(_))
+        ));
+
+        let non_numeric_condition = Converter::Conditional {
+            condition: Box::new(Converter::Utf8 {
+                lossy: false,
+                trim: false,
+            }),
+            then: Box::new(Converter::Raw),
+            else_: Box::new(Converter::Raw),
+        };
+        assert!(matches!(
+            non_numeric_condition.convert(b"1".to_vec()),
+            Err(ConversionError::IncompatibleInput { .. })
+        ));
+    }
+
+    #[test]
+    fn test_encode() {
+        let signed = Converter::Signed {
+            l: BoundedU8::new(2).unwrap(),
+            m: BoundedI8::new(1).unwrap(),
+            d: -1,
+            b: 0,
+        };
+        assert_eq!(signed.encode(b"21.5").unwrap(), 215i16.to_le_bytes().to_vec());
+        assert_eq!(signed.encode(b" -3.2 ").unwrap(), (-32i16).to_le_bytes().to_vec());
+        assert!(matches!(
+            signed.encode(b"4000"),
+            Err(ConversionError::OutOfRange { value: 40000, len: 2 })
+        ));
+        assert!(matches!(
+            signed.encode(b"warm"),
+            Err(ConversionError::InvalidCommand(_))
+        ));
+
+        let CharacteristicValue::F64(decoded) = signed.convert(signed.encode(b"-12.3").unwrap()).unwrap() else {
+            panic!("Unexpected result");
+        };
+        approx_eq!(f64, decoded, -12.3f64, ulps = 2);
+
+        let unsigned = Converter::Unsigned {
+            l: BoundedU8::new(1).unwrap(),
+            m: BoundedI8::new(1).unwrap(),
+            d: 0,
+            b: 0,
+        };
+        assert_eq!(unsigned.encode(b"255").unwrap(), vec![0xff]);
+        assert!(matches!(
+            unsigned.encode(b"-1"),
+            Err(ConversionError::OutOfRange { .. })
+        ));
+
+        assert_eq!(
+            (Converter::Utf8 {
+                lossy: false,
+                trim: false,
+            })
+            .encode(b"ON")
+            .unwrap(),
+            b"ON".to_vec()
+        );
+        assert_eq!(Converter::F32.encode(b"1.5").unwrap(), 1.5f32.to_le_bytes().to_vec());
+        assert!(matches!(
+            Converter::Round { digits: 1 }.encode(b"1.5"),
+            Err(ConversionError::NotReversible(_))
+        ));
+    }
+
+    #[test]
+    fn test_linear_map() {
+        let converter: Converter = serde_yaml::from_str(
+            r#"
+            !Chain
+              - !Unsigned { l: 2, m: 1, d: 0, b: 0 }
+              - !LinearMap { in_min: 0, in_max: 4095, out_min: 0, out_max: 3.3 }
+            "#,
+        )
+        .unwrap();
+        let volts = |count: u16| match converter.convert(count.to_le_bytes().to_vec()).unwrap() {
+            CharacteristicValue::F64(volts) => volts,
+            value => panic!("Unexpected result: {value:?}"),
+        };
+        assert!(approx_eq!(f64, volts(0), 0.0));
+        assert!(approx_eq!(f64, volts(4095), 3.3));
+        assert!(approx_eq!(f64, volts(2048), 2048.0 * 3.3 / 4095.0, epsilon = 1e-12));
+        // not clamped by default
+        assert!(approx_eq!(f64, volts(8190), 6.6, epsilon = 1e-12));
+
+        let map = |value: i64, clamp: bool| {
+            let converter = Converter::LinearMap {
+                in_min: ConfigF64(100.0),
+                in_max: ConfigF64(0.0),
+                out_min: ConfigF64(-10.0),
+                out_max: ConfigF64(10.0),
+                clamp,
+            };
+            match converter.convert_value(CharacteristicValue::I64(value)).unwrap() {
+                CharacteristicValue::F64(value) => value,
+                value => panic!("Unexpected result: {value:?}"),
+            }
+        };
+        // a reversed input range
+        assert_eq!(map(25, true), 5.0);
+        assert_eq!(map(100, true), -10.0);
+        assert_eq!(map(150, false), -20.0);
+        assert_eq!(map(150, true), -10.0);
+        assert_eq!(map(-50, true), 10.0);
+
+        let empty_range = Converter::LinearMap {
+            in_min: ConfigF64(1.0),
+            in_max: ConfigF64(1.0),
+            out_min: ConfigF64(0.0),
+            out_max: ConfigF64(1.0),
+            clamp: false,
+        };
+        assert!(matches!(
+            empty_range.convert_value(CharacteristicValue::I64(1)),
+            Err(ConversionError::EmptyRange(_))
+        ));
+        assert!(matches!(
+            empty_range.convert(vec![1]),
+            Err(ConversionError::IncompatibleInput { .. })
+        ));
+        assert!(matches!(
+            empty_range.encode(b"1"),
+            Err(ConversionError::NotReversible(_))
+        ));
+    }
+
+    #[test]
+    fn test_base64() {
+        let bytes = vec![0xfb, 0xff, 0x00, 0x41];
+
+        let converter: Converter = serde_yaml::from_str("!Base64 {}").unwrap();
+        assert_eq!(converter.to_string(), "Base64[Standard]");
+        let CharacteristicValue::Utf8(encoded) = converter.convert(bytes.clone()).unwrap() else {
+            panic!("Expected a string");
+        };
+        assert_eq!(encoded, "+/8AQQ==");
+        assert_eq!(converter.encode(encoded.as_bytes()).unwrap(), bytes);
+
+        let converter: Converter = serde_yaml::from_str("!Base64 { alphabet: UrlSafe }").unwrap();
+        let CharacteristicValue::Utf8(encoded) = converter.convert(bytes.clone()).unwrap() else {
+            panic!("Expected a string");
+        };
+        assert_eq!(encoded, "-_8AQQ==");
+        assert_eq!(converter.encode(encoded.as_bytes()).unwrap(), bytes);
+
+        assert!(matches!(
+            converter.encode(b"not base64!"),
+            Err(ConversionError::Base64Error(_))
+        ));
+    }
+
+    #[test]
+    fn test_timestamp() {
+        let convert = |format: EpochFormat, value: Vec<u8>| Converter::Timestamp { format }.convert(value);
+        let utf8 = |value: &str| Some(value.to_string());
+        let as_utf8 = |value: CharacteristicValue| match value {
+            CharacteristicValue::Utf8(value) => Some(value),
+            _ => None,
+        };
+
+        assert_eq!(
+            as_utf8(convert(EpochFormat::UnixSeconds, 1_700_000_000u32.to_le_bytes().to_vec()).unwrap()),
+            utf8("2023-11-14T22:13:20Z")
+        );
+        assert_eq!(
+            as_utf8(
+                convert(
+                    EpochFormat::UnixMilliseconds,
+                    1_700_000_000_123u64.to_le_bytes().to_vec()
+                )
+                .unwrap()
+            ),
+            utf8("2023-11-14T22:13:20.123Z")
+        );
+        assert_eq!(
+            as_utf8(convert(EpochFormat::BleEpoch, 0u32.to_le_bytes().to_vec()).unwrap()),
+            utf8("2000-01-01T00:00:00Z")
+        );
+
+        assert!(matches!(
+            convert(EpochFormat::UnixSeconds, vec![0; 8]),
+            Err(ConversionError::LenMismatch { expected: 4, actual: 8 })
+        ));
+        assert!(matches!(
+            convert(EpochFormat::UnixMilliseconds, u64::MAX.to_le_bytes().to_vec()),
+            Err(ConversionError::TimestampOverflow {
+                value: u64::MAX,
+                format: EpochFormat::UnixMilliseconds
+            })
+        ));
+        assert!(matches!(
+            Converter::Timestamp {
+                format: EpochFormat::BleEpoch
+            }
+            .encode(b"2000-01-01T00:00:00Z"),
+            Err(ConversionError::NotReversible(_))
+        ));
+    }
+
+    #[test]
+    fn test_utf8_options() {
+        let padded = b"\x01 sensor\xff\0\0".to_vec();
+        let text = |lossy, trim| Converter::Utf8 { lossy, trim }.convert(padded.clone());
+
+        assert!(matches!(text(false, false), Err(ConversionError::Utf8Error(_))));
+        assert!(matches!(text(false, true), Err(ConversionError::Utf8Error(_))));
+
+        let CharacteristicValue::Utf8(lossy) = text(true, false).unwrap() else {
+            panic!("Unexpected result");
+        };
+        assert_eq!(lossy, "\u{1} sensor\u{fffd}");
+
+        let CharacteristicValue::Utf8(trimmed) = text(true, true).unwrap() else {
+            panic!("Unexpected result");
+        };
+        assert_eq!(trimmed, "sensor\u{fffd}");
+
+        let CharacteristicValue::Utf8(trimmed) = (Converter::Utf8 {
+            lossy: false,
+            trim: true,
+        })
+        .convert(b"\t 21.5 \r\n".to_vec())
+        .unwrap() else {
+            panic!("Unexpected result");
+        };
+        assert_eq!(trimmed, "21.5");
+
+        let converter: Converter = serde_yaml::from_str("!Utf8 { lossy: true }").unwrap();
+        assert_eq!(converter.to_string(), "Utf8[lossy: true, trim: false]");
+        let converter: Converter = serde_yaml::from_str("!Utf8").unwrap();
+        assert_eq!(converter.to_string(), "Utf8");
+
+        for (json, expected) in [
+            (r#""Utf8""#, "Utf8"),
+            (r#"{"Utf8": {}}"#, "Utf8"),
+            (r#"{"Utf8": {"trim": true}}"#, "Utf8[lossy: false, trim: true]"),
+        ] {
+            let converter: Converter = serde_json::from_str(json).unwrap();
+            assert_eq!(converter.to_string(), expected, "{json}");
+        }
+
+        // the strict one keeps its unit form, nested ones go through the same implementation
+        let masked = Converter::Masked {
+            mask: vec![0xff],
+            inner: Box::new(Converter::Utf8 {
+                lossy: false,
+                trim: false,
+            }),
+        };
+        assert_eq!(
+            serde_json::to_value(&masked).unwrap(),
+            serde_json::json!({"Masked": {"mask": [255], "inner": "Utf8"}})
+        );
+        assert_eq!(
+            serde_json::to_value(Converter::Utf8 {
+                lossy: true,
+                trim: false
+            })
+            .unwrap(),
+            serde_json::json!({"Utf8": {"lossy": true, "trim": false}})
+        );
+    }
 }
@@ -0,0 +1,104 @@
+use btleplug::api::BDAddr;
+use dashmap::DashMap;
+
+use crate::inner::model::fqcn::Fqcn;
+
+pub(crate) struct LengthMismatchTracker {
+    failures: DashMap<Fqcn, usize>,
+    max_failures: Option<usize>,
+}
+
+impl LengthMismatchTracker {
+    pub(crate) fn new(max_failures: Option<usize>) -> Self {
+        Self {
+            failures: Default::default(),
+            max_failures,
+        }
+    }
+
+    /// Registers a length mismatch and returns the number of consecutive mismatches for this characteristic.
+    pub(crate) fn record(&self, fqcn: &Fqcn) -> usize {
+        let mut failures = self.failures.entry(fqcn.clone()).or_default();
+        *failures += 1;
+        *failures
+    }
+
+    pub(crate) fn reset(&self, fqcn: &Fqcn) {
+        self.failures.remove(fqcn);
+    }
+
+    /// Forgets the mismatches of every characteristic of the peripheral, re-enabling the disabled ones.
+    pub(crate) fn reset_peripheral(&self, peripheral: BDAddr) {
+        self.failures.retain(|fqcn, _| fqcn.peripheral != peripheral);
+    }
+
+    /// Log only on 1st, 2nd, 4th, 8th, ... consecutive mismatch to avoid flooding the logs.
+    pub(crate) fn should_log(consecutive_failures: usize) -> bool {
+        consecutive_failures.is_power_of_two()
+    }
+
+    pub(crate) fn is_disabled(&self, fqcn: &Fqcn) -> bool {
+        let Some(max_failures) = self.max_failures else {
+            return false;
+        };
+        self.failures
+            .get(fqcn)
+            .map(|failures| *failures >= max_failures)
+            .unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fqcn(peripheral: &str) -> Fqcn {
+        Fqcn {
+            peripheral: peripheral.parse().unwrap(),
+            service: "0000180f-0000-1000-8000-00805f9b34fb".parse().unwrap(),
+            characteristic: "00002a19-0000-1000-8000-00805f9b34fb".parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_disable_after_max_failures() {
+        let tracker = LengthMismatchTracker::new(Some(3));
+        let broken = fqcn("11:22:33:44:55:66");
+        let healthy = fqcn("66:55:44:33:22:11");
+
+        for _ in 0..2 {
+            tracker.record(&broken);
+            assert!(!tracker.is_disabled(&broken));
+        }
+
+        assert_eq!(tracker.record(&broken), 3);
+        assert!(tracker.is_disabled(&broken));
+        assert!(!tracker.is_disabled(&healthy));
+
+        tracker.reset(&broken);
+        assert!(!tracker.is_disabled(&broken));
+    }
+
+    #[test]
+    fn test_reset_peripheral() {
+        let tracker = LengthMismatchTracker::new(Some(1));
+        let broken = fqcn("11:22:33:44:55:66");
+        let other = fqcn("66:55:44:33:22:11");
+        tracker.record(&broken);
+        tracker.record(&other);
+
+        tracker.reset_peripheral(broken.peripheral);
+        assert!(!tracker.is_disabled(&broken));
+        assert!(tracker.is_disabled(&other));
+    }
+
+    #[test]
+    fn test_never_disabled_without_limit() {
+        let tracker = LengthMismatchTracker::new(None);
+        let broken = fqcn("11:22:33:44:55:66");
+        for _ in 0..100 {
+            tracker.record(&broken);
+        }
+        assert!(!tracker.is_disabled(&broken));
+    }
+}
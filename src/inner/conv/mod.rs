@@ -1 +1,2 @@
 pub(crate) mod converter;
+pub(crate) mod length_mismatch_tracker;
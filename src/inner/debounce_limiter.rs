@@ -1,11 +1,24 @@
 use std::collections::hash_map::Entry;
+use std::collections::hash_map::RandomState;
 use std::collections::HashMap;
-use std::hash::Hash;
+use std::hash::{BuildHasher, Hash};
 use std::time::Duration;
 
 use tokio::sync::RwLock;
 use tokio::time::Instant;
 
+/// What the discovery events are throttled by.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, clap::ValueEnum)]
+#[value(rename_all = "snake_case")]
+pub(crate) enum EventThrottlingMode {
+    /// Peripheral address (and name), only the first matching config is connected.
+    #[default]
+    Address,
+    /// Peripheral address and the name of each matching config, every pair has its own throttling window and
+    /// every matching config is connected.
+    AddressAndConfig,
+}
+
 pub(crate) struct DebounceLimiter<K> {
     store: RwLock<HashMap<K, Instant>>,
     default_duration: Duration,
@@ -61,3 +74,79 @@ where
             .retain(|_, v| v.elapsed() < self.default_duration);
     }
 }
+
+/// Spreads the keys over independent limiters, so events of unrelated peripherals don't contend for a single lock.
+pub(crate) struct ShardedDebounceLimiter<K> {
+    shards: Vec<DebounceLimiter<K>>,
+    hasher: RandomState,
+}
+
+impl<K> ShardedDebounceLimiter<K>
+where
+    K: Hash + Eq + PartialEq + Clone,
+{
+    pub(crate) fn new(shards: usize, sample_size: usize, threshold: f64, default_duration: Duration) -> Self {
+        Self {
+            shards: (0..shards.max(1))
+                .map(|_| DebounceLimiter::new(sample_size, threshold, default_duration))
+                .collect(),
+            hasher: Default::default(),
+        }
+    }
+
+    pub(crate) async fn throttle(&self, event: K) -> bool {
+        self.shard(&event).throttle(event).await
+    }
+
+    fn shard(&self, event: &K) -> &DebounceLimiter<K> {
+        let index = self.hasher.hash_one(event) as usize % self.shards.len();
+        &self.shards[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_keys_are_throttled_independently() {
+        let limiter = DebounceLimiter::new(100, 0.25, Duration::from_secs(60));
+        let sensor = Arc::new(("11:22:33:44:55:66".to_string(), Some("sensor".to_string())));
+        let thermostat = Arc::new(("11:22:33:44:55:66".to_string(), Some("thermostat".to_string())));
+
+        assert!(!limiter.throttle(Arc::clone(&sensor)).await);
+        assert!(limiter.throttle(Arc::clone(&sensor)).await);
+        assert!(!limiter.throttle(Arc::clone(&thermostat)).await);
+        assert!(limiter.throttle(thermostat).await);
+    }
+
+    #[tokio::test]
+    async fn test_sharded_limiter() {
+        let limiter = ShardedDebounceLimiter::new(4, 100, 0.25, Duration::from_secs(60));
+        assert_eq!(limiter.shards.len(), 4);
+
+        for key in 0..64 {
+            assert!(!limiter.throttle(key).await, "{key}");
+        }
+        for key in 0..64 {
+            assert!(limiter.throttle(key).await, "{key}");
+        }
+        // every key is kept in a single shard
+        let stored = futures_util::future::join_all(limiter.shards.iter().map(|shard| shard.store.read()))
+            .await
+            .iter()
+            .map(|store| store.len())
+            .collect::<Vec<_>>();
+        assert_eq!(stored.iter().sum::<usize>(), 64);
+        assert!(stored.iter().all(|&len| len < 64), "{stored:?}");
+
+        assert_eq!(
+            ShardedDebounceLimiter::<u8>::new(0, 100, 0.25, Duration::ZERO)
+                .shards
+                .len(),
+            1
+        );
+    }
+}
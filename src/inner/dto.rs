@@ -1,11 +1,20 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::Instant;
 
+use crate::inner::conv::converter::Converter;
+use crate::inner::error::{CollectorError, CollectorResult};
 use crate::inner::model::adapter_info::AdapterInfo;
 use crate::inner::model::fqcn::Fqcn;
+use crate::inner::publish::dto::to_hex;
 use bounded_integer::BoundedUsize;
-use btleplug::api::{BDAddr, Characteristic, Descriptor, Peripheral as _, PeripheralProperties, Service, WriteType};
+use btleplug::api::{
+    BDAddr, CentralState, CharPropFlags, Characteristic, Descriptor, Peripheral as _, PeripheralProperties, ScanFilter,
+    Service, ValueNotification, WriteType,
+};
 use btleplug::platform::Peripheral;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, DurationMilliSeconds};
 use tracing::{error, info};
@@ -32,6 +41,14 @@ impl AdapterDto {
     pub(crate) fn add_peripheral(&mut self, peripheral_dto: PeripheralDto) {
         self.peripherals.push(peripheral_dto)
     }
+
+    /// Sorts peripherals, services and characteristics so that repeated calls serialize identically.
+    pub(crate) fn sort(&mut self) {
+        self.peripherals.sort_unstable_by(|left, right| left.id.cmp(&right.id));
+        for peripheral in self.peripherals.iter_mut() {
+            peripheral.sort();
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -49,6 +66,20 @@ pub(crate) struct ServiceDto {
     pub characteristics: Vec<CharacteristicDto>,
 }
 
+impl ServiceDto {
+    /// Services and their characteristics ordered by uuid.
+    pub(crate) fn sorted(services: BTreeSet<Service>) -> Vec<Self> {
+        let mut services: Vec<_> = services.into_iter().map(Self::from).collect();
+        services.sort_unstable_by_key(|service| service.uuid);
+        for service in services.iter_mut() {
+            service
+                .characteristics
+                .sort_unstable_by_key(|characteristic| characteristic.uuid);
+        }
+        services
+    }
+}
+
 impl From<Service> for ServiceDto {
     fn from(value: Service) -> Self {
         Self {
@@ -59,7 +90,7 @@ impl From<Service> for ServiceDto {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Hash)]
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Hash, Ord, PartialOrd)]
 pub(crate) enum CharPropDto {
     Broadcast,
     Read,
@@ -72,6 +103,12 @@ pub(crate) enum CharPropDto {
     Unknown,
 }
 
+impl CharPropDto {
+    fn from_flags(flags: &CharPropFlags) -> BTreeSet<Self> {
+        flags.iter_names().map(|(name, _)| Self::from(name)).collect()
+    }
+}
+
 impl From<&str> for CharPropDto {
     fn from(value: &str) -> Self {
         match value {
@@ -92,7 +129,7 @@ impl From<&str> for CharPropDto {
 pub(crate) struct CharacteristicDto {
     pub uuid: Uuid,
     pub service_uuid: Uuid,
-    pub properties: HashSet<CharPropDto>,
+    pub properties: BTreeSet<CharPropDto>,
     pub descriptors: Vec<DescriptorDto>,
 }
 
@@ -101,12 +138,7 @@ impl From<Characteristic> for CharacteristicDto {
         Self {
             uuid: value.uuid,
             service_uuid: value.service_uuid,
-            properties: value
-                .properties
-                .iter_names()
-                .map(|(name, _)| name)
-                .map(CharPropDto::from)
-                .collect(),
+            properties: CharPropDto::from_flags(&value.properties),
             descriptors: value.descriptors.into_iter().map(DescriptorDto::from).collect(),
         }
     }
@@ -130,6 +162,20 @@ impl From<Descriptor> for DescriptorDto {
 }
 
 impl PeripheralDto {
+    pub(crate) fn sort(&mut self) {
+        self.services.sort_unstable_by_key(|service| service.uuid);
+        for service in self.services.iter_mut() {
+            service
+                .characteristics
+                .sort_unstable_by_key(|characteristic| characteristic.uuid);
+            for characteristic in service.characteristics.iter_mut() {
+                characteristic
+                    .descriptors
+                    .sort_unstable_by_key(|descriptor| descriptor.uuid);
+            }
+        }
+    }
+
     pub(crate) async fn from_platform(peripheral: Peripheral) -> btleplug::Result<Self> {
         if let Err(err) = peripheral.discover_services().await {
             error!(
@@ -160,18 +206,257 @@ impl PeripheralDto {
     }
 }
 
-impl TryFrom<String> for AdapterDto {
-    type Error = anyhow::Error;
-
-    fn try_from(value: String) -> Result<Self, Self::Error> {
-        let adapter_info = AdapterInfo::try_from(value)?;
-        Ok(Self {
+impl From<AdapterInfo> for AdapterDto {
+    fn from(adapter_info: AdapterInfo) -> Self {
+        Self {
             adapter_info,
             peripherals: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct AdapterStateDto {
+    pub(crate) adapter_info: AdapterInfo,
+    pub(crate) state: CentralState,
+    pub(crate) scan_filter: ScanFilterDto,
+    pub(crate) discovery_paused: bool,
+}
+
+/// An empty `service_uuids` list means that all peripherals are scanned.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct ScanFilterDto {
+    #[serde(default)]
+    pub(crate) service_uuids: Vec<Uuid>,
+}
+
+impl From<ScanFilter> for ScanFilterDto {
+    fn from(value: ScanFilter) -> Self {
+        Self {
+            service_uuids: value.services,
+        }
+    }
+}
+
+impl From<ScanFilterDto> for ScanFilter {
+    fn from(value: ScanFilterDto) -> Self {
+        Self {
+            services: value.service_uuids,
+        }
+    }
+}
+
+/// A single characteristic read result; `error` is set instead of `value` if the read failed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct CharacteristicReadDto {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) value: Option<Vec<u8>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) error: Option<String>,
+    pub(crate) ts: DateTime<Utc>,
+}
+
+impl<E: std::fmt::Display> From<Result<Vec<u8>, E>> for CharacteristicReadDto {
+    fn from(value: Result<Vec<u8>, E>) -> Self {
+        let (value, error) = match value {
+            Ok(value) => (Some(value), None),
+            Err(err) => (None, Some(err.to_string())),
+        };
+        Self {
+            value,
+            error,
+            ts: Utc::now(),
+        }
+    }
+}
+
+/// A characteristic of a GATT dump; values are only read for characteristics with the `READ` property.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct GattAttributeDto {
+    pub(crate) service_uuid: Uuid,
+    pub(crate) characteristic_uuid: Uuid,
+    pub(crate) properties: BTreeSet<CharPropDto>,
+    pub(crate) value_hex: Option<String>,
+    pub(crate) value_utf8_attempt: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) error: Option<String>,
+}
+
+impl GattAttributeDto {
+    pub(crate) fn new<E: std::fmt::Display>(
+        characteristic: &Characteristic,
+        value: Option<Result<Vec<u8>, E>>,
+    ) -> Self {
+        let (value, error) = match value {
+            Some(Ok(value)) => (Some(value), None),
+            Some(Err(err)) => (None, Some(err.to_string())),
+            None => (None, None),
+        };
+        Self {
+            service_uuid: characteristic.service_uuid,
+            characteristic_uuid: characteristic.uuid,
+            properties: CharPropDto::from_flags(&characteristic.properties),
+            value_hex: value.as_deref().map(to_hex),
+            value_utf8_attempt: value.and_then(|value| String::from_utf8(value).ok()),
+            error,
+        }
+    }
+}
+
+/// A characteristic notification forwarded as a Server-Sent Event.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct NotificationDto {
+    pub(crate) uuid: Uuid,
+    pub(crate) value: Vec<u8>,
+    pub(crate) ts: DateTime<Utc>,
+}
+
+impl From<ValueNotification> for NotificationDto {
+    fn from(value: ValueNotification) -> Self {
+        Self {
+            uuid: value.uuid,
+            value: value.value,
+            ts: Utc::now(),
+        }
+    }
+}
+
+/// Advertised peripheral properties, with manufacturer and service data hex-encoded.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct PeripheralPropertiesDto {
+    pub(crate) local_name: Option<String>,
+    pub(crate) tx_power_level: Option<i16>,
+    pub(crate) rssi: Option<i16>,
+    pub(crate) manufacturer_data: BTreeMap<u16, String>,
+    pub(crate) service_data: BTreeMap<Uuid, String>,
+    pub(crate) services: Vec<Uuid>,
+}
+
+impl From<PeripheralProperties> for PeripheralPropertiesDto {
+    fn from(value: PeripheralProperties) -> Self {
+        Self {
+            local_name: value.local_name,
+            tx_power_level: value.tx_power_level,
+            rssi: value.rssi,
+            manufacturer_data: value
+                .manufacturer_data
+                .into_iter()
+                .map(|(id, data)| (id, to_hex(&data)))
+                .collect(),
+            service_data: value
+                .service_data
+                .into_iter()
+                .map(|(uuid, data)| (uuid, to_hex(&data)))
+                .collect(),
+            services: value.services,
+        }
+    }
+}
+
+/// Services and characteristics of a peripheral as they were discovered by the collector, used to author configs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct CatalogPeripheralDto {
+    pub(crate) address: BDAddr,
+    pub(crate) name: Option<String>,
+    pub(crate) adapter: String,
+    pub(crate) services: Vec<ServiceDto>,
+}
+
+impl CatalogPeripheralDto {
+    /// Returns `None` if no services have been discovered for the peripheral yet.
+    pub(crate) fn from_services(
+        address: BDAddr,
+        name: Option<String>,
+        adapter: String,
+        services: BTreeSet<Service>,
+    ) -> Option<Self> {
+        if services.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            address,
+            name,
+            adapter,
+            services: ServiceDto::sorted(services),
         })
     }
 }
 
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Eq, PartialEq, Ord, PartialOrd)]
+pub(crate) enum TaskKindDto {
+    Poll,
+    Subscribe,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct TaskDto {
+    pub(crate) fqcn: Fqcn,
+    pub(crate) kind: TaskKindDto,
+    /// Seconds since the peripheral was subscribed to; set only for `Subscribe` tasks.
+    pub(crate) subscription_age_sec: Option<f64>,
+}
+
+impl TaskDto {
+    /// Lists polled and subscribed characteristics ordered by fqcn.
+    pub(crate) fn collect<'a>(
+        polled: impl IntoIterator<Item = &'a Arc<Fqcn>>,
+        subscribed: impl IntoIterator<Item = &'a Arc<Fqcn>>,
+        subscription_started_at: &HashMap<BDAddr, Instant>,
+    ) -> Vec<Self> {
+        let polled = polled.into_iter().map(|fqcn| Self {
+            fqcn: fqcn.as_ref().clone(),
+            kind: TaskKindDto::Poll,
+            subscription_age_sec: None,
+        });
+        let subscribed = subscribed.into_iter().map(|fqcn| Self {
+            fqcn: fqcn.as_ref().clone(),
+            kind: TaskKindDto::Subscribe,
+            subscription_age_sec: subscription_started_at
+                .get(&fqcn.peripheral)
+                .map(|started_at| started_at.elapsed().as_secs_f64()),
+        });
+
+        let mut tasks: Vec<_> = polled.chain(subscribed).collect();
+        tasks.sort_unstable_by(|left, right| (&left.fqcn, left.kind).cmp(&(&right.fqcn, right.kind)));
+        tasks
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct MatchingPeripheralDto {
+    pub(crate) address: BDAddr,
+    pub(crate) name: Option<String>,
+    pub(crate) adapter: String,
+    pub(crate) currently_connected: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct RssiReadingDto {
+    pub(crate) ts: DateTime<Utc>,
+    pub(crate) rssi: i16,
+}
+
+impl From<(Instant, i16)> for RssiReadingDto {
+    fn from((instant, rssi): (Instant, i16)) -> Self {
+        let age = chrono::Duration::from_std(instant.elapsed()).unwrap_or_else(|_| chrono::Duration::zero());
+        Self {
+            ts: Utc::now() - age,
+            rssi,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct CircuitBreakerDto {
+    /// Connect attempts are skipped while open.
+    pub(crate) open: bool,
+    pub(crate) consecutive_failures: u32,
+    pub(crate) trips: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) retry_in_ms: Option<u64>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) enum ResultDto<T> {
     Ok(T),
@@ -202,22 +487,126 @@ pub(crate) struct PeripheralIoBatchResponseDto {
     pub(crate) command_responses: Vec<Option<ResultDto<Vec<u8>>>>,
 }
 
+/// Writes the same value to every connected peripheral matching a configuration, i.e. to set all thermostats at once.
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct BulkWriteRequestDto {
+    pub(crate) service: Uuid,
+    pub(crate) characteristic: Uuid,
+    pub(crate) value: Vec<u8>,
+    pub(crate) wait_response: bool,
+    #[serde_as(as = "Option<DurationMilliSeconds>")]
+    pub(crate) timeout_ms: Option<std::time::Duration>,
+    /// How many peripherals are written to at a time.
+    pub(crate) parallelism: Option<BoundedUsize<1, 64>>,
+}
+
+impl BulkWriteRequestDto {
+    pub(crate) fn command_for(&self, peripheral: BDAddr) -> IoCommand {
+        IoCommand::Write {
+            fqcn: Fqcn {
+                peripheral,
+                service: self.service,
+                characteristic: self.characteristic,
+            },
+            value: self.value.clone(),
+            wait_response: self.wait_response,
+            timeout_ms: self.timeout_ms,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct BulkWriteResponseDto {
+    pub(crate) adapter: String,
+    pub(crate) address: BDAddr,
+    pub(crate) name: Option<String>,
+    pub(crate) result: ResultDto<()>,
+}
+
+/// A single line of the streamed r/w response, sent as soon as the command completes.
+#[derive(Debug, Serialize)]
+pub(crate) struct PeripheralIoCommandResponseDto {
+    pub(crate) batch_index: usize,
+    pub(crate) command_index: usize,
+    /// `null` for a successful write.
+    pub(crate) result: Option<ResultDto<Vec<u8>>>,
+}
+
+/// Sample bytes to run through a converter, i.e. when authoring a configuration.
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct ConvertRequestDto {
+    pub(crate) converter: Converter,
+    pub(crate) value: Vec<u8>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct PeripheralIoRequestDto {
     pub(crate) batches: Vec<PeripheralIoBatchRequestDto>,
     pub(crate) parallelism: Option<BoundedUsize<1, 64>>,
+    /// Run the commands of all batches in a single pool of `parallelism` commands instead of batch by batch.
+    #[serde(default)]
+    pub(crate) flatten: bool,
+    /// Keep the peripherals connected for `--keep-connected-timeout` after the request, so the next one reuses the
+    /// connection.
+    #[serde(default)]
+    pub(crate) keep_connected: bool,
+}
+
+impl PeripheralIoRequestDto {
+    pub(crate) fn has_writes(&self) -> bool {
+        self.batches
+            .iter()
+            .flat_map(|batch| batch.commands.iter())
+            .any(IoCommand::is_write)
+    }
+
+    /// Rejects batches with several commands for the same characteristic: a read waiting for a notification could
+    /// receive the one intended for another command.
+    pub(crate) fn validate(&self) -> CollectorResult<()> {
+        for batch in &self.batches {
+            let mut seen = BTreeSet::new();
+            for cmd in &batch.commands {
+                if !seen.insert(cmd.get_fqcn()) {
+                    return Err(CollectorError::DuplicateFqcnInBatch(cmd.get_fqcn().clone()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn peripherals(&self) -> BTreeSet<BDAddr> {
+        self.batches
+            .iter()
+            .flat_map(|batch| batch.commands.iter())
+            .map(|cmd| cmd.get_fqcn().peripheral)
+            .collect()
+    }
 }
 
+#[serde_as]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct PeripheralIoBatchRequestDto {
     pub(crate) commands: Vec<IoCommand>,
     pub(crate) parallelism: Option<BoundedUsize<1, 64>>,
+    /// How many times a command failing with a Bluetooth error is retried.
+    pub(crate) retry_count: Option<u8>,
+    /// Delay before the first retry, doubled after each attempt.
+    #[serde_as(as = "Option<DurationMilliSeconds>")]
+    pub(crate) retry_delay_ms: Option<std::time::Duration>,
+    /// Timeout of the commands that don't set their own `timeout_ms`.
+    #[serde_as(as = "Option<DurationMilliSeconds>")]
+    pub(crate) default_timeout_ms: Option<std::time::Duration>,
 }
 
 #[serde_as]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) enum IoCommand {
     Write {
+        #[cfg_attr(
+            feature = "human-readable-fqcn",
+            serde(with = "crate::inner::model::fqcn::human_readable")
+        )]
         fqcn: Fqcn,
         value: Vec<u8>,
         wait_response: bool,
@@ -225,6 +614,10 @@ pub(crate) enum IoCommand {
         timeout_ms: Option<std::time::Duration>,
     },
     Read {
+        #[cfg_attr(
+            feature = "human-readable-fqcn",
+            serde(with = "crate::inner::model::fqcn::human_readable")
+        )]
         fqcn: Fqcn,
         wait_notification: bool,
         #[serde_as(as = "Option<DurationMilliSeconds>")]
@@ -239,6 +632,15 @@ impl IoCommand {
             IoCommand::Read { timeout_ms, .. } => *timeout_ms,
         }
     }
+    /// Sets the timeout, unless the command has its own.
+    pub(crate) fn with_default_timeout(mut self, default_timeout: Option<std::time::Duration>) -> Self {
+        match &mut self {
+            IoCommand::Write { timeout_ms, .. } | IoCommand::Read { timeout_ms, .. } => {
+                *timeout_ms = timeout_ms.or(default_timeout);
+            }
+        }
+        self
+    }
     pub(crate) fn get_write_type(&self) -> WriteType {
         match self {
             IoCommand::Write { wait_response, .. } => {
@@ -252,6 +654,10 @@ impl IoCommand {
         }
     }
 
+    pub(crate) fn is_write(&self) -> bool {
+        matches!(self, IoCommand::Write { .. })
+    }
+
     pub(crate) fn get_fqcn(&self) -> &Fqcn {
         match self {
             IoCommand::Write { fqcn, .. } => fqcn,
@@ -281,4 +687,309 @@ mod tests {
 
         println!("{}", serialized);
     }
+
+    #[test]
+    fn test_peripheral_properties_hex_encoded() {
+        let properties = PeripheralProperties {
+            local_name: Some("Sensor".to_string()),
+            rssi: Some(-60),
+            manufacturer_data: [(0x004c, vec![0x02, 0x15, 0xff])].into_iter().collect(),
+            service_data: [(Uuid::nil(), vec![0x0a])].into_iter().collect(),
+            services: vec![Uuid::nil()],
+            ..Default::default()
+        };
+
+        let serialized = serde_json::to_value(PeripheralPropertiesDto::from(properties)).unwrap();
+        assert_eq!(
+            serialized,
+            serde_json::json!({
+                "local_name": "Sensor",
+                "tx_power_level": null,
+                "rssi": -60,
+                "manufacturer_data": {"76": "0215ff"},
+                "service_data": {"00000000-0000-0000-0000-000000000000": "0a"},
+                "services": ["00000000-0000-0000-0000-000000000000"],
+            })
+        );
+    }
+
+    fn characteristic_dto(service_uuid: Uuid, uuid: u128) -> CharacteristicDto {
+        CharacteristicDto {
+            uuid: Uuid::from_u128(uuid),
+            service_uuid,
+            properties: [CharPropDto::Read, CharPropDto::Notify].into_iter().collect(),
+            descriptors: vec![],
+        }
+    }
+
+    fn adapter_dto(reverse: bool) -> AdapterDto {
+        let mut peripherals = vec![];
+        for id in ["a", "b"] {
+            let mut services = vec![];
+            for service_uuid in [1u128, 2] {
+                let service_uuid = Uuid::from_u128(service_uuid);
+                let mut characteristics = vec![
+                    characteristic_dto(service_uuid, 10),
+                    characteristic_dto(service_uuid, 20),
+                ];
+                if reverse {
+                    characteristics.reverse();
+                }
+                services.push(ServiceDto {
+                    uuid: service_uuid,
+                    primary: true,
+                    characteristics,
+                });
+            }
+            if reverse {
+                services.reverse();
+            }
+            peripherals.push(PeripheralDto {
+                id: id.to_string(),
+                address: BDAddr::default(),
+                props: None,
+                services,
+            });
+        }
+        if reverse {
+            peripherals.reverse();
+        }
+
+        AdapterDto {
+            adapter_info: AdapterInfo::try_from("hci0 (usb:v1D6Bp0246d0537)".to_string()).unwrap(),
+            peripherals,
+        }
+    }
+
+    #[test]
+    fn test_catalog_reflects_discovered_services() {
+        let service_uuid = Uuid::from_u128(0x180f);
+        let characteristic = |uuid: u128, properties: CharPropFlags| Characteristic {
+            uuid: Uuid::from_u128(uuid),
+            service_uuid,
+            properties,
+            descriptors: Default::default(),
+        };
+        let services = BTreeSet::from([Service {
+            uuid: service_uuid,
+            primary: true,
+            characteristics: BTreeSet::from([
+                characteristic(0x2a19, CharPropFlags::READ | CharPropFlags::NOTIFY),
+                characteristic(0x2a1a, CharPropFlags::WRITE),
+            ]),
+        }]);
+
+        let address: BDAddr = "11:22:33:44:55:66".parse().unwrap();
+        let catalog =
+            CatalogPeripheralDto::from_services(address, Some("Sensor".to_string()), "hci0".to_string(), services)
+                .unwrap();
+
+        assert_eq!(catalog.address, address);
+        assert_eq!(catalog.services.len(), 1);
+        let characteristics = &catalog.services[0].characteristics;
+        assert_eq!(
+            characteristics
+                .iter()
+                .map(|characteristic| (characteristic.uuid, characteristic.properties.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    Uuid::from_u128(0x2a19),
+                    BTreeSet::from([CharPropDto::Read, CharPropDto::Notify])
+                ),
+                (Uuid::from_u128(0x2a1a), BTreeSet::from([CharPropDto::Write])),
+            ]
+        );
+
+        // services haven't been discovered yet
+        assert!(CatalogPeripheralDto::from_services(address, None, "hci0".to_string(), BTreeSet::new()).is_none());
+    }
+
+    #[test]
+    fn test_stable_ordering() {
+        let mut first = adapter_dto(false);
+        let mut second = adapter_dto(true);
+        first.sort();
+        second.sort();
+
+        assert_eq!(
+            serde_json::to_string(&first).unwrap(),
+            serde_json::to_string(&second).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_has_writes() {
+        let fqcn = r#"{"peripheral": "11:22:33:44:55:66", "service": "0000180f-0000-1000-8000-00805f9b34fb", "characteristic": "00002a19-0000-1000-8000-00805f9b34fb"}"#;
+        let read = format!(r#"{{"Read": {{"fqcn": {fqcn}, "wait_notification": false, "timeout_ms": null}}}}"#);
+        let write =
+            format!(r#"{{"Write": {{"fqcn": {fqcn}, "value": [1], "wait_response": true, "timeout_ms": null}}}}"#);
+
+        let request: PeripheralIoRequestDto = serde_json::from_str(&format!(
+            r#"{{"batches": [{{"commands": [{read}], "parallelism": null}}], "parallelism": null}}"#
+        ))
+        .unwrap();
+        assert!(!request.has_writes());
+
+        let request: PeripheralIoRequestDto = serde_json::from_str(&format!(
+            r#"{{"batches": [{{"commands": [{read}], "parallelism": null}}, {{"commands": [{read}, {write}], "parallelism": null}}], "parallelism": null}}"#
+        ))
+        .unwrap();
+        assert!(request.has_writes());
+    }
+
+    #[test]
+    fn test_duplicate_fqcn_in_batch() {
+        let fqcn = r#"{"peripheral": "11:22:33:44:55:66", "service": "0000180f-0000-1000-8000-00805f9b34fb", "characteristic": "00002a19-0000-1000-8000-00805f9b34fb"}"#;
+        let read = format!(r#"{{"Read": {{"fqcn": {fqcn}, "wait_notification": true, "timeout_ms": null}}}}"#);
+        let write =
+            format!(r#"{{"Write": {{"fqcn": {fqcn}, "value": [1], "wait_response": true, "timeout_ms": null}}}}"#);
+        let request = |batches: &str| -> PeripheralIoRequestDto {
+            serde_json::from_str(&format!(r#"{{"batches": {batches}, "parallelism": null}}"#)).unwrap()
+        };
+
+        // the same characteristic in different batches is fine
+        let valid = request(&format!(
+            r#"[{{"commands": [{read}], "parallelism": null}}, {{"commands": [{write}], "parallelism": null}}]"#
+        ));
+        assert!(valid.validate().is_ok());
+
+        let duplicate = request(&format!(r#"[{{"commands": [{read}, {write}], "parallelism": null}}]"#));
+        assert!(matches!(
+            duplicate.validate(),
+            Err(CollectorError::DuplicateFqcnInBatch(found)) if &found == valid.batches[0].commands[0].get_fqcn()
+        ));
+    }
+
+    #[cfg(feature = "human-readable-fqcn")]
+    #[test]
+    fn test_human_readable_fqcn() {
+        let read: IoCommand = serde_json::from_str(
+            r#"{"Read": {"fqcn": "11:22:33:44:55:66/0x180f/0x2a19", "wait_notification": false, "timeout_ms": null}}"#,
+        )
+        .unwrap();
+        let serialized = serde_json::to_value(&read).unwrap();
+        assert_eq!(serialized["Read"]["fqcn"], "11:22:33:44:55:66/0x180f/0x2a19");
+
+        let structured: IoCommand = serde_json::from_str(
+            r#"{"Read": {"fqcn": {"peripheral": "11:22:33:44:55:66", "service": "0000180f-0000-1000-8000-00805f9b34fb", "characteristic": "00002a19-0000-1000-8000-00805f9b34fb"}, "wait_notification": false, "timeout_ms": null}}"#,
+        )
+        .unwrap();
+        assert_eq!(structured.get_fqcn(), read.get_fqcn());
+
+        assert!(serde_json::from_str::<IoCommand>(
+            r#"{"Read": {"fqcn": "11:22:33:44:55:66/0x180f", "wait_notification": false, "timeout_ms": null}}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_collect_tasks() {
+        let fqcn = |peripheral: &str, characteristic: u16| {
+            Arc::new(Fqcn {
+                peripheral: peripheral.parse().unwrap(),
+                service: Uuid::from_u128(0x180f),
+                characteristic: Uuid::from_u128(characteristic as u128),
+            })
+        };
+        let polled = [fqcn("11:22:33:44:55:66", 0x2a1a)];
+        let subscribed = [fqcn("AA:BB:CC:DD:EE:FF", 0x2a19), fqcn("11:22:33:44:55:66", 0x2a19)];
+        let started_at = HashMap::from([(
+            "11:22:33:44:55:66".parse().unwrap(),
+            Instant::now() - std::time::Duration::from_secs(1),
+        )]);
+
+        let tasks = TaskDto::collect(&polled, &subscribed, &started_at);
+        assert_eq!(
+            tasks
+                .iter()
+                .map(|task| (task.fqcn.to_string(), task.kind))
+                .collect::<Vec<_>>(),
+            vec![
+                (subscribed[1].to_string(), TaskKindDto::Subscribe),
+                (polled[0].to_string(), TaskKindDto::Poll),
+                (subscribed[0].to_string(), TaskKindDto::Subscribe),
+            ]
+        );
+
+        assert!(tasks[0].subscription_age_sec.unwrap() >= 1.0);
+        assert_eq!(tasks[1].subscription_age_sec, None);
+        // the subscription start hasn't been recorded yet
+        assert_eq!(tasks[2].subscription_age_sec, None);
+    }
+
+    #[test]
+    fn test_gatt_attribute() {
+        let characteristic = |uuid: u128, properties: CharPropFlags| Characteristic {
+            uuid: Uuid::from_u128(uuid),
+            service_uuid: Uuid::from_u128(0x180a),
+            properties,
+            descriptors: Default::default(),
+        };
+
+        let model_number = GattAttributeDto::new::<String>(
+            &characteristic(0x2a24, CharPropFlags::READ | CharPropFlags::NOTIFY),
+            Some(Ok(b"ATC".to_vec())),
+        );
+        let serialized = serde_json::to_value(&model_number).unwrap();
+        assert_eq!(serialized["properties"], serde_json::json!(["Read", "Notify"]));
+        assert_eq!(serialized["value_hex"], "415443");
+        assert_eq!(serialized["value_utf8_attempt"], "ATC");
+        assert!(serialized.get("error").is_none());
+
+        let binary =
+            GattAttributeDto::new::<String>(&characteristic(0x2a19, CharPropFlags::READ), Some(Ok(vec![0xff, 0xfe])));
+        assert_eq!(binary.value_hex.as_deref(), Some("fffe"));
+        assert_eq!(binary.value_utf8_attempt, None);
+
+        let write_only = GattAttributeDto::new::<String>(&characteristic(0x2a1a, CharPropFlags::WRITE), None);
+        let serialized = serde_json::to_value(&write_only).unwrap();
+        assert!(serialized["value_hex"].is_null());
+        assert!(serialized["value_utf8_attempt"].is_null());
+    }
+
+    #[test]
+    fn test_sorted_services() {
+        let service = |uuid: u128, characteristics: &[u128]| Service {
+            uuid: Uuid::from_u128(uuid),
+            primary: true,
+            characteristics: characteristics
+                .iter()
+                .map(|characteristic_uuid| Characteristic {
+                    uuid: Uuid::from_u128(*characteristic_uuid),
+                    service_uuid: Uuid::from_u128(uuid),
+                    properties: CharPropFlags::READ,
+                    descriptors: Default::default(),
+                })
+                .collect(),
+        };
+        let services = ServiceDto::sorted(BTreeSet::from([
+            service(0x180f, &[0x2a19]),
+            service(0x180a, &[0x2a29, 0x2a24]),
+        ]));
+
+        let uuids = services
+            .iter()
+            .map(|service| {
+                (
+                    service.uuid,
+                    service
+                        .characteristics
+                        .iter()
+                        .map(|characteristic| characteristic.uuid)
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            uuids,
+            vec![
+                (
+                    Uuid::from_u128(0x180a),
+                    vec![Uuid::from_u128(0x2a24), Uuid::from_u128(0x2a29)]
+                ),
+                (Uuid::from_u128(0x180f), vec![Uuid::from_u128(0x2a19)]),
+            ]
+        );
+    }
 }
@@ -1,11 +1,15 @@
 use crate::inner::conf::model::characteristic_config::CharacteristicConfig;
 use crate::inner::conf::model::service_characteristic_key::ServiceCharacteristicKey;
+use btleplug::api::BDAddr;
 use rhai::EvalAltResult;
 use std::sync::Arc;
+use std::time::Duration;
 use tracing::error;
 use uuid::Uuid;
 
 use crate::inner::conv::converter::ConversionError;
+use crate::inner::key_lock::KeyLockError;
+use crate::inner::model::fqcn::Fqcn;
 
 #[derive(Debug, thiserror::Error)]
 pub(crate) enum CollectorError {
@@ -51,18 +55,42 @@ pub(crate) enum CollectorError {
     #[error("Join Error")]
     JoinError(#[from] tokio::task::JoinError),
 
+    #[error("Configuration `{0}` not found")]
+    ConfigurationNotFound(String),
+
     #[error("Adapter `{0}` not found")]
     AdapterNotFound(String),
 
+    #[error("Adapter `default` is ambiguous: {0} adapters are present, pick one or set --default-adapter")]
+    AmbiguousDefaultAdapter(usize),
+
+    #[error("Peripheral `{0}` not found")]
+    PeripheralNotFound(BDAddr),
+
+    #[error("Characteristic `{1}` not found on peripheral `{0}`")]
+    CharacteristicNotFound(BDAddr, Uuid),
+
+    #[error("On-connect write #{0} to {1} failed: {2}")]
+    OnConnectWriteFailed(usize, ServiceCharacteristicKey, Box<CollectorError>),
+
     #[error("Unexpected IO command")]
     UnexpectedIoCommand,
 
+    #[error("Characteristic writes are disabled with --read-only-api")]
+    ReadOnlyApi,
+
     #[error("Tracing filter parse error: {0}")]
     TracingFilterParseError(#[from] tracing_subscriber::filter::ParseError),
 
+    #[error("Tracing filter reload error: {0}")]
+    TracingReloadError(#[from] tracing_subscriber::reload::Error),
+
     #[error("Tracing filter parse error: {0}")]
     AcquireError(#[from] tokio::sync::AcquireError),
 
+    #[error("Key lock error: {0}")]
+    KeyLockError(#[from] KeyLockError),
+
     #[error("No MQTT config")]
     NoMqttConfig,
 
@@ -80,6 +108,15 @@ pub(crate) enum CollectorError {
 
     #[error("{0}")]
     ApiError(String),
+
+    #[error("Batch contains more than one command for {0}")]
+    DuplicateFqcnInBatch(Fqcn),
+
+    #[error("Request timed out after {0:?}")]
+    RequestTimeout(Duration),
+
+    #[error("Characteristic {0} is disabled after repeated length mismatches")]
+    CharacteristicDisabled(Arc<Fqcn>),
 }
 
 pub(crate) type CollectorResult<T> = Result<T, CollectorError>;
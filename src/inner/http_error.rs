@@ -9,6 +9,7 @@ use tracing::error;
 use crate::inner::dto::Envelope;
 use crate::inner::error::CollectorError;
 
+#[derive(Debug)]
 pub(crate) struct HttpError<E> {
     error: E,
     status: Status,
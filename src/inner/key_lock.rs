@@ -1,10 +1,20 @@
 use std::collections::HashMap;
 use std::hash::Hash;
 use std::sync::Arc;
+use std::time::Duration;
 
 use tokio::sync::{AcquireError, Mutex, OwnedSemaphorePermit, Semaphore};
 use tracing::{error, trace};
 
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum KeyLockError {
+    #[error("Acquire error: {0}")]
+    AcquireError(#[from] AcquireError),
+
+    #[error("Timed out waiting for the key lock")]
+    Timeout,
+}
+
 #[derive(Default, Debug)]
 pub(crate) struct KeyLock<K> {
     store: Arc<Mutex<HashMap<K, Arc<Semaphore>>>>,
@@ -46,12 +56,7 @@ where
     K: Hash + Eq + Clone + Send + Sync + 'static,
 {
     pub(crate) async fn lock_for(&self, key: K) -> Result<KeyLockGuard<K>, AcquireError> {
-        let mut store = self.store.lock().await;
-        let semaphore = store
-            .entry(key.clone())
-            .or_insert_with(|| Arc::new(Semaphore::new(1)))
-            .clone();
-        drop(store);
+        let semaphore = self.get_semaphore(&key).await;
 
         Ok(KeyLockGuard {
             key_lock: self,
@@ -59,6 +64,32 @@ where
             key: key.into(),
         })
     }
+
+    /// Same as `lock_for`, but gives up after `timeout` instead of waiting forever (i.e. on a reentrant lock).
+    pub(crate) async fn lock_for_with_timeout(
+        &self,
+        key: K,
+        timeout: Duration,
+    ) -> Result<KeyLockGuard<K>, KeyLockError> {
+        let semaphore = self.get_semaphore(&key).await;
+        let permit = tokio::time::timeout(timeout, semaphore.acquire_owned())
+            .await
+            .map_err(|_| KeyLockError::Timeout)??;
+
+        Ok(KeyLockGuard {
+            key_lock: self,
+            _permit: permit,
+            key: key.into(),
+        })
+    }
+
+    async fn get_semaphore(&self, key: &K) -> Arc<Semaphore> {
+        let mut store = self.store.lock().await;
+        store
+            .entry(key.clone())
+            .or_insert_with(|| Arc::new(Semaphore::new(1)))
+            .clone()
+    }
 }
 
 #[cfg(test)]
@@ -89,4 +120,19 @@ mod tests {
 
         assert!(l.store.lock().await.is_empty());
     }
+
+    #[tokio::test]
+    async fn reentrant_lock_times_out() {
+        let l = KeyLock::<usize>::default();
+        let guard = l.lock_for(1).await.unwrap();
+
+        let result = l.lock_for_with_timeout(1, Duration::from_millis(10)).await;
+        assert!(matches!(result, Err(KeyLockError::Timeout)));
+
+        let other_key_guard = l.lock_for_with_timeout(2, Duration::from_millis(10)).await;
+        assert!(other_key_guard.is_ok());
+
+        drop(guard);
+        assert!(l.lock_for_with_timeout(1, Duration::from_millis(10)).await.is_ok());
+    }
 }
@@ -0,0 +1,49 @@
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+use crate::inner::error::CollectorResult;
+
+/// Allows changing the tracing filter of a running collector.
+pub(crate) struct LogLevelManager {
+    handle: reload::Handle<EnvFilter, Registry>,
+}
+
+impl LogLevelManager {
+    pub(crate) fn new(handle: reload::Handle<EnvFilter, Registry>) -> Self {
+        Self { handle }
+    }
+
+    pub(crate) fn get(&self) -> CollectorResult<String> {
+        Ok(self.handle.with_current(|filter| filter.to_string())?)
+    }
+
+    /// Applies a new `EnvFilter` directive string and returns the active filter.
+    pub(crate) fn set(&self, directive: &str) -> CollectorResult<String> {
+        let filter = EnvFilter::try_new(directive)?;
+        self.handle.reload(filter)?;
+        self.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inner::error::CollectorError;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn test_change_level() {
+        let (filter_layer, handle) = reload::Layer::new(EnvFilter::new("info"));
+        let _subscriber = tracing_subscriber::registry().with(filter_layer);
+        let manager = LogLevelManager::new(handle);
+
+        assert_eq!(manager.get().unwrap(), "info");
+        assert_eq!(manager.set("debug").unwrap(), "debug");
+        assert_eq!(manager.get().unwrap(), "debug");
+
+        assert!(matches!(
+            manager.set("rocket=notalevel"),
+            Err(CollectorError::TracingFilterParseError(_))
+        ));
+        assert_eq!(manager.get().unwrap(), "debug");
+    }
+}
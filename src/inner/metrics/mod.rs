@@ -1,9 +1,11 @@
 use std::fmt::{Display, Formatter};
 
-use metrics::{counter, gauge, KeyName, SharedString, Unit};
+use metrics::{counter, gauge, histogram, KeyName, Label, SharedString, Unit};
 use serde::{Deserialize, Serialize};
 
 pub(crate) mod measure_execution_time;
+#[cfg(test)]
+pub(crate) mod testing;
 
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
 pub(crate) enum MetricType {
@@ -24,7 +26,7 @@ impl Display for MetricType {
 
 pub(crate) struct StaticMetric {
     pub(crate) metric_name: &'static str,
-    unit: Unit,
+    unit: Option<Unit>,
     description: &'static str,
     metric_type: MetricType,
 }
@@ -36,17 +38,17 @@ impl StaticMetric {
             match self.metric_type {
                 MetricType::Counter => recorder.describe_counter(
                     KeyName::from(self.metric_name),
-                    Some(unit),
+                    unit,
                     SharedString::from(self.description),
                 ),
                 MetricType::Gauge => recorder.describe_gauge(
                     KeyName::from(self.metric_name),
-                    Some(unit),
+                    unit,
                     SharedString::from(self.description),
                 ),
                 MetricType::Histogram => recorder.describe_histogram(
                     KeyName::from(self.metric_name),
-                    Some(unit),
+                    unit,
                     SharedString::from(self.description),
                 ),
             };
@@ -70,97 +72,274 @@ impl StaticMetric {
             _ => panic!("Metric type mismatch"),
         }
     }
+
+    /// Adds labels, i.e. the adapter or the peripheral, to a single operation on the metric.
+    pub(crate) fn with_labels(&self, labels: Vec<Label>) -> LabeledMetric<'_> {
+        LabeledMetric { metric: self, labels }
+    }
+}
+
+pub(crate) struct LabeledMetric<'a> {
+    metric: &'a StaticMetric,
+    labels: Vec<Label>,
+}
+
+impl LabeledMetric<'_> {
+    pub(crate) fn increment(self) {
+        match self.metric.metric_type {
+            MetricType::Counter => {
+                counter!(self.metric.metric_name, self.labels).increment(1);
+            }
+            _ => panic!("Metric type mismatch"),
+        }
+    }
+
+    pub(crate) fn gauge(self, value: f64) {
+        match self.metric.metric_type {
+            MetricType::Gauge => {
+                gauge!(self.metric.metric_name, self.labels).set(value);
+            }
+            _ => panic!("Metric type mismatch"),
+        }
+    }
+
+    pub(crate) fn record(self, value: f64) {
+        match self.metric.metric_type {
+            MetricType::Histogram => {
+                histogram!(self.metric.metric_name, self.labels).record(value);
+            }
+            _ => panic!("Metric type mismatch"),
+        }
+    }
 }
 
 pub(crate) const PAYLOAD_PROCESSED_COUNT: StaticMetric = StaticMetric {
     metric_name: "collector.payload.processed.count",
-    unit: Unit::Count,
+    unit: Some(Unit::Count),
     description: "The number of processed characteristic payloads",
     metric_type: MetricType::Counter,
 };
 
 pub(crate) const EVENT_COUNT: StaticMetric = StaticMetric {
     metric_name: "collector.event.count",
-    unit: Unit::Count,
+    unit: Some(Unit::Count),
     description: "The number of received events",
     metric_type: MetricType::Counter,
 };
 
 pub(crate) const EVENT_THROTTLED_COUNT: StaticMetric = StaticMetric {
     metric_name: "collector.event.throttled.count",
-    unit: Unit::Count,
+    unit: Some(Unit::Count),
     description: "The number of throttled events",
     metric_type: MetricType::Counter,
 };
 
+pub(crate) const EVENT_DENIED_COUNT: StaticMetric = StaticMetric {
+    metric_name: "collector.event.denied.count",
+    unit: Some(Unit::Count),
+    description: "The number of events ignored by adapter allow / deny lists",
+    metric_type: MetricType::Counter,
+};
+
+pub(crate) const EVENT_UNMATCHED_COUNT: StaticMetric = StaticMetric {
+    metric_name: "collector.event.unmatched.count",
+    unit: Some(Unit::Count),
+    description: "The number of events from peripherals without a matching config",
+    metric_type: MetricType::Counter,
+};
+
+pub(crate) const EVENT_CIRCUIT_OPEN_COUNT: StaticMetric = StaticMetric {
+    metric_name: "collector.event.circuit_open.count",
+    unit: Some(Unit::Count),
+    description: "The number of events ignored because the peripheral failed to connect too many times in a row",
+    metric_type: MetricType::Counter,
+};
+
 pub(crate) const CONNECTIONS_HANDLED: StaticMetric = StaticMetric {
     metric_name: "collector.connection.handled.count",
-    unit: Unit::Count,
+    unit: Some(Unit::Count),
     description: "The number of handled connections",
     metric_type: MetricType::Counter,
 };
 
 pub(crate) const CONNECTIONS_DROPPED: StaticMetric = StaticMetric {
     metric_name: "collector.connection.dropped.count",
-    unit: Unit::Count,
+    unit: Some(Unit::Count),
     description: "The number of dropped connections",
     metric_type: MetricType::Counter,
 };
 
 pub(crate) const CONNECTING_ERRORS: StaticMetric = StaticMetric {
     metric_name: "collector.connection.error.count",
-    unit: Unit::Count,
+    unit: Some(Unit::Count),
     description: "The number of connection errors",
     metric_type: MetricType::Counter,
 };
 
+pub(crate) const SUBSCRIBE_TIMEOUT_COUNT: StaticMetric = StaticMetric {
+    metric_name: "collector.subscribe.timeout.count",
+    unit: Some(Unit::Count),
+    description: "The number of subscriptions that haven't completed within the subscribe timeout",
+    metric_type: MetricType::Counter,
+};
+
 pub(crate) const CONNECTED_PERIPHERALS: StaticMetric = StaticMetric {
     metric_name: "collector.peripheral.connected.count",
-    unit: Unit::Count,
+    unit: Some(Unit::Count),
     description: "The number of connected peripherals",
     metric_type: MetricType::Gauge,
 };
 
 pub(crate) const TOTAL_CONNECTING_DURATION: StaticMetric = StaticMetric {
     metric_name: "collector.peripheral.connecting.total.duration",
-    unit: Unit::Milliseconds,
+    unit: Some(Unit::Milliseconds),
     description: "The total time spent connecting peripherals",
     metric_type: MetricType::Histogram,
 };
 
 pub(crate) const CONNECTING_DURATION: StaticMetric = StaticMetric {
     metric_name: "collector.peripheral.connecting.duration",
-    unit: Unit::Milliseconds,
+    unit: Some(Unit::Milliseconds),
     description: "The time spent connecting peripheral",
     metric_type: MetricType::Histogram,
 };
 
 pub(crate) const CONNECTION_DURATION: StaticMetric = StaticMetric {
     metric_name: "collector.peripheral.connection.duration",
-    unit: Unit::Milliseconds,
+    unit: Some(Unit::Milliseconds),
     description: "The time peripheral stays connected",
     metric_type: MetricType::Histogram,
 };
 
+pub(crate) const FIRST_PAYLOAD_DURATION: StaticMetric = StaticMetric {
+    metric_name: "collector.characteristic.first_payload.duration",
+    unit: Some(Unit::Milliseconds),
+    description:
+        "The time from starting to poll / listen for notifications until the first payload of a characteristic",
+    metric_type: MetricType::Histogram,
+};
+
 pub(crate) const SERVICE_DISCOVERY_DURATION: StaticMetric = StaticMetric {
     metric_name: "collector.peripheral.discovery.duration",
-    unit: Unit::Milliseconds,
+    unit: Some(Unit::Milliseconds),
     description: "The time spent discovering services",
     metric_type: MetricType::Histogram,
 };
 
+pub(crate) const PAYLOAD_DROPPED_COUNT: StaticMetric = StaticMetric {
+    metric_name: "collector.payload.dropped.count",
+    unit: Some(Unit::Count),
+    description: "The number of payloads that could not be delivered to a consumer",
+    metric_type: MetricType::Counter,
+};
+
+pub(crate) const CONVERSION_LENGTH_MISMATCH_COUNT: StaticMetric = StaticMetric {
+    metric_name: "collector.conversion.length_mismatch.count",
+    unit: Some(Unit::Count),
+    description: "The number of characteristic values with unexpected length",
+    metric_type: MetricType::Counter,
+};
+
+pub(crate) const CONVERSION_ERROR_COUNT: StaticMetric = StaticMetric {
+    metric_name: "collector.conversion.error.count",
+    unit: Some(Unit::Count),
+    description: "The number of broadcast values that failed to convert",
+    metric_type: MetricType::Counter,
+};
+
+pub(crate) const PERIPHERAL_RSSI: StaticMetric = StaticMetric {
+    metric_name: "collector.peripheral.rssi",
+    // there is no dBm unit
+    unit: None,
+    description: "Peripheral received signal strength in dBm",
+    metric_type: MetricType::Histogram,
+};
+
+pub(crate) const PERIPHERAL_CACHE_ENTRIES: StaticMetric = StaticMetric {
+    metric_name: "collector.peripheral.cache.entries",
+    unit: Some(Unit::Count),
+    description: "The number of peripherals in the adapter cache",
+    metric_type: MetricType::Gauge,
+};
+
+pub(crate) const UNSUPPORTED_CHARACTERISTIC_COUNT: StaticMetric = StaticMetric {
+    metric_name: "collector.characteristic.unsupported.count",
+    unit: Some(Unit::Count),
+    description: "The number of skipped characteristics lacking the properties required by their config",
+    metric_type: MetricType::Counter,
+};
+
+pub(crate) const PERIPHERAL_LIFECYCLE_COUNT: StaticMetric = StaticMetric {
+    metric_name: "collector.peripheral.lifecycle.count",
+    unit: Some(Unit::Count),
+    description: "The number of peripheral connect / disconnect transitions",
+    metric_type: MetricType::Counter,
+};
+
+pub(crate) const METRIC_VALUE_SKIPPED_COUNT: StaticMetric = StaticMetric {
+    metric_name: "collector.metric.value.skipped.count",
+    unit: Some(Unit::Count),
+    description: "The number of characteristic values that can't be represented by their metric type",
+    metric_type: MetricType::Counter,
+};
+
+pub(crate) const SUBSCRIPTION_AGE: StaticMetric = StaticMetric {
+    metric_name: "collector.peripheral.subscription.age.seconds",
+    unit: Some(Unit::Seconds),
+    description: "Time since the peripheral was subscribed to, updated on every notification",
+    metric_type: MetricType::Gauge,
+};
+
+pub(crate) const CHARACTERISTIC_AGE: StaticMetric = StaticMetric {
+    metric_name: "collector.characteristic.age.seconds",
+    unit: Some(Unit::Seconds),
+    description: "Time since the last value of a characteristic published as a metric, updated on every scrape",
+    metric_type: MetricType::Gauge,
+};
+
+pub(crate) const CONFIGURATION_COUNT: StaticMetric = StaticMetric {
+    metric_name: "collector.configuration.count",
+    unit: Some(Unit::Count),
+    description: "The number of loaded peripheral configurations",
+    metric_type: MetricType::Gauge,
+};
+
+pub(crate) const TASK_RESTART_COUNT: StaticMetric = StaticMetric {
+    metric_name: "collector.task.restart.count",
+    unit: Some(Unit::Count),
+    description: "The number of times a failed background task, i.e. an MQTT publisher, was restarted",
+    metric_type: MetricType::Counter,
+};
+
 pub(crate) fn describe_metrics() {
     PAYLOAD_PROCESSED_COUNT.describe();
     EVENT_THROTTLED_COUNT.describe();
+    EVENT_DENIED_COUNT.describe();
+    EVENT_UNMATCHED_COUNT.describe();
+    EVENT_CIRCUIT_OPEN_COUNT.describe();
     CONNECTIONS_HANDLED.describe();
     CONNECTIONS_DROPPED.describe();
     CONNECTING_ERRORS.describe();
+    SUBSCRIBE_TIMEOUT_COUNT.describe();
     CONNECTED_PERIPHERALS.describe();
     CONNECTION_DURATION.describe();
+    FIRST_PAYLOAD_DURATION.describe();
     TOTAL_CONNECTING_DURATION.describe();
     CONNECTING_DURATION.describe();
     SERVICE_DISCOVERY_DURATION.describe();
     EVENT_COUNT.describe();
+    CONVERSION_LENGTH_MISMATCH_COUNT.describe();
+    CONVERSION_ERROR_COUNT.describe();
+    PAYLOAD_DROPPED_COUNT.describe();
+    PERIPHERAL_RSSI.describe();
+    PERIPHERAL_CACHE_ENTRIES.describe();
+    UNSUPPORTED_CHARACTERISTIC_COUNT.describe();
+    PERIPHERAL_LIFECYCLE_COUNT.describe();
+    METRIC_VALUE_SKIPPED_COUNT.describe();
+    SUBSCRIPTION_AGE.describe();
+    CHARACTERISTIC_AGE.describe();
+    CONFIGURATION_COUNT.describe();
+    TASK_RESTART_COUNT.describe();
 }
 
 impl From<StaticMetric> for KeyName {
@@ -168,3 +347,60 @@ impl From<StaticMetric> for KeyName {
         KeyName::from(value.metric_name)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use metrics_util::debugging::DebugValue;
+
+    use super::testing::{capture_metrics, metric_key, MetricsCapture};
+    use super::*;
+
+    #[test]
+    fn test_labeled_metric() {
+        let ((), recorded) = capture_metrics(async {
+            let labels = |adapter: &'static str| vec![Label::new("adapter", adapter)];
+            EVENT_COUNT.with_labels(labels("hci0")).increment();
+            EVENT_COUNT.with_labels(labels("hci0")).increment();
+            EVENT_COUNT.with_labels(labels("hci1")).increment();
+            CONNECTED_PERIPHERALS.with_labels(labels("hci0")).gauge(2.0);
+            PERIPHERAL_RSSI.with_labels(labels("hci0")).record(-60.0);
+        });
+
+        let key = |metric: &StaticMetric, adapter: &str| metric_key(metric.metric_name, &[("adapter", adapter)]);
+        assert_eq!(
+            recorded,
+            HashMap::from([
+                (key(&CONNECTED_PERIPHERALS, "hci0"), DebugValue::Gauge(2.0.into())),
+                (key(&EVENT_COUNT, "hci0"), DebugValue::Counter(2)),
+                (key(&EVENT_COUNT, "hci1"), DebugValue::Counter(1)),
+                (
+                    key(&PERIPHERAL_RSSI, "hci0"),
+                    DebugValue::Histogram(vec![(-60.0).into()])
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_units() {
+        let capture = MetricsCapture::new();
+        capture.run(async {
+            describe_metrics();
+            PERIPHERAL_RSSI.with_labels(vec![]).record(-60.0);
+            EVENT_COUNT.with_labels(vec![]).increment();
+        });
+
+        let descriptions = capture.descriptions();
+        // dBm isn't a count
+        assert_eq!(descriptions[PERIPHERAL_RSSI.metric_name].0, None);
+        assert_eq!(descriptions[EVENT_COUNT.metric_name].0, Some(Unit::Count));
+    }
+
+    #[test]
+    #[should_panic(expected = "Metric type mismatch")]
+    fn test_labeled_metric_type_mismatch() {
+        EVENT_COUNT.with_labels(vec![]).record(1.0);
+    }
+}
@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+use std::future::Future;
+
+use metrics::Unit;
+use metrics_util::debugging::{DebugValue, DebuggingRecorder, Snapshotter};
+use metrics_util::CompositeKey;
+use tokio::runtime::Runtime;
+
+/// Records the metrics emitted by the futures it runs in a local [`DebuggingRecorder`].
+pub(crate) struct MetricsCapture {
+    recorder: DebuggingRecorder,
+    snapshotter: Snapshotter,
+    runtime: Runtime,
+}
+
+impl MetricsCapture {
+    pub(crate) fn new() -> Self {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        Self {
+            recorder,
+            snapshotter,
+            runtime,
+        }
+    }
+
+    pub(crate) fn run<F: Future>(&self, future: F) -> F::Output {
+        metrics::with_local_recorder(&self.recorder, || self.runtime.block_on(future))
+    }
+
+    /// Values recorded so far, keyed by [`metric_key`]. Histograms only keep the samples recorded
+    /// since the previous call.
+    pub(crate) fn values(&self) -> HashMap<String, DebugValue> {
+        self.snapshotter
+            .snapshot()
+            .into_vec()
+            .into_iter()
+            .map(|(key, _, _, value)| (composite_key(&key), value))
+            .collect()
+    }
+
+    /// Units and descriptions the recorded metrics were registered with, keyed by [`metric_key`].
+    pub(crate) fn descriptions(&self) -> HashMap<String, (Option<Unit>, Option<String>)> {
+        self.snapshotter
+            .snapshot()
+            .into_vec()
+            .into_iter()
+            .map(|(key, unit, description, _)| (composite_key(&key), (unit, description.map(|text| text.to_string()))))
+            .collect()
+    }
+}
+
+/// Runs the future in a fresh [`MetricsCapture`] and returns its output with the recorded values.
+pub(crate) fn capture_metrics<F: Future>(future: F) -> (F::Output, HashMap<String, DebugValue>) {
+    let capture = MetricsCapture::new();
+    let output = capture.run(future);
+    (output, capture.values())
+}
+
+/// `name` for a metric without labels, `name{key=value,..}` otherwise, labels in recording order.
+pub(crate) fn metric_key(name: &str, labels: &[(&str, &str)]) -> String {
+    if labels.is_empty() {
+        return name.to_string();
+    }
+    let labels = labels
+        .iter()
+        .map(|(key, value)| format!("{key}={value}"))
+        .collect::<Vec<_>>()
+        .join(",");
+    format!("{name}{{{labels}}}")
+}
+
+fn composite_key(key: &CompositeKey) -> String {
+    let labels = key
+        .key()
+        .labels()
+        .map(|label| (label.key(), label.value()))
+        .collect::<Vec<_>>();
+    metric_key(key.key().name(), &labels)
+}
@@ -1,6 +1,7 @@
 pub(crate) mod adapter_manager;
 pub(crate) mod api;
 pub(crate) mod batch_executor;
+pub(crate) mod compression;
 pub(crate) mod conf;
 pub(crate) mod conv;
 pub(crate) mod countdown_latch;
@@ -9,7 +10,13 @@ pub(crate) mod dto;
 pub(crate) mod error;
 pub(crate) mod http_error;
 mod key_lock;
+pub(crate) mod log_level;
 pub(crate) mod metrics;
 pub(crate) mod model;
 pub(crate) mod peripheral_manager;
 pub(crate) mod publish;
+pub(crate) mod recent_log;
+pub(crate) mod request_timeout;
+pub(crate) mod supervisor;
+#[cfg(test)]
+pub(crate) mod test_fixtures;
@@ -1,16 +1,53 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::time::Duration;
+
 use anyhow::Context;
+use btleplug::api::{BDAddr, Central};
+use btleplug::platform::Adapter;
 use serde::{Deserialize, Serialize};
-use std::fmt::{Display, Formatter};
+
+use crate::inner::error::CollectorResult;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct AdapterInfo {
     pub(crate) id: String,
     pub(crate) modalias: String,
+    pub(crate) address: Option<BDAddr>,
+    pub(crate) alias: Option<String>,
+}
+
+impl AdapterInfo {
+    /// Queries the adapter and parses the `hci0 (usb:v1D6Bp0246d0537) 00:1A:7D:DA:71:13` info string it reports.
+    pub(crate) async fn from_adapter(adapter: &Adapter) -> CollectorResult<Self> {
+        let adapter_info = adapter.adapter_info().await?;
+        Ok(Self::try_from(adapter_info)?)
+    }
+
+    pub(crate) fn with_alias(self, alias: Option<String>) -> Self {
+        Self { alias, ..self }
+    }
+
+    /// A friendly name used in the API and metrics: the alias if configured, the adapter id otherwise.
+    pub(crate) fn label(&self) -> &str {
+        self.alias.as_deref().unwrap_or(&self.id)
+    }
+
+    pub(crate) fn matches(&self, adapter_id: &str) -> bool {
+        self.id == adapter_id || self.alias.as_deref() == Some(adapter_id)
+    }
 }
 
 impl Display for AdapterInfo {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}[{}]", self.id, self.modalias)
+        write!(f, "{}[{}]", self.id, self.modalias)?;
+        if let Some(address) = self.address {
+            write!(f, " {address}")?;
+        }
+        if let Some(alias) = self.alias.as_ref() {
+            write!(f, " ({alias})")?;
+        }
+        Ok(())
     }
 }
 
@@ -24,6 +61,167 @@ impl TryFrom<String> for AdapterInfo {
         let modalias = modalias.strip_prefix('(').unwrap_or(modalias);
         let modalias = modalias.strip_suffix(')').unwrap_or(modalias);
         let modalias = modalias.to_string();
-        Ok(Self { id, modalias })
+        // not every backend reports the adapter address
+        let address = pair.next().and_then(|address| BDAddr::from_str_delim(address).ok());
+        Ok(Self {
+            id,
+            modalias,
+            address,
+            alias: None,
+        })
+    }
+}
+
+/// A friendly adapter name mapping in the `hci0=garage` form.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct AdapterAlias {
+    pub(crate) adapter_id: String,
+    pub(crate) alias: String,
+}
+
+impl FromStr for AdapterAlias {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (adapter_id, alias) = s.split_once('=').context("No = delimiter")?;
+        let (adapter_id, alias) = (adapter_id.trim(), alias.trim());
+        if adapter_id.is_empty() || alias.is_empty() {
+            anyhow::bail!("Adapter id and alias must not be empty");
+        }
+
+        Ok(Self {
+            adapter_id: adapter_id.to_string(),
+            alias: alias.to_string(),
+        })
+    }
+}
+
+/// A scan restart interval in the `30m` (every adapter) or `hci0=30m` (a single adapter, by id or alias) form.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct ScanRestartInterval {
+    pub(crate) adapter_id: Option<String>,
+    pub(crate) interval: Duration,
+}
+
+impl FromStr for ScanRestartInterval {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (adapter_id, interval) = match s.split_once('=') {
+            Some((adapter_id, interval)) => (Some(adapter_id.trim()), interval.trim()),
+            None => (None, s.trim()),
+        };
+        if adapter_id == Some("") {
+            anyhow::bail!("Adapter id must not be empty");
+        }
+        let interval = humantime::parse_duration(interval).with_context(|| format!("Invalid interval `{interval}`"))?;
+        if interval.is_zero() {
+            anyhow::bail!("Interval must not be zero");
+        }
+
+        Ok(Self {
+            adapter_id: adapter_id.map(str::to_string),
+            interval,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_alias() {
+        let alias = AdapterAlias::from_str("hci0=garage").unwrap();
+        assert_eq!(
+            alias,
+            AdapterAlias {
+                adapter_id: "hci0".to_string(),
+                alias: "garage".to_string(),
+            }
+        );
+
+        assert!(AdapterAlias::from_str("hci0").is_err());
+        assert!(AdapterAlias::from_str("hci0=").is_err());
+        assert!(AdapterAlias::from_str("=garage").is_err());
+    }
+
+    #[test]
+    fn test_parse_scan_restart_interval() {
+        assert_eq!(
+            ScanRestartInterval::from_str("30m").unwrap(),
+            ScanRestartInterval {
+                adapter_id: None,
+                interval: Duration::from_secs(1800),
+            }
+        );
+        assert_eq!(
+            ScanRestartInterval::from_str("garage=90s").unwrap(),
+            ScanRestartInterval {
+                adapter_id: Some("garage".to_string()),
+                interval: Duration::from_secs(90),
+            }
+        );
+
+        assert!(ScanRestartInterval::from_str("hci0").is_err());
+        assert!(ScanRestartInterval::from_str("hci0=").is_err());
+        assert!(ScanRestartInterval::from_str("=30m").is_err());
+        assert!(ScanRestartInterval::from_str("0s").is_err());
+    }
+
+    #[test]
+    fn test_scan_restart_interval_of_adapter() {
+        use clap::Parser;
+
+        use crate::inner::conf::cmd_args::AppConf;
+
+        let app_conf = AppConf::parse_from([
+            "ble-collector",
+            "--config",
+            "config.yaml",
+            "--scan-restart-interval",
+            "garage=5m",
+            "--scan-restart-interval",
+            "30m",
+        ]);
+        let adapter = |id: &str, alias: Option<&str>| {
+            AdapterInfo::try_from(format!("{id} (usb:v1D6Bp0246d0537)"))
+                .unwrap()
+                .with_alias(alias.map(str::to_string))
+        };
+
+        assert_eq!(
+            app_conf.get_scan_restart_interval(&adapter("hci0", Some("garage"))),
+            Some(Duration::from_secs(300))
+        );
+        assert_eq!(
+            app_conf.get_scan_restart_interval(&adapter("hci1", None)),
+            Some(Duration::from_secs(1800))
+        );
+
+        let app_conf = AppConf::parse_from(["ble-collector", "--config", "config.yaml"]);
+        assert_eq!(app_conf.get_scan_restart_interval(&adapter("hci0", None)), None);
+    }
+
+    #[test]
+    fn test_serialize() {
+        let info = AdapterInfo::try_from("hci0 (usb:v1D6Bp0246d0537)".to_string())
+            .unwrap()
+            .with_alias(Some("garage".to_string()));
+
+        assert_eq!(info.label(), "garage");
+        assert!(info.matches("hci0"));
+        assert!(info.matches("garage"));
+
+        assert_eq!(
+            serde_json::to_value(&info).unwrap(),
+            json!({
+                "id": "hci0",
+                "modalias": "usb:v1D6Bp0246d0537",
+                "address": null,
+                "alias": "garage",
+            })
+        );
     }
 }
@@ -2,6 +2,7 @@ use crate::inner::conf::model::characteristic_config::CharacteristicConfig;
 use crate::inner::conv::converter::CharacteristicValue;
 use crate::inner::model::adapter_info::AdapterInfo;
 use crate::inner::model::fqcn::Fqcn;
+use crate::inner::publish::value_delta::ValueDelta;
 use chrono::Utc;
 use serde::Serialize;
 use std::fmt::{Display, Formatter};
@@ -11,6 +12,11 @@ use std::sync::Arc;
 pub(crate) struct CharacteristicPayload {
     pub(crate) created_at: chrono::DateTime<Utc>,
     pub(crate) value: CharacteristicValue,
+    /// Unconverted value, captured only if `record_raw_bytes` or MQTT `include_raw_service_data` is enabled for the
+    /// characteristic.
+    pub(crate) raw_bytes: Option<Vec<u8>>,
+    /// Change since the previous value, tracked only with `--publish-value-delta`.
+    pub(crate) delta: Option<ValueDelta>,
     pub(crate) fqcn: Arc<Fqcn>,
     pub(crate) conf: Arc<CharacteristicConfig>,
     pub(crate) adapter_info: Arc<AdapterInfo>,
@@ -1,9 +1,15 @@
+use anyhow::Context;
+use btleplug::api::bleuuid::BleUuid;
 use btleplug::api::{BDAddr, ValueNotification};
 use metrics::Label;
+use rocket::request::FromParam;
 use serde::{Deserialize, Serialize};
 use std::fmt::{Display, Formatter};
+use std::str::FromStr;
 use uuid::Uuid;
 
+use crate::inner::conf::dto::short_uuid::parse_uuid;
+
 #[derive(Ord, PartialOrd, Eq, PartialEq, Debug, Clone, Hash, Serialize, Deserialize)]
 pub(crate) struct Fqcn {
     pub(crate) peripheral: BDAddr,
@@ -11,9 +17,46 @@ pub(crate) struct Fqcn {
     pub(crate) characteristic: Uuid,
 }
 
+/// `peripheral/service/characteristic`, with standard BLE uuids in the short form, i.e.
+/// `AA:BB:CC:DD:EE:FF/0x180f/0x2a19`.
 impl Display for Fqcn {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}::{}:{}", self.peripheral, self.service, self.characteristic)
+        write!(
+            f,
+            "{}/{}/{}",
+            self.peripheral,
+            self.service.to_short_string(),
+            self.characteristic.to_short_string()
+        )
+    }
+}
+
+impl FromStr for Fqcn {
+    type Err = anyhow::Error;
+
+    /// Parses the `Display` form; uuids may be written in full or as 16-bit BLE shorthand.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split('/');
+        let (Some(peripheral), Some(service), Some(characteristic), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            anyhow::bail!("Expected `peripheral/service/characteristic`, got `{s}`");
+        };
+
+        Ok(Self {
+            peripheral: BDAddr::from_str_delim(peripheral)
+                .with_context(|| format!("Invalid address `{peripheral}`"))?,
+            service: parse_uuid(service).map_err(anyhow::Error::msg)?,
+            characteristic: parse_uuid(characteristic).map_err(anyhow::Error::msg)?,
+        })
+    }
+}
+
+impl<'a> FromParam<'a> for Fqcn {
+    type Error = anyhow::Error;
+
+    fn from_param(param: &'a str) -> Result<Self, Self::Error> {
+        Fqcn::from_str(param)
     }
 }
 
@@ -42,3 +85,88 @@ impl Fqcn {
         Label::new("characteristic", self.characteristic.to_string())
     }
 }
+
+/// Serializes the `Display` form of the fqcn; both the string and the structured form are accepted.
+#[cfg(feature = "human-readable-fqcn")]
+pub(crate) mod human_readable {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use super::Fqcn;
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum FqcnRepr {
+        Str(String),
+        Struct(Fqcn),
+    }
+
+    pub(crate) fn serialize<S: Serializer>(fqcn: &Fqcn, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(fqcn)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Fqcn, D::Error> {
+        match FqcnRepr::deserialize(deserializer)? {
+            FqcnRepr::Str(value) => value.parse().map_err(serde::de::Error::custom),
+            FqcnRepr::Struct(fqcn) => Ok(fqcn),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use btleplug::api::bleuuid::{uuid_from_u16, uuid_from_u32};
+
+    use super::*;
+
+    #[test]
+    fn test_display_short_uuids() {
+        let fqcn = Fqcn {
+            peripheral: "11:22:33:44:55:66".parse().unwrap(),
+            service: "0000180f-0000-1000-8000-00805f9b34fb".parse().unwrap(),
+            characteristic: "a0e4d2ba-0002-8000-8789-00805f9b34fb".parse().unwrap(),
+        };
+        assert_eq!(
+            fqcn.to_string(),
+            "11:22:33:44:55:66/0x180f/a0e4d2ba-0002-8000-8789-00805f9b34fb"
+        );
+    }
+
+    #[test]
+    fn test_from_str_round_trip() {
+        let fqcn = Fqcn {
+            peripheral: "11:22:33:44:55:66".parse().unwrap(),
+            service: "0000180f-0000-1000-8000-00805f9b34fb".parse().unwrap(),
+            characteristic: "a0e4d2ba-0002-8000-8789-00805f9b34fb".parse().unwrap(),
+        };
+        assert_eq!(Fqcn::from_str(&fqcn.to_string()).unwrap(), fqcn);
+
+        let short = fqcn.with_characteristic(uuid_from_u16(0x180f), uuid_from_u32(0x11223344));
+        assert_eq!(short.to_string(), "11:22:33:44:55:66/0x180f/0x11223344");
+        assert_eq!(Fqcn::from_str(&short.to_string()).unwrap(), short);
+
+        // full UUIDs are accepted as well
+        assert_eq!(
+            Fqcn::from_str(
+                "11:22:33:44:55:66/0000180f-0000-1000-8000-00805f9b34fb/a0e4d2ba-0002-8000-8789-00805f9b34fb"
+            )
+            .unwrap(),
+            fqcn
+        );
+    }
+
+    #[test]
+    fn test_from_str_malformed() {
+        for malformed in [
+            "",
+            "11:22:33:44:55:66",
+            "11:22:33:44:55:66/0x180f",
+            "11:22:33:44:55:66/0x180f/0x2a19/0x2a1a",
+            "garbage/0x180f/0x2a19",
+            "11:22:33:44:55:66/0xZZZZ/0x2a19",
+            "11:22:33:44:55:66/0x180f/not-a-uuid",
+            "11:22:33:44:55:66::0x180f:0x2a19",
+        ] {
+            assert!(Fqcn::from_str(malformed).is_err(), "{malformed} must not be parsed");
+        }
+    }
+}
@@ -1,9 +1,11 @@
 use std::collections::HashMap;
 use std::fmt::Display;
+use std::str::FromStr;
 
 use anyhow::Context;
 use btleplug::api::BDAddr;
 use btleplug::platform::PeripheralId;
+use rocket::request::FromParam;
 
 #[derive(Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
 pub(crate) struct PeripheralKey {
@@ -40,3 +42,47 @@ impl TryFrom<&PeripheralId> for PeripheralKey {
         })
     }
 }
+
+impl FromStr for PeripheralKey {
+    type Err = anyhow::Error;
+
+    /// Parses `adapter_id/address`, i.e. `hci0/AA:BB:CC:DD:EE:FF`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (adapter, address) = s.split_once('/').context("No / delimiter")?;
+        if adapter.is_empty() {
+            anyhow::bail!("Empty adapter id");
+        }
+        let address = BDAddr::from_str_delim(address)?;
+
+        Ok(Self {
+            adapter_id: adapter.to_string(),
+            peripheral_address: address,
+            name: None,
+        })
+    }
+}
+
+impl<'a> FromParam<'a> for PeripheralKey {
+    type Error = anyhow::Error;
+
+    fn from_param(param: &'a str) -> Result<Self, Self::Error> {
+        PeripheralKey::from_str(param)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_str() {
+        let key = PeripheralKey::from_str("hci0/AA:BB:CC:DD:EE:FF").unwrap();
+        assert_eq!(key.adapter_id, "hci0");
+        assert_eq!(key.peripheral_address, "AA:BB:CC:DD:EE:FF".parse().unwrap());
+        assert_eq!(key.name, None);
+
+        assert!(PeripheralKey::from_str("AA:BB:CC:DD:EE:FF").is_err());
+        assert!(PeripheralKey::from_str("/AA:BB:CC:DD:EE:FF").is_err());
+        assert!(PeripheralKey::from_str("hci0/garbage").is_err());
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use metrics::gauge;
+use retainer::Cache;
+use tracing::debug;
+
+use crate::inner::metrics::PERIPHERAL_CACHE_ENTRIES;
+
+/// TTL cache with an optional cap on the number of entries.
+/// When the cap is reached, the least recently accessed entry is evicted before inserting a new one.
+pub(super) struct BoundedCache<K, V> {
+    cache: Cache<K, V>,
+    last_access: Mutex<HashMap<K, Instant>>,
+    max_entries: Option<usize>,
+    ttl: Duration,
+    adapter: String,
+}
+
+impl<K, V> BoundedCache<K, V>
+where
+    K: Ord + Hash + Clone + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    pub(super) fn new(ttl: Duration, max_entries: Option<usize>, adapter: &str) -> Self {
+        Self {
+            cache: Cache::new(),
+            last_access: Default::default(),
+            max_entries,
+            ttl,
+            adapter: adapter.to_string(),
+        }
+    }
+
+    /// Same as `Cache::monitor`, but the entries gauge is also updated after every purge of expired entries.
+    pub(super) async fn monitor(&self, sample: usize, threshold: f64, frequency: Duration) {
+        loop {
+            tokio::time::sleep(frequency).await;
+            self.purge(sample, threshold).await;
+        }
+    }
+
+    async fn purge(&self, sample: usize, threshold: f64) {
+        self.cache.purge(sample, threshold).await;
+        self.report_len().await;
+    }
+
+    pub(super) async fn get(&self, key: &K) -> Option<V> {
+        let value = self.cache.get(key).await.map(|value| V::clone(&value));
+        if value.is_some() && self.max_entries.is_some() {
+            self.last_access.lock().unwrap().insert(key.clone(), Instant::now());
+        }
+        value
+    }
+
+    pub(super) async fn insert(&self, key: K, value: V) {
+        if let Some(max_entries) = self.max_entries {
+            for evicted in self.track(key.clone(), max_entries) {
+                debug!("Evicting peripheral cache entry");
+                self.cache.remove(&evicted).await;
+            }
+        }
+
+        self.cache.insert(key, value, self.ttl).await;
+        self.report_len().await;
+    }
+
+    /// Marks the key as accessed and returns the keys that must be evicted to fit it.
+    /// Keys of expired entries are still tracked, so they occupy a slot until evicted; that keeps tracking bounded.
+    fn track(&self, key: K, max_entries: usize) -> Vec<K> {
+        let mut last_access = self.last_access.lock().unwrap();
+        let mut evicted = vec![];
+        if !last_access.contains_key(&key) {
+            while !last_access.is_empty() && last_access.len() >= max_entries {
+                let Some(oldest) = last_access
+                    .iter()
+                    .min_by_key(|(_, accessed_at)| **accessed_at)
+                    .map(|(key, _)| key.clone())
+                else {
+                    break;
+                };
+                last_access.remove(&oldest);
+                evicted.push(oldest);
+            }
+        }
+        last_access.insert(key, Instant::now());
+        evicted
+    }
+
+    async fn report_len(&self) {
+        let len = self.cache.len().await;
+        gauge!(PERIPHERAL_CACHE_ENTRIES.metric_name, "adapter" => self.adapter.clone()).set(len as f64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use metrics_util::debugging::DebugValue;
+
+    use super::*;
+    use crate::inner::metrics::testing::{metric_key, MetricsCapture};
+
+    fn cache_entries_gauge(metrics: &MetricsCapture) -> Option<DebugValue> {
+        metrics.values().remove(&metric_key(
+            PERIPHERAL_CACHE_ENTRIES.metric_name,
+            &[("adapter", "hci0")],
+        ))
+    }
+
+    #[test]
+    fn test_gauge_reflects_entries() {
+        let metrics = MetricsCapture::new();
+        let cache = BoundedCache::new(Duration::from_secs(60), None, "hci0");
+
+        metrics.run(async {
+            for key in 0..3u8 {
+                cache.insert(key, key).await;
+            }
+            // re-inserting an existing key doesn't change the number of entries
+            cache.insert(0, 42).await;
+        });
+
+        assert_eq!(cache_entries_gauge(&metrics), Some(DebugValue::Gauge(3.0.into())));
+    }
+
+    #[test]
+    fn test_gauge_drops_on_expiry() {
+        let metrics = MetricsCapture::new();
+        let cache = BoundedCache::new(Duration::from_millis(1), None, "hci0");
+
+        metrics.run(async {
+            for key in 0..3u8 {
+                cache.insert(key, key).await;
+            }
+        });
+        assert_eq!(cache_entries_gauge(&metrics), Some(DebugValue::Gauge(3.0.into())));
+
+        metrics.run(async {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            cache.purge(3, 0.0).await;
+        });
+        assert_eq!(cache_entries_gauge(&metrics), Some(DebugValue::Gauge(0.0.into())));
+    }
+
+    #[test]
+    fn test_cap_evicts_least_recently_accessed() {
+        let metrics = MetricsCapture::new();
+        let cache = BoundedCache::new(Duration::from_secs(60), Some(2), "hci0");
+
+        metrics.run(async {
+            cache.insert(1u8, 1u8).await;
+            tokio::time::sleep(Duration::from_millis(2)).await;
+            cache.insert(2, 2).await;
+            tokio::time::sleep(Duration::from_millis(2)).await;
+            assert_eq!(cache.get(&1).await, Some(1));
+
+            cache.insert(3, 3).await;
+
+            assert_eq!(cache.get(&1).await, Some(1));
+            assert_eq!(cache.get(&2).await, None);
+            assert_eq!(cache.get(&3).await, Some(3));
+        });
+
+        assert_eq!(cache_entries_gauge(&metrics), Some(DebugValue::Gauge(2.0.into())));
+    }
+}
@@ -0,0 +1,270 @@
+use std::sync::Arc;
+
+use btleplug::api::BDAddr;
+use tracing::{debug, warn};
+
+use crate::inner::conf::dto::characteristic::AdvertisementSource;
+use crate::inner::conf::model::characteristic_config::CharacteristicConfig;
+use crate::inner::conf::model::flat_peripheral_config::FlatPeripheralConfig;
+use crate::inner::conv::converter::CharacteristicValue;
+use crate::inner::error::CollectorResult;
+use crate::inner::metrics::CONVERSION_ERROR_COUNT;
+use crate::inner::model::characteristic_payload::CharacteristicPayload;
+use crate::inner::model::collector_event::CollectorEvent;
+use crate::inner::model::fqcn::Fqcn;
+use crate::inner::model::peripheral_key::PeripheralKey;
+use crate::inner::peripheral_manager::ext::Advertisement;
+use crate::inner::peripheral_manager::PeripheralManager;
+
+#[derive(Debug)]
+pub(super) struct BroadcastValue {
+    pub(super) fqcn: Arc<Fqcn>,
+    pub(super) conf: Arc<CharacteristicConfig>,
+    pub(super) value: Vec<u8>,
+}
+
+/// Extracts the bytes of a `Broadcast` characteristic; `None` if the advertisement doesn't carry them.
+fn extract_bytes(conf: &CharacteristicConfig, advertisement: &Advertisement) -> Option<Vec<u8>> {
+    let CharacteristicConfig::Broadcast {
+        service_uuid,
+        source,
+        offset,
+        length,
+        ..
+    } = conf
+    else {
+        return None;
+    };
+
+    let data = match (source, advertisement) {
+        (AdvertisementSource::ServiceData, Advertisement::ServiceData(service_data)) => service_data.get(service_uuid),
+        (AdvertisementSource::ManufacturerData(company_id), Advertisement::ManufacturerData(manufacturer_data)) => {
+            manufacturer_data.get(company_id)
+        }
+        _ => None,
+    }?;
+
+    let end = match length {
+        Some(length) => offset.checked_add(*length)?,
+        None => data.len(),
+    };
+    data.get(*offset..end).map(<[u8]>::to_vec)
+}
+
+/// Returns the raw values of all `Broadcast` characteristics carried by the advertisement.
+pub(super) fn decode_advertisement(
+    peripheral_config: &FlatPeripheralConfig,
+    address: BDAddr,
+    advertisement: &Advertisement,
+) -> Vec<BroadcastValue> {
+    peripheral_config
+        .broadcast_confs()
+        .filter_map(|conf| {
+            let value = extract_bytes(conf, advertisement)?;
+            let CharacteristicConfig::Broadcast { service_uuid, uuid, .. } = conf.as_ref() else {
+                return None;
+            };
+            Some(BroadcastValue {
+                fqcn: Arc::new(Fqcn {
+                    peripheral: address,
+                    service: *service_uuid,
+                    characteristic: *uuid,
+                }),
+                conf: Arc::clone(conf),
+                value,
+            })
+        })
+        .collect()
+}
+
+/// Skips a value that failed to convert, so a single malformed advertisement doesn't end the discovery.
+fn skip_conversion_error(
+    fqcn: &Fqcn,
+    converted: CollectorResult<Option<CharacteristicValue>>,
+) -> Option<CharacteristicValue> {
+    match converted {
+        Ok(value) => value,
+        Err(err) => {
+            warn!(%fqcn, "Failed to convert broadcast value: {err}");
+            CONVERSION_ERROR_COUNT.increment();
+            None
+        }
+    }
+}
+
+impl PeripheralManager {
+    /// Publishes the `Broadcast` characteristics carried by the advertisement without connecting to the peripheral.
+    pub(super) async fn handle_advertisement(
+        &self,
+        peripheral_key: &PeripheralKey,
+        advertisement: &Advertisement<'_>,
+    ) -> CollectorResult<()> {
+        let Some(peripheral_config) = self.configuration_manager.get_matching_config(peripheral_key).await else {
+            return Ok(());
+        };
+
+        for BroadcastValue { fqcn, conf, value } in
+            decode_advertisement(&peripheral_config, peripheral_key.peripheral_address, advertisement)
+        {
+            if self.length_mismatch_tracker.is_disabled(&fqcn) {
+                continue;
+            }
+
+            let raw_bytes = conf.captures_raw_bytes().then(|| value.clone());
+            let Some(value) = skip_conversion_error(&fqcn, self.convert_value(&fqcn, conf.converter(), value)) else {
+                continue;
+            };
+            debug!(%fqcn, %value, "Decoded broadcast value");
+
+            let payload = CharacteristicPayload {
+                adapter_info: self.adapter_info.clone(),
+                created_at: chrono::offset::Utc::now(),
+                delta: self.track_value_delta(&fqcn, &value),
+                value,
+                raw_bytes,
+                fqcn,
+                conf,
+            };
+            self.fanout_sender.send(CollectorEvent::Payload(payload.into())).await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use uuid::Uuid;
+
+    use metrics_util::debugging::DebugValue;
+
+    use crate::inner::conf::dto::peripheral::PeripheralConfigDto;
+    use crate::inner::error::CollectorError;
+    use crate::inner::metrics::testing::capture_metrics;
+
+    use super::*;
+
+    fn peripheral_config() -> FlatPeripheralConfig {
+        let dto: PeripheralConfigDto = serde_yaml::from_str(
+            r#"
+            name: 'Thermometer'
+            device_name: !StartsWith 'ATC'
+            services:
+              - uuid: '0x181A'
+                name: 'Environmental Sensing'
+                default_delay: 10s
+                default_history_size: 10
+                characteristics:
+                  - !Broadcast
+                    name: 'Temperature'
+                    uuid: '0x2A6E'
+                    offset: 6
+                    length: 2
+                    converter: !Signed { l: 2, m: 1, d: -2, b: 0 }
+                  - !Broadcast
+                    name: 'Battery'
+                    uuid: '0x2A19'
+                    source: !ManufacturerData 1177
+                    offset: 1
+                    length: 1
+                    converter: !Unsigned { l: 1, m: 1, d: 0, b: 0 }
+            "#,
+        )
+        .unwrap();
+        FlatPeripheralConfig::try_from(dto).unwrap()
+    }
+
+    #[test]
+    fn test_decode_service_data_advertisement() {
+        let config = peripheral_config();
+        assert!(config.is_broadcast_only());
+
+        let address: BDAddr = "A4:C1:38:00:11:22".parse().unwrap();
+        let service_uuid: Uuid = "0000181a-0000-1000-8000-00805f9b34fb".parse().unwrap();
+        // MAC (6 bytes), then 23.45°C as little-endian i16
+        let service_data = HashMap::from([(service_uuid, vec![0xA4, 0xC1, 0x38, 0x00, 0x11, 0x22, 0x29, 0x09])]);
+
+        let values = decode_advertisement(&config, address, &Advertisement::ServiceData(&service_data));
+        assert_eq!(values.len(), 1);
+        let BroadcastValue { fqcn, conf, value } = &values[0];
+        assert_eq!(fqcn.peripheral, address);
+        assert_eq!(fqcn.service, service_uuid);
+        assert_eq!(fqcn.characteristic.to_string(), "00002a6e-0000-1000-8000-00805f9b34fb");
+        assert_eq!(conf.name().unwrap().as_str(), "Temperature");
+        assert_eq!(value, &vec![0x29, 0x09]);
+
+        let CharacteristicValue::F64(temperature) = conf.converter().convert(value.clone()).unwrap() else {
+            panic!("Expected a float value");
+        };
+        assert!((temperature - 23.45).abs() < 1e-9);
+
+        // too short to carry the value
+        let service_data = HashMap::from([(service_uuid, vec![0xA4, 0xC1, 0x38])]);
+        assert!(decode_advertisement(&config, address, &Advertisement::ServiceData(&service_data)).is_empty());
+    }
+
+    #[test]
+    fn test_decode_manufacturer_data_advertisement() {
+        let config = peripheral_config();
+        let address: BDAddr = "A4:C1:38:00:11:22".parse().unwrap();
+        let manufacturer_data = HashMap::from([(1177, vec![0x01, 0x5A]), (76, vec![0x02, 0x15])]);
+
+        let values = decode_advertisement(&config, address, &Advertisement::ManufacturerData(&manufacturer_data));
+        assert_eq!(values.len(), 1);
+        assert_eq!(values[0].conf.name().unwrap().as_str(), "Battery");
+        assert_eq!(values[0].value, vec![0x5A]);
+    }
+
+    #[test]
+    fn test_conversion_error_is_skipped() {
+        let dto: PeripheralConfigDto = serde_yaml::from_str(
+            r#"
+            name: 'Clock'
+            device_name: !Equals 'Clock'
+            services:
+              - uuid: '0x1805'
+                default_delay: 10s
+                default_history_size: 10
+                characteristics:
+                  - !Broadcast
+                    name: 'Current Time'
+                    uuid: '0x2A2B'
+                    converter: !Timestamp { format: UnixMilliseconds }
+            "#,
+        )
+        .unwrap();
+        let config = FlatPeripheralConfig::try_from(dto).unwrap();
+        let address: BDAddr = "A4:C1:38:00:11:22".parse().unwrap();
+        let service_uuid: Uuid = "00001805-0000-1000-8000-00805f9b34fb".parse().unwrap();
+
+        let decode = |value: Vec<u8>| {
+            let service_data = HashMap::from([(service_uuid, value)]);
+            let mut values = decode_advertisement(&config, address, &Advertisement::ServiceData(&service_data));
+            let BroadcastValue { fqcn, conf, value } = values.pop().unwrap();
+            let converted = conf.converter().convert(value).map(Some).map_err(CollectorError::from);
+            skip_conversion_error(&fqcn, converted)
+        };
+
+        let (values, recorded) = capture_metrics(async {
+            vec![
+                // out of the timestamp range
+                decode(vec![0xFF; 8]),
+                // the following advertisements are still decoded
+                decode(1_700_000_000_000u64.to_le_bytes().to_vec()),
+            ]
+        });
+        assert_eq!(
+            values,
+            vec![
+                None,
+                Some(CharacteristicValue::Utf8("2023-11-14T22:13:20Z".to_string()))
+            ]
+        );
+        assert_eq!(
+            recorded.into_iter().collect::<Vec<_>>(),
+            vec![(CONVERSION_ERROR_COUNT.metric_name.to_string(), DebugValue::Counter(1))]
+        );
+    }
+}
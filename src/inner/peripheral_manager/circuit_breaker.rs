@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use btleplug::api::BDAddr;
+use tracing::warn;
+
+use crate::inner::dto::CircuitBreakerDto;
+
+#[derive(Debug, Default, Clone, Copy)]
+struct BreakerState {
+    consecutive_failures: u32,
+    /// How many times in a row the breaker has opened; the cooldown doubles with every trip.
+    trips: u32,
+    open_until: Option<Instant>,
+}
+
+/// Stops connecting to peripherals that failed to connect `threshold` times in a row, so a broken device doesn't
+/// cause a connect attempt on every advertisement. A single failure after the cooldown opens the breaker again.
+#[derive(Debug)]
+pub(super) struct CircuitBreaker {
+    threshold: u32,
+    min_cooldown: Duration,
+    max_cooldown: Duration,
+    states: Mutex<HashMap<BDAddr, BreakerState>>,
+}
+
+impl CircuitBreaker {
+    pub(super) fn new(threshold: u32, min_cooldown: Duration, max_cooldown: Duration) -> Self {
+        Self {
+            threshold,
+            min_cooldown,
+            max_cooldown,
+            states: Default::default(),
+        }
+    }
+
+    pub(super) fn is_open(&self, address: &BDAddr, now: Instant) -> bool {
+        let states = self.states.lock().unwrap();
+        states
+            .get(address)
+            .and_then(|state| state.open_until)
+            .is_some_and(|open_until| open_until > now)
+    }
+
+    pub(super) fn record_success(&self, address: &BDAddr) {
+        self.states.lock().unwrap().remove(address);
+    }
+
+    pub(super) fn record_failure(&self, address: BDAddr, now: Instant) {
+        if self.threshold == 0 {
+            return;
+        }
+
+        let mut states = self.states.lock().unwrap();
+        let state = states.entry(address).or_default();
+        state.consecutive_failures += 1;
+        if state.consecutive_failures < self.threshold {
+            return;
+        }
+
+        let cooldown = self
+            .min_cooldown
+            .saturating_mul(2u32.saturating_pow(state.trips))
+            .min(self.max_cooldown);
+        state.trips += 1;
+        state.open_until = Some(now + cooldown);
+        warn!(
+            peripheral = %address,
+            failures = state.consecutive_failures,
+            "Too many connect failures, not connecting for {cooldown:?}"
+        );
+    }
+
+    pub(super) fn state(&self, address: &BDAddr, now: Instant) -> CircuitBreakerDto {
+        let state = self.states.lock().unwrap().get(address).copied().unwrap_or_default();
+        let retry_in = state
+            .open_until
+            .map(|open_until| open_until.saturating_duration_since(now))
+            .filter(|retry_in| !retry_in.is_zero());
+        CircuitBreakerDto {
+            open: retry_in.is_some(),
+            consecutive_failures: state.consecutive_failures,
+            trips: state.trips,
+            retry_in_ms: retry_in.map(|retry_in| retry_in.as_millis() as u64),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_advertisements_are_ignored_until_cooldown_elapses() {
+        let breaker = CircuitBreaker::new(3, Duration::from_secs(10), Duration::from_secs(25));
+        let address: BDAddr = "A4:C1:38:00:11:22".parse().unwrap();
+        let other: BDAddr = "A4:C1:38:00:11:23".parse().unwrap();
+        let now = Instant::now();
+
+        breaker.record_failure(address, now);
+        breaker.record_failure(address, now);
+        assert!(!breaker.is_open(&address, now));
+
+        // the third failure opens the breaker for min_cooldown
+        breaker.record_failure(address, now);
+        assert!(breaker.is_open(&address, now));
+        assert!(breaker.is_open(&address, now + Duration::from_secs(9)));
+        assert!(!breaker.is_open(&other, now));
+        assert_eq!(breaker.state(&address, now).retry_in_ms, Some(10_000));
+
+        // half-open: a single failure opens it again, with a doubled cooldown
+        let now = now + Duration::from_secs(10);
+        assert!(!breaker.is_open(&address, now));
+        breaker.record_failure(address, now);
+        assert!(breaker.is_open(&address, now + Duration::from_secs(19)));
+        assert!(!breaker.is_open(&address, now + Duration::from_secs(20)));
+
+        // capped at max_cooldown
+        let now = now + Duration::from_secs(20);
+        breaker.record_failure(address, now);
+        assert_eq!(breaker.state(&address, now).retry_in_ms, Some(25_000));
+
+        let state = breaker.state(&address, now);
+        assert!(state.open);
+        assert_eq!((state.consecutive_failures, state.trips), (5, 3));
+
+        breaker.record_success(&address);
+        assert!(!breaker.is_open(&address, now));
+        assert_eq!(breaker.state(&address, now).consecutive_failures, 0);
+    }
+}
@@ -1,20 +1,29 @@
 use std::collections::hash_map::Entry::{Occupied, Vacant};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::pin;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use anyhow::Context;
-use btleplug::api::Peripheral as _;
+use btleplug::api::{BDAddr, Characteristic, Peripheral as _};
 use btleplug::platform::Peripheral;
-use futures_util::StreamExt;
+use futures_util::{stream, Stream, StreamExt};
+use tokio::sync::Mutex;
 use tokio::time::timeout;
-use tracing::{debug, info, info_span, warn, Span};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, info_span, warn, Instrument, Span};
 
 use crate::inner::conf::model::characteristic_config::CharacteristicConfig;
 use crate::inner::conf::model::flat_peripheral_config::FlatPeripheralConfig;
+use crate::inner::conv::converter::{CharacteristicValue, ConversionError, Converter};
+use crate::inner::conv::length_mismatch_tracker::LengthMismatchTracker;
 use crate::inner::error::{CollectorError, CollectorResult};
 use crate::inner::metrics::measure_execution_time::Measure;
 use crate::inner::metrics::{
     CONNECTED_PERIPHERALS, CONNECTING_DURATION, CONNECTIONS_DROPPED, CONNECTIONS_HANDLED, CONNECTION_DURATION,
-    TOTAL_CONNECTING_DURATION,
+    CONVERSION_LENGTH_MISMATCH_COUNT, SUBSCRIBE_TIMEOUT_COUNT, SUBSCRIPTION_AGE, TOTAL_CONNECTING_DURATION,
+    UNSUPPORTED_CHARACTERISTIC_COUNT,
 };
 use crate::inner::model::characteristic_payload::CharacteristicPayload;
 use crate::inner::model::collector_event::CollectorEvent;
@@ -22,10 +31,14 @@ use crate::inner::model::connect_peripheral_request::ConnectPeripheralRequest;
 use crate::inner::model::fqcn::Fqcn;
 use crate::inner::model::peripheral_key::PeripheralKey;
 use crate::inner::peripheral_manager::connection_context::ConnectionContext;
+use crate::inner::peripheral_manager::drainable_task::DrainableTask;
+use crate::inner::peripheral_manager::first_payload::FirstPayloadTimer;
+use crate::inner::peripheral_manager::on_connect::disconnect_on_failure;
 use crate::inner::peripheral_manager::PeripheralManager;
+use crate::inner::publish::value_delta::ValueDelta;
 
 impl PeripheralManager {
-    #[tracing::instrument(level = "info", skip_all, parent = & _parent_span, err)]
+    #[tracing::instrument(level = "info", skip_all, parent = & _parent_span, err, fields(config = %peripheral_config))]
     pub(super) async fn connect_all(
         self: Arc<Self>,
         peripheral_key: Arc<PeripheralKey>,
@@ -57,10 +70,22 @@ impl PeripheralManager {
                 characteristic: characteristic.uuid,
             });
 
+            if !self
+                .check_characteristic_is_supported(&characteristic, &characteristic_config, &fqcn)
+                .await
+            {
+                continue;
+            }
+
             if self.check_characteristic_is_handled(fqcn.as_ref()).await {
                 continue;
             };
 
+            if self.length_mismatch_tracker.is_disabled(fqcn.as_ref()) {
+                debug!(%fqcn, "Skipping disabled characteristic");
+                continue;
+            }
+
             self.fanout_sender
                 .send(CollectorEvent::Connect(ConnectPeripheralRequest {
                     peripheral_key: peripheral_key.clone(),
@@ -114,13 +139,16 @@ impl PeripheralManager {
                     .entry(ctx.fqcn.peripheral)
                     .or_insert_with(|| {
                         let span = info_span!(parent: self.span.clone(), "block_on_notifying", spawn_type = "notify", peripheral = % ctx.fqcn.peripheral);
-                        tokio::spawn(async move {
+                        DrainableTask::spawn(|token| async move {
                             let _ = self_clone
                                 .clone()
-                                .block_on_notifying(ctx, parent_span)
+                                .block_on_notifying(ctx, token.clone(), parent_span)
                                 .measure_execution_time(CONNECTION_DURATION, span)
                                 .await;
-                            self_clone.abort_subscription(fqcn.clone()).await;
+                            // a drained task has already been removed, the map may hold its replacement by now
+                            if !token.is_cancelled() {
+                                self_clone.abort_subscription(fqcn.clone()).await;
+                            }
                         })
                     });
             }
@@ -130,17 +158,25 @@ impl PeripheralManager {
                     .await
                     .entry(fqcn.clone())
                     .or_insert_with(|| {
-                        tokio::spawn(async move {
+                        DrainableTask::spawn(|token| async move {
                             let span = info_span!(parent: parent_span.clone(), "block_on_polling", spawn_type = "poll");
                             let _ = self_clone
                                 .clone()
-                                .block_on_polling(ctx, parent_span)
+                                .block_on_polling(ctx, token.clone(), parent_span)
                                 .measure_execution_time(CONNECTION_DURATION, span)
                                 .await;
-                            self_clone.abort_polling(fqcn.clone()).await;
+                            // a drained task has already been removed, the map may hold its replacement by now
+                            if !token.is_cancelled() {
+                                self_clone.abort_polling(fqcn.clone()).await;
+                            }
                         })
                     });
             }
+            CharacteristicConfig::Broadcast { .. } => {
+                return Err(CollectorError::UnexpectedCharacteristicConfiguration(
+                    ctx.characteristic_config.clone(),
+                ));
+            }
         }
         Ok(())
     }
@@ -159,15 +195,33 @@ impl PeripheralManager {
         }
         drop(subscribed_characteristics);
 
-        ctx.peripheral.subscribe(&ctx.characteristic).await?;
+        subscribe_or_rollback(
+            &self.subscribed_characteristics,
+            &ctx.fqcn,
+            self.app_conf.subscribe_timeout,
+            ctx.peripheral.subscribe(&ctx.characteristic),
+        )
+        .await?;
+        self.subscription_started_at
+            .lock()
+            .await
+            .entry(ctx.fqcn.peripheral)
+            .or_insert_with(Instant::now);
         let existing_connections = self.get_all_connected_peripherals().await;
         info!(%existing_connections, "Subscribed on characteristic");
 
         Ok(())
     }
+    /// Connects unless already connected, then runs the on-connect writes of the matching config.
     #[tracing::instrument(level = "info", skip_all, err)]
     pub(super) async fn connect(&self, peripheral: &Peripheral) -> CollectorResult<()> {
-        let _connect_permit = self.connection_lock.lock_for(peripheral.address()).await?;
+        // a concurrent holder keeps the lock for the connect itself, but also for the service discovery and the
+        // on-connect writes, so waiting is bounded rather than sized to outlast it: a reentrant lock surfaces as a
+        // timeout, and a caller that gives up retries later and finds the connection established
+        let _connect_permit = self
+            .connection_lock
+            .lock_for_with_timeout(peripheral.address(), self.app_conf.peripheral_connect_timeout / 2)
+            .await?;
         if peripheral.is_connected().await? {
             debug!("Already connected");
             return Ok(());
@@ -185,72 +239,234 @@ impl PeripheralManager {
             info!("Forced service discovery for peripheral completed");
         }
 
+        // also when the connection was established by an API request rather than by discovery
+        let peripheral_key = self.build_peripheral_key(&peripheral.id()).await?;
+        if let Some(peripheral_config) = self.configuration_manager.get_matching_config(&peripheral_key).await {
+            disconnect_on_failure(self.run_on_connect(peripheral, &peripheral_config.on_connect), || {
+                peripheral.disconnect()
+            })
+            .await?;
+        }
+
         Ok(())
     }
 
+    /// Returns `false` if the characteristic lacks the properties required by its config; warns once per fqcn.
+    pub(super) async fn check_characteristic_is_supported(
+        &self,
+        characteristic: &Characteristic,
+        characteristic_config: &CharacteristicConfig,
+        fqcn: &Arc<Fqcn>,
+    ) -> bool {
+        if characteristic_config.is_supported_by(characteristic.properties) {
+            return true;
+        }
+
+        UNSUPPORTED_CHARACTERISTIC_COUNT.increment();
+        if self.unsupported_characteristics.lock().await.insert(Arc::clone(fqcn)) {
+            warn!(
+                %fqcn,
+                properties = ?characteristic.properties,
+                "Skipping characteristic: it doesn't support the configured operation"
+            );
+        }
+        false
+    }
+
     async fn check_characteristic_is_handled(&self, fqcn: &Fqcn) -> bool {
         self.poll_handle_map.lock().await.get(fqcn).is_some()
             || self.subscribed_characteristics.lock().await.get(fqcn).is_some()
     }
 
-    async fn abort_subscription(&self, fqcn: Arc<Fqcn>) {
-        let mut subscribed_characteristics = self.subscribed_characteristics.lock().await;
-        subscribed_characteristics.retain(|present_tk, _| present_tk.peripheral != fqcn.peripheral);
+    async fn abort_subscription(self: &Arc<Self>, fqcn: Arc<Fqcn>) {
+        {
+            let mut subscribed_characteristics = self.subscribed_characteristics.lock().await;
+            subscribed_characteristics.retain(|present_tk, _| present_tk.peripheral != fqcn.peripheral);
+
+            self.subscription_started_at.lock().await.remove(&fqcn.peripheral);
+
+            if let Some(task) = self.subscription_map.lock().await.remove(&fqcn.peripheral) {
+                task.drain(self.app_conf.task_drain_timeout);
+                warn!("Aborted subscription");
+            } else {
+                warn!("Can't abort subscription: no handle found");
+            }
+        }
+
+        self.schedule_idle_disconnect(fqcn.peripheral);
+    }
+
+    /// Disconnects the peripheral after `unsubscribe_on_idle_delay` unless new tasks were spawned in the meantime.
+    /// Runs in a separate task, since the caller may be the (aborted) task that has just ended.
+    fn schedule_idle_disconnect(self: &Arc<Self>, address: BDAddr) {
+        let Some(delay) = self.app_conf.unsubscribe_on_idle_delay else {
+            return;
+        };
+
+        let peripheral_manager = Arc::clone(self);
+        tokio::spawn(
+            async move {
+                tokio::time::sleep(delay).await;
+                let Some(peripheral) = peripheral_manager.get_cached_peripheral(&address).await else {
+                    return;
+                };
+                if !matches!(peripheral.is_connected().await, Ok(true)) {
+                    return;
+                }
+                if let Err(err) = peripheral_manager.disconnect_if_has_no_tasks(peripheral).await {
+                    warn!("Failed to disconnect idle peripheral: {err}");
+                }
+            }
+            .instrument(Span::current()),
+        );
+    }
 
-        if let Some(handle) = self.subscription_map.lock().await.remove(&fqcn.peripheral) {
-            handle.abort();
-            warn!("Aborted subscription");
+    /// Returns `None` if the value has an unexpected length, so a single malformed frame doesn't end the task.
+    /// Stateful converters (`Average`) keep their state per characteristic in `converter_state`.
+    pub(super) fn convert_value(
+        &self,
+        fqcn: &Arc<Fqcn>,
+        converter: &Converter,
+        value: Vec<u8>,
+    ) -> CollectorResult<Option<CharacteristicValue>> {
+        let result = if converter.is_stateful() {
+            let mut converter_state = self.converter_state.lock().unwrap();
+            converter.convert_with_state(value, converter_state.entry(Arc::clone(fqcn)).or_default())
         } else {
-            warn!("Can't abort subscription: no handle found");
+            converter.convert(value)
+        };
+
+        match result {
+            Ok(value) => {
+                self.length_mismatch_tracker.reset(fqcn);
+                Ok(Some(value))
+            }
+            Err(ConversionError::LenMismatch { expected, actual }) => {
+                CONVERSION_LENGTH_MISMATCH_COUNT.increment();
+                let consecutive_failures = self.length_mismatch_tracker.record(fqcn);
+                if self.length_mismatch_tracker.is_disabled(fqcn) {
+                    warn!(%fqcn, consecutive_failures, "Disabling characteristic after repeated length mismatches");
+                } else if LengthMismatchTracker::should_log(consecutive_failures) {
+                    warn!(%fqcn, expected, actual, consecutive_failures, "Unexpected value length");
+                }
+                Ok(None)
+            }
+            Err(err) => Err(err.into()),
         }
     }
 
-    async fn abort_polling(&self, fqcn: Arc<Fqcn>) {
-        if let Some(handle) = self.poll_handle_map.lock().await.remove(&fqcn) {
-            handle.abort();
+    pub(super) fn track_value_delta(&self, fqcn: &Arc<Fqcn>, value: &CharacteristicValue) -> Option<ValueDelta> {
+        if !self.app_conf.publish_value_delta {
+            return None;
+        }
+        self.value_delta_tracker.track(fqcn, value)
+    }
+
+    async fn abort_polling(self: &Arc<Self>, fqcn: Arc<Fqcn>) {
+        if let Some(task) = self.poll_handle_map.lock().await.remove(&fqcn) {
+            task.drain(self.app_conf.task_drain_timeout);
             warn!("Aborted polling");
         } else {
             warn!("Can't abort polling: no handle found");
         }
+
+        self.schedule_idle_disconnect(fqcn.peripheral);
     }
 }
 
 impl PeripheralManager {
     #[tracing::instrument(level = "info", skip_all, parent = & _parent_span, err)]
-    async fn block_on_polling(self: Arc<Self>, ctx: ConnectionContext, _parent_span: Span) -> CollectorResult<()> {
+    async fn block_on_polling(
+        self: Arc<Self>,
+        ctx: ConnectionContext,
+        token: CancellationToken,
+        _parent_span: Span,
+    ) -> CollectorResult<()> {
         info!("Polling characteristic");
 
-        let CharacteristicConfig::Poll {
-            delay_sec,
-            ref converter,
-            ..
-        } = ctx.characteristic_config.as_ref()
-        else {
+        let CharacteristicConfig::Poll { delay_sec, .. } = ctx.characteristic_config.as_ref() else {
             return Err(CollectorError::UnexpectedCharacteristicConfiguration(
                 ctx.characteristic_config.clone(),
             ));
         };
 
+        let mut first_payload_timer = FirstPayloadTimer::start();
         loop {
-            let value = ctx.peripheral.read(&ctx.characteristic).await?;
-            let value = converter.convert(value)?;
+            if self.poll_once(&ctx).await? {
+                first_payload_timer.on_payload(&ctx.fqcn);
+            }
+            tokio::select! {
+                _ = token.cancelled() => return Ok(()),
+                _ = tokio::time::sleep(*delay_sec) => {}
+            }
+        }
+    }
+
+    /// Reads the characteristic and publishes the converted value; returns `false` if the value was skipped.
+    pub(super) async fn poll_once(&self, ctx: &ConnectionContext) -> CollectorResult<bool> {
+        let CharacteristicConfig::Poll { ref converter, .. } = ctx.characteristic_config.as_ref() else {
+            return Err(CollectorError::UnexpectedCharacteristicConfiguration(
+                ctx.characteristic_config.clone(),
+            ));
+        };
+
+        let value = ctx.peripheral.read(&ctx.characteristic).await?;
+        let raw_bytes = ctx.characteristic_config.captures_raw_bytes().then(|| value.clone());
+        if let Some(value) = self.convert_value(&ctx.fqcn, converter, value)? {
             let value = CharacteristicPayload {
                 adapter_info: self.adapter_info.clone(),
                 created_at: chrono::offset::Utc::now(),
+                delta: self.track_value_delta(&ctx.fqcn, &value),
                 value,
+                raw_bytes,
                 fqcn: ctx.fqcn.clone(),
                 conf: Arc::clone(&ctx.characteristic_config),
             };
             self.fanout_sender.send(CollectorEvent::Payload(value.into())).await?;
-            tokio::time::sleep(*delay_sec).await;
+            return Ok(true);
+        }
+        if self.length_mismatch_tracker.is_disabled(&ctx.fqcn) {
+            return Err(CollectorError::CharacteristicDisabled(ctx.fqcn.clone()));
         }
+
+        Ok(false)
     }
 
-    async fn block_on_notifying(self: Arc<Self>, ctx: ConnectionContext, _parent_span: Span) -> CollectorResult<()> {
+    /// A single task per peripheral handles notifications of all its subscribed characteristics: btleplug's
+    /// notification stream carries every characteristic of the peripheral, and the config is looked up in
+    /// `subscribed_characteristics` per event. Characteristics subscribed after the task has started are picked up
+    /// by the next notification without restarting the stream.
+    async fn block_on_notifying(
+        self: Arc<Self>,
+        ctx: ConnectionContext,
+        token: CancellationToken,
+        _parent_span: Span,
+    ) -> CollectorResult<()> {
         info!("Subscribing to notifications");
-        let mut notification_stream = ctx.peripheral.notifications().await?;
+        let peripheral = Arc::clone(&ctx.peripheral);
+        let notification_stream = reacquiring_stream(
+            ctx.peripheral.notifications().await?,
+            self.app_conf.notification_stream_reacquire_attempts,
+            || {
+                let peripheral = Arc::clone(&peripheral);
+                async move { peripheral.notifications().await.map_err(CollectorError::from) }
+            },
+            || {
+                let peripheral = Arc::clone(&peripheral);
+                async move { matches!(peripheral.is_connected().await, Ok(true)) }
+            },
+        );
+        let mut notification_stream = pin!(notification_stream);
+        let mut first_payload_timer = FirstPayloadTimer::start();
 
-        while let Some(event) = notification_stream.next().await {
+        loop {
+            let event = tokio::select! {
+                _ = token.cancelled() => return Ok(()),
+                event = notification_stream.next() => event,
+            };
+            let Some(event) = event else {
+                break;
+            };
             let fqcn = Arc::new(ctx.fqcn.with_characteristic(event.service_uuid, event.uuid));
             let Some(conf) = self.get_characteristic_conf(&fqcn).await else {
                 // warn!("No conf found for characteristic: {fqcn}; {:?}", ctx.peripheral);
@@ -260,11 +476,25 @@ impl PeripheralManager {
                 return Err(CollectorError::UnexpectedCharacteristicConfiguration(conf));
             };
 
-            let value = converter.convert(event.value)?;
+            if let Some(age) = self.get_subscription_age(&fqcn).await {
+                SUBSCRIPTION_AGE.gauge(age.as_secs_f64());
+            }
+
+            if self.length_mismatch_tracker.is_disabled(&fqcn) {
+                continue;
+            }
+
+            let raw_bytes = conf.captures_raw_bytes().then(|| event.value.clone());
+            let Some(value) = self.convert_value(&fqcn, converter, event.value)? else {
+                continue;
+            };
+            first_payload_timer.on_payload(&fqcn);
             let value = CharacteristicPayload {
                 adapter_info: self.adapter_info.clone(),
                 created_at: chrono::offset::Utc::now(),
+                delta: self.track_value_delta(&fqcn, &value),
                 value,
+                raw_bytes,
                 fqcn,
                 conf,
             };
@@ -274,9 +504,25 @@ impl PeripheralManager {
         Err(CollectorError::EndOfStream)
     }
 
+    /// Waits for `--disconnect-grace-period`; returns `false` if the peripheral has reconnected in the meantime,
+    /// so its tasks must be kept.
+    pub(super) async fn is_disconnected_after_grace_period(&self, address: BDAddr) -> bool {
+        let Some(grace_period) = self.app_conf.disconnect_grace_period else {
+            return true;
+        };
+
+        is_still_disconnected(grace_period, || async {
+            match self.get_cached_peripheral(&address).await {
+                Some(peripheral) => matches!(peripheral.is_connected().await, Ok(true)),
+                None => false,
+            }
+        })
+        .await
+    }
+
     #[tracing::instrument(level = "info", skip_all, parent = & _parent_span)]
     pub(crate) async fn handle_disconnect(
-        &self,
+        self: &Arc<Self>,
         peripheral_key: &PeripheralKey,
         _parent_span: Span,
     ) -> CollectorResult<()> {
@@ -289,23 +535,28 @@ impl PeripheralManager {
             // self.peripheral_cache.remove(&peripheral_key.peripheral_address).await;
 
             subscribed_characteristics.retain(|fqcn, _| fqcn.peripheral != peripheral_key.peripheral_address);
-
-            poll_handle_map.retain(|fqcn, handle| {
-                if fqcn.peripheral == peripheral_key.peripheral_address {
-                    handle.abort();
-                    false
-                } else {
-                    true
+            // the next connection gets another chance at the characteristics disabled after length mismatches
+            self.length_mismatch_tracker
+                .reset_peripheral(peripheral_key.peripheral_address);
+
+            let drain_timeout = self.app_conf.task_drain_timeout;
+            let polled_characteristics = poll_handle_map
+                .keys()
+                .filter(|fqcn| fqcn.peripheral == peripheral_key.peripheral_address)
+                .cloned()
+                .collect::<Vec<_>>();
+            for fqcn in polled_characteristics {
+                if let Some(task) = poll_handle_map.remove(&fqcn) {
+                    task.drain(drain_timeout);
                 }
-            });
-            subscription_map.retain(|address, handle| {
-                if *address == peripheral_key.peripheral_address {
-                    handle.abort();
-                    false
-                } else {
-                    true
-                }
-            });
+            }
+            if let Some(task) = subscription_map.remove(&peripheral_key.peripheral_address) {
+                task.drain(drain_timeout);
+            }
+            self.subscription_started_at
+                .lock()
+                .await
+                .remove(&peripheral_key.peripheral_address);
         }
 
         // we assume that this configuration still exists; it might not be the case in the future
@@ -325,6 +576,195 @@ impl PeripheralManager {
         let existing_peripherals = self.get_all_connected_peripherals().await;
         warn!(%existing_peripherals,"Peripheral disconnected");
 
+        self.schedule_idle_disconnect(peripheral_key.peripheral_address);
+
         Ok(())
     }
 }
+
+/// Waits for `grace_period` and returns `true` if the peripheral hasn't reconnected in the meantime.
+async fn is_still_disconnected<F, Fut>(grace_period: Duration, is_connected: F) -> bool
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = bool>,
+{
+    tokio::time::sleep(grace_period).await;
+    if is_connected().await {
+        info!("Peripheral reconnected within {grace_period:?}, keeping its tasks");
+        return false;
+    }
+    true
+}
+
+/// Yields the items of `stream`. btleplug may end a notification stream while the peripheral is still connected, so
+/// an ended stream is replaced with a new one from `acquire` if `is_connected`, up to `max_attempts` times in a row.
+fn reacquiring_stream<S, A, AFut, C, CFut>(
+    stream: S,
+    max_attempts: u32,
+    acquire: A,
+    is_connected: C,
+) -> impl Stream<Item = S::Item>
+where
+    S: Stream + Unpin,
+    A: FnMut() -> AFut,
+    AFut: Future<Output = CollectorResult<S>>,
+    C: FnMut() -> CFut,
+    CFut: Future<Output = bool>,
+{
+    stream::unfold(
+        (stream, 0, acquire, is_connected),
+        move |(mut stream, mut attempts, mut acquire, mut is_connected)| async move {
+            loop {
+                if let Some(item) = stream.next().await {
+                    return Some((item, (stream, 0, acquire, is_connected)));
+                }
+                if attempts >= max_attempts || !is_connected().await {
+                    return None;
+                }
+                attempts += 1;
+                match acquire().await {
+                    Ok(reacquired) => {
+                        warn!(attempts, "Notification stream has ended, re-acquired it");
+                        stream = reacquired;
+                    }
+                    Err(err) => {
+                        warn!(attempts, "Failed to re-acquire the notification stream: {err}");
+                        return None;
+                    }
+                }
+            }
+        },
+    )
+}
+
+/// Awaits the CCCD write of a subscription. If it fails or doesn't complete within `subscribe_timeout`, the
+/// `subscribed_characteristics` entry is removed, so the next connection attempt subscribes again.
+async fn subscribe_or_rollback<F>(
+    subscribed_characteristics: &Mutex<HashMap<Arc<Fqcn>, Arc<CharacteristicConfig>>>,
+    fqcn: &Arc<Fqcn>,
+    subscribe_timeout: Duration,
+    subscribe: F,
+) -> CollectorResult<()>
+where
+    F: Future<Output = btleplug::Result<()>>,
+{
+    let result = match timeout(subscribe_timeout, subscribe).await {
+        Ok(result) => result.map_err(CollectorError::from),
+        Err(elapsed) => {
+            SUBSCRIBE_TIMEOUT_COUNT.increment();
+            warn!(%fqcn, "Subscription hasn't completed in {subscribe_timeout:?}");
+            Err(elapsed.into())
+        }
+    };
+
+    if result.is_err() {
+        subscribed_characteristics.lock().await.remove(fqcn);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Mutex as StdMutex;
+
+    use super::*;
+    use crate::inner::test_fixtures::{fqcn, subscribe_config};
+
+    fn subscribed_characteristic() -> (Arc<Fqcn>, Arc<CharacteristicConfig>) {
+        let fqcn = fqcn();
+        let conf = Arc::new(subscribe_config(&fqcn));
+        (fqcn, conf)
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_timeout_rolls_back() {
+        let (fqcn, conf) = subscribed_characteristic();
+        let subscribed_characteristics = Mutex::new(HashMap::from([(Arc::clone(&fqcn), conf)]));
+
+        // a device that never acknowledges the CCCD write
+        let result = subscribe_or_rollback(
+            &subscribed_characteristics,
+            &fqcn,
+            Duration::from_millis(10),
+            std::future::pending(),
+        )
+        .await;
+
+        assert!(matches!(result, Err(CollectorError::TimeoutError(_))));
+        assert!(subscribed_characteristics.lock().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_keeps_entry_on_success() {
+        let (fqcn, conf) = subscribed_characteristic();
+        let subscribed_characteristics = Mutex::new(HashMap::from([(Arc::clone(&fqcn), conf)]));
+
+        subscribe_or_rollback(&subscribed_characteristics, &fqcn, Duration::from_secs(1), async {
+            Ok(())
+        })
+        .await
+        .unwrap();
+        assert!(subscribed_characteristics.lock().await.contains_key(&fqcn));
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_within_grace_period_prevents_teardown() {
+        let connected = Arc::new(AtomicBool::new(false));
+        {
+            let connected = Arc::clone(&connected);
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+                connected.store(true, Ordering::SeqCst);
+            });
+        }
+
+        let is_connected = || async { connected.load(Ordering::SeqCst) };
+        assert!(!is_still_disconnected(Duration::from_millis(100), is_connected).await);
+    }
+
+    #[tokio::test]
+    async fn test_teardown_after_grace_period() {
+        assert!(is_still_disconnected(Duration::from_millis(10), || async { false }).await);
+    }
+
+    #[tokio::test]
+    async fn test_ended_stream_is_reacquired() {
+        let reacquired = StdMutex::new(vec![stream::iter(vec![3, 4]), stream::iter(vec![])]);
+        let acquire = || {
+            let next = reacquired.lock().unwrap().remove(0);
+            async move { Ok::<_, CollectorError>(next) }
+        };
+
+        // the first stream ends once, the second one resumes; the third one is empty and the retries are exhausted
+        let items = reacquiring_stream(stream::iter(vec![1, 2]), 1, acquire, || async { true })
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(items, vec![1, 2, 3, 4]);
+        assert!(reacquired.lock().unwrap().is_empty());
+
+        let items = reacquiring_stream(
+            stream::iter(vec![1, 2]),
+            1,
+            || async { Ok::<_, CollectorError>(stream::iter(vec![3])) },
+            || async { false },
+        )
+        .collect::<Vec<_>>()
+        .await;
+        assert_eq!(
+            items,
+            vec![1, 2],
+            "a disconnected peripheral's stream is not re-acquired"
+        );
+
+        let items = reacquiring_stream(
+            stream::iter(vec![1]),
+            0,
+            || async { Ok::<_, CollectorError>(stream::iter(vec![3])) },
+            || async { true },
+        )
+        .collect::<Vec<_>>()
+        .await;
+        assert_eq!(items, vec![1]);
+    }
+}
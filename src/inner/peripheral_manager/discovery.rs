@@ -1,27 +1,121 @@
-use crate::inner::debounce_limiter::DebounceLimiter;
+use crate::inner::conf::model::flat_peripheral_config::FlatPeripheralConfig;
+use crate::inner::conf::traits::Evaluate;
+use crate::inner::debounce_limiter::{DebounceLimiter, EventThrottlingMode, ShardedDebounceLimiter};
+use crate::inner::dto::AdapterStateDto;
 use crate::inner::error::{CollectorError, CollectorResult};
-use crate::inner::metrics::{CONNECTING_ERRORS, EVENT_COUNT, EVENT_THROTTLED_COUNT};
+use crate::inner::metrics::{
+    CONNECTING_ERRORS, EVENT_CIRCUIT_OPEN_COUNT, EVENT_COUNT, EVENT_DENIED_COUNT, EVENT_THROTTLED_COUNT,
+    EVENT_UNMATCHED_COUNT,
+};
 use crate::inner::model::peripheral_key::PeripheralKey;
+use crate::inner::peripheral_manager::circuit_breaker::CircuitBreaker;
 use crate::inner::peripheral_manager::ext::CentralEventExt;
 use crate::inner::peripheral_manager::PeripheralManager;
-use btleplug::api::{Central, CentralEvent, ScanFilter};
-use futures_util::StreamExt;
+use btleplug::api::{BDAddr, Central, CentralEvent, ScanFilter};
+use futures_util::{future, StreamExt};
+use std::future::Future;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
-use tokio::time::timeout;
-use tracing::{debug, info, Span};
+use std::time::{Duration, Instant};
+use tokio::time::{timeout, MissedTickBehavior};
+use tracing::{debug, info, warn, Span};
+
+/// The peripheral and, in the `address_and_config` throttling mode, the name of one of its matching configs.
+type ThrottleKey = (Arc<PeripheralKey>, Option<Arc<String>>);
+
+/// Discovery event limiters, kept for the lifetime of the event stream.
+struct Limiters {
+    events: ShardedDebounceLimiter<ThrottleKey>,
+    unmatched_log: DebounceLimiter<BDAddr>,
+}
 
 impl PeripheralManager {
     #[tracing::instrument(level="info", skip_all, parent = &self.span)]
     pub(crate) async fn start_discovery(self: Arc<Self>) -> CollectorResult<()> {
-        self.adapter.start_scan(ScanFilter::default()).await?;
+        let scan_filter = self.active_scan_filter.lock().await.clone();
+        self.adapter.start_scan(scan_filter).await?;
 
         let self_clone = Arc::clone(&self);
-        let result = self_clone.discover_task().await;
+        let manager = self.as_ref();
+        let scan_restarts = async move {
+            match manager.app_conf.get_scan_restart_interval(&manager.adapter_info) {
+                Some(interval) => restart_periodically(interval, move || manager.restart_scan()).await,
+                None => future::pending().await,
+            }
+        };
+        let result = tokio::select! {
+            result = self_clone.discover_task() => result,
+            () = scan_restarts => unreachable!("Scan restarts never end"),
+        };
         info!("Discovery task has ended: {result:?}");
 
         Err(CollectorError::EndOfStream)
     }
 
+    pub(crate) async fn get_scan_filter(&self) -> ScanFilter {
+        self.active_scan_filter.lock().await.clone()
+    }
+
+    #[tracing::instrument(level="info", parent = &self.span, skip(self), err)]
+    pub(crate) async fn set_scan_filter(&self, scan_filter: ScanFilter) -> CollectorResult<()> {
+        let mut active_scan_filter = self.active_scan_filter.lock().await;
+        // applied on resume
+        if !self.is_discovery_paused() {
+            self.adapter.stop_scan().await?;
+            self.adapter.start_scan(scan_filter.clone()).await?;
+        }
+        *active_scan_filter = scan_filter;
+        info!("Scan filter updated");
+        Ok(())
+    }
+
+    /// Restarts scanning with the active scan filter, i.e. when the adapter has stopped reporting events.
+    #[tracing::instrument(level="info", parent = &self.span, skip(self), err)]
+    pub(crate) async fn restart_scan(&self) -> CollectorResult<()> {
+        let active_scan_filter = self.active_scan_filter.lock().await;
+        if self.is_discovery_paused() {
+            info!("Discovery is paused, not restarting the scan");
+            return Ok(());
+        }
+        self.adapter.stop_scan().await?;
+        self.adapter.start_scan(active_scan_filter.clone()).await?;
+        info!("Scan restarted");
+        Ok(())
+    }
+
+    /// Stops scanning, i.e. to save power; discovered peripherals are ignored until `resume_discovery`.
+    /// Connected peripherals and their tasks are kept.
+    #[tracing::instrument(level="info", parent = &self.span, skip(self), err)]
+    pub(crate) async fn pause_discovery(&self) -> CollectorResult<()> {
+        let _active_scan_filter = self.active_scan_filter.lock().await;
+        self.adapter.stop_scan().await?;
+        self.discovery_paused.store(true, Ordering::SeqCst);
+        info!("Discovery paused");
+        Ok(())
+    }
+
+    #[tracing::instrument(level="info", parent = &self.span, skip(self), err)]
+    pub(crate) async fn resume_discovery(&self) -> CollectorResult<()> {
+        let active_scan_filter = self.active_scan_filter.lock().await;
+        self.discovery_paused.store(false, Ordering::SeqCst);
+        self.adapter.start_scan(active_scan_filter.clone()).await?;
+        info!("Discovery resumed");
+        Ok(())
+    }
+
+    pub(crate) fn is_discovery_paused(&self) -> bool {
+        self.discovery_paused.load(Ordering::SeqCst)
+    }
+
+    pub(crate) async fn get_adapter_state(&self) -> CollectorResult<AdapterStateDto> {
+        Ok(AdapterStateDto {
+            adapter_info: self.adapter_info.as_ref().clone(),
+            state: self.adapter.adapter_state().await?,
+            scan_filter: self.get_scan_filter().await.into(),
+            discovery_paused: self.is_discovery_paused(),
+        })
+    }
+
     async fn discover_task(self: Arc<Self>) -> CollectorResult<()> {
         loop {
             match self.clone().discover_task_internal().await {
@@ -36,19 +130,31 @@ impl PeripheralManager {
 
     #[tracing::instrument(level="info", parent = &self.span, skip(self), err)]
     async fn discover_task_internal(self: Arc<Self>) -> CollectorResult<()> {
-        let limiter = DebounceLimiter::new(
-            self.app_conf.event_throttling_purge_samples,
-            self.app_conf.event_throttling_purge_threshold,
-            self.app_conf.event_throttling,
-        );
+        let limiters = Limiters {
+            events: ShardedDebounceLimiter::new(
+                self.app_conf.event_throttling_shards,
+                self.app_conf.event_throttling_purge_samples,
+                self.app_conf.event_throttling_purge_threshold,
+                self.app_conf.event_throttling,
+            ),
+            unmatched_log: DebounceLimiter::new(
+                self.app_conf.event_throttling_purge_samples,
+                self.app_conf.event_throttling_purge_threshold,
+                self.app_conf.unmatched_peripheral_log_interval,
+            ),
+        };
 
         let mut stream = self.adapter.events().await?;
         while let Some(event) = timeout(self.app_conf.notification_stream_read_timeout, stream.next()).await? {
             debug!(?event, "Received CentralEvent");
+            // disconnects are still handled to tear down the tasks of the connected peripherals
+            if self.is_discovery_paused() && !matches!(event, CentralEvent::DeviceDisconnected(_)) {
+                continue;
+            }
             let peripheral_id = event.get_peripheral_id();
             let peripheral_key = Arc::new(self.build_peripheral_key(peripheral_id).await?);
             self.clone()
-                .handle_single_event(event, &limiter, peripheral_key)
+                .handle_single_event(event, &limiters, peripheral_key)
                 .await?;
         }
 
@@ -62,7 +168,7 @@ impl PeripheralManager {
     async fn handle_single_event(
         self: Arc<Self>,
         event: CentralEvent,
-        limiter: &DebounceLimiter<Arc<PeripheralKey>>,
+        limiters: &Limiters,
         peripheral_key: Arc<PeripheralKey>,
     ) -> CollectorResult<()> {
         EVENT_COUNT.increment();
@@ -72,35 +178,332 @@ impl PeripheralManager {
             CentralEvent::DeviceDisconnected(_) => {
                 let peripheral_manager = Arc::clone(&self);
                 tokio::spawn(async move {
+                    if !peripheral_manager
+                        .is_disconnected_after_grace_period(peripheral_key.peripheral_address)
+                        .await
+                    {
+                        return Ok(());
+                    }
                     peripheral_manager.handle_disconnect(&peripheral_key, span).await?;
+                    peripheral_manager.notify_persistent_disconnect(&peripheral_key).await;
                     Ok::<_, anyhow::Error>(())
                 });
             }
             _ => {
-                if limiter.throttle(peripheral_key.clone()).await {
+                if !self.configuration_manager.is_peripheral_allowed(&peripheral_key).await {
+                    EVENT_DENIED_COUNT.increment();
+                    return Ok(());
+                }
+
+                // advertisements are decoded before throttling, since every one of them carries a fresh value
+                if let Some(advertisement) = event.advertisement() {
+                    self.handle_advertisement(&peripheral_key, &advertisement).await?;
+                }
+
+                // persistent peripherals are kept connected by their supervisor, advertisements are irrelevant
+                if self
+                    .persistent_supervisors
+                    .lock()
+                    .await
+                    .contains_key(&peripheral_key.peripheral_address)
+                {
+                    return Ok(());
+                }
+
+                // in the `address_and_config` mode every matching config has its own throttling window and is
+                // connected on its own, otherwise the peripheral is throttled before the first matching config is
+                // looked up
+                let configs = if self.app_conf.event_throttling_mode == EventThrottlingMode::AddressAndConfig {
+                    let configs = self
+                        .configuration_manager
+                        .get_all_matching_configs(&peripheral_key)
+                        .await;
+                    if configs.is_empty() {
+                        report_unmatched(&limiters.unmatched_log, &peripheral_key).await;
+                        return Ok(());
+                    }
+                    unthrottled_configs(&limiters.events, &peripheral_key, configs).await
+                } else if limiters.events.throttle((Arc::clone(&peripheral_key), None)).await {
+                    vec![]
+                } else {
+                    let Some(config) = self.configuration_manager.get_matching_config(&peripheral_key).await else {
+                        report_unmatched(&limiters.unmatched_log, &peripheral_key).await;
+                        return Ok(());
+                    };
+                    vec![config]
+                };
+                if configs.is_empty() {
                     debug!("Throttled CentralEvent");
                     EVENT_THROTTLED_COUNT.increment();
                     return Ok(());
-                };
+                }
 
-                let Some(config) = self.configuration_manager.get_matching_config(&peripheral_key).await else {
+                let mut connect_configs = Vec::with_capacity(configs.len());
+                for config in configs {
+                    if config.is_broadcast_only() {
+                        continue;
+                    }
+                    if !self.passes_discovery_filter(&peripheral_key, &config).await {
+                        debug!(config = %config.name, "Peripheral doesn't pass the discovery filter, not connecting");
+                        continue;
+                    }
+                    if config.persistent {
+                        Arc::clone(&self)
+                            .ensure_persistent_supervisor(Arc::clone(&peripheral_key), config, span.clone())
+                            .await;
+                        continue;
+                    }
+                    connect_configs.push(config);
+                }
+                if connect_configs.is_empty() {
                     return Ok(());
-                };
+                }
+
+                let address = peripheral_key.peripheral_address;
                 let peripheral_manager = Arc::clone(&self);
+                // the configs are connected one by one, so they don't time out waiting for each other's connect lock
                 tokio::spawn(async move {
-                    if peripheral_manager
-                        .clone()
-                        .connect_all(peripheral_key, config, span.clone())
-                        .await
-                        .is_err()
-                    {
-                        span.in_scope(|| {
-                            CONNECTING_ERRORS.increment();
-                        });
+                    for config in connect_configs {
+                        let connect = Arc::clone(&peripheral_manager).connect_all(
+                            Arc::clone(&peripheral_key),
+                            config,
+                            span.clone(),
+                        );
+                        let breaker = &peripheral_manager.circuit_breaker;
+                        match connect_through_breaker(breaker, address, Instant::now, connect).await {
+                            None => span.in_scope(|| {
+                                debug!("Circuit breaker is open, not connecting");
+                                EVENT_CIRCUIT_OPEN_COUNT.increment();
+                            }),
+                            Some(Err(_)) => span.in_scope(|| {
+                                CONNECTING_ERRORS.increment();
+                            }),
+                            Some(Ok(())) => {}
+                        }
                     }
                 });
             }
         }
         Ok(())
     }
+
+    /// Checks the `discovery_filter` of the config against the last advertised properties of the peripheral.
+    /// A peripheral without known properties, i.e. one that has just left the cache, doesn't pass.
+    async fn passes_discovery_filter(&self, peripheral_key: &PeripheralKey, config: &FlatPeripheralConfig) -> bool {
+        let Some(discovery_filter) = &config.discovery_filter else {
+            return true;
+        };
+        match self.get_peripheral_properties(peripheral_key.peripheral_address).await {
+            Ok(properties) => discovery_filter.evaluate(&properties),
+            Err(err) => {
+                debug!("Failed to get the properties for the discovery filter: {err}");
+                false
+            }
+        }
+    }
+}
+
+/// Runs `connect` unless the circuit breaker of the peripheral is open, in which case returns `None`. A timed out
+/// connect lock means that another connect is in progress, so it doesn't count as a failure of the peripheral.
+async fn connect_through_breaker<Fut>(
+    breaker: &CircuitBreaker,
+    address: BDAddr,
+    now: impl Fn() -> Instant,
+    connect: Fut,
+) -> Option<CollectorResult<()>>
+where
+    Fut: Future<Output = CollectorResult<()>>,
+{
+    if breaker.is_open(&address, now()) {
+        return None;
+    }
+
+    let result = connect.await;
+    match &result {
+        Ok(()) => breaker.record_success(&address),
+        Err(CollectorError::KeyLockError(_)) => {}
+        Err(_) => breaker.record_failure(address, now()),
+    }
+    Some(result)
+}
+
+/// Keeps the configs that aren't throttled for the peripheral, each peripheral and config pair is throttled on its own.
+async fn unthrottled_configs(
+    limiter: &ShardedDebounceLimiter<ThrottleKey>,
+    peripheral_key: &Arc<PeripheralKey>,
+    configs: Vec<Arc<FlatPeripheralConfig>>,
+) -> Vec<Arc<FlatPeripheralConfig>> {
+    let mut unthrottled = Vec::with_capacity(configs.len());
+    for config in configs {
+        if !limiter
+            .throttle((Arc::clone(peripheral_key), Some(Arc::clone(&config.name))))
+            .await
+        {
+            unthrottled.push(config);
+        }
+    }
+    unthrottled
+}
+
+/// Calls `restart` every `interval`, starting one interval from now; failed restarts are retried on the next tick.
+async fn restart_periodically<F, Fut>(interval: Duration, mut restart: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = CollectorResult<()>>,
+{
+    let mut ticks = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        ticks.tick().await;
+        if let Err(err) = restart().await {
+            warn!("Failed to restart the scan: {err}");
+        }
+    }
+}
+
+/// Counts the events of a peripheral without a matching config, logging it at most once per limiter window so busy
+/// areas don't flood the logs. Returns `true` if it was logged.
+async fn report_unmatched(limiter: &DebounceLimiter<BDAddr>, peripheral_key: &PeripheralKey) -> bool {
+    EVENT_UNMATCHED_COUNT.increment();
+    if limiter.throttle(peripheral_key.peripheral_address).await {
+        return false;
+    }
+    info!(
+        peripheral = %peripheral_key.peripheral_address,
+        name = ?peripheral_key.name,
+        adapter = %peripheral_key.adapter_id,
+        "No peripheral config matches the discovered device"
+    );
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::sync::atomic::AtomicUsize;
+
+    use metrics_util::debugging::DebugValue;
+
+    use super::*;
+    use crate::inner::key_lock::KeyLockError;
+    use crate::inner::metrics::testing::capture_metrics;
+
+    #[test]
+    fn test_unmatched_peripheral_is_reported() {
+        let limiter = DebounceLimiter::new(100, 0.25, Duration::from_secs(60));
+        let peripheral_key = |address: &str| PeripheralKey {
+            adapter_id: "hci0".to_string(),
+            peripheral_address: address.parse().unwrap(),
+            name: Some("Phone".to_string()),
+        };
+
+        let (logged, recorded) = capture_metrics(async {
+            vec![
+                report_unmatched(&limiter, &peripheral_key("11:22:33:44:55:66")).await,
+                report_unmatched(&limiter, &peripheral_key("11:22:33:44:55:66")).await,
+                report_unmatched(&limiter, &peripheral_key("AA:BB:CC:DD:EE:FF")).await,
+            ]
+        });
+        // logged once per peripheral
+        assert_eq!(logged, vec![true, false, true]);
+        assert_eq!(
+            recorded.into_iter().collect::<Vec<_>>(),
+            vec![(EVENT_UNMATCHED_COUNT.metric_name.to_string(), DebugValue::Counter(3))]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_each_matching_config_is_throttled_on_its_own() {
+        let limiter = ShardedDebounceLimiter::new(4, 100, 0.25, Duration::from_secs(60));
+        let peripheral_key = Arc::new(PeripheralKey {
+            adapter_id: "hci0".to_string(),
+            peripheral_address: "11:22:33:44:55:66".parse().unwrap(),
+            name: Some("Sensor Hub".to_string()),
+        });
+        let config = |name: &str| {
+            Arc::new(FlatPeripheralConfig {
+                name: Arc::new(name.to_string()),
+                adapter: None,
+                device_id: None,
+                device_name: None,
+                on_connect: vec![],
+                persistent: false,
+                max_reconnect_attempts: None,
+                discovery_filter: None,
+                service_map: Default::default(),
+            })
+        };
+        let names = |configs: Vec<Arc<FlatPeripheralConfig>>| {
+            configs.iter().map(|config| config.name.to_string()).collect::<Vec<_>>()
+        };
+
+        let configs = unthrottled_configs(&limiter, &peripheral_key, vec![config("sensor")]).await;
+        assert_eq!(names(configs), vec!["sensor"]);
+
+        // a config matching later isn't held back by the window of the one that has just connected
+        let configs = unthrottled_configs(&limiter, &peripheral_key, vec![config("hub"), config("sensor")]).await;
+        assert_eq!(names(configs), vec!["hub"]);
+
+        let configs = unthrottled_configs(&limiter, &peripheral_key, vec![config("hub"), config("sensor")]).await;
+        assert!(configs.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_connects_are_skipped_until_cooldown_elapses() {
+        let breaker = CircuitBreaker::new(2, Duration::from_secs(10), Duration::from_secs(60));
+        let address: BDAddr = "A4:C1:38:00:11:22".parse().unwrap();
+        let now = &Cell::new(Instant::now());
+        let attempts = &Cell::new(0);
+        let try_connect = |error: Option<fn() -> CollectorError>| {
+            connect_through_breaker(&breaker, address, move || now.get(), async move {
+                attempts.set(attempts.get() + 1);
+                error.map_or(Ok(()), |error| Err(error()))
+            })
+        };
+        let lock_timeout = || CollectorError::KeyLockError(KeyLockError::Timeout);
+        let not_connected = || CollectorError::BluetoothError(btleplug::Error::NotConnected);
+
+        // waiting for another connect isn't a failure of the peripheral
+        for _ in 0..3 {
+            assert!(try_connect(Some(lock_timeout)).await.is_some());
+        }
+        assert!(try_connect(Some(not_connected)).await.is_some());
+        assert!(try_connect(Some(not_connected)).await.is_some());
+        assert_eq!(attempts.get(), 5);
+
+        assert!(try_connect(None).await.is_none());
+        now.set(now.get() + Duration::from_secs(9));
+        assert!(try_connect(None).await.is_none());
+        assert_eq!(attempts.get(), 5);
+
+        now.set(now.get() + Duration::from_secs(1));
+        assert!(matches!(try_connect(None).await, Some(Ok(()))));
+        // the successful connect has closed the breaker
+        assert!(matches!(try_connect(Some(not_connected)).await, Some(Err(_))));
+        assert!(try_connect(None).await.is_some());
+        assert_eq!(attempts.get(), 8);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_scan_is_restarted_periodically() {
+        let restarts = AtomicUsize::new(0);
+        let restarts_ref = &restarts;
+        let restart = move || async move {
+            // a failed restart doesn't stop the next ones
+            if restarts_ref.fetch_add(1, Ordering::SeqCst) == 0 {
+                return Err(CollectorError::EndOfStream);
+            }
+            Ok(())
+        };
+
+        let result = timeout(
+            Duration::from_secs(150),
+            restart_periodically(Duration::from_secs(60), restart),
+        )
+        .await;
+        assert!(result.is_err(), "never ends");
+
+        // at 60 and 120 seconds, not right away
+        assert_eq!(restarts.load(Ordering::SeqCst), 2);
+    }
 }
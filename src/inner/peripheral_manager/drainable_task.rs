@@ -0,0 +1,84 @@
+use std::future::Future;
+use std::time::Duration;
+
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::warn;
+
+/// A polling / notification task that is asked to stop via its `CancellationToken` before being aborted,
+/// so it can finish the current read and publish its last payload.
+pub(crate) struct DrainableTask {
+    handle: JoinHandle<()>,
+    token: CancellationToken,
+}
+
+impl DrainableTask {
+    pub(crate) fn spawn<F, Fut>(task: F) -> Self
+    where
+        F: FnOnce(CancellationToken) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let token = CancellationToken::new();
+        let handle = tokio::spawn(task(token.clone()));
+        Self { handle, token }
+    }
+
+    /// Signals cancellation and aborts the task if it hasn't exited within `drain_timeout`.
+    /// Doesn't wait for the task, since the caller may be the task itself; the returned handle resolves to `true`
+    /// if the task has exited on its own.
+    pub(crate) fn drain(self, drain_timeout: Duration) -> JoinHandle<bool> {
+        let Self { mut handle, token } = self;
+        token.cancel();
+
+        tokio::spawn(async move {
+            match tokio::time::timeout(drain_timeout, &mut handle).await {
+                Ok(_) => true,
+                Err(_) => {
+                    warn!("Task hasn't exited in {drain_timeout:?}, aborting");
+                    handle.abort();
+                    false
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_task_exits_on_cancellation() {
+        let flushed = Arc::new(AtomicBool::new(false));
+        let task = {
+            let flushed = Arc::clone(&flushed);
+            DrainableTask::spawn(|token| async move {
+                token.cancelled().await;
+                // i.e. publish the last payload
+                tokio::task::yield_now().await;
+                flushed.store(true, Ordering::SeqCst);
+            })
+        };
+
+        assert!(task.drain(Duration::from_secs(1)).await.unwrap());
+        assert!(flushed.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_task_is_aborted_after_timeout() {
+        let finished = Arc::new(AtomicBool::new(false));
+        let task = {
+            let finished = Arc::clone(&finished);
+            DrainableTask::spawn(|_token| async move {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                finished.store(true, Ordering::SeqCst);
+            })
+        };
+
+        assert!(!task.drain(Duration::from_millis(10)).await.unwrap());
+        assert!(!finished.load(Ordering::SeqCst));
+    }
+}
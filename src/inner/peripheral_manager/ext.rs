@@ -1,8 +1,19 @@
+use std::collections::HashMap;
+
 use btleplug::api::CentralEvent;
 use btleplug::platform::PeripheralId;
+use uuid::Uuid;
+
+/// Advertisement data that can be decoded into `Broadcast` characteristic values.
+#[derive(Debug)]
+pub(super) enum Advertisement<'a> {
+    ServiceData(&'a HashMap<Uuid, Vec<u8>>),
+    ManufacturerData(&'a HashMap<u16, Vec<u8>>),
+}
 
 pub(super) trait CentralEventExt {
     fn get_peripheral_id(&self) -> &PeripheralId;
+    fn advertisement(&self) -> Option<Advertisement<'_>>;
 }
 
 impl CentralEventExt for CentralEvent {
@@ -17,4 +28,16 @@ impl CentralEventExt for CentralEvent {
             | CentralEvent::ServicesAdvertisement { id, .. } => id,
         }
     }
+
+    fn advertisement(&self) -> Option<Advertisement<'_>> {
+        match self {
+            CentralEvent::ServiceDataAdvertisement { service_data, .. } => {
+                Some(Advertisement::ServiceData(service_data))
+            }
+            CentralEvent::ManufacturerDataAdvertisement { manufacturer_data, .. } => {
+                Some(Advertisement::ManufacturerData(manufacturer_data))
+            }
+            _ => None,
+        }
+    }
 }
@@ -0,0 +1,93 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Instant;
+
+use metrics::Label;
+
+use crate::inner::metrics::FIRST_PAYLOAD_DURATION;
+use crate::inner::model::fqcn::Fqcn;
+
+/// Records how long it took for each characteristic of a polling / notification task to produce its first payload.
+/// A notification task serves every subscribed characteristic of the peripheral, so the characteristics subscribed
+/// after it has started are measured from the start of the task as well.
+pub(super) struct FirstPayloadTimer {
+    started_at: Instant,
+    recorded: HashSet<Arc<Fqcn>>,
+}
+
+impl FirstPayloadTimer {
+    pub(super) fn start() -> Self {
+        Self {
+            started_at: Instant::now(),
+            recorded: Default::default(),
+        }
+    }
+
+    /// Returns `true` if this was the first payload of the characteristic.
+    pub(super) fn on_payload(&mut self, fqcn: &Arc<Fqcn>) -> bool {
+        if self.recorded.contains(fqcn) {
+            return false;
+        }
+        self.recorded.insert(Arc::clone(fqcn));
+        FIRST_PAYLOAD_DURATION
+            .with_labels(vec![Label::new("characteristic", fqcn.characteristic.to_string())])
+            .record(self.started_at.elapsed().as_millis() as f64);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use metrics_util::debugging::DebugValue;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::inner::metrics::testing::{capture_metrics, metric_key};
+
+    #[test]
+    fn test_recorded_once_per_characteristic() {
+        let fqcn = |characteristic: u128| {
+            Arc::new(Fqcn {
+                peripheral: "11:22:33:44:55:66".parse().unwrap(),
+                service: Uuid::from_u128(0x180f),
+                characteristic: Uuid::from_u128(characteristic),
+            })
+        };
+
+        let (first, recorded) = capture_metrics(async {
+            let mut timer = FirstPayloadTimer::start();
+            let first = vec![
+                timer.on_payload(&fqcn(1)),
+                timer.on_payload(&fqcn(1)),
+                timer.on_payload(&fqcn(2)),
+                timer.on_payload(&fqcn(1)),
+            ];
+
+            // a new session, i.e. after a reconnect
+            let mut timer = FirstPayloadTimer::start();
+            timer.on_payload(&fqcn(1));
+            first
+        });
+        assert_eq!(first, vec![true, false, true, false]);
+
+        let samples = recorded
+            .into_iter()
+            .map(|(key, value)| {
+                let DebugValue::Histogram(samples) = value else {
+                    panic!("Unexpected metric value: {value:?}");
+                };
+                (key, samples.len())
+            })
+            .collect::<HashMap<_, _>>();
+        let key = |characteristic: u128| {
+            let characteristic = Uuid::from_u128(characteristic).to_string();
+            metric_key(
+                FIRST_PAYLOAD_DURATION.metric_name,
+                &[("characteristic", &characteristic)],
+            )
+        };
+        assert_eq!(samples, HashMap::from([(key(1), 2), (key(2), 1)]));
+    }
+}
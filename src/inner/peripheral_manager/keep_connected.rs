@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use btleplug::api::{BDAddr, Peripheral as _};
+use tracing::{warn, Instrument, Span};
+
+use crate::inner::peripheral_manager::PeripheralManager;
+
+/// Peripherals the API asked to keep connected between requests, with the time they may be disconnected at.
+#[derive(Debug, Default)]
+pub(super) struct KeepConnected {
+    deadlines: Mutex<HashMap<BDAddr, Instant>>,
+}
+
+impl KeepConnected {
+    /// Keeps the peripheral connected for at least `timeout`; returns the new deadline.
+    fn extend(&self, address: BDAddr, timeout: Duration) -> Instant {
+        let deadline = Instant::now() + timeout;
+        let mut deadlines = self.deadlines.lock().unwrap();
+        let entry = deadlines.entry(address).or_insert(deadline);
+        *entry = (*entry).max(deadline);
+        *entry
+    }
+
+    pub(super) fn is_kept(&self, address: &BDAddr) -> bool {
+        let mut deadlines = self.deadlines.lock().unwrap();
+        match deadlines.get(address) {
+            Some(deadline) if *deadline > Instant::now() => true,
+            Some(_) => {
+                deadlines.remove(address);
+                false
+            }
+            None => false,
+        }
+    }
+}
+
+impl PeripheralManager {
+    /// Prevents `disconnect_if_has_no_tasks` from disconnecting the peripheral for `keep_connected_timeout`, so a
+    /// burst of API requests reuses one connection. It's disconnected afterwards if it has no tasks.
+    pub(crate) fn keep_connected(self: &Arc<Self>, address: BDAddr) {
+        let deadline = self
+            .keep_connected
+            .extend(address, self.app_conf.keep_connected_timeout);
+
+        let peripheral_manager = Arc::clone(self);
+        tokio::spawn(
+            async move {
+                tokio::time::sleep_until(deadline.into()).await;
+                // extended by a later request, which has its own timer
+                if peripheral_manager.keep_connected.is_kept(&address) {
+                    return;
+                }
+                let Some(peripheral) = peripheral_manager.get_cached_peripheral(&address).await else {
+                    return;
+                };
+                if !matches!(peripheral.is_connected().await, Ok(true)) {
+                    return;
+                }
+                if let Err(err) = peripheral_manager.disconnect_if_has_no_tasks(peripheral).await {
+                    warn!("Failed to disconnect idle peripheral: {err}");
+                }
+            }
+            .instrument(Span::current()),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sequential_reads_are_kept_connected() {
+        let keep_connected = KeepConnected::default();
+        let address: BDAddr = "A4:C1:38:00:11:22".parse().unwrap();
+        let other: BDAddr = "A4:C1:38:00:11:23".parse().unwrap();
+        assert!(!keep_connected.is_kept(&address));
+
+        // the first read
+        let first_deadline = keep_connected.extend(address, Duration::from_secs(60));
+        assert!(keep_connected.is_kept(&address));
+        assert!(!keep_connected.is_kept(&other));
+
+        // the second read extends the deadline, a shorter timeout doesn't shorten it
+        let second_deadline = keep_connected.extend(address, Duration::from_secs(120));
+        assert!(second_deadline > first_deadline);
+        assert_eq!(keep_connected.extend(address, Duration::ZERO), second_deadline);
+        assert!(keep_connected.is_kept(&address));
+
+        keep_connected.extend(other, Duration::ZERO);
+        assert!(!keep_connected.is_kept(&other));
+        assert!(keep_connected.deadlines.lock().unwrap().get(&other).is_none());
+    }
+}
@@ -0,0 +1,94 @@
+use std::future::ready;
+use std::sync::Arc;
+
+use btleplug::api::{BDAddr, Characteristic, Peripheral as _, ValueNotification};
+use btleplug::platform::Peripheral;
+use futures_util::{Stream, StreamExt};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::inner::error::{CollectorError, CollectorResult};
+use crate::inner::peripheral_manager::PeripheralManager;
+
+/// Unsubscribes from the listened characteristic once the notification stream is dropped,
+/// i.e. after the last event was sent or when the client has gone away.
+struct ListenGuard {
+    peripheral_manager: Arc<PeripheralManager>,
+    peripheral: Arc<Peripheral>,
+    characteristic: Characteristic,
+}
+
+impl Drop for ListenGuard {
+    fn drop(&mut self) {
+        let peripheral_manager = Arc::clone(&self.peripheral_manager);
+        let peripheral = Arc::clone(&self.peripheral);
+        let characteristic = self.characteristic.clone();
+
+        tokio::spawn(async move {
+            if peripheral_manager
+                .is_subscribed(peripheral.address(), characteristic.uuid)
+                .await
+            {
+                return;
+            }
+            if let Err(err) = peripheral.unsubscribe(&characteristic).await {
+                warn!(uuid = %characteristic.uuid, "Failed to unsubscribe: {err}");
+            }
+            if let Err(err) = peripheral_manager.disconnect_if_has_no_tasks(peripheral).await {
+                warn!("Failed to disconnect: {err}");
+            }
+        });
+    }
+}
+
+impl PeripheralManager {
+    /// Subscribes to a characteristic and yields its next `count` notifications. The characteristic is unsubscribed
+    /// afterwards, unless it's also subscribed by the collector itself.
+    pub(crate) async fn listen_notifications(
+        self: Arc<Self>,
+        address: BDAddr,
+        characteristic_uuid: Uuid,
+        count: usize,
+    ) -> CollectorResult<impl Stream<Item = ValueNotification>> {
+        let peripheral = self
+            .get_peripheral(&address)
+            .await?
+            .ok_or(CollectorError::PeripheralNotFound(address))?;
+
+        self.connect(&peripheral).await?;
+        let Some(characteristic) = peripheral
+            .characteristics()
+            .into_iter()
+            .find(|characteristic| characteristic.uuid == characteristic_uuid)
+        else {
+            self.disconnect_if_has_no_tasks(peripheral).await?;
+            return Err(CollectorError::CharacteristicNotFound(address, characteristic_uuid));
+        };
+
+        // take the stream before subscribing, so the first notification isn't missed
+        let notifications = peripheral.notifications().await?;
+        let guard = ListenGuard {
+            peripheral_manager: Arc::clone(&self),
+            peripheral: Arc::clone(&peripheral),
+            characteristic: characteristic.clone(),
+        };
+        peripheral.subscribe(&characteristic).await?;
+        info!(uuid = %characteristic_uuid, count, "Listening for notifications");
+
+        Ok(notifications
+            .filter(move |notification| ready(notification.uuid == characteristic_uuid))
+            .take(count)
+            .map(move |notification| {
+                let _guard = &guard;
+                notification
+            }))
+    }
+
+    async fn is_subscribed(&self, address: BDAddr, characteristic_uuid: Uuid) -> bool {
+        self.subscribed_characteristics
+            .lock()
+            .await
+            .keys()
+            .any(|fqcn| fqcn.peripheral == address && fqcn.characteristic == characteristic_uuid)
+    }
+}
@@ -1,50 +1,86 @@
-use std::collections::HashMap;
-use std::sync::Arc;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex as StdMutex};
 use std::time::{Duration, Instant};
 
 use anyhow::Context;
-use btleplug::api::{BDAddr, Characteristic, Peripheral as _};
+use btleplug::api::{
+    BDAddr, CharPropFlags, Characteristic, Peripheral as _, PeripheralProperties, ScanFilter, WriteType,
+};
 use btleplug::platform::{Adapter, Peripheral};
-use retainer::Cache;
-use tokio::sync::Mutex;
+use futures_util::{stream, StreamExt};
+use tokio::sync::{Mutex, Notify};
 use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
 use tracing::{info, Span};
+use uuid::Uuid;
 
 use crate::inner::conf::cmd_args::AppConf;
 use crate::inner::conf::manager::ConfigurationManager;
 use crate::inner::conf::model::characteristic_config::CharacteristicConfig;
-use crate::inner::error::CollectorResult;
+use crate::inner::conv::converter::ConverterState;
+use crate::inner::conv::length_mismatch_tracker::LengthMismatchTracker;
+use crate::inner::dto::{CharacteristicReadDto, CircuitBreakerDto, GattAttributeDto, PeripheralDto, ServiceDto};
+use crate::inner::error::{CollectorError, CollectorResult};
 use crate::inner::key_lock::KeyLock;
 use crate::inner::model::adapter_info::AdapterInfo;
 use crate::inner::model::collector_event::CollectorEvent;
 use crate::inner::model::fqcn::Fqcn;
+use crate::inner::peripheral_manager::bounded_cache::BoundedCache;
+use crate::inner::peripheral_manager::circuit_breaker::CircuitBreaker;
+use crate::inner::peripheral_manager::drainable_task::DrainableTask;
+use crate::inner::peripheral_manager::keep_connected::KeepConnected;
+use crate::inner::publish::value_delta::ValueDeltaTracker;
 use crate::inner::publish::FanOutSender;
 
+mod bounded_cache;
+mod broadcast;
+mod circuit_breaker;
 mod connection;
 mod connection_context;
 mod discovery;
+mod drainable_task;
 mod ext;
+mod first_payload;
+mod keep_connected;
+mod listen;
+mod on_connect;
+mod once;
+mod persistent;
 pub mod util;
 
 pub(crate) struct PeripheralManager {
     pub(crate) adapter: Arc<Adapter>,
-    peripheral_cache: Arc<Cache<BDAddr, Arc<Peripheral>>>,
+    peripheral_cache: Arc<BoundedCache<BDAddr, Arc<Peripheral>>>,
     peripheral_cache_updated_at: Mutex<Instant>,
     cache_monitor: JoinHandle<()>,
-    poll_handle_map: Mutex<HashMap<Arc<Fqcn>, JoinHandle<()>>>,
-    subscription_map: Mutex<HashMap<BDAddr, JoinHandle<()>>>,
+    poll_handle_map: Mutex<HashMap<Arc<Fqcn>, DrainableTask>>,
+    subscription_map: Mutex<HashMap<BDAddr, DrainableTask>>,
     subscribed_characteristics: Mutex<HashMap<Arc<Fqcn>, Arc<CharacteristicConfig>>>,
+    subscription_started_at: Mutex<HashMap<BDAddr, Instant>>,
     fanout_sender: Arc<FanOutSender<CollectorEvent>>,
     configuration_manager: Arc<ConfigurationManager>,
     pub(crate) app_conf: Arc<AppConf>,
     span: Span,
     connection_lock: KeyLock<BDAddr>,
     adapter_info: Arc<AdapterInfo>,
+    length_mismatch_tracker: LengthMismatchTracker,
+    active_scan_filter: Mutex<ScanFilter>,
+    rssi_history: Mutex<HashMap<BDAddr, VecDeque<(Instant, i16)>>>,
+    persistent_supervisors: Mutex<HashMap<BDAddr, Arc<Notify>>>,
+    persistent_shutdown: CancellationToken,
+    unsupported_characteristics: Mutex<HashSet<Arc<Fqcn>>>,
+    converter_state: StdMutex<HashMap<Arc<Fqcn>, ConverterState>>,
+    value_delta_tracker: ValueDeltaTracker,
+    keep_connected: KeepConnected,
+    discovery_paused: AtomicBool,
+    circuit_breaker: CircuitBreaker,
 }
 
 impl Drop for PeripheralManager {
     fn drop(&mut self) {
         self.cache_monitor.abort();
+        self.persistent_shutdown.cancel();
     }
 }
 
@@ -57,10 +93,20 @@ impl PeripheralManager {
         span: Span,
         adapter_info: AdapterInfo,
     ) -> Self {
-        let cache = Arc::new(Cache::new());
+        let cache = Arc::new(BoundedCache::new(
+            app_conf.peripheral_cache_ttl,
+            app_conf.peripheral_cache_max_entries,
+            adapter_info.label(),
+        ));
         let clone = cache.clone();
 
         let monitor = tokio::spawn(async move { clone.monitor(10, 0.25, Duration::from_secs(10)).await });
+        let length_mismatch_tracker = LengthMismatchTracker::new(app_conf.max_conversion_length_mismatches);
+        let circuit_breaker = CircuitBreaker::new(
+            app_conf.circuit_breaker_threshold,
+            app_conf.circuit_breaker_min_cooldown,
+            app_conf.circuit_breaker_max_cooldown,
+        );
 
         Self {
             adapter: Arc::new(adapter),
@@ -70,12 +116,24 @@ impl PeripheralManager {
             poll_handle_map: Default::default(),
             subscription_map: Default::default(),
             subscribed_characteristics: Default::default(),
+            subscription_started_at: Default::default(),
             fanout_sender,
             configuration_manager,
             app_conf,
             span,
             connection_lock: Default::default(),
             adapter_info: adapter_info.into(),
+            length_mismatch_tracker,
+            active_scan_filter: Default::default(),
+            rssi_history: Default::default(),
+            persistent_supervisors: Default::default(),
+            persistent_shutdown: Default::default(),
+            unsupported_characteristics: Default::default(),
+            converter_state: Default::default(),
+            value_delta_tracker: Default::default(),
+            keep_connected: Default::default(),
+            discovery_paused: Default::default(),
+            circuit_breaker,
         }
     }
 }
@@ -107,6 +165,146 @@ impl PeripheralManager {
         Ok((peripheral, characteristic))
     }
 
+    /// Connects to the peripheral and enumerates its services without spawning any poll / subscribe tasks.
+    pub(crate) async fn probe(&self, address: BDAddr) -> CollectorResult<PeripheralDto> {
+        let peripheral = self
+            .get_peripheral(&address)
+            .await?
+            .ok_or(CollectorError::PeripheralNotFound(address))?;
+
+        self.connect(&peripheral).await?;
+        let result = PeripheralDto::from_platform(peripheral.as_ref().clone()).await;
+        self.disconnect_if_has_no_tasks(peripheral).await?;
+
+        Ok(result?)
+    }
+
+    /// Connects to the peripheral to discover its services, disconnecting afterwards unless it has tasks.
+    pub(crate) async fn get_services(&self, address: BDAddr) -> CollectorResult<Vec<ServiceDto>> {
+        let peripheral = self
+            .get_peripheral(&address)
+            .await?
+            .ok_or(CollectorError::PeripheralNotFound(address))?;
+
+        self.connect(&peripheral).await?;
+        let services = ServiceDto::sorted(peripheral.services());
+        self.disconnect_if_has_no_tasks(peripheral).await?;
+
+        Ok(services)
+    }
+
+    /// Returns the last known advertised properties without connecting to the peripheral.
+    pub(crate) async fn get_peripheral_properties(&self, address: BDAddr) -> CollectorResult<PeripheralProperties> {
+        let peripheral = self
+            .get_peripheral(&address)
+            .await?
+            .ok_or(CollectorError::PeripheralNotFound(address))?;
+
+        peripheral
+            .properties()
+            .await?
+            .ok_or(CollectorError::PeripheralNotFound(address))
+    }
+
+    pub(crate) fn get_circuit_breaker_state(&self, address: &BDAddr) -> CircuitBreakerDto {
+        self.circuit_breaker.state(address, Instant::now())
+    }
+
+    /// Writes a single value, using a write with response if the characteristic supports it.
+    pub(crate) async fn write_characteristic(&self, fqcn: &Fqcn, value: &[u8]) -> CollectorResult<()> {
+        let (peripheral, characteristic) = self.get_peripheral_characteristic(fqcn).await?;
+        let write_type = if characteristic.properties.contains(CharPropFlags::WRITE) {
+            WriteType::WithResponse
+        } else {
+            WriteType::WithoutResponse
+        };
+
+        let result = tokio::time::timeout(
+            self.app_conf.default_write_timeout,
+            peripheral.write(&characteristic, value, write_type),
+        )
+        .await;
+        self.disconnect_if_has_no_tasks(peripheral).await?;
+        result??;
+
+        Ok(())
+    }
+
+    /// Reads the characteristics of a single peripheral in parallel; a failed read doesn't fail the others.
+    pub(crate) async fn read_characteristics(
+        &self,
+        address: BDAddr,
+        characteristic_uuids: Vec<Uuid>,
+    ) -> CollectorResult<BTreeMap<Uuid, CharacteristicReadDto>> {
+        let peripheral = self
+            .get_peripheral(&address)
+            .await?
+            .ok_or(CollectorError::PeripheralNotFound(address))?;
+
+        self.connect(&peripheral).await?;
+        let characteristics = peripheral.characteristics();
+
+        let results = stream::iter(characteristic_uuids)
+            .map(|uuid| {
+                let peripheral = &peripheral;
+                let characteristic = characteristics
+                    .iter()
+                    .find(|characteristic| characteristic.uuid == uuid);
+                async move {
+                    let result = match characteristic {
+                        Some(characteristic) => {
+                            tokio::time::timeout(self.app_conf.default_read_timeout, peripheral.read(characteristic))
+                                .await
+                                .map_err(CollectorError::from)
+                                .and_then(|result| result.map_err(CollectorError::from))
+                        }
+                        None => Err(CollectorError::CharacteristicNotFound(address, uuid)),
+                    };
+                    (uuid, CharacteristicReadDto::from(result))
+                }
+            })
+            .buffer_unordered(self.app_conf.default_batch_parallelism)
+            .collect::<BTreeMap<_, _>>()
+            .await;
+
+        self.disconnect_if_has_no_tasks(peripheral).await?;
+
+        Ok(results)
+    }
+
+    /// Reads every readable characteristic of the peripheral, i.e. to explore an unknown device.
+    pub(crate) async fn dump_gatt(&self, address: BDAddr) -> CollectorResult<Vec<GattAttributeDto>> {
+        let peripheral = self
+            .get_peripheral(&address)
+            .await?
+            .ok_or(CollectorError::PeripheralNotFound(address))?;
+
+        self.connect(&peripheral).await?;
+
+        let attributes = stream::iter(peripheral.characteristics())
+            .map(|characteristic| {
+                let peripheral = &peripheral;
+                async move {
+                    if !characteristic.properties.contains(CharPropFlags::READ) {
+                        return GattAttributeDto::new::<CollectorError>(&characteristic, None);
+                    }
+                    let value =
+                        tokio::time::timeout(self.app_conf.default_read_timeout, peripheral.read(&characteristic))
+                            .await
+                            .map_err(CollectorError::from)
+                            .and_then(|result| result.map_err(CollectorError::from));
+                    GattAttributeDto::new(&characteristic, Some(value))
+                }
+            })
+            .buffered(self.app_conf.default_batch_parallelism)
+            .collect::<Vec<_>>()
+            .await;
+
+        self.disconnect_if_has_no_tasks(peripheral).await?;
+
+        Ok(attributes)
+    }
+
     pub(crate) async fn disconnect_if_has_no_tasks(&self, peripheral: Arc<Peripheral>) -> CollectorResult<()> {
         let poll_handle_map = self.poll_handle_map.lock().await;
         let subscription_map = self.subscription_map.lock().await;
@@ -117,6 +315,9 @@ impl PeripheralManager {
         if poll_handle_map.keys().any(|fqcn| fqcn.peripheral == peripheral_address) {
             return Ok(());
         }
+        if self.keep_connected.is_kept(&peripheral_address) {
+            return Ok(());
+        }
 
         info!("Disconnecting from {}", peripheral_address);
 
@@ -0,0 +1,159 @@
+use std::future::Future;
+
+use anyhow::Context;
+use btleplug::api::{Characteristic, Peripheral as _, WriteType};
+use btleplug::platform::Peripheral;
+use tokio::time::timeout;
+use tracing::{info, warn};
+
+use crate::inner::conf::dto::peripheral::OnConnectWriteDto;
+use crate::inner::conf::model::service_characteristic_key::ServiceCharacteristicKey;
+use crate::inner::error::{CollectorError, CollectorResult};
+use crate::inner::peripheral_manager::PeripheralManager;
+
+impl PeripheralManager {
+    #[tracing::instrument(level = "info", skip_all, err)]
+    pub(super) async fn run_on_connect(
+        &self,
+        peripheral: &Peripheral,
+        writes: &[OnConnectWriteDto],
+    ) -> CollectorResult<()> {
+        if writes.is_empty() {
+            return Ok(());
+        }
+
+        info!("Running {} on-connect write(s)", writes.len());
+        execute_in_order(writes, |write| async move {
+            let characteristic = find_characteristic(peripheral, write)?;
+            let write_type = if write.wait_response {
+                WriteType::WithResponse
+            } else {
+                WriteType::WithoutResponse
+            };
+            timeout(
+                self.app_conf.default_write_timeout,
+                peripheral.write(&characteristic, &write.value, write_type),
+            )
+            .await??;
+            Ok(())
+        })
+        .await
+    }
+}
+
+fn find_characteristic(peripheral: &Peripheral, write: &OnConnectWriteDto) -> CollectorResult<Characteristic> {
+    let characteristic = peripheral
+        .services()
+        .into_iter()
+        .filter(|service| service.uuid == write.service)
+        .flat_map(|service| service.characteristics.into_iter())
+        .find(|characteristic| characteristic.uuid == write.characteristic)
+        .context("Failed to find characteristic".to_string())?;
+    Ok(characteristic)
+}
+
+/// Disconnects the peripheral (best effort) if its on-connect writes have failed, so it isn't left connected without
+/// the initialization it needs. The error of the writes is returned either way.
+pub(super) async fn disconnect_on_failure<Fut, F, D>(on_connect: Fut, disconnect: F) -> CollectorResult<()>
+where
+    Fut: Future<Output = CollectorResult<()>>,
+    F: FnOnce() -> D,
+    D: Future<Output = btleplug::Result<()>>,
+{
+    let result = on_connect.await;
+    if result.is_err() {
+        if let Err(err) = disconnect().await {
+            warn!("Failed to disconnect after failed on-connect writes: {err}");
+        }
+    }
+    result
+}
+
+/// Executes the writes one by one and stops at the first failure.
+async fn execute_in_order<'a, F, Fut>(writes: &'a [OnConnectWriteDto], mut execute: F) -> CollectorResult<()>
+where
+    F: FnMut(&'a OnConnectWriteDto) -> Fut,
+    Fut: Future<Output = CollectorResult<()>>,
+{
+    for (index, write) in writes.iter().enumerate() {
+        if let Err(err) = execute(write).await {
+            let key = ServiceCharacteristicKey {
+                service_uuid: write.service,
+                characteristic_uuid: write.characteristic,
+            };
+            return Err(CollectorError::OnConnectWriteFailed(index, key, Box::new(err)));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn write(value: u8) -> OnConnectWriteDto {
+        OnConnectWriteDto {
+            service: Uuid::nil(),
+            characteristic: Uuid::from_u128(value as u128),
+            value: vec![value],
+            wait_response: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_execute_in_order() {
+        let writes = vec![write(1), write(2), write(3)];
+        let executed = Mutex::new(vec![]);
+
+        execute_in_order(&writes, |write| {
+            executed.lock().unwrap().push(write.value[0]);
+            async { Ok(()) }
+        })
+        .await
+        .unwrap();
+        assert_eq!(*executed.lock().unwrap(), vec![1, 2, 3]);
+
+        executed.lock().unwrap().clear();
+        let result = execute_in_order(&writes, |write| {
+            executed.lock().unwrap().push(write.value[0]);
+            let value = write.value[0];
+            async move {
+                if value == 2 {
+                    return Err(CollectorError::EndOfStream);
+                }
+                Ok(())
+            }
+        })
+        .await;
+
+        assert!(matches!(result, Err(CollectorError::OnConnectWriteFailed(1, _, _))));
+        assert_eq!(*executed.lock().unwrap(), vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_on_failure() {
+        let disconnected = Mutex::new(0);
+        let disconnect = || async {
+            *disconnected.lock().unwrap() += 1;
+            Ok(())
+        };
+
+        disconnect_on_failure(async { Ok(()) }, disconnect).await.unwrap();
+        assert_eq!(*disconnected.lock().unwrap(), 0);
+
+        let result = disconnect_on_failure(async { Err(CollectorError::EndOfStream) }, disconnect).await;
+        assert!(matches!(result, Err(CollectorError::EndOfStream)));
+        assert_eq!(*disconnected.lock().unwrap(), 1);
+
+        // a failed disconnect doesn't hide the original error
+        let result = disconnect_on_failure(async { Err(CollectorError::EndOfStream) }, || async {
+            Err(btleplug::Error::NotConnected)
+        })
+        .await;
+        assert!(matches!(result, Err(CollectorError::EndOfStream)));
+    }
+}
@@ -0,0 +1,199 @@
+use std::future::Future;
+use std::sync::Arc;
+
+use btleplug::api::{Central, Characteristic, Peripheral as _};
+use tokio::time::timeout;
+use tracing::{info, warn};
+
+use crate::inner::conf::model::characteristic_config::CharacteristicConfig;
+use crate::inner::conf::model::flat_peripheral_config::FlatPeripheralConfig;
+use crate::inner::error::{CollectorError, CollectorResult};
+use crate::inner::metrics::CONNECTING_ERRORS;
+use crate::inner::model::collector_event::CollectorEvent;
+use crate::inner::model::connect_peripheral_request::ConnectPeripheralRequest;
+use crate::inner::model::fqcn::Fqcn;
+use crate::inner::model::peripheral_key::PeripheralKey;
+use crate::inner::peripheral_manager::connection_context::ConnectionContext;
+use crate::inner::peripheral_manager::PeripheralManager;
+
+impl PeripheralManager {
+    /// Scans for `once_scan_duration`, then reads every `Poll` characteristic of the matching peripherals once.
+    /// Returns the number of successful reads.
+    #[tracing::instrument(level = "info", skip_all, parent = &self.span, err)]
+    pub(crate) async fn read_once(&self) -> CollectorResult<usize> {
+        let scan_filter = self.active_scan_filter.lock().await.clone();
+        self.adapter.start_scan(scan_filter).await?;
+        tokio::time::sleep(self.app_conf.once_scan_duration).await;
+        self.adapter.stop_scan().await?;
+
+        let mut reads = 0;
+        for peripheral in self.adapter.peripherals().await? {
+            let peripheral_key = Arc::new(self.build_peripheral_key(&peripheral.id()).await?);
+            let Some(config) = self.configuration_manager.get_matching_config(&peripheral_key).await else {
+                continue;
+            };
+
+            match self.read_peripheral_once(Arc::clone(&peripheral_key), config).await {
+                Ok(count) => reads += count,
+                Err(err) => {
+                    CONNECTING_ERRORS.increment();
+                    warn!(peripheral = %peripheral_key, "Failed to read peripheral: {err}");
+                }
+            }
+        }
+
+        Ok(reads)
+    }
+
+    async fn read_peripheral_once(
+        &self,
+        peripheral_key: Arc<PeripheralKey>,
+        peripheral_config: Arc<FlatPeripheralConfig>,
+    ) -> CollectorResult<usize> {
+        let peripheral = self
+            .get_peripheral(&peripheral_key.peripheral_address)
+            .await?
+            .ok_or(CollectorError::PeripheralNotFound(peripheral_key.peripheral_address))?;
+
+        self.connect(&peripheral).await?;
+
+        let characteristics = peripheral
+            .services()
+            .into_iter()
+            .flat_map(|service| service.characteristics.into_iter());
+        let mut contexts = vec![];
+        for (characteristic, characteristic_config) in poll_characteristics(&peripheral_config, characteristics) {
+            let fqcn = Arc::new(Fqcn {
+                peripheral: peripheral_key.peripheral_address,
+                service: characteristic.service_uuid,
+                characteristic: characteristic.uuid,
+            });
+            if !self
+                .check_characteristic_is_supported(&characteristic, &characteristic_config, &fqcn)
+                .await
+            {
+                continue;
+            }
+
+            // publishers rely on the connect event, i.e. for the MQTT discovery
+            self.fanout_sender
+                .send(CollectorEvent::Connect(ConnectPeripheralRequest {
+                    peripheral_key: Arc::clone(&peripheral_key),
+                    fqcn: Arc::clone(&fqcn),
+                    conf: Arc::clone(&characteristic_config),
+                }))
+                .await?;
+
+            contexts.push(ConnectionContext {
+                peripheral: Arc::clone(&peripheral),
+                characteristic,
+                characteristic_config,
+                fqcn,
+                peripheral_config: Arc::clone(&peripheral_config),
+            });
+        }
+
+        let reads = read_each_once(contexts, |ctx| async move {
+            timeout(self.app_conf.default_read_timeout, self.poll_once(&ctx)).await??;
+            Ok(())
+        })
+        .await;
+        info!(reads, "Read peripheral once");
+
+        self.disconnect_if_has_no_tasks(peripheral).await?;
+
+        Ok(reads)
+    }
+}
+
+/// The characteristics configured to be polled, with their configs; the others are never read in one-shot mode.
+fn poll_characteristics(
+    peripheral_config: &FlatPeripheralConfig,
+    characteristics: impl IntoIterator<Item = Characteristic>,
+) -> Vec<(Characteristic, Arc<CharacteristicConfig>)> {
+    characteristics
+        .into_iter()
+        .filter_map(|characteristic| {
+            let characteristic_config = peripheral_config.get_conf(&characteristic)?;
+            matches!(characteristic_config.as_ref(), CharacteristicConfig::Poll { .. })
+                .then_some((characteristic, characteristic_config))
+        })
+        .collect()
+}
+
+/// Reads every item exactly once, one by one; a failed read doesn't stop the others.
+/// Returns the number of successful reads.
+async fn read_each_once<T, F, Fut>(items: Vec<T>, mut read: F) -> usize
+where
+    F: FnMut(T) -> Fut,
+    Fut: Future<Output = CollectorResult<()>>,
+{
+    let mut reads = 0;
+    for item in items {
+        match read(item).await {
+            Ok(()) => reads += 1,
+            Err(err) => warn!("One-shot read failed: {err}"),
+        }
+    }
+    reads
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::inner::conf::dto::peripheral::PeripheralConfigDto;
+
+    #[tokio::test]
+    async fn test_reads_each_poll_characteristic_once() {
+        let config: PeripheralConfigDto = serde_yaml::from_str(
+            r#"
+            name: 'Sensor'
+            services:
+              - uuid: '0000180a-0000-1000-8000-00805f9b34fb'
+                default_delay: 60s
+                default_history_size: 10
+                characteristics:
+                  - !Poll
+                    name: 'Temperature'
+                    uuid: '00000000-0000-0000-0000-000000000001'
+                  - !Subscribe
+                    name: 'Button'
+                    uuid: '00000000-0000-0000-0000-000000000002'
+                  - !Poll
+                    name: 'Broken'
+                    uuid: '00000000-0000-0000-0000-000000000003'
+            "#,
+        )
+        .unwrap();
+        let service_uuid = config.services[0].uuid;
+        let config = FlatPeripheralConfig::try_from(config).unwrap();
+        // 4 isn't configured at all
+        let characteristics = [1, 2, 3, 4].map(|uuid| Characteristic {
+            uuid: Uuid::from_u128(uuid),
+            service_uuid,
+            properties: Default::default(),
+            descriptors: Default::default(),
+        });
+        let read_log = Mutex::new(vec![]);
+
+        let reads = read_each_once(poll_characteristics(&config, characteristics), |(_, conf)| {
+            let name = conf.name().unwrap().to_string();
+            read_log.lock().unwrap().push(name.clone());
+            async move {
+                if name == "Broken" {
+                    return Err(CollectorError::EndOfStream);
+                }
+                Ok(())
+            }
+        })
+        .await;
+
+        // the failed read doesn't stop the others
+        assert_eq!(reads, 1);
+        assert_eq!(*read_log.lock().unwrap(), vec!["Temperature", "Broken"]);
+    }
+}
@@ -0,0 +1,307 @@
+use std::future::Future;
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+use anyhow::Context;
+use tokio::sync::Notify;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn, Instrument, Span};
+
+use crate::inner::conf::model::flat_peripheral_config::FlatPeripheralConfig;
+use crate::inner::error::CollectorResult;
+use crate::inner::model::peripheral_key::PeripheralKey;
+use crate::inner::peripheral_manager::PeripheralManager;
+
+/// Exponential reconnect delay: starts at `min`, doubles on every failure up to `max`.
+pub(super) struct Backoff {
+    min: Duration,
+    max: Duration,
+    current: Duration,
+}
+
+impl Backoff {
+    pub(super) fn new(min: Duration, max: Duration) -> Self {
+        Self { min, max, current: min }
+    }
+
+    fn next_delay(&mut self) -> Duration {
+        let delay = self.current;
+        self.current = (self.current * 2).min(self.max);
+        delay
+    }
+
+    fn reset(&mut self) {
+        self.current = self.min;
+    }
+}
+
+/// Connects, waits for the connection to drop and reconnects right away; failed attempts are retried with backoff.
+/// Gives up after `max_attempts` consecutive failed attempts, if set.
+pub(super) async fn supervise<Connect, ConnectFut, Wait, WaitFut>(
+    mut connect: Connect,
+    mut wait_for_disconnect: Wait,
+    mut backoff: Backoff,
+    max_attempts: Option<u32>,
+) where
+    Connect: FnMut() -> ConnectFut,
+    ConnectFut: Future<Output = CollectorResult<()>>,
+    Wait: FnMut() -> WaitFut,
+    WaitFut: Future<Output = ()>,
+{
+    let mut failed_attempts = 0;
+    loop {
+        match connect().await {
+            Ok(()) => {
+                backoff.reset();
+                failed_attempts = 0;
+                wait_for_disconnect().await;
+                info!("Persistent peripheral disconnected, reconnecting");
+            }
+            Err(err) => {
+                failed_attempts += 1;
+                if max_attempts.is_some_and(|max_attempts| failed_attempts >= max_attempts) {
+                    warn!(failed_attempts, "Giving up reconnecting persistent peripheral: {err}");
+                    return;
+                }
+                let delay = backoff.next_delay();
+                warn!(?delay, "Failed to connect persistent peripheral: {err}");
+                tokio::time::sleep(delay).await;
+            }
+        }
+    }
+}
+
+/// Supervises the connection on behalf of a weakly held owner until `supervise` gives up (`true`) or `shutdown` is
+/// cancelled (`false`). The owner is only upgraded for a connect attempt, so the supervisor doesn't keep it alive;
+/// the attempts made after it's dropped fail.
+async fn supervise_weakly<T, Connect, ConnectFut, Wait, WaitFut>(
+    owner: &Weak<T>,
+    shutdown: &CancellationToken,
+    mut connect: Connect,
+    wait_for_disconnect: Wait,
+    backoff: Backoff,
+    max_attempts: Option<u32>,
+) -> bool
+where
+    Connect: FnMut(Arc<T>) -> ConnectFut,
+    ConnectFut: Future<Output = CollectorResult<()>>,
+    Wait: FnMut() -> WaitFut,
+    WaitFut: Future<Output = ()>,
+{
+    let supervision = supervise(
+        || {
+            let connect = owner.upgrade().map(&mut connect);
+            async move { connect.context("Peripheral manager is dropped")?.await }
+        },
+        wait_for_disconnect,
+        backoff,
+        max_attempts,
+    );
+    tokio::select! {
+        _ = supervision => true,
+        _ = shutdown.cancelled() => false,
+    }
+}
+
+impl PeripheralManager {
+    /// Starts a supervisor holding the connection of a `persistent` peripheral, unless it is already running.
+    /// Advertisements of supervised peripherals are ignored afterwards. A supervisor that gave up after
+    /// `max_reconnect_attempts` is removed, so the next advertisement starts a new one.
+    pub(super) async fn ensure_persistent_supervisor(
+        self: Arc<Self>,
+        peripheral_key: Arc<PeripheralKey>,
+        config: Arc<FlatPeripheralConfig>,
+        span: Span,
+    ) {
+        let mut supervisors = self.persistent_supervisors.lock().await;
+        if supervisors.contains_key(&peripheral_key.peripheral_address) {
+            return;
+        }
+
+        info!("Starting persistent connection supervisor");
+        let disconnected = Arc::new(Notify::new());
+        let backoff = Backoff::new(
+            self.app_conf.persistent_reconnect_min_backoff,
+            self.app_conf.persistent_reconnect_max_backoff,
+        );
+
+        // a strong reference would keep the manager alive, so it could never be dropped and cancel the supervisor
+        let manager = Arc::downgrade(&self);
+        let shutdown = self.persistent_shutdown.clone();
+        let notify = Arc::clone(&disconnected);
+        let address = peripheral_key.peripheral_address;
+        let max_attempts = config.max_reconnect_attempts;
+        tokio::spawn(
+            async move {
+                let gave_up = supervise_weakly(
+                    &manager,
+                    &shutdown,
+                    |manager| manager.connect_all(Arc::clone(&peripheral_key), Arc::clone(&config), Span::current()),
+                    || notify.notified(),
+                    backoff,
+                    max_attempts,
+                )
+                .await;
+                if !gave_up {
+                    return;
+                }
+                if let Some(manager) = manager.upgrade() {
+                    manager.persistent_supervisors.lock().await.remove(&address);
+                }
+            }
+            .instrument(span),
+        );
+
+        supervisors.insert(peripheral_key.peripheral_address, disconnected);
+    }
+
+    pub(super) async fn notify_persistent_disconnect(&self, peripheral_key: &PeripheralKey) {
+        if let Some(disconnected) = self
+            .persistent_supervisors
+            .lock()
+            .await
+            .get(&peripheral_key.peripheral_address)
+        {
+            disconnected.notify_one();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::inner::error::CollectorError;
+
+    #[test]
+    fn test_backoff() {
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(5));
+        let delays = (0..4).map(|_| backoff.next_delay().as_secs()).collect::<Vec<_>>();
+        assert_eq!(delays, vec![1, 2, 4, 5]);
+
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), Duration::from_secs(1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_reconnect_after_disconnect() {
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let disconnected = Arc::new(Notify::new());
+
+        let supervisor = {
+            let attempts = Arc::clone(&attempts);
+            let disconnected = Arc::clone(&disconnected);
+            tokio::spawn(async move {
+                supervise(
+                    || {
+                        let attempt = attempts.fetch_add(1, Ordering::SeqCst);
+                        async move {
+                            // the first attempt fails, the rest succeed
+                            if attempt == 0 {
+                                return Err(CollectorError::EndOfStream);
+                            }
+                            Ok(())
+                        }
+                    },
+                    || disconnected.notified(),
+                    Backoff::new(Duration::from_secs(1), Duration::from_secs(60)),
+                    None,
+                )
+                .await
+            })
+        };
+
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+
+        // no advertisement involved: a disconnect alone triggers the next attempt
+        disconnected.notify_one();
+        tokio::task::yield_now().await;
+        tokio::time::sleep(Duration::from_millis(1)).await;
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+
+        supervisor.abort();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_gives_up_after_max_attempts() {
+        let attempts = AtomicUsize::new(0);
+        supervise(
+            || {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                async { Err(CollectorError::EndOfStream) }
+            },
+            std::future::pending,
+            Backoff::new(Duration::from_secs(1), Duration::from_secs(60)),
+            Some(3),
+        )
+        .await;
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_supervisor_doesnt_keep_owner_alive() {
+        let owner = Arc::new(());
+        let weak_owner = Arc::downgrade(&owner);
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let disconnected = Arc::new(Notify::new());
+
+        let supervisor = {
+            let attempts = Arc::clone(&attempts);
+            let disconnected = Arc::clone(&disconnected);
+            tokio::spawn(async move {
+                supervise_weakly(
+                    &weak_owner,
+                    &CancellationToken::new(),
+                    |_owner| {
+                        attempts.fetch_add(1, Ordering::SeqCst);
+                        async { Ok(()) }
+                    },
+                    || disconnected.notified(),
+                    Backoff::new(Duration::from_secs(1), Duration::from_secs(60)),
+                    Some(1),
+                )
+                .await
+            })
+        };
+
+        tokio::time::sleep(Duration::from_millis(1)).await;
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+        // waiting for a disconnect holds no strong reference
+        assert_eq!(Arc::strong_count(&owner), 1);
+
+        // the next attempt fails without the owner, and the supervisor gives up
+        drop(owner);
+        disconnected.notify_one();
+        assert!(supervisor.await.unwrap());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_supervisor_stops_on_shutdown() {
+        let owner = Arc::new(());
+        let shutdown = CancellationToken::new();
+        let attempts = AtomicUsize::new(0);
+
+        let supervision = supervise_weakly(
+            &Arc::downgrade(&owner),
+            &shutdown,
+            |_owner| {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                async { Ok(()) }
+            },
+            std::future::pending,
+            Backoff::new(Duration::from_secs(1), Duration::from_secs(60)),
+            None,
+        );
+        let cancel = async {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            shutdown.cancel();
+        };
+
+        let (gave_up, ()) = tokio::join!(supervision, cancel);
+        assert!(!gave_up);
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+}
@@ -1,14 +1,17 @@
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use btleplug::api::{BDAddr, Central, Peripheral as _};
 use btleplug::platform::{Peripheral, PeripheralId};
 use futures_util::{stream, StreamExt};
-use tracing::{info, Span};
+use metrics::Label;
+use tracing::{debug, info, Span};
 
 use crate::inner::conf::model::characteristic_config::CharacteristicConfig;
+use crate::inner::dto::{CatalogPeripheralDto, TaskDto};
 use crate::inner::error::{CollectorError, CollectorResult};
 use crate::inner::metrics::measure_execution_time::Measure;
-use crate::inner::metrics::SERVICE_DISCOVERY_DURATION;
+use crate::inner::metrics::{PERIPHERAL_RSSI, SERVICE_DISCOVERY_DURATION};
 use crate::inner::model::connected_peripherals::ConnectedPeripherals;
 use crate::inner::model::fqcn::Fqcn;
 use crate::inner::model::peripheral_key::PeripheralKey;
@@ -24,10 +27,7 @@ impl PeripheralManager {
         Ok(self.get_cached_peripheral(peripheral).await)
     }
     pub(super) async fn get_cached_peripheral(&self, address: &BDAddr) -> Option<Arc<Peripheral>> {
-        if let Some(p) = self.peripheral_cache.get(address).await {
-            return Some(Arc::clone(&p));
-        }
-        None
+        self.peripheral_cache.get(address).await
     }
 
     #[tracing::instrument(level = "info", skip(self), err)]
@@ -44,11 +44,7 @@ impl PeripheralManager {
             .map(|peripheral| async {
                 self.discover_services(&peripheral).await?;
                 self.peripheral_cache
-                    .insert(
-                        peripheral.address(),
-                        Arc::new(peripheral),
-                        self.app_conf.peripheral_cache_ttl,
-                    )
+                    .insert(peripheral.address(), Arc::new(peripheral))
                     .await;
 
                 Ok::<(), CollectorError>(())
@@ -78,6 +74,9 @@ impl PeripheralManager {
         let mut peripheral_key = PeripheralKey::try_from(peripheral_id)?;
         if let Some(peripheral) = self.get_peripheral(&peripheral_key.peripheral_address).await? {
             if let Some(props) = peripheral.properties().await? {
+                if let Some(rssi) = props.rssi {
+                    self.record_rssi(peripheral_key.peripheral_address, rssi).await;
+                }
                 peripheral_key.name = props.local_name;
             }
         }
@@ -85,6 +84,87 @@ impl PeripheralManager {
         Ok(peripheral_key)
     }
 
+    /// Builds keys for the peripherals currently held in the cache, without refreshing it.
+    /// The cache can't be enumerated, so the adapter's peripherals are looked up in it instead.
+    pub(crate) async fn get_cached_peripherals_as_keys(&self) -> CollectorResult<Vec<PeripheralKey>> {
+        let mut peripheral_keys = vec![];
+        for peripheral in self.adapter.peripherals().await? {
+            let Some(peripheral) = self.get_cached_peripheral(&peripheral.address()).await else {
+                continue;
+            };
+            let mut peripheral_key = PeripheralKey::try_from(&peripheral.id())?;
+            peripheral_key.name = peripheral.properties().await?.and_then(|props| props.local_name);
+            peripheral_keys.push(peripheral_key);
+        }
+
+        peripheral_keys.sort_unstable();
+        Ok(peripheral_keys)
+    }
+
+    /// Lists the services discovered for the cached peripherals, without connecting or refreshing the cache.
+    /// The catalog isn't cached itself: it's built from the services btleplug already keeps in memory, so it's cheap
+    /// to build and a cached copy would only go stale on every (re)connect. A peripheral whose properties can't be
+    /// read, e.g. one removed by the adapter meanwhile, is skipped rather than failing the whole catalog.
+    pub(crate) async fn get_catalog(&self) -> CollectorResult<Vec<CatalogPeripheralDto>> {
+        let mut catalog = vec![];
+        for peripheral in self.adapter.peripherals().await? {
+            let Some(peripheral) = self.get_cached_peripheral(&peripheral.address()).await else {
+                continue;
+            };
+            let name = match peripheral.properties().await {
+                Ok(properties) => properties.and_then(|props| props.local_name),
+                Err(err) => {
+                    debug!(peripheral = %peripheral.address(), "Skipping the peripheral in the catalog: {err}");
+                    continue;
+                }
+            };
+            catalog.extend(CatalogPeripheralDto::from_services(
+                peripheral.address(),
+                name,
+                self.adapter_info.label().to_string(),
+                peripheral.services(),
+            ));
+        }
+
+        catalog.sort_unstable_by_key(|peripheral| peripheral.address);
+        Ok(catalog)
+    }
+
+    pub(crate) async fn is_connected(&self, address: &BDAddr) -> CollectorResult<bool> {
+        match self.get_cached_peripheral(address).await {
+            Some(peripheral) => Ok(peripheral.is_connected().await?),
+            None => Ok(false),
+        }
+    }
+
+    async fn record_rssi(&self, address: BDAddr, rssi: i16) {
+        PERIPHERAL_RSSI
+            .with_labels(vec![Label::new("peripheral", address.to_string())])
+            .record(rssi as f64);
+
+        let history_size = self.app_conf.rssi_history_size;
+        if history_size == 0 {
+            return;
+        }
+
+        let mut rssi_history = self.rssi_history.lock().await;
+        let readings = rssi_history.entry(address).or_default();
+        while readings.len() >= history_size {
+            readings.pop_front();
+        }
+        readings.push_back((Instant::now(), rssi));
+    }
+
+    /// Returns the recorded RSSI readings for the peripheral, oldest first.
+    pub(crate) async fn get_rssi_history(&self, address: &BDAddr) -> Vec<(Instant, i16)> {
+        self.rssi_history
+            .lock()
+            .await
+            .get(address)
+            .map(|readings| readings.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
     pub(crate) async fn get_all_connected_peripherals(&self) -> ConnectedPeripherals {
         let poll_handle_map = self.poll_handle_map.lock().await;
         let subscription_map = self.subscription_map.lock().await;
@@ -97,6 +177,47 @@ impl PeripheralManager {
         )
     }
 
+    /// Builds keys for the peripherals of the adapter that are currently connected.
+    pub(crate) async fn get_connected_peripherals_as_keys(&self) -> CollectorResult<Vec<PeripheralKey>> {
+        let mut peripheral_keys = vec![];
+        for peripheral in self.adapter.peripherals().await? {
+            if !peripheral.is_connected().await? {
+                continue;
+            }
+            let mut peripheral_key = PeripheralKey::try_from(&peripheral.id())?;
+            peripheral_key.name = peripheral.properties().await?.and_then(|props| props.local_name);
+            peripheral_keys.push(peripheral_key);
+        }
+
+        peripheral_keys.sort_unstable();
+        Ok(peripheral_keys)
+    }
+
+    /// Lists the poll tasks and subscribed characteristics of all peripherals.
+    pub(crate) async fn get_tasks(&self) -> Vec<TaskDto> {
+        let poll_handle_map = self.poll_handle_map.lock().await;
+        let subscribed_characteristics = self.subscribed_characteristics.lock().await;
+        let subscription_started_at = self.subscription_started_at.lock().await;
+
+        TaskDto::collect(
+            poll_handle_map.keys(),
+            subscribed_characteristics.keys(),
+            &subscription_started_at,
+        )
+    }
+
+    /// Returns how long the peripheral of a subscribed characteristic has been subscribed to.
+    pub(crate) async fn get_subscription_age(&self, fqcn: &Fqcn) -> Option<Duration> {
+        if !self.subscribed_characteristics.lock().await.contains_key(fqcn) {
+            return None;
+        }
+        self.subscription_started_at
+            .lock()
+            .await
+            .get(&fqcn.peripheral)
+            .map(Instant::elapsed)
+    }
+
     pub(super) async fn get_characteristic_conf(&self, fqcn: &Fqcn) -> Option<Arc<CharacteristicConfig>> {
         self.subscribed_characteristics.lock().await.get(fqcn).cloned()
     }
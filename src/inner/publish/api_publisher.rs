@@ -1,14 +1,19 @@
 use std::collections::VecDeque;
-use std::sync::Arc;
+use std::sync::{Arc, Weak};
+use std::time::Duration;
 
+use async_trait::async_trait;
 use btleplug::api::BDAddr;
 use chrono::{DateTime, Utc};
 use dashmap::DashMap;
 use serde::Serialize;
+use tokio::task::JoinHandle;
+use tracing::debug;
 use uuid::Uuid;
 
+use crate::inner::error::CollectorResult;
 use crate::inner::model::characteristic_payload::CharacteristicPayload;
-use crate::inner::publish::dto::ApiDataPoint;
+use crate::inner::publish::dto::{ApiDataPoint, LatestApiDataPoint, TimestampFormat};
 use crate::inner::publish::PublishPayload;
 
 #[derive(Debug, Default, Serialize)]
@@ -16,6 +21,32 @@ pub(crate) struct CharacteristicStorage {
     pub(crate) name: Option<Arc<String>>,
     pub(crate) values: VecDeque<ApiDataPoint>,
     pub(crate) num_updates: usize,
+    #[serde(skip)]
+    history_window: Option<Duration>,
+    /// The adapter that has received the most recent data point.
+    #[serde(skip)]
+    adapter_id: String,
+}
+
+impl CharacteristicStorage {
+    /// Drops data points older than the history window; returns the number of evicted data points.
+    fn evict_expired(&mut self, now: DateTime<Utc>) -> usize {
+        let Some(history_window) = self
+            .history_window
+            .and_then(|window| chrono::Duration::from_std(window).ok())
+        else {
+            return 0;
+        };
+
+        let initial_len = self.values.len();
+        while let Some(data_point) = self.values.front() {
+            if now - data_point.ts.value <= history_window {
+                break;
+            }
+            self.values.pop_front();
+        }
+        initial_len - self.values.len()
+    }
 }
 
 #[derive(Debug, Default, Serialize)]
@@ -35,12 +66,15 @@ pub(crate) struct PeripheralStorage {
 #[derive(Debug, Serialize)]
 pub(crate) struct ApiPublisher {
     pub(crate) peripherals: DashMap<BDAddr, PeripheralStorage>,
+    #[serde(skip)]
+    timestamp_format: TimestampFormat,
 }
 
 impl ApiPublisher {
-    pub(crate) fn new() -> Self {
+    pub(crate) fn new(timestamp_format: TimestampFormat) -> Self {
         Self {
             peripherals: DashMap::new(),
+            timestamp_format,
         }
     }
     pub(crate) fn process(&self, payload: Arc<CharacteristicPayload>) {
@@ -58,17 +92,198 @@ impl ApiPublisher {
 
         char_storage.num_updates += 1;
         char_storage.name = payload.conf.name();
+        char_storage.history_window = payload.conf.history_window();
+        char_storage.adapter_id.clone_from(&payload.adapter_info.id);
         while char_storage.values.len() > payload.conf.history_size() {
             char_storage.values.pop_front();
         }
 
-        let data_point = ApiDataPoint::from(payload.as_ref());
+        let data_point = ApiDataPoint::new(payload.as_ref(), self.timestamp_format);
         char_storage.values.push_back(data_point);
+        char_storage.evict_expired(Utc::now());
+    }
+
+    /// Returns the most recent data point of the characteristic received by the adapter; without a service, the
+    /// service with the lowest uuid that has data for the characteristic is used.
+    pub(crate) fn get_latest(
+        &self,
+        adapter_id: &str,
+        peripheral: BDAddr,
+        service: Option<Uuid>,
+        characteristic: Uuid,
+    ) -> Option<LatestApiDataPoint> {
+        let peripheral = self.peripherals.get(&peripheral)?;
+        // DashMap iterates in an arbitrary order, the uuids are sorted so the same service is picked every time
+        let mut service_uuids = peripheral
+            .services
+            .iter()
+            .map(|entry| *entry.key())
+            .filter(|uuid| service.is_none_or(|service| *uuid == service))
+            .collect::<Vec<_>>();
+        service_uuids.sort_unstable();
+
+        service_uuids.into_iter().find_map(|uuid| {
+            let service = peripheral.services.get(&uuid)?;
+            let char_storage = service.characteristics.get(&characteristic)?;
+            if char_storage.adapter_id != adapter_id {
+                return None;
+            }
+            Some(LatestApiDataPoint {
+                data_point: char_storage.values.back()?.clone(),
+                num_updates: char_storage.num_updates,
+                characteristic_name: char_storage.name.clone(),
+            })
+        })
+    }
+
+    /// Removes data points that fell out of their characteristic history window.
+    pub(crate) fn evict_expired(&self, now: DateTime<Utc>) -> usize {
+        let mut evicted = 0;
+        for peripheral in self.peripherals.iter() {
+            for service in peripheral.services.iter() {
+                for mut char_storage in service.characteristics.iter_mut() {
+                    evicted += char_storage.evict_expired(now);
+                }
+            }
+        }
+        evicted
+    }
+
+    /// Periodically evicts expired data points, so stale values don't stay around when no new data arrives.
+    /// The task ends when the publisher is dropped.
+    pub(crate) fn start_eviction(self: &Arc<Self>, eviction_interval: Duration) -> JoinHandle<()> {
+        let publisher: Weak<Self> = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(eviction_interval);
+            loop {
+                interval.tick().await;
+                let Some(publisher) = publisher.upgrade() else {
+                    break;
+                };
+                let evicted = publisher.evict_expired(Utc::now());
+                debug!(evicted, "Evicted expired data points");
+            }
+        })
     }
 }
 
+#[async_trait]
 impl PublishPayload for ApiPublisher {
-    fn publish(&self, payload: Arc<CharacteristicPayload>) {
+    async fn publish(&self, payload: Arc<CharacteristicPayload>) -> CollectorResult<()> {
         self.process(payload);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inner::conf::model::characteristic_config::CharacteristicConfig;
+    use crate::inner::conv::converter::CharacteristicValue;
+    use crate::inner::model::fqcn::Fqcn;
+    use crate::inner::test_fixtures::{self, fqcn, subscribe_config};
+
+    fn payload(created_at: DateTime<Utc>) -> Arc<CharacteristicPayload> {
+        let fqcn = fqcn();
+        let mut conf = subscribe_config(&fqcn);
+        let CharacteristicConfig::Subscribe { history_window_sec, .. } = &mut conf else {
+            unreachable!()
+        };
+        *history_window_sec = Some(Duration::from_secs(60));
+        Arc::new(CharacteristicPayload {
+            created_at,
+            ..test_fixtures::payload(fqcn, conf, CharacteristicValue::I64(42))
+        })
+    }
+
+    fn num_values(publisher: &ApiPublisher) -> usize {
+        let mut num_values = 0;
+        for peripheral in publisher.peripherals.iter() {
+            for service in peripheral.services.iter() {
+                num_values += service
+                    .characteristics
+                    .iter()
+                    .map(|char_storage| char_storage.values.len())
+                    .sum::<usize>();
+            }
+        }
+        num_values
+    }
+
+    #[test]
+    fn test_evict_expired() {
+        let publisher = ApiPublisher::new(TimestampFormat::Rfc3339);
+        let now = Utc::now();
+        publisher.process(payload(now - chrono::Duration::seconds(30)));
+        publisher.process(payload(now - chrono::Duration::seconds(10)));
+        assert_eq!(num_values(&publisher), 2);
+
+        assert_eq!(publisher.evict_expired(now + chrono::Duration::seconds(40)), 1);
+        assert_eq!(num_values(&publisher), 1);
+
+        assert_eq!(publisher.evict_expired(now + chrono::Duration::seconds(60)), 1);
+        assert_eq!(num_values(&publisher), 0);
+    }
+
+    #[test]
+    fn test_get_latest() {
+        let publisher = ApiPublisher::new(TimestampFormat::Rfc3339);
+        let now = Utc::now();
+        let fqcn = payload(now).fqcn.clone();
+        assert!(publisher
+            .get_latest("hci0", fqcn.peripheral, None, fqcn.characteristic)
+            .is_none());
+
+        publisher.process(payload(now - chrono::Duration::seconds(5)));
+        publisher.process(payload(now));
+
+        let latest = publisher
+            .get_latest("hci0", fqcn.peripheral, Some(fqcn.service), fqcn.characteristic)
+            .unwrap();
+        assert_eq!(latest.num_updates, 2);
+        assert_eq!(latest.data_point.ts.value, now);
+        assert!(publisher
+            .get_latest("hci0", fqcn.peripheral, None, fqcn.characteristic)
+            .is_some());
+        assert!(publisher
+            .get_latest("hci0", fqcn.peripheral, Some(fqcn.characteristic), fqcn.characteristic)
+            .is_none());
+
+        let json = serde_json::to_value(&latest).unwrap();
+        assert_eq!(json["value"], 42);
+        assert_eq!(json["num_updates"], 2);
+        assert!(json["ts"].is_string());
+        assert!(json.get("characteristic_name").is_some());
+    }
+
+    #[test]
+    fn test_get_latest_filters_adapter_and_orders_services() {
+        let publisher = ApiPublisher::new(TimestampFormat::Rfc3339);
+        let in_service = |service: u128, value: i64| {
+            let fqcn = Arc::new(Fqcn {
+                service: Uuid::from_u128(service),
+                ..fqcn().as_ref().clone()
+            });
+            Arc::new(test_fixtures::payload(
+                Arc::clone(&fqcn),
+                subscribe_config(&fqcn),
+                CharacteristicValue::I64(value),
+            ))
+        };
+        // the same characteristic in two services, the one with the higher uuid is processed first
+        publisher.process(in_service(2, 2));
+        publisher.process(in_service(1, 1));
+        let fqcn = fqcn();
+
+        let latest = publisher
+            .get_latest("hci0", fqcn.peripheral, None, fqcn.characteristic)
+            .unwrap();
+        assert_eq!(serde_json::to_value(&latest).unwrap()["value"], 1);
+        assert!(publisher
+            .get_latest("hci1", fqcn.peripheral, None, fqcn.characteristic)
+            .is_none());
+        assert!(publisher
+            .get_latest("hci1", fqcn.peripheral, Some(Uuid::from_u128(2)), fqcn.characteristic)
+            .is_none());
     }
 }
@@ -1,36 +1,214 @@
 use crate::inner::conv::converter::CharacteristicValue;
 use crate::inner::model::characteristic_payload::CharacteristicPayload;
 use crate::inner::model::fqcn::Fqcn;
+use crate::inner::publish::value_delta::ValueDelta;
 use chrono::{DateTime, Utc};
-use serde::Serialize;
+use serde::{Deserialize, Serialize, Serializer};
 use std::sync::Arc;
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Serialize, Deserialize, clap::ValueEnum)]
+pub(crate) enum TimestampFormat {
+    #[default]
+    Rfc3339,
+    EpochMillis,
+}
+
+/// Encoding of the MQTT state messages; subscribers must decode `Proto` messages with
+/// `proto/characteristic_payload.proto`.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Serialize, Deserialize, clap::ValueEnum)]
+pub(crate) enum MqttSerialization {
+    #[default]
+    Json,
+    Proto,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Timestamp {
+    pub(crate) value: DateTime<Utc>,
+    pub(crate) format: TimestampFormat,
+}
+
+impl Serialize for Timestamp {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self.format {
+            TimestampFormat::Rfc3339 => self.value.serialize(serializer),
+            TimestampFormat::EpochMillis => serializer.serialize_i64(self.value.timestamp_millis()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub(crate) struct ApiDataPoint {
-    pub(crate) ts: DateTime<Utc>,
+    pub(crate) ts: Timestamp,
     pub(crate) value: CharacteristicValue,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) raw_bytes_hex: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) delta: Option<ValueDelta>,
 }
 
-impl From<&CharacteristicPayload> for ApiDataPoint {
-    fn from(value: &CharacteristicPayload) -> Self {
+impl ApiDataPoint {
+    pub(crate) fn new(value: &CharacteristicPayload, timestamp_format: TimestampFormat) -> Self {
         Self {
-            ts: value.created_at,
+            ts: Timestamp {
+                value: value.created_at,
+                format: timestamp_format,
+            },
             value: value.value.clone(),
+            raw_bytes_hex: value
+                .raw_bytes
+                .as_deref()
+                .filter(|_| value.conf.record_raw_bytes())
+                .map(to_hex),
+            delta: value.delta,
         }
     }
 }
 
+/// The most recent data point of a characteristic, along with its storage metadata.
+#[derive(Debug, Serialize)]
+pub(crate) struct LatestApiDataPoint {
+    #[serde(flatten)]
+    pub(crate) data_point: ApiDataPoint,
+    pub(crate) num_updates: usize,
+    pub(crate) characteristic_name: Option<Arc<String>>,
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
 #[derive(Debug, Serialize)]
 pub(crate) struct MqttDataPoint {
     pub(crate) fqcn: Arc<Fqcn>,
     pub(crate) value: CharacteristicValue,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) ts: Option<Timestamp>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) adapter_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) raw_hex: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) delta: Option<ValueDelta>,
 }
 
-impl From<&CharacteristicPayload> for MqttDataPoint {
-    fn from(value: &CharacteristicPayload) -> Self {
+impl MqttDataPoint {
+    pub(crate) fn new(value: &CharacteristicPayload, timestamp_format: TimestampFormat) -> Self {
+        let mqtt_conf = value.conf.publish_mqtt();
+        let include_raw_service_data = mqtt_conf
+            .map(|mqtt_conf| mqtt_conf.include_raw_service_data)
+            .unwrap_or(false);
+        let include_metadata = mqtt_conf.map(|mqtt_conf| mqtt_conf.include_metadata).unwrap_or(false);
+
         Self {
             fqcn: value.fqcn.clone(),
             value: value.value.clone(),
+            ts: include_metadata.then_some(Timestamp {
+                value: value.created_at,
+                format: timestamp_format,
+            }),
+            adapter_id: include_metadata.then(|| value.adapter_info.id.clone()),
+            raw_hex: value
+                .raw_bytes
+                .as_deref()
+                .filter(|_| include_raw_service_data)
+                .map(to_hex),
+            delta: value.delta,
         }
     }
 }
+
+#[derive(Debug, Serialize)]
+pub(crate) struct MqttHeartbeat {
+    pub(crate) ts: DateTime<Utc>,
+    pub(crate) adapter_count: usize,
+    pub(crate) connected_peripherals: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inner::conf::dto::publish::PublishMqttConfigDto;
+    use crate::inner::conf::model::characteristic_config::CharacteristicConfig;
+    use crate::inner::test_fixtures::{self, fqcn, subscribe_config};
+    use chrono::TimeZone;
+
+    fn payload(include_raw_service_data: bool, include_metadata: bool) -> CharacteristicPayload {
+        let fqcn = fqcn();
+        let mut conf = subscribe_config(&fqcn);
+        let CharacteristicConfig::Subscribe { publish_mqtt, .. } = &mut conf else {
+            unreachable!()
+        };
+        *publish_mqtt = Some(PublishMqttConfigDto {
+            state_topic: Arc::new("`state`".to_string()),
+            unit: None,
+            retain: false,
+            qos: Default::default(),
+            command_topic: None,
+            include_raw_service_data,
+            include_metadata,
+            discovery: None,
+        });
+        CharacteristicPayload {
+            raw_bytes: Some(vec![0x2a, 0x00]),
+            ..test_fixtures::payload(fqcn, conf, CharacteristicValue::I64(42))
+        }
+    }
+
+    #[test]
+    fn test_mqtt_data_point_raw_hex() {
+        let data_point =
+            serde_json::to_value(MqttDataPoint::new(&payload(true, false), TimestampFormat::Rfc3339)).unwrap();
+        assert_eq!(data_point["raw_hex"], "2a00");
+        assert_eq!(data_point["value"], 42);
+
+        let data_point =
+            serde_json::to_value(MqttDataPoint::new(&payload(false, false), TimestampFormat::Rfc3339)).unwrap();
+        assert!(data_point.get("raw_hex").is_none());
+
+        // the raw bytes were captured for MQTT only
+        assert!(ApiDataPoint::new(&payload(true, false), TimestampFormat::Rfc3339)
+            .raw_bytes_hex
+            .is_none());
+    }
+
+    #[test]
+    fn test_mqtt_data_point_metadata() {
+        let enriched = payload(false, true);
+        let data_point = serde_json::to_value(MqttDataPoint::new(&enriched, TimestampFormat::Rfc3339)).unwrap();
+        assert_eq!(data_point["adapter_id"], "hci0");
+        assert_eq!(data_point["ts"], serde_json::to_value(enriched.created_at).unwrap());
+        assert_eq!(data_point["fqcn"]["peripheral"], "11:22:33:44:55:66");
+
+        let data_point = serde_json::to_value(MqttDataPoint::new(&enriched, TimestampFormat::EpochMillis)).unwrap();
+        assert_eq!(data_point["ts"], enriched.created_at.timestamp_millis());
+
+        let data_point =
+            serde_json::to_value(MqttDataPoint::new(&payload(false, false), TimestampFormat::Rfc3339)).unwrap();
+        assert!(data_point.get("ts").is_none());
+        assert!(data_point.get("adapter_id").is_none());
+    }
+
+    #[test]
+    fn test_serialize_timestamp() {
+        let value = Utc.timestamp_millis_opt(1_700_000_000_123).unwrap();
+
+        let rfc3339 = Timestamp {
+            value,
+            format: TimestampFormat::Rfc3339,
+        };
+        assert_eq!(
+            serde_json::to_string(&rfc3339).unwrap(),
+            r#""2023-11-14T22:13:20.123Z""#
+        );
+
+        let epoch_millis = Timestamp {
+            value,
+            format: TimestampFormat::EpochMillis,
+        };
+        assert_eq!(serde_json::to_string(&epoch_millis).unwrap(), "1700000000123");
+    }
+}
@@ -0,0 +1,148 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use btleplug::api::BDAddr;
+use futures_util::{stream, Stream};
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::inner::error::CollectorResult;
+use crate::inner::model::characteristic_payload::CharacteristicPayload;
+use crate::inner::model::fqcn::Fqcn;
+use crate::inner::publish::dto::{ApiDataPoint, TimestampFormat};
+use crate::inner::publish::PublishPayload;
+
+/// Characteristics a client of the `/events` stream is interested in; an empty filter matches every payload.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct StreamFilter {
+    pub(crate) peripheral: Option<BDAddr>,
+    pub(crate) service: Option<Uuid>,
+    pub(crate) characteristic: Option<Uuid>,
+}
+
+impl StreamFilter {
+    fn matches(&self, fqcn: &Fqcn) -> bool {
+        self.peripheral.is_none_or(|peripheral| peripheral == fqcn.peripheral)
+            && self.service.is_none_or(|service| service == fqcn.service)
+            && self
+                .characteristic
+                .is_none_or(|characteristic| characteristic == fqcn.characteristic)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct StreamEventDto {
+    pub(crate) fqcn: Arc<Fqcn>,
+    #[serde(flatten)]
+    pub(crate) data_point: ApiDataPoint,
+}
+
+/// Broadcasts payloads to the connected `/events` clients; payloads are dropped if nobody is listening.
+pub(crate) struct EventStreamPublisher {
+    sender: broadcast::Sender<Arc<CharacteristicPayload>>,
+    timestamp_format: TimestampFormat,
+}
+
+impl EventStreamPublisher {
+    pub(crate) fn new(capacity: usize, timestamp_format: TimestampFormat) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self {
+            sender,
+            timestamp_format,
+        }
+    }
+
+    /// Returns the payloads matching the filter, published after the subscription. A client that falls more than
+    /// `capacity` payloads behind skips the missed ones.
+    pub(crate) fn subscribe(&self, filter: StreamFilter) -> impl Stream<Item = StreamEventDto> {
+        let timestamp_format = self.timestamp_format;
+        stream::unfold(
+            (self.sender.subscribe(), filter),
+            move |(mut receiver, filter)| async move {
+                loop {
+                    match receiver.recv().await {
+                        Ok(payload) if filter.matches(&payload.fqcn) => {
+                            let event = StreamEventDto {
+                                fqcn: Arc::clone(&payload.fqcn),
+                                data_point: ApiDataPoint::new(&payload, timestamp_format),
+                            };
+                            return Some((event, (receiver, filter)));
+                        }
+                        Ok(_) => continue,
+                        Err(RecvError::Lagged(skipped)) => {
+                            warn!(skipped, "Event stream client is lagging behind");
+                            continue;
+                        }
+                        Err(RecvError::Closed) => return None,
+                    }
+                }
+            },
+        )
+    }
+}
+
+#[async_trait]
+impl PublishPayload for EventStreamPublisher {
+    async fn publish(&self, payload: Arc<CharacteristicPayload>) -> CollectorResult<()> {
+        // fails only if there are no clients
+        let _ = self.sender.send(payload);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::StreamExt;
+
+    use crate::inner::conv::converter::CharacteristicValue;
+    use crate::inner::test_fixtures::{self, peripheral_fqcn, subscribe_config};
+
+    use super::*;
+
+    fn payload(peripheral: &str, characteristic: u128, value: i64) -> Arc<CharacteristicPayload> {
+        let fqcn = peripheral_fqcn(peripheral, characteristic);
+        let conf = subscribe_config(&fqcn);
+        Arc::new(test_fixtures::payload(fqcn, conf, CharacteristicValue::I64(value)))
+    }
+
+    #[tokio::test]
+    async fn test_clients_receive_matching_payloads() {
+        let publisher = EventStreamPublisher::new(16, TimestampFormat::EpochMillis);
+        let everything = publisher.subscribe(StreamFilter::default());
+        let battery = publisher.subscribe(StreamFilter {
+            peripheral: Some("11:22:33:44:55:66".parse().unwrap()),
+            characteristic: Some(Uuid::from_u128(0x2a19)),
+            ..Default::default()
+        });
+
+        publisher
+            .publish(payload("11:22:33:44:55:66", 0x2a19, 1))
+            .await
+            .unwrap();
+        publisher
+            .publish(payload("11:22:33:44:55:66", 0x2a6e, 2))
+            .await
+            .unwrap();
+        publisher
+            .publish(payload("AA:BB:CC:DD:EE:FF", 0x2a19, 3))
+            .await
+            .unwrap();
+        publisher
+            .publish(payload("11:22:33:44:55:66", 0x2a19, 4))
+            .await
+            .unwrap();
+        drop(publisher);
+
+        let values = |events: Vec<StreamEventDto>| {
+            events
+                .into_iter()
+                .map(|event| serde_json::to_value(event).unwrap()["value"].as_i64().unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(values(everything.collect().await), vec![1, 2, 3, 4]);
+        assert_eq!(values(battery.collect().await), vec![1, 4]);
+    }
+}
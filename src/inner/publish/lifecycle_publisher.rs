@@ -0,0 +1,181 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use btleplug::api::BDAddr;
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use kanal::AsyncReceiver;
+use metrics::counter;
+use serde::Serialize;
+use tracing::info;
+
+use crate::inner::metrics::PERIPHERAL_LIFECYCLE_COUNT;
+use crate::inner::model::collector_event::CollectorEvent;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize)]
+pub(crate) enum LifecycleEventKind {
+    Connected,
+    Disconnected,
+}
+
+impl LifecycleEventKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            LifecycleEventKind::Connected => "connected",
+            LifecycleEventKind::Disconnected => "disconnected",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct LifecycleEventDto {
+    pub(crate) ts: DateTime<Utc>,
+    pub(crate) peripheral: BDAddr,
+    pub(crate) kind: LifecycleEventKind,
+}
+
+#[derive(Default)]
+struct LifecycleState {
+    connected: HashSet<BDAddr>,
+    history: HashMap<BDAddr, VecDeque<LifecycleEventDto>>,
+}
+
+/// Records when peripherals connect and disconnect, keeping the last `history_size` events per peripheral.
+/// `Connect` / `Disconnect` events are sent per characteristic, only peripheral state transitions are recorded.
+pub(crate) struct LifecyclePublisher {
+    history_size: usize,
+    state: Mutex<LifecycleState>,
+}
+
+impl LifecyclePublisher {
+    pub(crate) fn new(history_size: usize) -> Self {
+        Self {
+            history_size,
+            state: Default::default(),
+        }
+    }
+
+    pub(crate) async fn block_on_receiving(self: Arc<Self>, receiver: AsyncReceiver<CollectorEvent>) {
+        let mut stream = receiver.stream();
+        while let Some(event) = stream.next().await {
+            self.process(&event, Utc::now());
+        }
+    }
+
+    fn process(&self, event: &CollectorEvent, ts: DateTime<Utc>) {
+        let (peripheral, kind) = match event {
+            CollectorEvent::Connect(request) => (request.fqcn.peripheral, LifecycleEventKind::Connected),
+            CollectorEvent::Disconnect(fqcn, _) => (fqcn.peripheral, LifecycleEventKind::Disconnected),
+            CollectorEvent::Payload(_) => return,
+        };
+
+        let mut state = self.state.lock().unwrap();
+        let is_transition = match kind {
+            LifecycleEventKind::Connected => state.connected.insert(peripheral),
+            LifecycleEventKind::Disconnected => state.connected.remove(&peripheral),
+        };
+        if !is_transition {
+            return;
+        }
+
+        info!(%peripheral, ?kind, "Peripheral lifecycle event");
+        counter!(
+            PERIPHERAL_LIFECYCLE_COUNT.metric_name,
+            "peripheral" => peripheral.to_string(),
+            "event" => kind.as_str()
+        )
+        .increment(1);
+
+        if self.history_size == 0 {
+            return;
+        }
+        let history = state.history.entry(peripheral).or_default();
+        while history.len() >= self.history_size {
+            history.pop_front();
+        }
+        history.push_back(LifecycleEventDto { ts, peripheral, kind });
+    }
+
+    /// Returns the recorded events, oldest first; all peripherals are included if `peripheral` is not set.
+    pub(crate) fn get_history(&self, peripheral: Option<BDAddr>) -> Vec<LifecycleEventDto> {
+        let state = self.state.lock().unwrap();
+        let mut events = state
+            .history
+            .iter()
+            .filter(|(address, _)| peripheral.map(|peripheral| peripheral == **address).unwrap_or(true))
+            .flat_map(|(_, events)| events.iter().cloned())
+            .collect::<Vec<_>>();
+        events.sort_by_key(|event| event.ts);
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inner::model::connect_peripheral_request::ConnectPeripheralRequest;
+    use crate::inner::model::fqcn::Fqcn;
+    use crate::inner::model::peripheral_key::PeripheralKey;
+    use crate::inner::test_fixtures::{peripheral_fqcn, subscribe_config};
+
+    fn connect(fqcn: Arc<Fqcn>) -> CollectorEvent {
+        CollectorEvent::Connect(ConnectPeripheralRequest {
+            peripheral_key: Arc::new(PeripheralKey {
+                adapter_id: "hci0".to_string(),
+                peripheral_address: fqcn.peripheral,
+                name: None,
+            }),
+            conf: Arc::new(subscribe_config(&fqcn)),
+            fqcn,
+        })
+    }
+
+    fn disconnect(fqcn: Arc<Fqcn>) -> CollectorEvent {
+        let conf = Arc::new(subscribe_config(&fqcn));
+        CollectorEvent::Disconnect(fqcn, conf)
+    }
+
+    #[test]
+    fn test_connect_and_disconnect_are_recorded() {
+        let publisher = LifecyclePublisher::new(10);
+        let now = Utc::now();
+        let at = |seconds| now + chrono::Duration::seconds(seconds);
+
+        // every characteristic of a peripheral sends its own event
+        publisher.process(&connect(peripheral_fqcn("11:22:33:44:55:66", 1)), at(0));
+        publisher.process(&connect(peripheral_fqcn("11:22:33:44:55:66", 2)), at(1));
+        publisher.process(&connect(peripheral_fqcn("AA:BB:CC:DD:EE:FF", 1)), at(2));
+        publisher.process(&disconnect(peripheral_fqcn("11:22:33:44:55:66", 1)), at(3));
+        publisher.process(&disconnect(peripheral_fqcn("11:22:33:44:55:66", 2)), at(4));
+
+        let history = publisher
+            .get_history(None)
+            .into_iter()
+            .map(|event| (event.peripheral.to_string(), event.kind))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            history,
+            vec![
+                ("11:22:33:44:55:66".to_string(), LifecycleEventKind::Connected),
+                ("AA:BB:CC:DD:EE:FF".to_string(), LifecycleEventKind::Connected),
+                ("11:22:33:44:55:66".to_string(), LifecycleEventKind::Disconnected),
+            ]
+        );
+
+        let history = publisher.get_history(Some("AA:BB:CC:DD:EE:FF".parse().unwrap()));
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].ts, at(2));
+    }
+
+    #[test]
+    fn test_history_size() {
+        let publisher = LifecyclePublisher::new(2);
+        let now = Utc::now();
+        for _ in 0..3 {
+            publisher.process(&connect(peripheral_fqcn("11:22:33:44:55:66", 1)), now);
+            publisher.process(&disconnect(peripheral_fqcn("11:22:33:44:55:66", 1)), now);
+        }
+
+        assert_eq!(publisher.get_history(None).len(), 2);
+    }
+}
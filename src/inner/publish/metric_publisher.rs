@@ -1,25 +1,76 @@
+use std::collections::HashSet;
 use std::sync::Arc;
+use std::time::Duration;
 
 use crate::inner::conf::dto::publish::PublishMetricConfigDto;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use dashmap::DashMap;
-use metrics::{counter, gauge, histogram, KeyName, SharedString};
+use metrics::{counter, gauge, histogram, KeyName, Label, SharedString};
 use tracing::warn;
 
-use crate::inner::metrics::MetricType;
+use crate::inner::conv::converter::Converter;
+use crate::inner::error::CollectorResult;
+use crate::inner::metrics::{MetricType, CHARACTERISTIC_AGE, METRIC_VALUE_SKIPPED_COUNT};
 use crate::inner::model::characteristic_payload::CharacteristicPayload;
+use crate::inner::model::fqcn::Fqcn;
 use crate::inner::publish::PublishPayload;
 
 pub(crate) struct MetricPublisher {
     registered_metrics: DashMap<Arc<String>, ()>,
+    labels_allowlist: Option<HashSet<String>>,
+    filtered_metrics: DashMap<Arc<String>, ()>,
+    last_updates: DashMap<Arc<Fqcn>, DateTime<Utc>>,
+    idle_timeout: Duration,
 }
 
 impl MetricPublisher {
-    pub(crate) fn new() -> MetricPublisher {
+    pub(crate) fn new(labels_allowlist: Option<Vec<String>>, idle_timeout: Duration) -> MetricPublisher {
         Self {
             registered_metrics: Default::default(),
+            labels_allowlist: labels_allowlist.map(HashSet::from_iter),
+            filtered_metrics: Default::default(),
+            last_updates: Default::default(),
+            idle_timeout,
         }
     }
 
+    /// Sets the age of every characteristic published as a metric, so stale values can be alerted on.
+    /// Characteristics without updates for longer than the metrics idle timeout are dropped, along with their series.
+    pub(crate) fn record_ages(&self, now: DateTime<Utc>) {
+        self.last_updates.retain(|fqcn, updated_at| {
+            let age = (now - *updated_at).to_std().unwrap_or_default();
+            if age > self.idle_timeout {
+                return false;
+            }
+            CHARACTERISTIC_AGE
+                .with_labels(vec![
+                    fqcn.peripheral_label(),
+                    fqcn.service_label(),
+                    fqcn.characteristic_label(),
+                ])
+                .gauge(age.as_secs_f64());
+            true
+        });
+    }
+
+    fn filter_labels(&self, metric_name: &Arc<String>, labels: Vec<Label>) -> Vec<Label> {
+        let Some(allowlist) = self.labels_allowlist.as_ref() else {
+            return labels;
+        };
+
+        let (allowed, filtered): (Vec<_>, Vec<_>) =
+            labels.into_iter().partition(|label| allowlist.contains(label.key()));
+
+        // label keys are static per metric, so warning once is enough
+        if !filtered.is_empty() && self.filtered_metrics.insert(metric_name.clone(), ()).is_none() {
+            let keys = filtered.iter().map(|label| label.key()).collect::<Vec<_>>();
+            warn!("Labels {keys:?} of metric {metric_name} are not in the allowlist and were dropped");
+        }
+
+        allowed
+    }
+
     fn register_metric(&self, metric_conf: &PublishMetricConfigDto) {
         self.registered_metrics
             .entry(metric_conf.name.clone())
@@ -34,50 +85,233 @@ impl MetricPublisher {
                     let description = SharedString::from(description);
 
                     let name = KeyName::from(metric_conf.name.to_string());
+                    let unit = metric_conf.metric_unit();
 
                     match metric_conf.metric_type {
-                        MetricType::Counter => recorder.describe_counter(name, None, description),
-                        MetricType::Gauge => recorder.describe_gauge(name, None, description),
-                        MetricType::Histogram => recorder.describe_histogram(name, None, description),
+                        MetricType::Counter => recorder.describe_counter(name, unit, description),
+                        MetricType::Gauge => recorder.describe_gauge(name, unit, description),
+                        MetricType::Histogram => recorder.describe_histogram(name, unit, description),
                     };
                 });
             });
     }
 }
 
+#[async_trait]
 impl PublishPayload for MetricPublisher {
-    fn publish(&self, payload: Arc<CharacteristicPayload>) {
+    async fn publish(&self, payload: Arc<CharacteristicPayload>) -> CollectorResult<()> {
         let conf = payload.conf.as_ref();
         let Some(metric_conf) = conf.publish_metrics() else {
-            return;
+            return Ok(());
         };
-
-        if !payload.value.is_numeric() {
-            warn!(
-                "Non-numeric value received for metric {}: {} ({})",
-                metric_conf.metric_type, payload.value, payload.fqcn
-            );
-            return;
+        // binary data, can't be a metric value
+        if matches!(conf.converter(), Converter::Base64 { .. }) {
+            return Ok(());
         }
 
         self.register_metric(metric_conf);
-        let mut labels = metric_conf.labels();
-        labels.extend([
-            payload.fqcn.peripheral_label(),
-            payload.fqcn.service_label(),
-            payload.fqcn.characteristic_label(),
-        ]);
+        self.last_updates.insert(payload.fqcn.clone(), payload.created_at);
+        let labels = self.filter_labels(&metric_conf.name, metric_conf.with_fqcn_labels(&payload.fqcn));
 
         let name = metric_conf.name.to_string();
 
-        match metric_conf.metric_type {
-            MetricType::Counter => counter!(name, labels).absolute(payload.value.as_u64().unwrap()),
-            MetricType::Gauge => {
-                gauge!(name, labels).set(payload.value.as_f64().unwrap());
-            }
-            MetricType::Histogram => {
-                histogram!(name, labels).record(payload.value.as_f64().unwrap());
-            }
+        let recorded = match metric_conf.metric_type {
+            MetricType::Counter => payload
+                .value
+                .as_u64()
+                .map(|value| counter!(name.clone(), labels).absolute(value)),
+            MetricType::Gauge => payload
+                .value
+                .as_f64()
+                .map(|value| gauge!(name.clone(), labels).set(value)),
+            MetricType::Histogram => payload
+                .value
+                .as_f64()
+                .map(|value| histogram!(name.clone(), labels).record(value)),
+        };
+
+        if recorded.is_none() {
+            warn!(
+                "Value {} can't be recorded by {} metric {name} ({})",
+                payload.value, metric_conf.metric_type, payload.fqcn
+            );
+            METRIC_VALUE_SKIPPED_COUNT
+                .with_labels(vec![Label::new("metric", name)])
+                .increment();
         }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use metrics::Unit;
+    use metrics_util::debugging::DebugValue;
+
+    use crate::inner::conf::model::characteristic_config::CharacteristicConfig;
+    use crate::inner::conv::converter::CharacteristicValue;
+    use crate::inner::metrics::testing::{capture_metrics, metric_key, MetricsCapture};
+    use crate::inner::test_fixtures::{self, fqcn, subscribe_config};
+
+    use super::*;
+
+    const IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+    const FQCN_LABELS: [(&str, &str); 3] = [
+        ("peripheral", "11:22:33:44:55:66"),
+        ("service", "0000180f-0000-1000-8000-00805f9b34fb"),
+        ("characteristic", "00002a19-0000-1000-8000-00805f9b34fb"),
+    ];
+
+    fn payload(metric_type: MetricType, value: CharacteristicValue) -> Arc<CharacteristicPayload> {
+        converted_payload(metric_type, Default::default(), value)
+    }
+
+    fn converted_payload(
+        metric_type: MetricType,
+        converter: Converter,
+        value: CharacteristicValue,
+    ) -> Arc<CharacteristicPayload> {
+        let fqcn = fqcn();
+        let mut conf = subscribe_config(&fqcn);
+        let CharacteristicConfig::Subscribe {
+            converter: conf_converter,
+            publish_metrics,
+            ..
+        } = &mut conf
+        else {
+            unreachable!()
+        };
+        *conf_converter = converter;
+        *publish_metrics = Some(PublishMetricConfigDto {
+            metric_type,
+            name: Arc::new("sensor_value".to_string()),
+            description: None,
+            unit: Arc::new("count".to_string()),
+            labels: None,
+            device_class: None,
+            auto_fqcn_labels: true,
+        });
+        Arc::new(test_fixtures::payload(fqcn, conf, value))
+    }
+
+    fn publish(payload: Arc<CharacteristicPayload>) -> HashMap<String, DebugValue> {
+        let publisher = MetricPublisher::new(None, IDLE_TIMEOUT);
+        let (result, recorded) = capture_metrics(publisher.publish(payload));
+        result.unwrap();
+        recorded
+    }
+
+    fn skipped_count(count: u64) -> HashMap<String, DebugValue> {
+        HashMap::from([(
+            metric_key(METRIC_VALUE_SKIPPED_COUNT.metric_name, &[("metric", "sensor_value")]),
+            DebugValue::Counter(count),
+        )])
+    }
+
+    #[test]
+    fn test_filter_labels() {
+        let name = Arc::new("temperature".to_string());
+        let labels = vec![Label::new("peripheral", "a"), Label::new("room", "kitchen")];
+
+        let publisher = MetricPublisher::new(None, IDLE_TIMEOUT);
+        assert_eq!(publisher.filter_labels(&name, labels.clone()), labels);
+
+        let publisher = MetricPublisher::new(Some(vec!["peripheral".to_string()]), IDLE_TIMEOUT);
+        assert_eq!(
+            publisher.filter_labels(&name, labels),
+            vec![Label::new("peripheral", "a")]
+        );
+        assert!(publisher.filtered_metrics.contains_key(&name));
+    }
+
+    #[test]
+    fn test_unit_is_registered() {
+        let metric_conf: PublishMetricConfigDto = serde_yaml::from_str(
+            r#"
+            metric_type: Gauge
+            name: 'sensor_uptime_seconds'
+            description: 'Sensor uptime'
+            unit: 'Seconds'
+            device_class: 'duration'
+            "#,
+        )
+        .unwrap();
+
+        let metrics = MetricsCapture::new();
+        let publisher = MetricPublisher::new(None, IDLE_TIMEOUT);
+        metrics.run(async {
+            publisher.register_metric(&metric_conf);
+            gauge!(metric_conf.name.to_string(), metric_conf.labels()).set(42.0);
+        });
+
+        assert_eq!(
+            metrics.descriptions().into_iter().collect::<Vec<_>>(),
+            vec![(
+                metric_key("sensor_uptime_seconds", &[("device_class", "duration")]),
+                (Some(Unit::Seconds), Some("Sensor uptime".to_string()))
+            )]
+        );
+    }
+
+    #[test]
+    fn test_negative_value_is_skipped_by_counter() {
+        let recorded = publish(payload(MetricType::Counter, CharacteristicValue::I64(-5)));
+        assert_eq!(recorded, skipped_count(1));
+
+        let recorded = publish(payload(MetricType::Gauge, CharacteristicValue::I64(-5)));
+        assert_eq!(
+            recorded,
+            HashMap::from([(
+                metric_key("sensor_value", &FQCN_LABELS),
+                DebugValue::Gauge((-5.0).into())
+            )])
+        );
+    }
+
+    #[test]
+    fn test_non_numeric_value_is_skipped() {
+        let recorded = publish(payload(
+            MetricType::Histogram,
+            CharacteristicValue::Utf8("on".to_string()),
+        ));
+        assert_eq!(recorded, skipped_count(1));
+    }
+
+    #[test]
+    fn test_base64_value_is_not_published() {
+        let recorded = publish(converted_payload(
+            MetricType::Gauge,
+            Converter::Base64 {
+                alphabet: Default::default(),
+            },
+            CharacteristicValue::Utf8("AQI=".to_string()),
+        ));
+        assert!(recorded.is_empty());
+    }
+
+    #[test]
+    fn test_age_increases_without_new_data() {
+        let metrics = MetricsCapture::new();
+        let publisher = MetricPublisher::new(None, IDLE_TIMEOUT);
+        let payload = payload(MetricType::Gauge, CharacteristicValue::I64(5));
+        let published_at = payload.created_at;
+
+        let age = |seconds: i64| {
+            metrics.run(async { publisher.record_ages(published_at + chrono::Duration::seconds(seconds)) });
+            metrics
+                .values()
+                .remove(&metric_key(CHARACTERISTIC_AGE.metric_name, &FQCN_LABELS))
+        };
+
+        metrics.run(publisher.publish(payload)).unwrap();
+        assert_eq!(age(10), Some(DebugValue::Gauge(10.0.into())));
+        assert_eq!(age(20), Some(DebugValue::Gauge(20.0.into())));
+
+        // past the idle timeout, the age is no longer updated
+        age(IDLE_TIMEOUT.as_secs() as i64 + 1);
+        assert!(publisher.last_updates.is_empty());
     }
 }
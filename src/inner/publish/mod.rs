@@ -1,38 +1,102 @@
+use crate::inner::error::CollectorResult;
+use crate::inner::metrics::PAYLOAD_DROPPED_COUNT;
 use crate::inner::model::characteristic_payload::CharacteristicPayload;
+use async_trait::async_trait;
+use metrics::Label;
+use std::borrow::Cow;
 use std::sync::Arc;
+use tracing::warn;
 
 pub(crate) mod api_publisher;
 pub(crate) mod dto;
+pub(crate) mod event_stream;
+pub(crate) mod lifecycle_publisher;
 pub(crate) mod metric_publisher;
+pub(crate) mod mqtt_command;
 pub(crate) mod mqtt_discovery_payload;
 pub(crate) mod mqtt_interpolator;
+pub(crate) mod mqtt_target;
 pub(crate) mod multi_publisher;
+pub(crate) mod proto;
+pub(crate) mod value_delta;
 
+#[async_trait]
 pub(crate) trait PublishPayload {
-    fn publish(&self, payload: Arc<CharacteristicPayload>);
+    async fn publish(&self, payload: Arc<CharacteristicPayload>) -> CollectorResult<()>;
 }
 
 pub(crate) struct FanOutSender<T> {
-    senders: Vec<kanal::AsyncSender<T>>,
+    senders: Vec<(Cow<'static, str>, kanal::AsyncSender<T>)>,
 }
 
 impl<T> FanOutSender<T> {
-    pub(crate) fn new(senders: Vec<kanal::AsyncSender<T>>) -> Self {
-        Self { senders }
+    pub(crate) fn new(senders: Vec<(&'static str, kanal::AsyncSender<T>)>) -> Self {
+        Self {
+            senders: senders
+                .into_iter()
+                .map(|(consumer, sender)| (Cow::Borrowed(consumer), sender))
+                .collect(),
+        }
     }
 
-    pub(crate) fn add(&mut self, sender: kanal::AsyncSender<T>) {
-        self.senders.push(sender);
+    pub(crate) fn add(&mut self, consumer: impl Into<Cow<'static, str>>, sender: kanal::AsyncSender<T>) {
+        self.senders.push((consumer.into(), sender));
     }
 
+    /// Sends the payload to every consumer; a failing consumer does not prevent delivery to the others.
     pub(crate) async fn send(&self, payload: T) -> Result<(), kanal::SendError>
     where
         T: Clone,
     {
-        for sender in &self.senders {
-            sender.send(payload.clone()).await?;
+        let mut result = Ok(());
+        for (consumer, sender) in &self.senders {
+            if let Err(err) = sender.send(payload.clone()).await {
+                let reason = match err {
+                    kanal::SendError::Closed => "closed",
+                    kanal::SendError::ReceiveClosed => "receive_closed",
+                };
+                warn!(%consumer, reason, "Dropped payload");
+                PAYLOAD_DROPPED_COUNT
+                    .with_labels(vec![
+                        Label::new("consumer", consumer.clone()),
+                        Label::new("reason", reason),
+                    ])
+                    .increment();
+                result = Err(err);
+            }
         }
 
-        Ok(())
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use metrics_util::debugging::DebugValue;
+
+    use super::*;
+    use crate::inner::metrics::testing::{capture_metrics, metric_key};
+
+    #[test]
+    fn test_dropped_payload_counter() {
+        let (closed_sender, closed_receiver) = kanal::unbounded_async::<usize>();
+        drop(closed_receiver);
+        let (sender, receiver) = kanal::unbounded_async::<usize>();
+        let fanout_sender = FanOutSender::new(vec![("closed", closed_sender), ("open", sender)]);
+
+        let (result, recorded) = capture_metrics(fanout_sender.send(42));
+
+        assert!(result.is_err());
+        assert_eq!(receiver.try_recv().unwrap(), Some(42));
+        assert_eq!(
+            recorded.into_iter().collect::<Vec<_>>(),
+            vec![(
+                metric_key(
+                    PAYLOAD_DROPPED_COUNT.metric_name,
+                    &[("consumer", "closed"), ("reason", "receive_closed")]
+                ),
+                DebugValue::Counter(1)
+            )]
+        );
     }
 }
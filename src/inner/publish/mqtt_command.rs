@@ -0,0 +1,247 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use futures_util::StreamExt;
+use kanal::{AsyncReceiver, AsyncSender};
+use tracing::{info, warn};
+
+use crate::inner::adapter_manager::AdapterManager;
+use crate::inner::conf::model::characteristic_config::CharacteristicConfig;
+use crate::inner::error::{CollectorError, CollectorResult};
+use crate::inner::model::connect_peripheral_request::ConnectPeripheralRequest;
+use crate::inner::model::fqcn::Fqcn;
+use crate::inner::publish::mqtt_interpolator::MqttInterpolator;
+
+#[derive(Debug, Clone)]
+pub(crate) struct MqttCommand {
+    pub(crate) topic: String,
+    pub(crate) payload: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub(crate) struct MqttCommandTarget {
+    pub(crate) adapter_id: String,
+    pub(crate) fqcn: Arc<Fqcn>,
+    pub(crate) conf: Arc<CharacteristicConfig>,
+}
+
+#[async_trait]
+pub(crate) trait CharacteristicWriter {
+    async fn write_characteristic(&self, adapter_id: &str, fqcn: &Fqcn, value: Vec<u8>) -> CollectorResult<()>;
+}
+
+#[async_trait]
+impl CharacteristicWriter for AdapterManager {
+    async fn write_characteristic(&self, adapter_id: &str, fqcn: &Fqcn, value: Vec<u8>) -> CollectorResult<()> {
+        let peripheral_manager = self
+            .get_peripheral_manager(adapter_id)
+            .await?
+            .ok_or_else(|| CollectorError::AdapterNotFound(adapter_id.to_string()))?;
+        peripheral_manager.write_characteristic(fqcn, &value).await
+    }
+}
+
+/// Maps MQTT command topics to characteristics and turns incoming commands into writes.
+pub(crate) struct MqttCommandRouter {
+    targets: DashMap<String, Arc<MqttCommandTarget>>,
+    sender: AsyncSender<MqttCommand>,
+    receiver: AsyncReceiver<MqttCommand>,
+}
+
+impl Default for MqttCommandRouter {
+    fn default() -> Self {
+        let (sender, receiver) = kanal::unbounded_async();
+        Self {
+            targets: Default::default(),
+            sender,
+            receiver,
+        }
+    }
+}
+
+impl MqttCommandRouter {
+    /// Registers the command topic of a connected characteristic; returns the topic if it has to be subscribed to.
+    pub(crate) fn register(
+        &self,
+        interpolator: &MqttInterpolator,
+        request: &ConnectPeripheralRequest,
+    ) -> CollectorResult<Option<String>> {
+        let Some(topic) = interpolator.interpolate_command_topic(request)? else {
+            return Ok(None);
+        };
+
+        let target = MqttCommandTarget {
+            adapter_id: request.peripheral_key.adapter_id.clone(),
+            fqcn: Arc::clone(&request.fqcn),
+            conf: Arc::clone(&request.conf),
+        };
+
+        let is_new = self.targets.insert(topic.clone(), Arc::new(target)).is_none();
+        Ok(is_new.then_some(topic))
+    }
+
+    pub(crate) async fn enqueue(&self, command: MqttCommand) -> CollectorResult<()> {
+        self.sender.send(command).await?;
+        Ok(())
+    }
+
+    pub(crate) async fn block_on_receiving(&self, writer: &(dyn CharacteristicWriter + Sync)) {
+        let mut stream = self.receiver.stream();
+        while let Some(command) = stream.next().await {
+            if let Err(err) = self.handle_command(&command, writer).await {
+                warn!(topic = command.topic, "Failed to handle MQTT command: {err}");
+            }
+        }
+    }
+
+    pub(crate) async fn handle_command(
+        &self,
+        command: &MqttCommand,
+        writer: &(dyn CharacteristicWriter + Sync),
+    ) -> CollectorResult<()> {
+        let Some(target) = self.targets.get(&command.topic).map(|target| Arc::clone(&target)) else {
+            warn!(topic = command.topic, "Received a command for an unknown topic");
+            return Ok(());
+        };
+
+        let value = target.conf.converter().encode(&command.payload)?;
+        info!(fqcn = %target.fqcn, ?value, "Writing MQTT command");
+
+        writer
+            .write_characteristic(&target.adapter_id, &target.fqcn, value)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use bounded_integer::{BoundedI8, BoundedU8};
+
+    use super::*;
+    use crate::inner::conf::dto::publish::PublishMqttConfigDto;
+    use crate::inner::conv::converter::{ConversionError, Converter};
+    use crate::inner::model::peripheral_key::PeripheralKey;
+    use crate::inner::test_fixtures::{fqcn, subscribe_config};
+
+    #[derive(Default)]
+    struct RecordingWriter {
+        writes: Mutex<Vec<(String, Fqcn, Vec<u8>)>>,
+    }
+
+    #[async_trait]
+    impl CharacteristicWriter for RecordingWriter {
+        async fn write_characteristic(&self, adapter_id: &str, fqcn: &Fqcn, value: Vec<u8>) -> CollectorResult<()> {
+            self.writes
+                .lock()
+                .unwrap()
+                .push((adapter_id.to_string(), fqcn.clone(), value));
+            Ok(())
+        }
+    }
+
+    fn connect_request() -> ConnectPeripheralRequest {
+        let fqcn = fqcn();
+        let mut conf = subscribe_config(&fqcn);
+        let CharacteristicConfig::Subscribe {
+            name,
+            service_name,
+            converter,
+            publish_mqtt,
+            ..
+        } = &mut conf
+        else {
+            unreachable!()
+        };
+        *name = Some("target".to_string().into());
+        *service_name = Some("thermostat".to_string().into());
+        *converter = Converter::Signed {
+            l: BoundedU8::new(2).unwrap(),
+            m: BoundedI8::new(1).unwrap(),
+            d: -1,
+            b: 0,
+        };
+        *publish_mqtt = Some(PublishMqttConfigDto {
+            state_topic: Arc::new("`state`".to_string()),
+            unit: None,
+            retain: false,
+            qos: Default::default(),
+            command_topic: Some(Arc::new("`thermostat/${ctx.clean_fqcn.peripheral}/set`".to_string())),
+            include_raw_service_data: false,
+            include_metadata: false,
+            discovery: None,
+        });
+
+        ConnectPeripheralRequest {
+            peripheral_key: Arc::new(PeripheralKey {
+                adapter_id: "hci0".to_string(),
+                peripheral_address: fqcn.peripheral,
+                name: None,
+            }),
+            fqcn,
+            conf: Arc::new(conf),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_command_triggers_write() {
+        let router = MqttCommandRouter::default();
+        let interpolator = MqttInterpolator::default();
+        let request = connect_request();
+
+        let topic = router.register(&interpolator, &request).unwrap().unwrap();
+        assert_eq!(topic, "thermostat/11_22_33_44_55_66/set");
+        assert!(router.register(&interpolator, &request).unwrap().is_none());
+
+        let writer = RecordingWriter::default();
+        router
+            .handle_command(
+                &MqttCommand {
+                    topic: topic.clone(),
+                    payload: b"21.5".to_vec(),
+                },
+                &writer,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            writer.writes.lock().unwrap().as_slice(),
+            &[("hci0".to_string(), request.fqcn.as_ref().clone(), vec![0xd7, 0x00])]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_malformed_command_is_rejected() {
+        let router = MqttCommandRouter::default();
+        let topic = router
+            .register(&MqttInterpolator::default(), &connect_request())
+            .unwrap()
+            .unwrap();
+        let writer = RecordingWriter::default();
+
+        let result = router
+            .handle_command(
+                &MqttCommand {
+                    topic,
+                    payload: b"warm".to_vec(),
+                },
+                &writer,
+            )
+            .await;
+        assert!(matches!(
+            result,
+            Err(CollectorError::ConversionError(ConversionError::InvalidCommand(_)))
+        ));
+
+        let unknown_topic = MqttCommand {
+            topic: "unknown/set".to_string(),
+            payload: b"21.5".to_vec(),
+        };
+        router.handle_command(&unknown_topic, &writer).await.unwrap();
+
+        assert!(writer.writes.lock().unwrap().is_empty());
+    }
+}
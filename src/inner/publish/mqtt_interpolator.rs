@@ -115,6 +115,20 @@ impl MqttInterpolator {
         Ok(result)
     }
 
+    #[tracing::instrument(skip(self), err)]
+    pub(crate) fn interpolate_command_topic(
+        &self,
+        request: &ConnectPeripheralRequest,
+    ) -> CollectorResult<Option<String>> {
+        let Some(command_topic) = request.conf.publish_mqtt().and_then(|conf| conf.command_topic.as_ref()) else {
+            return Ok(None);
+        };
+
+        let mut scope = Scope::try_from(Context::from(request))?;
+        let result: String = self.eval(&mut scope, command_topic.as_str())?;
+        Ok(Some(result))
+    }
+
     #[tracing::instrument(skip(self), err)]
     pub(crate) fn interpolate_discovery(
         &self,
@@ -136,6 +150,10 @@ impl MqttInterpolator {
         scope // add topics to the context
             .push("state_topic", state_topic)
             .push("config_topic", config_topic.clone());
+        if let Some(command_topic) = mqtt_conf.command_topic.as_ref() {
+            let command_topic: String = self.eval(&mut scope, command_topic.as_str())?;
+            scope.push("command_topic", command_topic);
+        }
 
         let mut interpolated_mqtt_conf = serde_json::to_value(&discovery.remainder)?;
         self.interpolate_value(&mut interpolated_mqtt_conf, &mut scope)?;
@@ -230,6 +248,9 @@ mod tests {
             unit: Some(Arc::new("`test-${ctx.peripheral}-test`".to_string())),
             retain: true,
             qos: Default::default(),
+            command_topic: None,
+            include_raw_service_data: false,
+            include_metadata: false,
             discovery: Some(Arc::new(DiscoverySettings {
                 config_topic: Arc::new("`config-test-${ctx.clean_fqcn.peripheral}`".to_string()),
                 retain: Default::default(),
@@ -245,6 +266,8 @@ mod tests {
             uuid: "00002a19-0000-1000-8000-00805f9b34fb".parse().unwrap(),
             history_size: 42,
             converter: Converter::F32,
+            history_window_sec: None,
+            record_raw_bytes: false,
             publish_metrics: None,
             publish_mqtt: Some(mqtt_conf.clone()),
         });
@@ -252,11 +275,15 @@ mod tests {
         let payload = CharacteristicPayload {
             fqcn: fqcn.clone(),
             value: CharacteristicValue::F64(42.0),
+            raw_bytes: None,
+            delta: None,
             created_at: Utc::now(),
             conf: char_conf.clone(),
             adapter_info: Arc::new(AdapterInfo {
                 id: "hci0".to_string(),
                 modalias: "smth".to_string(),
+                address: None,
+                alias: None,
             }),
         };
 
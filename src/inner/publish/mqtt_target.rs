@@ -0,0 +1,173 @@
+use prost::Message;
+use rumqttc::v5::mqttbytes::QoS;
+
+use crate::inner::conf::dto::mqtt_target::MqttTargetConfigDto;
+use crate::inner::error::CollectorResult;
+use crate::inner::model::characteristic_payload::CharacteristicPayload;
+use crate::inner::publish::dto::{MqttDataPoint, MqttSerialization, TimestampFormat};
+use crate::inner::publish::mqtt_interpolator::MqttInterpolator;
+use crate::inner::publish::proto::pb;
+
+/// Per-broker publishing settings; the broker passed with `--mqtt-address` uses the defaults.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct MqttTarget {
+    pub(crate) topic_prefix: Option<String>,
+    pub(crate) qos: Option<QoS>,
+    pub(crate) skip_discovery: bool,
+    pub(crate) serialization: MqttSerialization,
+    pub(crate) timestamp_format: TimestampFormat,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub(crate) struct MqttStateMessage {
+    pub(crate) topic: String,
+    pub(crate) qos: QoS,
+    pub(crate) retain: bool,
+    pub(crate) data_point: Vec<u8>,
+}
+
+impl From<&MqttTargetConfigDto> for MqttTarget {
+    fn from(value: &MqttTargetConfigDto) -> Self {
+        Self {
+            topic_prefix: value.topic_prefix.clone(),
+            qos: value.qos.map(QoS::from),
+            skip_discovery: !value.discovery,
+            serialization: value.serialization,
+            ..Default::default()
+        }
+    }
+}
+
+impl MqttTarget {
+    /// Builds the state message for this broker; `None` if the characteristic isn't published to MQTT.
+    pub(crate) fn state_message(
+        &self,
+        interpolator: &MqttInterpolator,
+        payload: &CharacteristicPayload,
+    ) -> CollectorResult<Option<MqttStateMessage>> {
+        let Some(mqtt_conf) = payload.conf.publish_mqtt() else {
+            return Ok(None);
+        };
+
+        let mut topic = interpolator.interpolate_state_topic(mqtt_conf.state_topic.as_str(), payload)?;
+        if let Some(prefix) = self.topic_prefix.as_ref() {
+            topic.insert_str(0, prefix);
+        }
+
+        let data_point = match self.serialization {
+            MqttSerialization::Json => serde_json::to_vec(&MqttDataPoint::new(payload, self.timestamp_format))?,
+            MqttSerialization::Proto => pb::CharacteristicPayload::from(payload).encode_to_vec(),
+        };
+
+        Ok(Some(MqttStateMessage {
+            topic,
+            qos: self.qos.unwrap_or(mqtt_conf.qos()),
+            retain: mqtt_conf.retain,
+            data_point,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::inner::conf::dto::collector_configuration::CollectorConfigurationDto;
+    use crate::inner::conf::dto::publish::PublishMqttConfigDto;
+    use crate::inner::conf::model::characteristic_config::CharacteristicConfig;
+    use crate::inner::conv::converter::{CharacteristicValue, Converter};
+    use crate::inner::model::collector_event::CollectorEvent;
+    use crate::inner::publish::FanOutSender;
+    use crate::inner::test_fixtures::{self, fqcn, subscribe_config};
+
+    use super::*;
+
+    fn payload() -> CharacteristicPayload {
+        let fqcn = fqcn();
+        let mut conf = subscribe_config(&fqcn);
+        let CharacteristicConfig::Subscribe {
+            converter,
+            publish_mqtt,
+            ..
+        } = &mut conf
+        else {
+            unreachable!()
+        };
+        *converter = Converter::F32;
+        *publish_mqtt = Some(PublishMqttConfigDto {
+            state_topic: Arc::new("`sensors/${ctx.clean_fqcn.peripheral}`".to_string()),
+            unit: None,
+            retain: true,
+            qos: Default::default(),
+            command_topic: None,
+            include_raw_service_data: false,
+            include_metadata: false,
+            discovery: None,
+        });
+        test_fixtures::payload(fqcn, conf, CharacteristicValue::F64(42.0))
+    }
+
+    #[tokio::test]
+    async fn test_payload_is_published_to_every_target() {
+        let config: CollectorConfigurationDto = serde_yaml::from_str(
+            r#"
+            peripherals: []
+            mqtt_targets:
+              - name: local
+                host: localhost
+              - name: cloud
+                host: mqtt.example.com
+                port: 8883
+                topic_prefix: 'home/'
+                qos: AtMostOnce
+                serialization: Proto
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.mqtt_targets.len(), 2);
+
+        let mut fanout_sender = FanOutSender::new(vec![]);
+        let mut receivers = vec![];
+        for target_conf in &config.mqtt_targets {
+            let (sender, receiver) = kanal::unbounded_async::<CollectorEvent>();
+            fanout_sender.add(format!("mqtt:{}", target_conf.name), sender);
+            receivers.push((MqttTarget::from(target_conf), receiver));
+        }
+
+        let payload = Arc::new(payload());
+        fanout_sender
+            .send(CollectorEvent::Payload(Arc::clone(&payload)))
+            .await
+            .unwrap();
+
+        let interpolator = MqttInterpolator::default();
+        let mut messages = vec![];
+        for (target, receiver) in receivers {
+            let Some(CollectorEvent::Payload(payload)) = receiver.try_recv().unwrap() else {
+                panic!("Expected a payload event");
+            };
+            messages.push(target.state_message(&interpolator, &payload).unwrap().unwrap());
+        }
+
+        let json_data_point =
+            serde_json::to_vec(&MqttDataPoint::new(payload.as_ref(), TimestampFormat::Rfc3339)).unwrap();
+        let proto_data_point = pb::CharacteristicPayload::from(payload.as_ref()).encode_to_vec();
+        assert_eq!(
+            messages,
+            vec![
+                MqttStateMessage {
+                    topic: "sensors/11_22_33_44_55_66".to_string(),
+                    qos: QoS::AtLeastOnce,
+                    retain: true,
+                    data_point: json_data_point,
+                },
+                MqttStateMessage {
+                    topic: "home/sensors/11_22_33_44_55_66".to_string(),
+                    qos: QoS::AtMostOnce,
+                    retain: true,
+                    data_point: proto_data_point,
+                },
+            ]
+        );
+    }
+}
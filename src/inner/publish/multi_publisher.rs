@@ -1,8 +1,9 @@
-use kanal::Receiver;
+use kanal::AsyncReceiver;
 use std::sync::Arc;
 
-use metrics::{counter, Label};
-use tracing::debug;
+use futures_util::StreamExt;
+use metrics::Label;
+use tracing::{debug, error};
 
 use crate::inner::metrics::PAYLOAD_PROCESSED_COUNT;
 use crate::inner::model::characteristic_payload::CharacteristicPayload;
@@ -10,41 +11,43 @@ use crate::inner::model::collector_event::CollectorEvent;
 use crate::inner::publish::PublishPayload;
 
 pub(crate) struct MultiPublisher {
-    receiver: Receiver<CollectorEvent>,
+    receiver: AsyncReceiver<CollectorEvent>,
     publishers: Vec<Arc<dyn PublishPayload + Send + Sync>>,
 }
 
 impl MultiPublisher {
     pub(crate) fn new(
-        receiver: Receiver<CollectorEvent>,
+        receiver: AsyncReceiver<CollectorEvent>,
         publishers: Vec<Arc<dyn PublishPayload + Send + Sync>>,
     ) -> Self {
         Self { receiver, publishers }
     }
-    pub(crate) fn block_on_receiving(self: Arc<Self>) {
-        let receiver = self.receiver.clone();
-        for (index, payload) in receiver.enumerate() {
+    pub(crate) async fn block_on_receiving(self: Arc<Self>) {
+        let mut stream = self.receiver.stream().enumerate();
+        while let Some((index, payload)) = stream.next().await {
             let CollectorEvent::Payload(payload) = payload else {
                 continue;
             };
             let metric_labels = vec![
-                Label::new("adapter", payload.adapter_info.id.to_string()),
+                Label::new("adapter", payload.adapter_info.label().to_string()),
                 Label::new("scope", "processing"),
                 Label::new("peripheral", payload.fqcn.peripheral.to_string()),
                 Label::new("service", payload.fqcn.service.to_string()),
                 Label::new("characteristic", payload.fqcn.characteristic.to_string()),
             ];
-            self.publish(payload);
-            counter!(PAYLOAD_PROCESSED_COUNT.metric_name, metric_labels).increment(1);
+            self.publish(payload).await;
+            PAYLOAD_PROCESSED_COUNT.with_labels(metric_labels).increment();
             if index % 10000 == 0 {
                 debug!("Processed {index} payloads");
             }
         }
     }
 
-    pub(crate) fn publish(&self, payload: Arc<CharacteristicPayload>) {
+    pub(crate) async fn publish(&self, payload: Arc<CharacteristicPayload>) {
         for publisher in &self.publishers {
-            publisher.publish(Arc::clone(&payload));
+            if let Err(err) = publisher.publish(Arc::clone(&payload)).await {
+                error!(fqcn = %payload.fqcn, "Failed to publish payload: {err:?}");
+            }
         }
     }
 }
@@ -0,0 +1,62 @@
+use crate::inner::conv::converter::CharacteristicValue;
+use crate::inner::model::characteristic_payload::CharacteristicPayload;
+
+/// Generated from `proto/characteristic_payload.proto`.
+pub(crate) mod pb {
+    include!(concat!(env!("OUT_DIR"), "/ble_collector.rs"));
+}
+
+impl From<&CharacteristicPayload> for pb::CharacteristicPayload {
+    fn from(value: &CharacteristicPayload) -> Self {
+        let proto_value = match &value.value {
+            CharacteristicValue::Raw(bytes) => pb::characteristic_payload::Value::Raw(bytes.clone()),
+            CharacteristicValue::Utf8(text) => pb::characteristic_payload::Value::Utf8(text.clone()),
+            CharacteristicValue::I64(number) => pb::characteristic_payload::Value::I64(*number),
+            CharacteristicValue::F64(number) => pb::characteristic_payload::Value::F64(*number),
+        };
+
+        Self {
+            fqcn: Some(pb::Fqcn {
+                peripheral: value.fqcn.peripheral.to_string(),
+                service: value.fqcn.service.to_string(),
+                characteristic: value.fqcn.characteristic.to_string(),
+            }),
+            value: Some(proto_value),
+            timestamp_millis: value.created_at.timestamp_millis(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use prost::Message;
+
+    use crate::inner::test_fixtures::{self, fqcn, subscribe_config};
+
+    use super::*;
+
+    #[test]
+    fn test_encode_decode() {
+        let payload = CharacteristicPayload {
+            created_at: Utc.timestamp_millis_opt(1_700_000_000_123).unwrap(),
+            ..test_fixtures::payload(fqcn(), subscribe_config(&fqcn()), CharacteristicValue::F64(21.5))
+        };
+
+        let encoded = pb::CharacteristicPayload::from(&payload).encode_to_vec();
+        let decoded = pb::CharacteristicPayload::decode(encoded.as_slice()).unwrap();
+
+        assert_eq!(
+            decoded,
+            pb::CharacteristicPayload {
+                fqcn: Some(pb::Fqcn {
+                    peripheral: "11:22:33:44:55:66".to_string(),
+                    service: "0000180f-0000-1000-8000-00805f9b34fb".to_string(),
+                    characteristic: "00002a19-0000-1000-8000-00805f9b34fb".to_string(),
+                }),
+                value: Some(pb::characteristic_payload::Value::F64(21.5)),
+                timestamp_millis: 1_700_000_000_123,
+            }
+        );
+    }
+}
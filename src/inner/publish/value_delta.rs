@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+
+use crate::inner::conv::converter::CharacteristicValue;
+use crate::inner::model::fqcn::Fqcn;
+
+/// Change of a numeric value since the previous sample of the same characteristic.
+/// Both fields are `None` for the first sample.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub(crate) struct ValueDelta {
+    pub(crate) previous: Option<f64>,
+    pub(crate) delta: Option<f64>,
+}
+
+/// Keeps the last numeric value per characteristic to compute `ValueDelta`s.
+#[derive(Debug, Default)]
+pub(crate) struct ValueDeltaTracker {
+    previous_values: Mutex<HashMap<Arc<Fqcn>, f64>>,
+}
+
+impl ValueDeltaTracker {
+    /// Returns `None` for non-numeric values, they don't replace the previous value either.
+    pub(crate) fn track(&self, fqcn: &Arc<Fqcn>, value: &CharacteristicValue) -> Option<ValueDelta> {
+        let current = value.as_f64()?;
+        let previous = self.previous_values.lock().unwrap().insert(Arc::clone(fqcn), current);
+
+        Some(ValueDelta {
+            previous,
+            delta: previous.map(|previous| current - previous),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fqcn(characteristic: u128) -> Arc<Fqcn> {
+        Arc::new(Fqcn {
+            peripheral: "11:22:33:44:55:66".parse().unwrap(),
+            service: "0000180f-0000-1000-8000-00805f9b34fb".parse().unwrap(),
+            characteristic: uuid::Uuid::from_u128(characteristic),
+        })
+    }
+
+    #[test]
+    fn test_first_sample() {
+        let tracker = ValueDeltaTracker::default();
+        assert_eq!(
+            tracker.track(&fqcn(1), &CharacteristicValue::I64(10)),
+            Some(ValueDelta {
+                previous: None,
+                delta: None,
+            })
+        );
+        // every characteristic has its own first sample
+        assert_eq!(
+            tracker.track(&fqcn(2), &CharacteristicValue::F64(1.5)),
+            Some(ValueDelta {
+                previous: None,
+                delta: None,
+            })
+        );
+        assert_eq!(
+            tracker.track(&fqcn(3), &CharacteristicValue::Utf8("on".to_string())),
+            None
+        );
+    }
+
+    #[test]
+    fn test_increase_and_decrease() {
+        let tracker = ValueDeltaTracker::default();
+        tracker.track(&fqcn(1), &CharacteristicValue::I64(10));
+
+        assert_eq!(
+            tracker.track(&fqcn(1), &CharacteristicValue::F64(12.5)),
+            Some(ValueDelta {
+                previous: Some(10.0),
+                delta: Some(2.5),
+            })
+        );
+        assert_eq!(
+            tracker.track(&fqcn(1), &CharacteristicValue::I64(7)),
+            Some(ValueDelta {
+                previous: Some(12.5),
+                delta: Some(-5.5),
+            })
+        );
+    }
+}
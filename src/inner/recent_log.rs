@@ -0,0 +1,156 @@
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::Layer;
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct RecentLogEntry {
+    pub(crate) ts: DateTime<Utc>,
+    pub(crate) level: String,
+    pub(crate) target: String,
+    pub(crate) message: String,
+}
+
+/// Bounded buffer with the most recent WARN and ERROR events; the oldest entries are evicted first.
+pub(crate) struct RecentLogBuffer {
+    capacity: usize,
+    entries: Mutex<VecDeque<RecentLogEntry>>,
+}
+
+impl RecentLogBuffer {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    fn push(&self, entry: RecentLogEntry) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+
+    /// Returns up to `limit` latest entries, oldest first.
+    pub(crate) fn get_recent(&self, limit: Option<usize>) -> Vec<RecentLogEntry> {
+        let entries = self.entries.lock().unwrap();
+        let skip = limit.map(|limit| entries.len().saturating_sub(limit)).unwrap_or(0);
+        entries.iter().skip(skip).cloned().collect()
+    }
+}
+
+pub(crate) struct RecentLogLayer {
+    buffer: Arc<RecentLogBuffer>,
+}
+
+impl RecentLogLayer {
+    pub(crate) fn new(buffer: Arc<RecentLogBuffer>) -> Self {
+        Self { buffer }
+    }
+}
+
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+    fields: Vec<String>,
+}
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if field.name() == "message" {
+            self.message = format!("{value:?}");
+        } else {
+            self.fields.push(format!("{}={value:?}", field.name()));
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_string();
+        } else {
+            self.fields.push(format!("{}={value}", field.name()));
+        }
+    }
+}
+
+impl MessageVisitor {
+    fn into_message(self) -> String {
+        if self.fields.is_empty() {
+            return self.message;
+        }
+        format!("{} {}", self.message, self.fields.join(" "))
+    }
+}
+
+impl<S: Subscriber> Layer<S> for RecentLogLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        if *metadata.level() > Level::WARN {
+            return;
+        }
+
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+
+        self.buffer.push(RecentLogEntry {
+            ts: Utc::now(),
+            level: metadata.level().to_string(),
+            target: metadata.target().to_string(),
+            message: visitor.into_message(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing::{info, warn};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn test_captures_warnings() {
+        let buffer = Arc::new(RecentLogBuffer::new(2));
+        let subscriber = tracing_subscriber::registry().with(RecentLogLayer::new(Arc::clone(&buffer)));
+
+        tracing::subscriber::with_default(subscriber, || {
+            info!("ignored");
+            warn!(peripheral = "AA:BB", "first");
+            warn!("second");
+            tracing::error!("third");
+        });
+
+        let entries = buffer.get_recent(None);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].level, "WARN");
+        assert_eq!(entries[0].message, "second");
+        assert_eq!(entries[1].level, "ERROR");
+        assert_eq!(entries[1].message, "third");
+
+        let entries = buffer.get_recent(Some(1));
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].message, "third");
+    }
+
+    #[test]
+    fn test_formats_fields() {
+        let buffer = Arc::new(RecentLogBuffer::new(10));
+        let subscriber = tracing_subscriber::registry().with(RecentLogLayer::new(Arc::clone(&buffer)));
+
+        tracing::subscriber::with_default(subscriber, || {
+            warn!(peripheral = "AA:BB", "Failed to connect");
+        });
+
+        assert_eq!(buffer.get_recent(None)[0].message, "Failed to connect peripheral=AA:BB");
+    }
+}
@@ -0,0 +1,77 @@
+use std::time::Duration;
+
+use rocket::http::Status;
+use rocket::route::{Handler, Outcome};
+use rocket::{Data, Request, Route};
+use tracing::warn;
+
+use crate::inner::error::CollectorError;
+use crate::inner::http_error::HttpError;
+
+/// Responds with `504 Gateway Timeout` if the wrapped handler doesn't finish in time, i.e. while discovering the
+/// services of every peripheral. Streamed bodies (Server-Sent Events) are not limited, only producing the response is.
+#[derive(Clone)]
+struct RequestTimeout {
+    handler: Box<dyn Handler>,
+    timeout: Duration,
+}
+
+#[rocket::async_trait]
+impl Handler for RequestTimeout {
+    async fn handle<'r>(&self, request: &'r Request<'_>, data: Data<'r>) -> Outcome<'r> {
+        match tokio::time::timeout(self.timeout, self.handler.handle(request, data)).await {
+            Ok(outcome) => outcome,
+            Err(_) => {
+                let error = CollectorError::RequestTimeout(self.timeout);
+                warn!(uri = %request.uri(), "{error}");
+                Outcome::from(request, HttpError::new(error).with_status(Status::GatewayTimeout))
+            }
+        }
+    }
+}
+
+/// Fairings only see the request before and the response after the handler, so the timeout wraps the route handlers.
+pub(crate) fn with_request_timeout(routes: Vec<Route>, timeout: Duration) -> Vec<Route> {
+    routes
+        .into_iter()
+        .map(|mut route| {
+            route.handler = Box::new(RequestTimeout {
+                handler: route.handler,
+                timeout,
+            });
+            route
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::get;
+    use rocket::local::asynchronous::Client;
+    use rocket::routes;
+
+    use super::*;
+
+    #[get("/sleep/<millis>")]
+    async fn sleep(millis: u64) -> &'static str {
+        tokio::time::sleep(Duration::from_millis(millis)).await;
+        "done"
+    }
+
+    #[rocket::async_test]
+    async fn test_slow_handler_times_out() {
+        let routes = with_request_timeout(routes![sleep], Duration::from_millis(100));
+        let client = Client::tracked(rocket::build().mount("/", routes)).await.unwrap();
+
+        let response = client.get("/sleep/0").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.into_string().await.unwrap(), "done");
+
+        let response = client.get("/sleep/1000").dispatch().await;
+        assert_eq!(response.status(), Status::GatewayTimeout);
+        assert_eq!(
+            response.into_string().await.unwrap(),
+            "504 Gateway Timeout: Request timed out after 100ms"
+        );
+    }
+}
@@ -0,0 +1,99 @@
+use std::future::Future;
+use std::time::Duration;
+
+use metrics::Label;
+use tracing::error;
+
+use crate::inner::metrics::TASK_RESTART_COUNT;
+
+/// Runs `task` until it completes, running it again after `restart_delay` when it fails with an error that is not
+/// `fatal`, so a single malformed event doesn't stop a publisher for good. Restarts are counted per `name`.
+pub(crate) async fn supervise<F, Fut>(
+    name: &str,
+    restart_delay: Duration,
+    fatal: fn(&anyhow::Error) -> bool,
+    mut task: F,
+) -> anyhow::Result<()>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = anyhow::Result<()>>,
+{
+    loop {
+        match task().await {
+            Ok(()) => return Ok(()),
+            Err(err) if fatal(&err) => return Err(err),
+            Err(err) => {
+                error!(task = name, "Task has failed, restarting in {restart_delay:?}: {err:?}");
+                TASK_RESTART_COUNT
+                    .with_labels(vec![Label::new("task", name.to_string())])
+                    .increment();
+                tokio::time::sleep(restart_delay).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use metrics_util::debugging::DebugValue;
+
+    use super::*;
+    use crate::inner::error::CollectorError;
+    use crate::inner::metrics::testing::{capture_metrics, metric_key};
+
+    fn run(task: impl Future<Output = anyhow::Result<()>>) -> (anyhow::Result<()>, Option<DebugValue>) {
+        let (result, mut recorded) = capture_metrics(task);
+        let restarts = recorded.remove(&metric_key(TASK_RESTART_COUNT.metric_name, &[("task", "test")]));
+        (result, restarts)
+    }
+
+    #[test]
+    fn test_failed_task_is_restarted_until_receiver_is_closed() {
+        let (sender, receiver) = kanal::unbounded_async::<i32>();
+        for value in [1, -1, 2] {
+            sender.try_send(value).unwrap();
+        }
+        drop(sender);
+
+        let consumed = Mutex::new(vec![]);
+        let receiver = &receiver;
+        let consumed_ref = &consumed;
+        let (result, restarts) = run(supervise(
+            "test",
+            Duration::ZERO,
+            |_| false,
+            move || async move {
+                while let Ok(value) = receiver.recv().await {
+                    if value < 0 {
+                        anyhow::bail!("Negative value {value}");
+                    }
+                    consumed_ref.lock().unwrap().push(value);
+                }
+                // the receiver is closed and drained
+                Ok(())
+            },
+        ));
+
+        assert!(result.is_ok());
+        assert_eq!(restarts, Some(DebugValue::Counter(1)));
+        assert_eq!(*consumed.lock().unwrap(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_fatal_error_is_propagated() {
+        let (result, restarts) = run(supervise(
+            "test",
+            Duration::ZERO,
+            |err| err.downcast_ref::<CollectorError>().is_some(),
+            || async { Err(CollectorError::EndOfStream.into()) },
+        ));
+
+        assert!(matches!(
+            result.unwrap_err().downcast_ref::<CollectorError>(),
+            Some(CollectorError::EndOfStream)
+        ));
+        assert_eq!(restarts, None);
+    }
+}
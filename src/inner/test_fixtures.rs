@@ -0,0 +1,69 @@
+use std::sync::Arc;
+
+use uuid::Uuid;
+
+use crate::inner::conf::model::characteristic_config::CharacteristicConfig;
+use crate::inner::conv::converter::CharacteristicValue;
+use crate::inner::model::adapter_info::AdapterInfo;
+use crate::inner::model::characteristic_payload::CharacteristicPayload;
+use crate::inner::model::fqcn::Fqcn;
+
+/// Battery level characteristic of the `11:22:33:44:55:66` peripheral.
+pub(crate) fn fqcn() -> Arc<Fqcn> {
+    Arc::new(Fqcn {
+        peripheral: "11:22:33:44:55:66".parse().unwrap(),
+        service: "0000180f-0000-1000-8000-00805f9b34fb".parse().unwrap(),
+        characteristic: "00002a19-0000-1000-8000-00805f9b34fb".parse().unwrap(),
+    })
+}
+
+/// A characteristic of the battery service of the given peripheral.
+pub(crate) fn peripheral_fqcn(peripheral: &str, characteristic: u128) -> Arc<Fqcn> {
+    Arc::new(Fqcn {
+        peripheral: peripheral.parse().unwrap(),
+        service: "0000180f-0000-1000-8000-00805f9b34fb".parse().unwrap(),
+        characteristic: Uuid::from_u128(characteristic),
+    })
+}
+
+/// Subscription to the characteristic with the default converter and nothing published.
+pub(crate) fn subscribe_config(fqcn: &Fqcn) -> CharacteristicConfig {
+    CharacteristicConfig::Subscribe {
+        name: None,
+        service_name: None,
+        service_uuid: fqcn.service,
+        uuid: fqcn.characteristic,
+        history_size: 10,
+        history_window_sec: None,
+        converter: Default::default(),
+        record_raw_bytes: false,
+        publish_metrics: None,
+        publish_mqtt: None,
+    }
+}
+
+pub(crate) fn adapter_info() -> Arc<AdapterInfo> {
+    Arc::new(AdapterInfo {
+        id: "hci0".to_string(),
+        modalias: "smth".to_string(),
+        address: None,
+        alias: None,
+    })
+}
+
+/// A payload received just now by the `hci0` adapter.
+pub(crate) fn payload(
+    fqcn: Arc<Fqcn>,
+    conf: CharacteristicConfig,
+    value: CharacteristicValue,
+) -> CharacteristicPayload {
+    CharacteristicPayload {
+        created_at: chrono::Utc::now(),
+        value,
+        raw_bytes: None,
+        delta: None,
+        fqcn,
+        conf: Arc::new(conf),
+        adapter_info: adapter_info(),
+    }
+}
@@ -3,18 +3,27 @@ use std::sync::Arc;
 use clap::Parser;
 use rumqttc::v5::MqttOptions;
 use tokio::task::JoinSet;
-use tracing::warn;
+use tracing::{info, warn};
 
 use inner::publish::api_publisher::ApiPublisher;
 
-use crate::init::{init_mqtt, init_multi_publisher, init_prometheus, init_rocket, init_tracing};
+use crate::init::{
+    init_lifecycle_publisher, init_mqtt, init_mqtt_commands, init_mqtt_heartbeat, init_multi_publisher,
+    init_prometheus, init_rocket, init_tracing,
+};
 use crate::inner::adapter_manager::AdapterManager;
+use crate::inner::api::ApiSettings;
 use crate::inner::conf::cmd_args::AppConf;
 use crate::inner::conf::dto::collector_configuration::CollectorConfigurationDto;
 use crate::inner::conf::manager::ConfigurationManager;
 use crate::inner::model::collector_event::CollectorEvent;
+use crate::inner::publish::event_stream::EventStreamPublisher;
+use crate::inner::publish::lifecycle_publisher::LifecyclePublisher;
 use crate::inner::publish::metric_publisher::MetricPublisher;
+use crate::inner::publish::mqtt_command::MqttCommandRouter;
+use crate::inner::publish::mqtt_target::MqttTarget;
 use crate::inner::publish::FanOutSender;
+use crate::inner::recent_log::RecentLogBuffer;
 
 mod init;
 mod inner;
@@ -22,9 +31,10 @@ mod inner;
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let mut join_set: JoinSet<anyhow::Result<()>> = JoinSet::new();
-    init_tracing()?;
-
     let app_conf = Arc::new(AppConf::parse());
+    let recent_log_buffer = Arc::new(RecentLogBuffer::new(app_conf.recent_log_capacity));
+    let log_level_manager = init_tracing(Arc::clone(&recent_log_buffer))?;
+
     let prometheus_handle = init_prometheus(app_conf.metrics_idle_timeout)?;
 
     let collector_conf = CollectorConfigurationDto::try_from(app_conf.as_ref())?;
@@ -32,19 +42,66 @@ async fn main() -> anyhow::Result<()> {
     configuration_manager
         .add_peripherals(collector_conf.peripherals)
         .await?;
+    configuration_manager
+        .add_adapter_filters(collector_conf.adapter_filters)
+        .await;
 
     let (payload_sender, payload_receiver) = kanal::unbounded_async::<CollectorEvent>();
-    let mut fanout_sender = FanOutSender::new(vec![payload_sender]);
+    let mut fanout_sender = FanOutSender::new(vec![("publisher", payload_sender)]);
 
-    match MqttOptions::try_from(app_conf.as_ref()) {
+    let command_router = Arc::new(MqttCommandRouter::default());
+    let mqtt_client = match MqttOptions::try_from(app_conf.as_ref()) {
         Ok(opts) => {
             let (mqtt_sender, mqtt_receiver) = kanal::unbounded_async::<CollectorEvent>();
-            fanout_sender.add(mqtt_sender);
-            init_mqtt(opts, mqtt_receiver, app_conf.mqtt_cap, &mut join_set).await?;
+            fanout_sender.add("mqtt", mqtt_sender);
+            Some(
+                init_mqtt(
+                    "mqtt".to_string(),
+                    opts,
+                    mqtt_receiver,
+                    app_conf.mqtt_cap,
+                    MqttTarget {
+                        serialization: app_conf.mqtt_serialization,
+                        timestamp_format: app_conf.timestamp_format,
+                        ..Default::default()
+                    },
+                    Some(Arc::clone(&command_router)),
+                    &mut join_set,
+                )
+                .await?,
+            )
         }
         Err(error) => {
             warn!(%error, "Failed to create an MQTT client");
+            None
         }
+    };
+
+    for target_conf in &collector_conf.mqtt_targets {
+        let (mqtt_sender, mqtt_receiver) = kanal::unbounded_async::<CollectorEvent>();
+        let name = format!("mqtt:{}", target_conf.name);
+        fanout_sender.add(name.clone(), mqtt_sender);
+        init_mqtt(
+            name,
+            MqttOptions::from(target_conf),
+            mqtt_receiver,
+            target_conf.cap,
+            MqttTarget {
+                timestamp_format: app_conf.timestamp_format,
+                ..MqttTarget::from(target_conf)
+            },
+            None,
+            &mut join_set,
+        )
+        .await?;
+        info!(target = target_conf.name, "Publishing to an additional MQTT broker");
+    }
+
+    let lifecycle_publisher = Arc::new(LifecyclePublisher::new(app_conf.lifecycle_history_size.unwrap_or(0)));
+    if app_conf.lifecycle_history_size.is_some() {
+        let (lifecycle_sender, lifecycle_receiver) = kanal::unbounded_async::<CollectorEvent>();
+        fanout_sender.add("lifecycle", lifecycle_sender);
+        init_lifecycle_publisher(Arc::clone(&lifecycle_publisher), lifecycle_receiver, &mut join_set);
     }
 
     let adapter_manager = Arc::new(AdapterManager::new(
@@ -54,21 +111,62 @@ async fn main() -> anyhow::Result<()> {
     ));
     adapter_manager.init().await?;
 
-    let api_publisher = Arc::new(ApiPublisher::new());
-    let metric_publisher = Arc::new(MetricPublisher::new());
-    let multi_publisher = init_multi_publisher(&api_publisher, &metric_publisher, payload_receiver.clone_sync());
+    let api_publisher = Arc::new(ApiPublisher::new(app_conf.timestamp_format));
+    api_publisher.start_eviction(app_conf.api_eviction_interval);
+    let metric_publisher = Arc::new(MetricPublisher::new(
+        app_conf.metrics_labels_allowlist.clone(),
+        app_conf.metrics_idle_timeout,
+    ));
+    let event_stream_publisher = Arc::new(EventStreamPublisher::new(
+        app_conf.event_stream_capacity.get(),
+        app_conf.timestamp_format,
+    ));
+    let multi_publisher = init_multi_publisher(
+        &api_publisher,
+        &metric_publisher,
+        &event_stream_publisher,
+        payload_receiver,
+    );
 
     {
-        let sync_multi_publisher = multi_publisher.clone();
-        join_set.spawn_blocking(|| {
-            let handle = std::thread::spawn(move || {
-                sync_multi_publisher.block_on_receiving();
-            });
-            let result = handle.join();
-            warn!("Storage receiver has ended: {result:?}");
+        let multi_publisher = multi_publisher.clone();
+        join_set.spawn(async move {
+            multi_publisher.block_on_receiving().await;
+            warn!("Storage receiver has ended");
             Ok(())
         });
     }
+
+    if app_conf.once {
+        let reads = adapter_manager.read_once().await?;
+        info!(reads, "One-shot read has completed");
+        // the publishers end after draining their queues once the last sender is dropped
+        drop(adapter_manager);
+        // values still queued would be lost, so a timeout is a failure
+        return tokio::time::timeout(app_conf.once_flush_timeout, async {
+            while let Some(result) = join_set.join_next().await {
+                result??;
+            }
+            Ok::<_, anyhow::Error>(())
+        })
+        .await
+        .map_err(|_| anyhow::anyhow!("Publishers haven't finished in {:?}", app_conf.once_flush_timeout))?;
+    }
+
+    if mqtt_client.is_some() {
+        init_mqtt_commands(command_router, Arc::clone(&adapter_manager), &mut join_set);
+    }
+
+    if let (Some(mqtt_client), Some(topic)) = (mqtt_client, app_conf.mqtt_heartbeat_topic.clone()) {
+        init_mqtt_heartbeat(
+            mqtt_client,
+            Arc::clone(&adapter_manager),
+            topic,
+            app_conf.mqtt_heartbeat_interval,
+            &mut join_set,
+        );
+    }
+
     {
         let adapter_manager = adapter_manager.clone();
         join_set.spawn(async move {
@@ -77,8 +175,17 @@ async fn main() -> anyhow::Result<()> {
                 adapter_manager,
                 api_publisher,
                 prometheus_handle,
+                log_level_manager,
+                recent_log_buffer,
                 app_conf.listen_address,
+                app_conf.api_request_timeout,
             )
+            .manage(lifecycle_publisher)
+            .manage(event_stream_publisher)
+            .manage(metric_publisher)
+            .manage(ApiSettings {
+                read_only: app_conf.read_only_api,
+            })
             .launch()
             .await?;
 